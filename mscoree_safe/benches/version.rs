@@ -0,0 +1,73 @@
+// version.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Version reads against a stand-in for GetVersionString that counts how 
+// often it's entered. The double-call path always crosses twice; the sized 
+// path once for every version string the shim actually returns.
+#![feature(test)]
+extern crate mscoree_safe;
+extern crate test;
+extern crate winapi;
+
+use std::cell::Cell;
+use std::ptr;
+
+use test::{black_box, Bencher};
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, HRESULT, HRESULT_FROM_WIN32, S_OK};
+
+use mscoree_safe::buffer::{double_call_string, sized_call_string};
+
+const VERSION: &str = "v4.0.30319";
+
+fn get_version_string(calls: &Cell<u32>, buf: *mut u16, len: *mut DWORD) -> HRESULT {
+    calls.set(calls.get() + 1);
+    let wide: Vec<u16> = VERSION.encode_utf16().chain(Some(0)).collect();
+    unsafe {
+        if buf.is_null() || (*len as usize) < wide.len() {
+            *len = wide.len() as DWORD;
+            return HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER);
+        }
+        ptr::copy_nonoverlapping(wide.as_ptr(), buf, wide.len());
+        *len = wide.len() as DWORD;
+    }
+    S_OK
+}
+
+#[bench]
+fn double_call(b: &mut Bencher) {
+    let calls = Cell::new(0);
+    b.iter(|| black_box(double_call_string(|buf, len| get_version_string(&calls, buf, len))));
+    assert_eq!(calls.get() % 2, 0);
+}
+
+#[bench]
+fn sized_call(b: &mut Bencher) {
+    let calls = Cell::new(0);
+    let mut iterations = 0;
+    b.iter(|| {
+        iterations += 1;
+        black_box(sized_call_string(32, |buf, len| get_version_string(&calls, buf, len)))
+    });
+    assert_eq!(calls.get(), iterations);
+}
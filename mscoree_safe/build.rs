@@ -1,4 +1,11 @@
 
+//mscoree.dll is delay-loaded so that clr::installed_versions() can report 
+// NotInstalled on machines without the .NET Framework instead of the loader 
+// refusing to start the binary. This only covers this crate's own tests, 
+// examples and benches; binaries depending on it need the same two lines.
 fn main() {
-
+    if std::env::var("CARGO_CFG_TARGET_ENV").map(|env| env == "msvc").unwrap_or(false) {
+        println!("cargo:rustc-link-arg=/DELAYLOAD:mscoree.dll");
+        println!("cargo:rustc-link-lib=delayimp");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,239 @@
+// apartment.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//COM apartment state for the threads that talk to the CLR. A thread has 
+// to be in an apartment before it makes COM calls, and every successful 
+// CoInitializeEx has to be paired with a CoUninitialize on the same thread, 
+// which ApartmentGuard takes care of. Metahost creation goes through 
+// ensure_current(), so callers that never think about COM get an MTA.
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{CO_E_NOTINITIALIZED, E_FAIL, RPC_E_CHANGED_MODE, S_FALSE, S_OK};
+use winapi::um::combaseapi::{CoGetApartmentType, CoInitializeEx, CoUninitialize};
+use winapi::um::objbase::{COINIT_APARTMENTTHREADED, COINIT_MULTITHREADED};
+use winapi::um::objidlbase::{APTTYPE, APTTYPEQUALIFIER, APTTYPE_MTA};
+
+use error::HostingError;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Apartment {
+    MultiThreaded, 
+    SingleThreaded,
+}
+
+impl Apartment {
+    fn coinit(self) -> DWORD {
+        match self {
+            Apartment::MultiThreaded => COINIT_MULTITHREADED, 
+            Apartment::SingleThreaded => COINIT_APARTMENTTHREADED,
+        }
+    }
+}
+
+//What ensure_current() does on a thread that isn't in an apartment yet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ApartmentPolicy {
+    //Join the MTA for the rest of the thread's life; the default
+    Establish, 
+    //Fail with CO_E_NOTINITIALIZED and leave it to the caller
+    Require, 
+    //Don't check at all
+    Ignore,
+}
+
+static POLICY: AtomicUsize = AtomicUsize::new(0);
+
+impl ApartmentPolicy {
+    fn to_raw(self) -> usize {
+        match self {
+            ApartmentPolicy::Establish => 0, 
+            ApartmentPolicy::Require => 1, 
+            ApartmentPolicy::Ignore => 2,
+        }
+    }
+
+    fn from_raw(value: usize) -> ApartmentPolicy {
+        match value {
+            1 => ApartmentPolicy::Require, 
+            2 => ApartmentPolicy::Ignore, 
+            _ => ApartmentPolicy::Establish,
+        }
+    }
+}
+
+pub fn set_policy(policy: ApartmentPolicy) {
+    POLICY.store(policy.to_raw(), Ordering::SeqCst);
+}
+
+pub fn policy() -> ApartmentPolicy {
+    ApartmentPolicy::from_raw(POLICY.load(Ordering::SeqCst))
+}
+
+//One CoInitializeEx on the current thread, undone on drop. Not Send: the 
+// CoUninitialize has to happen on the thread that initialized.
+pub struct ApartmentGuard {
+    apartment: Apartment, 
+    _thread_bound: PhantomData<*const ()>,
+}
+
+impl ApartmentGuard {
+    //Nested entries into the same apartment are fine and counted by COM. 
+    // RPC_E_CHANGED_MODE when the thread is already in the other kind.
+    pub fn enter(apartment: Apartment) -> Result<ApartmentGuard, HostingError> {
+        let hr = unsafe { CoInitializeEx(ptr::null_mut(), apartment.coinit()) };
+        match hr {
+            S_OK | S_FALSE => Ok(ApartmentGuard { apartment, _thread_bound: PhantomData }), 
+            _ => Err(HostingError::from_hresult(hr, CALL!(ole32::CoInitializeEx))),
+        }
+    }
+
+    pub fn apartment(&self) -> Apartment {
+        self.apartment
+    }
+}
+
+impl Drop for ApartmentGuard {
+    fn drop(&mut self) {
+        unsafe { CoUninitialize() };
+    }
+}
+
+//None when the thread hasn't joined an apartment. Threads that are in the 
+// MTA only implicitly, because some other thread joined it, report 
+// MultiThreaded too; COM calls work from them.
+pub fn current() -> Result<Option<Apartment>, HostingError> {
+    let mut kind: APTTYPE = 0;
+    let mut qualifier: APTTYPEQUALIFIER = 0;
+    let hr = unsafe { CoGetApartmentType(&mut kind, &mut qualifier) };
+    match hr {
+        S_OK if kind == APTTYPE_MTA => Ok(Some(Apartment::MultiThreaded)), 
+        S_OK => Ok(Some(Apartment::SingleThreaded)), 
+        CO_E_NOTINITIALIZED => Ok(None), 
+        _ => Err(HostingError::from_hresult(hr, CALL!(ole32::CoGetApartmentType))),
+    }
+}
+
+thread_local! {
+    //The apartment ensure_current() joined on this thread, left at thread exit
+    static IMPLICIT: RefCell<Option<ApartmentGuard>> = RefCell::new(None);
+}
+
+//Applies the policy to the current thread. Any existing apartment is 
+// accepted as-is.
+pub fn ensure_current() -> Result<(), HostingError> {
+    let policy = policy();
+    if policy == ApartmentPolicy::Ignore || current()?.is_some() {
+        return Ok(());
+    }
+    if policy == ApartmentPolicy::Require {
+        return Err(HostingError::from_hresult(CO_E_NOTINITIALIZED, CALL!(ole32::CoGetApartmentType)));
+    }
+    let guard = ApartmentGuard::enter(Apartment::MultiThreaded)?;
+    IMPLICIT.with(|implicit| *implicit.borrow_mut() = Some(guard));
+    Ok(())
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+//A thread of our own that sits in an apartment and runs closures for its 
+// owner, for hosts whose own threads are in the wrong apartment or mustn't 
+// be touched. COM objects created inside a closure belong to the worker, 
+// so keep them there and hand back plain data.
+pub struct ComWorker {
+    jobs: Option<Sender<Job>>, 
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ComWorker {
+    pub fn spawn(apartment: Apartment) -> Result<ComWorker, HostingError> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready, started) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("mscoree-com".to_string())
+            .spawn(move || {
+                let guard = match ApartmentGuard::enter(apartment) {
+                    Ok(guard) => guard, 
+                    Err(err) => {
+                        let _ = ready.send(Err(err));
+                        return;
+                    },
+                };
+                let _ = ready.send(Ok(()));
+                for job in queue {
+                    job();
+                }
+                drop(guard);
+            })
+            .map_err(|_| HostingError::from_hresult(E_FAIL, CALL!(ComWorker::spawn)))?;
+        started.recv().unwrap_or_else(|_| Err(HostingError::from_hresult(E_FAIL, CALL!(ComWorker::spawn))))?;
+        Ok(ComWorker { jobs: Some(jobs), thread: Some(thread) })
+    }
+
+    //Blocks until the closure has run on the worker. A panic in it is 
+    // resumed here.
+    pub fn run<F, R>(&self, f: F) -> R 
+        where F: FnOnce() -> R + Send + 'static, 
+              R: Send + 'static
+    {
+        let (done, result) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            let _ = done.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        });
+        self.jobs.as_ref().expect("only taken on drop").send(job).expect("worker outlives its sender");
+        match result.recv().expect("worker always answers") {
+            Ok(value) => value, 
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Drop for ComWorker {
+    fn drop(&mut self) {
+        //Closing the channel ends the worker's loop
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn policy_roundtrip() {
+        //Goes through the raw encoding only; POLICY is process-wide and 
+        // other tests depend on the default
+        for &p in &[ApartmentPolicy::Require, ApartmentPolicy::Ignore, ApartmentPolicy::Establish] {
+            assert_eq!(ApartmentPolicy::from_raw(p.to_raw()), p);
+        }
+        assert_eq!(ApartmentPolicy::from_raw(0), ApartmentPolicy::Establish);
+    }
+}
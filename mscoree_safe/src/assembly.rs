@@ -0,0 +1,312 @@
+// assembly.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Assembly identities as the loader sees them. Name and culture compare 
+// case-insensitively, "neutral" and no culture are the same thing, and the 
+// public key token is compared as raw bytes. Everything that needs to decide 
+// whether two references mean "the same assembly" should go through here.
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct AssemblyVersion {
+    pub major: u16, 
+    pub minor: u16, 
+    pub build: u16, 
+    pub revision: u16,
+}
+
+impl AssemblyVersion {
+    pub fn new(major: u16, minor: u16, build: u16, revision: u16) -> AssemblyVersion {
+        AssemblyVersion { major, minor, build, revision }
+    }
+}
+
+impl fmt::Display for AssemblyVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.build, self.revision)
+    }
+}
+
+impl FromStr for AssemblyVersion {
+    type Err = AssemblyNameError;
+    fn from_str(s: &str) -> Result<AssemblyVersion, AssemblyNameError> {
+        let mut parts = [0u16; 4];
+        let mut count = 0;
+        for part in s.trim().split('.') {
+            if count == 4 {
+                return Err(AssemblyNameError::BadVersion(s.to_string()));
+            }
+            parts[count] = part.parse().map_err(|_| AssemblyNameError::BadVersion(s.to_string()))?;
+            count += 1;
+        }
+        if count < 2 {
+            return Err(AssemblyNameError::BadVersion(s.to_string()));
+        }
+        Ok(AssemblyVersion::new(parts[0], parts[1], parts[2], parts[3]))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum AssemblyNameError {
+    EmptyName, 
+    BadVersion(String), 
+    BadPublicKeyToken(String), 
+    BadProperty(String),
+}
+
+//How much of two identities has to agree for them to be considered equal
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AssemblyMatch {
+    //Simple name only
+    Name, 
+    //Simple name and public key token
+    NameAndToken, 
+    //Name, public key token, culture and version
+    Full,
+}
+
+#[derive(Clone, Debug)]
+pub struct AssemblyName {
+    pub name: String, 
+    pub version: Option<AssemblyVersion>, 
+    pub culture: Option<String>, 
+    pub public_key_token: Option<[u8; 8]>,
+}
+
+impl AssemblyName {
+    pub fn new(name: &str) -> AssemblyName {
+        AssemblyName {
+            name: name.to_string(), 
+            version: None, 
+            culture: None, 
+            public_key_token: None,
+        }
+    }
+
+    //Culture with "neutral" and "" folded into None
+    pub fn normalized_culture(&self) -> Option<String> {
+        match self.culture {
+            Some(ref c) if !c.is_empty() && !c.eq_ignore_ascii_case("neutral") => Some(c.to_ascii_lowercase()), 
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, other: &AssemblyName, level: AssemblyMatch) -> bool {
+        if !self.name.eq_ignore_ascii_case(&other.name) {
+            return false;
+        }
+        if level == AssemblyMatch::Name {
+            return true;
+        }
+        if self.public_key_token != other.public_key_token {
+            return false;
+        }
+        if level == AssemblyMatch::NameAndToken {
+            return true;
+        }
+        self.version == other.version && self.normalized_culture() == other.normalized_culture()
+    }
+
+    //Does a partial reference (e.g. "System.Xml, PublicKeyToken=...") 
+    // bind to this identity? Fields the reference leaves out are not compared.
+    pub fn satisfies(&self, reference: &AssemblyName) -> bool {
+        self.name.eq_ignore_ascii_case(&reference.name) 
+            && (reference.public_key_token.is_none() || reference.public_key_token == self.public_key_token) 
+            && (reference.version.is_none() || reference.version == self.version) 
+            && (reference.culture.is_none() || reference.normalized_culture() == self.normalized_culture())
+    }
+
+    //FNV-1a over the normalized identity. Unlike DefaultHasher this is 
+    // stable across processes and compiler versions, so it can be persisted.
+    pub fn stable_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl PartialEq for AssemblyName {
+    fn eq(&self, other: &AssemblyName) -> bool {
+        self.matches(other, AssemblyMatch::Full)
+    }
+}
+
+impl Eq for AssemblyName {}
+
+impl Hash for AssemblyName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for b in self.name.bytes() {
+            state.write_u8(b.to_ascii_lowercase());
+        }
+        state.write_u8(0);
+        match self.version {
+            Some(v) => { state.write_u8(1); v.hash(state); }, 
+            None => state.write_u8(0),
+        }
+        match self.normalized_culture() {
+            Some(c) => { state.write_u8(1); state.write(c.as_bytes()); state.write_u8(0); }, 
+            None => state.write_u8(0),
+        }
+        match self.public_key_token {
+            Some(t) => { state.write_u8(1); state.write(&t); }, 
+            None => state.write_u8(0),
+        }
+    }
+}
+
+impl fmt::Display for AssemblyName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(v) = self.version {
+            write!(f, ", Version={}", v)?;
+        }
+        if let Some(ref c) = self.culture {
+            write!(f, ", Culture={}", if c.is_empty() { "neutral" } else { c })?;
+        }
+        if let Some(t) = self.public_key_token {
+            write!(f, ", PublicKeyToken=")?;
+            for b in t.iter() {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Parses display names of the form 
+// "Name, Version=1.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089". 
+// Unknown properties (ProcessorArchitecture, Retargetable...) are ignored.
+impl FromStr for AssemblyName {
+    type Err = AssemblyNameError;
+    fn from_str(s: &str) -> Result<AssemblyName, AssemblyNameError> {
+        let mut parts = s.split(',');
+        let name = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err(AssemblyNameError::EmptyName);
+        }
+        let mut result = AssemblyName::new(name);
+        for part in parts {
+            let mut kv = part.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim();
+            let value = match kv.next() {
+                Some(v) => v.trim(), 
+                None => return Err(AssemblyNameError::BadProperty(part.trim().to_string())),
+            };
+            if key.eq_ignore_ascii_case("Version") {
+                result.version = Some(value.parse()?);
+            } else if key.eq_ignore_ascii_case("Culture") {
+                result.culture = Some(value.to_string());
+            } else if key.eq_ignore_ascii_case("PublicKeyToken") {
+                result.public_key_token = parse_token(value)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn parse_token(s: &str) -> Result<Option<[u8; 8]>, AssemblyNameError> {
+    if s.eq_ignore_ascii_case("null") {
+        return Ok(None);
+    }
+    if s.len() != 16 || !s.is_ascii() {
+        return Err(AssemblyNameError::BadPublicKeyToken(s.to_string()));
+    }
+    let mut token = [0u8; 8];
+    for i in 0..8 {
+        token[i] = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+            .map_err(|_| AssemblyNameError::BadPublicKeyToken(s.to_string()))?;
+    }
+    Ok(Some(token))
+}
+
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    fn new() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MSCORLIB: &str = "mscorlib, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089";
+
+    #[test]
+    fn parse_and_display_roundtrip() {
+        let name: AssemblyName = MSCORLIB.parse().unwrap();
+        assert_eq!(name.version, Some(AssemblyVersion::new(4, 0, 0, 0)));
+        assert_eq!(name.public_key_token, Some([0xb7, 0x7a, 0x5c, 0x56, 0x19, 0x34, 0xe0, 0x89]));
+        assert_eq!(name.to_string(), MSCORLIB);
+    }
+
+    #[test]
+    fn equality_folds_case_and_culture() {
+        let a: AssemblyName = MSCORLIB.parse().unwrap();
+        let mut b = a.clone();
+        b.name = String::from("MSCORLIB");
+        b.culture = None;
+        assert_eq!(a, b);
+        assert_eq!(a.stable_hash(), b.stable_hash());
+    }
+
+    #[test]
+    fn partial_matches() {
+        let a: AssemblyName = MSCORLIB.parse().unwrap();
+        let mut b = a.clone();
+        b.version = Some(AssemblyVersion::new(2, 0, 0, 0));
+        assert!(a != b);
+        assert!(a.matches(&b, AssemblyMatch::NameAndToken));
+        b.public_key_token = None;
+        assert!(!a.matches(&b, AssemblyMatch::NameAndToken));
+        assert!(a.matches(&b, AssemblyMatch::Name));
+    }
+
+    #[test]
+    fn reference_satisfied_by_identity() {
+        let a: AssemblyName = MSCORLIB.parse().unwrap();
+        let r: AssemblyName = "mscorlib, PublicKeyToken=b77a5c561934e089".parse().unwrap();
+        assert!(a.satisfies(&r));
+        assert!(!r.satisfies(&a));
+    }
+
+    #[test]
+    fn rejects_bad_tokens() {
+        let r = "mscorlib, PublicKeyToken=b77a".parse::<AssemblyName>();
+        assert_eq!(r.err(), Some(AssemblyNameError::BadPublicKeyToken(String::from("b77a"))));
+    }
+}
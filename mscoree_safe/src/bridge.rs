@@ -0,0 +1,122 @@
+// bridge.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Host/managed communication through a custom AppDomainManager. The runtime 
+// creates the manager type in every domain and reports each instance to 
+// IHostControl::SetAppDomainManager; ManagedBridge keeps those, keyed by 
+// domain id, so the host can call into its managed half by name. The 
+// managed half can call back through whatever the host passes it, e.g. 
+// delegates or objects set with AppDomain::set_data.
+//
+//  let bridge = ManagedBridge::new("Bootstrap, Version=1.0.0.0, Culture=neutral, PublicKeyToken=null", "Bootstrap.HostManager");
+//  bridge.attach(&host, HostControl::new())?;
+//  host.start()?;
+//  bridge.invoke_on_manager(DEFAULT_APP_DOMAIN_ID, "Ping", &[])?;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::E_INVALIDARG;
+use winapi::um::unknwnbase::IUnknown;
+
+use error::HostingError;
+use managers::HostControl;
+use reflection::{InvokeError, ManagedObject};
+use runtimehost::ClrRuntimeHost;
+use variant::{ClrObject, ClrValue};
+
+//Managers are reported on whichever thread creates the domain. A managed 
+// object's COM-callable wrapper is free-threaded, so handing it between 
+// threads is fine.
+struct Manager(ClrObject);
+
+unsafe impl Send for Manager {}
+
+#[derive(Default)]
+pub(crate) struct Managers {
+    by_domain: Mutex<HashMap<DWORD, Manager>>,
+}
+
+impl Managers {
+    fn lock(&self) -> MutexGuard<HashMap<DWORD, Manager>> {
+        self.by_domain.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    //Called from IHostControl::SetAppDomainManager; AddRefs the manager
+    pub(crate) fn record(&self, domain_id: DWORD, manager: *mut IUnknown) -> Result<(), HostingError> {
+        let manager = ClrObject::from_borrowed(manager)?;
+        self.lock().insert(domain_id, Manager(manager));
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct ManagedBridge {
+    assembly_name: String, 
+    type_name: String, 
+    managers: Arc<Managers>,
+}
+
+impl ManagedBridge {
+    //assembly_name is a display name the runtime can resolve by probing
+    pub fn new(assembly_name: &str, type_name: &str) -> ManagedBridge {
+        ManagedBridge {
+            assembly_name: assembly_name.to_string(), 
+            type_name: type_name.to_string(), 
+            managers: Arc::new(Managers::default()),
+        }
+    }
+
+    //Installs the manager type and a host control that captures each 
+    // instance. Both only take effect before Start, so this must come first.
+    pub fn attach(&self, host: &ClrRuntimeHost, control: HostControl) -> Result<(), HostingError> {
+        host.set_host_control(control.app_domain_managers(self))?;
+        host.control()?.set_app_domain_manager(&self.assembly_name, &self.type_name)
+    }
+
+    pub(crate) fn managers(&self) -> Arc<Managers> {
+        self.managers.clone()
+    }
+
+    //Domains reported so far, in no particular order. Unloaded domains 
+    // stay until forget() is called for them.
+    pub fn domain_ids(&self) -> Vec<DWORD> {
+        self.managers.lock().keys().cloned().collect()
+    }
+
+    pub fn manager(&self, domain_id: DWORD) -> Option<ClrObject> {
+        self.managers.lock().get(&domain_id).map(|manager| manager.0.clone())
+    }
+
+    //Drops the bridge's reference, e.g. once the domain has been unloaded
+    pub fn forget(&self, domain_id: DWORD) {
+        self.managers.lock().remove(&domain_id);
+    }
+
+    //Public instance method on the manager created for domain_id. 
+    // E_INVALIDARG when that domain hasn't reported a manager.
+    pub fn invoke_on_manager(&self, domain_id: DWORD, method: &str, args: &[ClrValue]) -> Result<ClrValue, InvokeError> {
+        let manager = self.manager(domain_id)
+            .ok_or_else(|| HostingError::from_hresult(E_INVALIDARG, CALL!(IHostControl::SetAppDomainManager)))?;
+        ManagedObject::from_value(ClrValue::Object(manager))?.invoke(method, args)
+    }
+}
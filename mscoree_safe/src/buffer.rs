@@ -0,0 +1,202 @@
+// buffer.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+use std::cmp;
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+use std::slice;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, HRESULT, HRESULT_FROM_WIN32, S_OK};
+
+//Drives the "call once for the size, call again for the data" pattern 
+// used by GetVersionString, GetRuntimeDirectory and friends. 
+// The returned buffer has its length set to what the callee reported.
+fn double_call<T, F>(mut call: F) -> Result<Vec<T>, HRESULT> 
+    where T: Copy + Default, F: FnMut(*mut T, *mut DWORD) -> HRESULT
+{
+    let mut len: DWORD = 0;
+    let hr = call(ptr::null_mut(), &mut len);
+    if hr != S_OK && hr != HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) {
+        return Err(hr);
+    }
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut buffer: Vec<T> = vec![T::default(); len as usize];
+    let hr = call(buffer.as_mut_ptr(), &mut len);
+    if hr != S_OK {
+        return Err(hr);
+    }
+    buffer.truncate(len as usize);
+    Ok(buffer)
+}
+
+//Wide character variant, for string out-params. Trailing NULs are trimmed.
+pub fn double_call_buffer<F>(call: F) -> Result<Vec<u16>, HRESULT> 
+    where F: FnMut(*mut u16, *mut DWORD) -> HRESULT
+{
+    let mut buffer = double_call(call)?;
+    trim_nuls(&mut buffer);
+    Ok(buffer)
+}
+
+//For values with a typical size, such as version strings: a buffer of 
+// `capacity` is offered up front so the common case is one call, and the 
+// callee's reported size is only used when that turns out too small.
+pub fn sized_call_buffer<F>(capacity: usize, mut call: F) -> Result<Vec<u16>, HRESULT> 
+    where F: FnMut(*mut u16, *mut DWORD) -> HRESULT
+{
+    let mut buffer = vec![0u16; capacity];
+    let mut len = capacity as DWORD;
+    let mut hr = call(buffer.as_mut_ptr(), &mut len);
+    if hr == HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) && len as usize > capacity {
+        buffer = vec![0; len as usize];
+        hr = call(buffer.as_mut_ptr(), &mut len);
+    }
+    if hr != S_OK {
+        return Err(hr);
+    }
+    buffer.truncate(cmp::min(len as usize, buffer.len()));
+    trim_nuls(&mut buffer);
+    Ok(buffer)
+}
+
+pub fn sized_call_string<F>(capacity: usize, call: F) -> Result<String, HRESULT> 
+    where F: FnMut(*mut u16, *mut DWORD) -> HRESULT
+{
+    sized_call_buffer(capacity, call).map(|buffer| String::from_utf16_lossy(&buffer))
+}
+
+fn trim_nuls(buffer: &mut Vec<u16>) {
+    while buffer.last() == Some(&0) {
+        buffer.pop();
+    }
+}
+
+//Byte variant, for blobs such as public keys and tokens. Returned as-is, 
+// since a trailing zero byte is valid data there.
+pub fn double_call_bytes<F>(call: F) -> Result<Vec<u8>, HRESULT> 
+    where F: FnMut(*mut u8, *mut DWORD) -> HRESULT
+{
+    double_call(call)
+}
+
+pub fn double_call_string<F>(call: F) -> Result<String, HRESULT> 
+    where F: FnMut(*mut u16, *mut DWORD) -> HRESULT
+{
+    double_call_buffer(call).map(|buffer| String::from_utf16_lossy(&buffer))
+}
+
+//NUL-terminated UTF-16 for LPCWSTR parameters. Paths go through OsStr 
+// unchanged, so names that aren't valid Unicode survive the trip.
+pub(crate) fn wide<S: AsRef<OsStr> + ?Sized>(s: &S) -> Vec<u16> {
+    s.as_ref().encode_wide().chain(Some(0)).collect()
+}
+
+//Copies a NUL-terminated wide string handed to us by the runtime
+pub(crate) unsafe fn wide_str(s: LPCWSTR) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    while *s.offset(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(slice::from_raw_parts(s, len as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use winapi::shared::winerror::E_FAIL;
+
+    fn fake_query(data: &'static str) -> impl FnMut(*mut u16, *mut DWORD) -> HRESULT {
+        let mut wide: Vec<u16> = data.encode_utf16().collect();
+        wide.push(0);
+        move |buf, len| unsafe {
+            if buf.is_null() || (*len as usize) < wide.len() {
+                *len = wide.len() as DWORD;
+                return HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER);
+            }
+            ptr::copy_nonoverlapping(wide.as_ptr(), buf, wide.len());
+            *len = wide.len() as DWORD;
+            S_OK
+        }
+    }
+
+    #[test]
+    fn sizes_fills_and_trims() {
+        let s = double_call_string(fake_query("v4.0.30319")).unwrap();
+        assert_eq!(s, String::from("v4.0.30319"));
+    }
+
+    #[test]
+    fn bytes_keep_trailing_zeros() {
+        let blob: [u8; 4] = [0xb7, 0x7a, 0x5c, 0x00];
+        let r = double_call_bytes(|buf, len| unsafe {
+            if buf.is_null() {
+                *len = blob.len() as DWORD;
+                return HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER);
+            }
+            ptr::copy_nonoverlapping(blob.as_ptr(), buf, blob.len());
+            S_OK
+        });
+        assert_eq!(r, Ok(blob.to_vec()));
+    }
+
+    #[test]
+    fn sized_call_fits_in_one_call() {
+        let mut calls = 0;
+        let mut query = fake_query("v4.0.30319");
+        let s = sized_call_string(32, |buf, len| {
+            calls += 1;
+            query(buf, len)
+        });
+        assert_eq!(s, Ok(String::from("v4.0.30319")));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn sized_call_grows_when_too_small() {
+        let s = sized_call_string(4, fake_query("v4.0.30319"));
+        assert_eq!(s, Ok(String::from("v4.0.30319")));
+    }
+
+    #[test]
+    fn wide_keeps_unpaired_surrogates() {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::OsStringExt;
+        let path = OsString::from_wide(&[0x43, 0xD800, 0x2E]);
+        assert_eq!(wide(&path), vec![0x43, 0xD800, 0x2E, 0]);
+        assert_eq!(wide("v4"), vec![0x76, 0x34, 0]);
+    }
+
+    #[test]
+    fn propagates_failure() {
+        let r = double_call_buffer(|_buf, _len| E_FAIL);
+        assert_eq!(r, Err(E_FAIL));
+    }
+}
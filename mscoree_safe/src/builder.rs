@@ -0,0 +1,168 @@
+// builder.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//One validated entry point for bringing up a runtime: metahost lookup, 
+// startup flags and host config, then ICLRRuntimeHost::Start.
+use std::path::{Path, PathBuf};
+
+use winapi::shared::minwindef::DWORD;
+
+use mscoree_sys::mscoree::{STARTUP_CONCURRENT_GC, STARTUP_SERVER_GC};
+
+use error::HostingError;
+use manifest::{HostingManifest, ManifestBuilder, ManifestError};
+use metahost::{RuntimeInfo, RuntimeInfoImpl, RuntimeVersion};
+use runtimehost::ClrRuntimeHost;
+
+#[derive(Debug)]
+pub enum BuilderError {
+    RuntimeNotFound(RuntimeVersion, HostingError), 
+    NotLoadable(RuntimeVersion), 
+    //The runtime was already started, by us or someone else, so the 
+    // requested flags can't be applied
+    AlreadyStarted(RuntimeVersion), 
+    HostConfigMissing(PathBuf), 
+    Startup(HostingError),
+}
+
+#[derive(Clone, Debug)]
+pub struct HostBuilder {
+    version: RuntimeVersion, 
+    server_gc: Option<bool>, 
+    concurrent_gc: Option<bool>, 
+    host_config: Option<PathBuf>,
+}
+
+impl HostBuilder {
+    pub fn new() -> HostBuilder {
+        HostBuilder {
+            version: RuntimeVersion::V4, 
+            server_gc: None, 
+            concurrent_gc: None, 
+            host_config: None,
+        }
+    }
+
+    pub fn version(mut self, version: RuntimeVersion) -> HostBuilder {
+        self.version = version;
+        self
+    }
+
+    pub fn server_gc(mut self, enabled: bool) -> HostBuilder {
+        self.server_gc = Some(enabled);
+        self
+    }
+
+    pub fn concurrent_gc(mut self, enabled: bool) -> HostBuilder {
+        self.concurrent_gc = Some(enabled);
+        self
+    }
+
+    pub fn host_config<P: AsRef<Path>>(mut self, path: P) -> HostBuilder {
+        self.host_config = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Flags left unset keep the runtime's defaults
+    pub fn startup_flags(&self, defaults: DWORD) -> DWORD {
+        let mut flags = defaults;
+        if let Some(enabled) = self.server_gc {
+            flags = set_flag(flags, STARTUP_SERVER_GC, enabled);
+        }
+        if let Some(enabled) = self.concurrent_gc {
+            flags = set_flag(flags, STARTUP_CONCURRENT_GC, enabled);
+        }
+        flags
+    }
+
+    pub fn start(self) -> Result<StartedHost, BuilderError> {
+        if let Some(ref path) = self.host_config {
+            if !path.is_file() {
+                return Err(BuilderError::HostConfigMissing(path.clone()));
+            }
+        }
+        let runtime = RuntimeInfoImpl::from_version(self.version.clone())
+            .map_err(|hr| BuilderError::RuntimeNotFound(self.version.clone(), hr))?;
+        if !runtime.loadable() {
+            return Err(BuilderError::NotLoadable(self.version.clone()));
+        }
+        if runtime.startup_flags().is_some() {
+            return Err(BuilderError::AlreadyStarted(self.version.clone()));
+        }
+
+        let defaults = runtime.default_startup_flags().map_err(BuilderError::Startup)?;
+        let flags = self.startup_flags(defaults);
+        runtime.set_default_startup_flags(flags, self.host_config.as_ref().map(|p| p.as_path()))
+            .map_err(BuilderError::Startup)?;
+
+        let host = ClrRuntimeHost::new(&runtime).map_err(BuilderError::Startup)?;
+        host.start().map_err(BuilderError::Startup)?;
+        Ok(StartedHost { runtime, host, host_config: self.host_config })
+    }
+}
+
+impl Default for HostBuilder {
+    fn default() -> HostBuilder {
+        HostBuilder::new()
+    }
+}
+
+pub struct StartedHost {
+    runtime: RuntimeInfoImpl, 
+    host: ClrRuntimeHost, 
+    host_config: Option<PathBuf>,
+}
+
+impl StartedHost {
+    pub fn host(&self) -> &ClrRuntimeHost {
+        &self.host
+    }
+
+    pub fn runtime(&self) -> &dyn RuntimeInfo {
+        &self.runtime
+    }
+
+    //Reproducibility manifest for what was actually started
+    pub fn manifest(&self) -> Result<HostingManifest, ManifestError> {
+        let mut builder = ManifestBuilder::new();
+        if let Some(ref path) = self.host_config {
+            builder = builder.host_config(path);
+        }
+        builder.capture(&self.runtime)
+    }
+}
+
+fn set_flag(flags: DWORD, flag: DWORD, enabled: bool) -> DWORD {
+    if enabled { flags | flag } else { flags & !flag }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags_override_defaults() {
+        let builder = HostBuilder::new().server_gc(true).concurrent_gc(false);
+        assert_eq!(builder.startup_flags(STARTUP_CONCURRENT_GC), STARTUP_SERVER_GC);
+        assert_eq!(HostBuilder::new().startup_flags(STARTUP_CONCURRENT_GC), STARTUP_CONCURRENT_GC);
+    }
+}
@@ -0,0 +1,328 @@
+// callback.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Rust closures as native function pointers, for managed code to turn into 
+// delegates with Marshal.GetDelegateForFunctionPointer. Without generating 
+// code at runtime every pointer has to be its own extern fn, so a fixed 
+// pool of SLOTS trampolines is stamped out per signature and each 
+// NativeCallback borrows one for as long as it lives. A delegate that 
+// outlives its callback finds the slot empty and gets R::default() back 
+// instead of calling into freed memory.
+// CallbackRegistry is the by-name alternative: hosts register functions 
+// under a name, and a managed shim looks them up through two fixed entry 
+// points, so plugins need no per-callback pointer plumbing at all.
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::slice;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::winerror::E_OUTOFMEMORY;
+
+use once_cell::sync::Lazy;
+
+use buffer::wide_str;
+use error::HostingError;
+use reflection::AppDomain;
+use unwind::catch_silently;
+use variant::ClrValue;
+
+//Live callbacks per signature
+pub const SLOTS: usize = 16;
+
+type Closure<A, R> = Box<dyn Fn(A) -> R + Send + Sync>;
+
+//Keyed by the signature's TypeId and the slot index
+static TABLE: Lazy<Mutex<HashMap<(TypeId, usize), Arc<dyn Any + Send + Sync>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+//A closure parked in a trampoline slot. A is the single argument the 
+// managed delegate passes; use a pointer to a struct for more than one.
+pub struct NativeCallback<A: 'static, R: Default + 'static> {
+    index: usize, 
+    _signature: PhantomData<fn(A) -> R>,
+}
+
+impl<A: 'static, R: Default + 'static> NativeCallback<A, R> {
+    //E_OUTOFMEMORY once all SLOTS for this signature are taken
+    pub fn new<F>(f: F) -> Result<NativeCallback<A, R>, HostingError> 
+        where F: Fn(A) -> R + Send + Sync + 'static
+    {
+        let key = signature::<A, R>();
+        let closure: Closure<A, R> = Box::new(f);
+        let mut table = TABLE.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = (0..SLOTS).find(|&i| !table.contains_key(&(key, i)))
+            .ok_or_else(|| HostingError::from_hresult(E_OUTOFMEMORY, CALL!(NativeCallback::new)))?;
+        table.insert((key, index), Arc::new(closure));
+        Ok(NativeCallback { index, _signature: PhantomData })
+    }
+
+    pub fn function(&self) -> extern "system" fn(A) -> R {
+        trampoline::<A, R>(self.index)
+    }
+
+    pub fn address(&self) -> usize {
+        self.function() as usize
+    }
+
+    //The address as a long, for a managed method to wrap in an IntPtr; 
+    // COM interop has no VARIANT type that arrives as IntPtr itself
+    pub fn to_value(&self) -> ClrValue {
+        ClrValue::I8(self.address() as i64)
+    }
+}
+
+impl<A: 'static, R: Default + 'static> Drop for NativeCallback<A, R> {
+    fn drop(&mut self) {
+        let mut table = TABLE.lock().unwrap_or_else(PoisonError::into_inner);
+        table.remove(&(signature::<A, R>(), self.index));
+    }
+}
+
+fn signature<A: 'static, R: 'static>() -> TypeId {
+    TypeId::of::<fn(A) -> R>()
+}
+
+//The lock is dropped before the closure runs so it may create or drop 
+// other callbacks, including from the managed code it calls into
+fn dispatch<A: 'static, R: Default + 'static>(index: usize, a: A) -> R {
+    let closure = {
+        let table = TABLE.lock().unwrap_or_else(PoisonError::into_inner);
+        table.get(&(signature::<A, R>(), index)).cloned()
+    };
+    let mut out = R::default();
+    if let Some(closure) = closure {
+        if let Some(f) = closure.downcast_ref::<Closure<A, R>>() {
+            catch_silently(|| out = f(a));
+        }
+    }
+    out
+}
+
+macro_rules! TRAMPOLINES {
+    ($($index:expr => $name:ident),*) => {
+        $(
+            extern "system" fn $name<A: 'static, R: Default + 'static>(a: A) -> R {
+                dispatch::<A, R>($index, a)
+            }
+        )*
+
+        fn trampoline<A: 'static, R: Default + 'static>(index: usize) -> extern "system" fn(A) -> R {
+            match index {
+                $($index => $name::<A, R>,)*
+                _ => unreachable!("slot indices are below SLOTS"),
+            }
+        }
+    };
+}
+
+TRAMPOLINES!(
+    0 => slot0, 1 => slot1, 2 => slot2, 3 => slot3, 
+    4 => slot4, 5 => slot5, 6 => slot6, 7 => slot7, 
+    8 => slot8, 9 => slot9, 10 => slot10, 11 => slot11, 
+    12 => slot12, 13 => slot13, 14 => slot14, 15 => slot15
+);
+
+//What resolve and invoke return for unknown names and ids, and what 
+// invoke returns when the callback panicked
+pub const CALLBACK_FAILED: i32 = ::std::i32::MIN;
+
+//AppDomain data slots publish() fills with the two entry points
+pub const RESOLVE_DATA_NAME: &str = "MSCOREE_CALLBACK_RESOLVE";
+pub const INVOKE_DATA_NAME: &str = "MSCOREE_CALLBACK_INVOKE";
+
+//The managed half, for plugins to compile in or hosts to feed to the 
+// scripting module. Callbacks take and return raw bytes; the encoding is 
+// between the host and its plugins.
+pub const SHIM_SOURCE: &str = r#"
+using System;
+using System.Runtime.InteropServices;
+
+public static class HostCallbacks {
+    [UnmanagedFunctionPointer(CallingConvention.StdCall, CharSet = CharSet.Unicode)]
+    delegate int Resolve(string name);
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    delegate int Invoke(int id, byte[] args, int len);
+
+    static readonly Resolve resolve = Bind<Resolve>("MSCOREE_CALLBACK_RESOLVE");
+    static readonly Invoke invoke = Bind<Invoke>("MSCOREE_CALLBACK_INVOKE");
+
+    static T Bind<T>(string slot) where T : class {
+        var address = new IntPtr((long)AppDomain.CurrentDomain.GetData(slot));
+        return (T)(object)Marshal.GetDelegateForFunctionPointer(address, typeof(T));
+    }
+
+    public static int Lookup(string name) { return resolve(name); }
+
+    public static int Call(int id, byte[] args) {
+        args = args ?? new byte[0];
+        return invoke(id, args, args.Length);
+    }
+
+    public static int Call(string name, byte[] args) { return Call(Lookup(name), args); }
+}
+"#;
+
+type Handler = Arc<dyn Fn(&[u8]) -> i32 + Send + Sync>;
+
+static REGISTRY: Lazy<CallbackRegistry> = Lazy::new(|| CallbackRegistry { entries: Mutex::new(Vec::new()) });
+
+//Process-wide, since the entry points managed code calls carry no context. 
+// Ids are stable: re-registering a name keeps its id, and unregistering 
+// leaves a hole that makes further calls through the old id fail.
+pub struct CallbackRegistry {
+    entries: Mutex<Vec<Option<(String, Handler)>>>,
+}
+
+impl CallbackRegistry {
+    pub fn global() -> &'static CallbackRegistry {
+        &REGISTRY
+    }
+
+    //Replaces any function already registered under name; returns its id
+    pub fn register<F>(&self, name: &str, f: F) -> i32 
+        where F: Fn(&[u8]) -> i32 + Send + Sync + 'static
+    {
+        let handler: Handler = Arc::new(f);
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let existing = entries.iter().position(|e| e.as_ref().map_or(false, |&(ref n, _)| n == name));
+        match existing {
+            Some(id) => {
+                entries[id] = Some((name.to_string(), handler));
+                id as i32
+            }, 
+            None => {
+                entries.push(Some((name.to_string(), handler)));
+                entries.len() as i32 - 1
+            },
+        }
+    }
+
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        match entries.iter_mut().find(|e| e.as_ref().map_or(false, |&(ref n, _)| n == name)) {
+            Some(entry) => {
+                *entry = None;
+                true
+            }, 
+            None => false,
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.iter().filter_map(|e| e.as_ref().map(|&(ref n, _)| n.clone())).collect()
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<i32> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.iter().position(|e| e.as_ref().map_or(false, |&(ref n, _)| n == name)).map(|id| id as i32)
+    }
+
+    //Calls the function directly, the same way the invoke entry point does
+    pub fn invoke(&self, id: i32, args: &[u8]) -> i32 {
+        let handler = {
+            let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+            if id < 0 { None } else { entries.get(id as usize).and_then(|e| e.as_ref().map(|&(_, ref h)| h.clone())) }
+        };
+        let mut out = CALLBACK_FAILED;
+        if let Some(handler) = handler {
+            catch_silently(|| out = handler(args));
+        }
+        out
+    }
+
+    //`int Resolve(string name)`, marshaled as an LPCWSTR
+    pub fn resolve_function() -> extern "system" fn(LPCWSTR) -> i32 {
+        resolve_entry
+    }
+
+    //`int Invoke(int id, byte* args, int len)`
+    pub fn invoke_function() -> extern "system" fn(i32, *const u8, i32) -> i32 {
+        invoke_entry
+    }
+
+    //Stores both entry points in the domain's data under RESOLVE_DATA_NAME 
+    // and INVOKE_DATA_NAME, where SHIM_SOURCE picks them up
+    pub fn publish(&self, domain: &AppDomain) -> Result<(), HostingError> {
+        domain.set_data(RESOLVE_DATA_NAME, &ClrValue::I8(CallbackRegistry::resolve_function() as usize as i64))?;
+        domain.set_data(INVOKE_DATA_NAME, &ClrValue::I8(CallbackRegistry::invoke_function() as usize as i64))
+    }
+}
+
+extern "system" fn resolve_entry(name: LPCWSTR) -> i32 {
+    if name.is_null() {
+        return CALLBACK_FAILED;
+    }
+    let name = unsafe { wide_str(name) };
+    REGISTRY.resolve(&name).unwrap_or(CALLBACK_FAILED)
+}
+
+extern "system" fn invoke_entry(id: i32, args: *const u8, len: i32) -> i32 {
+    let args = if args.is_null() || len <= 0 { &[][..] } else { unsafe { slice::from_raw_parts(args, len as usize) } };
+    REGISTRY.invoke(id, args)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn callback_runs_closure_until_dropped() {
+        let offset = 10;
+        let callback = NativeCallback::<i32, i32>::new(move |x| x + offset).unwrap();
+        let f = callback.function();
+        assert_eq!(f(5), 15);
+        drop(callback);
+        assert_eq!(f(5), 0);
+    }
+
+    #[test]
+    fn slots_are_per_signature_and_bounded() {
+        let taken: Vec<_> = (0..SLOTS).map(|_| NativeCallback::<u8, u8>::new(|x| x).unwrap()).collect();
+        assert!(NativeCallback::<u8, u8>::new(|x| x).is_err());
+        assert!(NativeCallback::<u16, u16>::new(|x| x).is_ok());
+        drop(taken);
+        assert!(NativeCallback::<u8, u8>::new(|x| x).is_ok());
+    }
+
+    #[test]
+    fn panics_return_default() {
+        let callback = NativeCallback::<i32, i32>::new(|_| panic!("boom")).unwrap();
+        assert_eq!((callback.function())(1), 0);
+    }
+
+    #[test]
+    fn registry_resolves_and_invokes_by_name() {
+        let registry = CallbackRegistry::global();
+        let id = registry.register("test.sum", |args| args.iter().map(|&b| b as i32).sum());
+        let name: Vec<u16> = "test.sum".encode_utf16().chain(Some(0)).collect();
+        assert_eq!(resolve_entry(name.as_ptr()), id);
+        let args = [1u8, 2, 3];
+        assert_eq!(invoke_entry(id, args.as_ptr(), args.len() as i32), 6);
+        assert_eq!(registry.register("test.sum", |_| 7), id);
+        assert_eq!(invoke_entry(id, ::std::ptr::null(), 0), 7);
+        assert!(registry.unregister("test.sum"));
+        assert_eq!(invoke_entry(id, ::std::ptr::null(), 0), CALLBACK_FAILED);
+        assert_eq!(resolve_entry(name.as_ptr()), CALLBACK_FAILED);
+    }
+}
@@ -0,0 +1,317 @@
+// clr.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Environment probing, plus (with the `fullstack` feature) an end-to-end 
+// entry point for the common journey: start a runtime, grab the default 
+// domain, load an assembly and call into it, with ClrValue doing the 
+// marshaling. Everything here is a thin composition of the lower-level 
+// modules, which remain available when more control is needed.
+//
+// The probes find mscoree.dll with LoadLibrary before anything imported 
+// from it is touched. Together with the /DELAYLOAD:mscoree.dll set up in 
+// build.rs, a machine without the .NET Framework gets a NotInstalled error 
+// rather than a binary that won't start.
+#[cfg(feature = "fullstack")]
+use std::collections::HashMap;
+#[cfg(feature = "fullstack")]
+use std::fs;
+use std::mem;
+#[cfg(feature = "fullstack")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "fullstack")]
+use std::sync::{Mutex, PoisonError};
+
+use winapi::shared::guiddef::{REFCLSID, REFIID};
+use winapi::shared::minwindef::LPVOID;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+
+#[cfg(feature = "fullstack")]
+use once_cell::sync::OnceCell;
+
+use mscoree_sys::corerror::CLR_E_SHIM_INSTALLROOT;
+use mscoree_sys::metahost::{CLSID_CLRMetaHost, ICLRMetaHost, IID_ICLRMetaHost};
+
+use apartment;
+use buffer::wide;
+use comptr::ComPtr;
+#[cfg(feature = "fullstack")]
+use corhost::CorRuntimeHost;
+use error::{HostingError, Hresult, last_error};
+#[cfg(feature = "fullstack")]
+use exception::ManagedException;
+#[cfg(feature = "fullstack")]
+use metahost::{RuntimeHandle, RuntimeInfoImpl};
+use metahost::{MetaHostImpl, RuntimeInfo, RuntimeVersion};
+#[cfg(feature = "fullstack")]
+use reflection::{AppDomain, InvokeError, ManagedAssembly, ManagedObject, ManagedType};
+
+#[cfg(feature = "fullstack")]
+pub use variant::{ClrObject, ClrValue};
+
+type ClrCreateInstanceFn = unsafe extern "system" fn(REFCLSID, REFIID, *mut LPVOID) -> HRESULT;
+
+//Installed runtimes, oldest first. NotInstalled when mscoree.dll is 
+// missing or predates the v4 shim.
+pub fn installed_versions() -> Result<Vec<RuntimeVersion>, HostingError> {
+    apartment::ensure_current()?;
+    let create = clr_create_instance()?;
+    let metahost: ComPtr<ICLRMetaHost> = unsafe {
+        ComPtr::from_out(CALL!(mscoree::CLRCreateInstance), |p: *mut *mut ICLRMetaHost| {
+            create(&CLSID_CLRMetaHost, &IID_ICLRMetaHost, p as *mut LPVOID)
+        })?
+    };
+    let metahost = unsafe { MetaHostImpl::from_raw(metahost.into_raw()) }
+        .expect("from_out already rejected null pointers");
+    Ok(metahost.installed_runtimes()?.sorted().into_iter().map(|handle| handle.version()).collect())
+}
+
+//Ok(false) when the Framework is there but this version isn't
+pub fn is_installed(version: &RuntimeVersion) -> Result<bool, HostingError> {
+    Ok(installed_versions()?.contains(version))
+}
+
+fn clr_create_instance() -> Result<ClrCreateInstanceFn, HostingError> {
+    let name = wide("mscoree.dll");
+    let module = unsafe { LoadLibraryW(name.as_ptr()) };
+    if module.is_null() {
+        return Err(HostingError::NotInstalled { call: CALL!(kernel32::LoadLibraryW), source: Hresult(last_error()) });
+    }
+    let proc = unsafe { GetProcAddress(module, "CLRCreateInstance\0".as_ptr() as *const i8) };
+    if proc.is_null() {
+        return Err(HostingError::from_hresult(CLR_E_SHIM_INSTALLROOT, CALL!(kernel32::GetProcAddress)));
+    }
+    Ok(unsafe { mem::transmute(proc) })
+}
+
+#[cfg(feature = "fullstack")]
+#[derive(Debug)]
+pub enum ClrError {
+    RuntimeNotFound(RuntimeVersion, HostingError), 
+    Start(HostingError), 
+    Call(HostingError), 
+    //The call failed because managed code threw
+    Managed(HostingError, ManagedException), 
+    //The method ran but returned something other than what was asked for
+    UnexpectedResult(ClrValue),
+}
+
+#[cfg(feature = "fullstack")]
+impl From<HostingError> for ClrError {
+    //Picks up the exception the failed call left behind, if there is one
+    fn from(err: HostingError) -> ClrError {
+        match ManagedException::capture() {
+            Some(exception) => ClrError::Managed(err, exception), 
+            None => ClrError::Call(err),
+        }
+    }
+}
+
+//The exception was already picked up where the call failed
+#[cfg(feature = "fullstack")]
+impl From<InvokeError> for ClrError {
+    fn from(err: InvokeError) -> ClrError {
+        match err.exception {
+            Some(exception) => ClrError::Managed(err.error, exception), 
+            None => ClrError::Call(err.error),
+        }
+    }
+}
+
+#[cfg(feature = "fullstack")]
+pub struct Clr {
+    runtime: RuntimeInfoImpl, 
+    host: CorRuntimeHost, 
+    domain: AppDomain, 
+    //The default domain never unloads, so loading a file a second time 
+    // would only leak another copy of it
+    assemblies: AssemblyCache<ManagedAssembly>,
+}
+
+//The runtime started by clr::run and friends. The hosting interfaces and 
+// the default domain are free-threaded.
+#[cfg(feature = "fullstack")]
+struct SharedClr(Clr);
+
+#[cfg(feature = "fullstack")]
+unsafe impl Send for SharedClr {}
+#[cfg(feature = "fullstack")]
+unsafe impl Sync for SharedClr {}
+
+#[cfg(feature = "fullstack")]
+static SHARED_CLR: OnceCell<SharedClr> = OnceCell::new();
+
+#[cfg(feature = "fullstack")]
+impl Clr {
+    pub fn start(version: RuntimeVersion) -> Result<Clr, ClrError> {
+        let runtime = RuntimeInfoImpl::from_version(version.clone())
+            .map_err(|hr| ClrError::RuntimeNotFound(version, hr))?;
+        Clr::start_runtime(runtime)
+    }
+
+    //The newest installed runtime that can still be loaded into this process
+    pub fn latest() -> Result<Clr, ClrError> {
+        let runtime = MetaHostImpl::create()
+            .and_then(|metahost| metahost.installed_runtimes())
+            .map_err(ClrError::Start)?
+            .sorted()
+            .into_iter()
+            .rev()
+            .find(|handle| handle.loadable())
+            .map(RuntimeHandle::into_info)
+            .ok_or_else(|| ClrError::Start(HostingError::from_hresult(CLR_E_SHIM_INSTALLROOT, CALL!(ICLRMetaHost::EnumerateInstalledRuntimes))))?;
+        Clr::start_runtime(runtime)
+    }
+
+    pub fn start_runtime(runtime: RuntimeInfoImpl) -> Result<Clr, ClrError> {
+        let host = CorRuntimeHost::new(&runtime).map_err(ClrError::Start)?;
+        host.start().map_err(ClrError::Start)?;
+        let domain = host.default_domain().map_err(ClrError::Start)?;
+        Ok(Clr { runtime, host, domain, assemblies: AssemblyCache::new() })
+    }
+
+    //The newest runtime, started once for the whole process. A failed 
+    // start isn't remembered, so a later call tries again.
+    pub fn shared() -> Result<&'static Clr, ClrError> {
+        SHARED_CLR.get_or_try_init(|| Clr::latest().map(SharedClr)).map(|shared| &shared.0)
+    }
+
+    pub fn runtime(&self) -> &dyn RuntimeInfo {
+        &self.runtime
+    }
+
+    pub fn host(&self) -> &CorRuntimeHost {
+        &self.host
+    }
+
+    pub fn domain(&self) -> &AppDomain {
+        &self.domain
+    }
+
+    //Each file is loaded once; later calls return the same assembly
+    pub fn load_assembly<P: AsRef<Path>>(&self, path: P) -> Result<ManagedAssembly, ClrError> {
+        Ok(self.assemblies.get_or_load(path.as_ref(), |path| self.domain.load_assembly(path))?)
+    }
+
+    pub fn load(&self, display_name: &str) -> Result<ManagedAssembly, ClrError> {
+        Ok(self.domain.load(display_name)?)
+    }
+
+    pub fn get_type(&self, assembly: &ManagedAssembly, type_name: &str) -> Result<ManagedType, ClrError> {
+        Ok(assembly.get_type(type_name)?)
+    }
+
+    pub fn create_instance(&self, assembly: &ManagedAssembly, type_name: &str) -> Result<ManagedObject, ClrError> {
+        Ok(assembly.create_instance(type_name)?)
+    }
+
+    //Loads the assembly file, resolves the type and calls a public static method
+    pub fn invoke_static<P: AsRef<Path>>(&self, assembly: P, type_name: &str, method: &str, args: &[ClrValue]) -> Result<ClrValue, ClrError> {
+        let assembly = self.load_assembly(assembly)?;
+        let ty = assembly.get_type(type_name)?;
+        Ok(ty.invoke_static(method, args)?)
+    }
+}
+
+//Script-style calls into `public static T Method(string)` on the newest 
+// runtime, e.g. clr::run("Tools.dll", "Tools.Entry", "Main", "--verbose"). 
+// The runtime (Clr::shared) stays started and each assembly stays loaded, 
+// so repeated calls are cheap.
+#[cfg(feature = "fullstack")]
+pub fn run<P: AsRef<Path>>(assembly: P, type_name: &str, method: &str, argument: &str) -> Result<i32, ClrError> {
+    match run_value(assembly, type_name, method, argument)? {
+        ClrValue::I4(i) => Ok(i), 
+        other => Err(ClrError::UnexpectedResult(other)),
+    }
+}
+
+#[cfg(feature = "fullstack")]
+pub fn run_string<P: AsRef<Path>>(assembly: P, type_name: &str, method: &str, argument: &str) -> Result<String, ClrError> {
+    match run_value(assembly, type_name, method, argument)? {
+        ClrValue::String(s) => Ok(s), 
+        other => Err(ClrError::UnexpectedResult(other)),
+    }
+}
+
+//A null byte[] comes back empty
+#[cfg(feature = "fullstack")]
+pub fn run_bytes<P: AsRef<Path>>(assembly: P, type_name: &str, method: &str, argument: &str) -> Result<Vec<u8>, ClrError> {
+    match run_value(assembly, type_name, method, argument)? {
+        ClrValue::Bytes(bytes) => Ok(bytes), 
+        ClrValue::Null => Ok(Vec::new()), 
+        other => Err(ClrError::UnexpectedResult(other)),
+    }
+}
+
+#[cfg(feature = "fullstack")]
+fn run_value<P: AsRef<Path>>(assembly: P, type_name: &str, method: &str, argument: &str) -> Result<ClrValue, ClrError> {
+    Clr::shared()?.invoke_static(assembly, type_name, method, &[ClrValue::from(argument)])
+}
+
+#[cfg(feature = "fullstack")]
+struct AssemblyCache<A> {
+    loaded: Mutex<HashMap<PathBuf, A>>,
+}
+
+#[cfg(feature = "fullstack")]
+impl<A: Clone> AssemblyCache<A> {
+    fn new() -> AssemblyCache<A> {
+        AssemblyCache { loaded: Mutex::new(HashMap::new()) }
+    }
+
+    //Keyed by canonical path, so "Tools.dll" and ".\Tools.dll" are one 
+    // entry. The lock is held across the load so racing callers don't both 
+    // load the file; failures aren't cached.
+    fn get_or_load<E, F>(&self, path: &Path, load: F) -> Result<A, E> 
+        where F: FnOnce(&Path) -> Result<A, E>
+    {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut loaded = self.loaded.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(assembly) = loaded.get(&key) {
+            return Ok(assembly.clone());
+        }
+        let assembly = load(path)?;
+        loaded.insert(key, assembly.clone());
+        Ok(assembly)
+    }
+}
+
+#[cfg(all(test, feature = "fullstack"))]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::env;
+
+    #[test]
+    fn assemblies_load_once_per_file() {
+        let path = env::temp_dir().join("mscoree_safe_assembly_cache.dll");
+        fs::write(&path, b"").unwrap();
+        let cache = AssemblyCache::new();
+        let loads = Cell::new(0);
+        let load = |_: &Path| -> Result<u32, ()> { loads.set(loads.get() + 1); Ok(7) };
+        assert_eq!(cache.get_or_load(&path, &load), Ok(7));
+        let other_spelling = path.parent().unwrap().join(".").join(path.file_name().unwrap());
+        assert_eq!(cache.get_or_load(&other_spelling, &load), Ok(7));
+        assert_eq!(loads.get(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+// clrhost.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//A started ICLRRuntimeHost together with what it hands out, so that 
+// shutdown can take things apart in the right order: cached managers first, 
+// then Stop, then the host and runtime references. shutdown_on_exit arranges 
+// for the same Stop to happen from an atexit handler, so a process that 
+// returns from main without calling shutdown still lets the finalizer run. 
+// latest() and run() cover the whole metahost-to-execute pipeline for hosts 
+// that just want to call one method.
+use std::cell::{Cell, Ref, RefCell};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Once;
+
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::DWORD;
+
+use mscoree_sys::corerror::CLR_E_SHIM_INSTALLROOT;
+use mscoree_sys::mscoree::ICLRRuntimeHost;
+
+use control::ClrControl;
+use error::HostingError;
+use metahost::{MetaHostImpl, RuntimeHandle, RuntimeInfo, RuntimeInfoImpl, RuntimeVersion};
+use runtimehost::ClrRuntimeHost;
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+//AddRef'd host the exit handler stops; null when nothing is registered
+static EXIT_HOST: AtomicPtr<ICLRRuntimeHost> = AtomicPtr::new(ptr::null_mut());
+static EXIT_HOOK: Once = Once::new();
+
+//Fields drop in order, which is also the release order
+pub struct ClrHost {
+    control: RefCell<Option<ClrControl>>, 
+    host: ClrRuntimeHost, 
+    runtime: RuntimeInfoImpl, 
+    stopped: Cell<bool>,
+}
+
+impl ClrHost {
+    pub fn start(version: RuntimeVersion) -> Result<ClrHost, HostingError> {
+        ClrHost::start_runtime(RuntimeInfoImpl::from_version(version)?)
+    }
+
+    //Newest installed runtime that can still be loaded into this process
+    pub fn latest() -> Result<ClrHost, HostingError> {
+        let runtime = MetaHostImpl::create()?
+            .installed_runtimes()?
+            .sorted()
+            .into_iter()
+            .rev()
+            .find(|handle| handle.loadable())
+            .map(RuntimeHandle::into_info)
+            .ok_or_else(|| HostingError::from_hresult(CLR_E_SHIM_INSTALLROOT, CALL!(ICLRMetaHost::EnumerateInstalledRuntimes)))?;
+        ClrHost::start_runtime(runtime)
+    }
+
+    pub fn start_runtime(runtime: RuntimeInfoImpl) -> Result<ClrHost, HostingError> {
+        let host = ClrRuntimeHost::new(&runtime)?;
+        host.start()?;
+        Ok(ClrHost {
+            control: RefCell::new(None), 
+            host, 
+            runtime, 
+            stopped: Cell::new(false),
+        })
+    }
+
+    pub fn runtime(&self) -> &dyn RuntimeInfo {
+        &self.runtime
+    }
+
+    pub fn runtime_host(&self) -> &ClrRuntimeHost {
+        &self.host
+    }
+
+    //Fetched once and kept until shutdown
+    pub fn control(&self) -> Result<Ref<ClrControl>, HostingError> {
+        if self.control.borrow().is_none() {
+            *self.control.borrow_mut() = Some(self.host.control()?);
+        }
+        Ok(Ref::map(self.control.borrow(), |control| control.as_ref().expect("filled above")))
+    }
+
+    //Calls `public static int Method(string)` on type_name in the default 
+    // domain, e.g. run("Tools.dll", "Tools.Entry", "Main", "--verbose")
+    pub fn run<P: AsRef<Path>>(&self, assembly: P, type_name: &str, method: &str, argument: &str) -> Result<DWORD, HostingError> {
+        self.host.execute_in_default_app_domain(assembly, type_name, method, argument)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.get()
+    }
+
+    //Stops the runtime; later calls are no-ops. A v4 runtime can't be 
+    // started again in the same process once stopped.
+    pub fn shutdown(&self) -> Result<(), HostingError> {
+        if self.stopped.get() {
+            return Ok(());
+        }
+        self.control.borrow_mut().take();
+        release_exit_host(self.host.as_raw());
+        self.host.stop()?;
+        self.stopped.set(true);
+        Ok(())
+    }
+
+    //Opt-in: stop this host from an atexit handler if shutdown hasn't been 
+    // called by then. Only one host is stopped at exit; registering another 
+    // replaces the previous one.
+    pub fn shutdown_on_exit(&self) {
+        let raw = self.host.as_raw();
+        unsafe { (*raw).AddRef() };
+        let previous = EXIT_HOST.swap(raw, Ordering::SeqCst);
+        if !previous.is_null() {
+            unsafe { (*previous).Release() };
+        }
+        EXIT_HOOK.call_once(|| unsafe {
+            atexit(stop_at_exit);
+        });
+    }
+}
+
+extern "C" fn stop_at_exit() {
+    let raw = EXIT_HOST.swap(ptr::null_mut(), Ordering::SeqCst);
+    if !raw.is_null() {
+        unsafe {
+            let _ = (*raw).Stop();
+            (*raw).Release();
+        }
+    }
+}
+
+//Drops the exit registration if it's for this host
+fn release_exit_host(raw: *mut ICLRRuntimeHost) {
+    if EXIT_HOST.compare_exchange(raw, ptr::null_mut(), Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+        unsafe { (*raw).Release() };
+    }
+}
@@ -0,0 +1,146 @@
+// com.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Just enough machinery to hand Rust values to the runtime as COM objects: 
+// a heap block laid out as [vtable pointer, vtable, refcount, iids, value], 
+// with generic IUnknown entries. The vtable lives inside the allocation, so 
+// one ComBox type serves any interface without needing generic statics.
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualGUID, GUID, REFIID};
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_NOINTERFACE, E_POINTER, HRESULT, S_OK};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::Interface;
+
+use unwind::catch_silently;
+
+#[repr(C)]
+pub(crate) struct ComBox<V, T> {
+    vtbl: *const V, 
+    vtable: V, 
+    refs: AtomicUsize, 
+    //IUnknown is always answered; these are the other interfaces we expose
+    iids: Vec<GUID>, 
+    pub(crate) value: T,
+}
+
+impl<V, T> ComBox<V, T> {
+    //IUnknown entries for the head of an interface vtable
+    pub(crate) fn unknown_vtbl() -> IUnknownVtbl {
+        IUnknownVtbl {
+            QueryInterface: query_interface::<V, T>, 
+            AddRef: add_ref::<V, T>, 
+            Release: release::<V, T>,
+        }
+    }
+
+    //Returns the object with a reference count of one, owned by the caller
+    pub(crate) fn new(vtable: V, iids: Vec<GUID>, value: T) -> *mut ComBox<V, T> {
+        let raw = Box::into_raw(Box::new(ComBox {
+            vtbl: ptr::null(), 
+            vtable, 
+            refs: AtomicUsize::new(1), 
+            iids, 
+            value,
+        }));
+        unsafe { (*raw).vtbl = &(*raw).vtable };
+        raw
+    }
+
+    //`this` must be a pointer previously produced by ComBox::new
+    pub(crate) unsafe fn from_this<'a, I>(this: *mut I) -> &'a ComBox<V, T> {
+        &*(this as *const ComBox<V, T>)
+    }
+
+    pub(crate) fn as_interface<I>(raw: *mut ComBox<V, T>) -> *mut I {
+        raw as *mut I
+    }
+
+    //Drops the caller's reference
+    pub(crate) unsafe fn release(raw: *mut ComBox<V, T>) {
+        release::<V, T>(raw as *mut IUnknown);
+    }
+}
+
+unsafe extern "system" fn query_interface<V, T>(this: *mut IUnknown, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    let object = &*(this as *const ComBox<V, T>);
+    let iid = &*riid;
+    if IsEqualGUID(iid, &IUnknown::uuidof()) || object.iids.iter().any(|known| IsEqualGUID(iid, known)) {
+        add_ref::<V, T>(this);
+        *ppv = this as *mut c_void;
+        S_OK
+    } else {
+        *ppv = ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn add_ref<V, T>(this: *mut IUnknown) -> ULONG {
+    let object = &*(this as *const ComBox<V, T>);
+    (object.refs.fetch_add(1, Ordering::Relaxed) + 1) as ULONG
+}
+
+unsafe extern "system" fn release<V, T>(this: *mut IUnknown) -> ULONG {
+    let object = this as *mut ComBox<V, T>;
+    let remaining = (*object).refs.fetch_sub(1, Ordering::Release) - 1;
+    if remaining == 0 {
+        atomic::fence(Ordering::Acquire);
+        //Host state is dropped on whatever runtime thread made the last Release
+        catch_silently(|| mem::drop(Box::from_raw(object)));
+    }
+    remaining as ULONG
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    #[repr(C)]
+    struct TestVtbl {
+        parent: IUnknownVtbl,
+    }
+
+    #[test]
+    fn refcount_drops_value_on_last_release() {
+        let tracker = Rc::new(());
+        let raw = ComBox::new(TestVtbl { parent: ComBox::<TestVtbl, Rc<()>>::unknown_vtbl() }, Vec::new(), tracker.clone());
+        let unk: *mut IUnknown = ComBox::as_interface(raw);
+        unsafe {
+            assert_eq!(((*(*unk).lpVtbl).AddRef)(unk), 2);
+            let mut out: *mut c_void = ptr::null_mut();
+            assert_eq!(((*(*unk).lpVtbl).QueryInterface)(unk, &IUnknown::uuidof(), &mut out), S_OK);
+            assert_eq!(Rc::strong_count(&tracker), 2);
+            ((*(*unk).lpVtbl).Release)(unk);
+            ((*(*unk).lpVtbl).Release)(unk);
+            ComBox::release(raw);
+        }
+        assert_eq!(Rc::strong_count(&tracker), 1);
+    }
+}
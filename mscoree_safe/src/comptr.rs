@@ -0,0 +1,146 @@
+// comptr.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Owning smart pointer over any COM interface. Holds exactly one reference: 
+// Clone AddRefs, Drop Releases. Deref gives the interface so methods can be 
+// called as `unsafe { ptr.Method(..) }` without juggling raw pointers.
+use std::cmp::Ordering;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+use winapi::shared::minwindef::LPVOID;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use error::{Call, HostingError};
+
+pub struct ComPtr<T: Interface> {
+    inner: NonNull<T>,
+}
+
+impl<T: Interface> ComPtr<T> {
+    //Takes over a reference the caller already owns, None for null
+    pub unsafe fn from_raw(p: *mut T) -> Option<ComPtr<T>> {
+        NonNull::new(p).map(|inner| ComPtr { inner })
+    }
+
+    //For pointers the caller doesn't own (e.g. callback arguments): AddRefs 
+    // so the ComPtr can outlive the call
+    pub unsafe fn from_borrowed(p: *mut T) -> Option<ComPtr<T>> {
+        ComPtr::from_raw(p).map(|ptr| {
+            ptr.add_ref();
+            ptr
+        })
+    }
+
+    //Runs an out-parameter call, failures are attributed to `call`. A 
+    // success code that leaves the pointer null is reported as E_POINTER.
+    pub unsafe fn from_out<F>(call: Call, f: F) -> Result<ComPtr<T>, HostingError> 
+        where F: FnOnce(*mut *mut T) -> HRESULT
+    {
+        let mut p: *mut T = ptr::null_mut();
+        let hr = f(&mut p);
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, call));
+        }
+        ComPtr::from_raw(p).ok_or_else(|| HostingError::null_pointer(call))
+    }
+
+    //Borrowed: valid while self is, no reference is transferred
+    pub fn as_raw(&self) -> *mut T {
+        self.inner.as_ptr()
+    }
+
+    //Hands the reference to the caller, who becomes responsible for Release
+    pub fn into_raw(self) -> *mut T {
+        let p = self.inner.as_ptr();
+        mem::forget(self);
+        p
+    }
+
+    pub fn as_unknown(&self) -> *mut IUnknown {
+        self.inner.as_ptr() as *mut IUnknown
+    }
+
+    pub fn query_interface<U: Interface>(&self) -> Result<ComPtr<U>, HostingError> {
+        unsafe {
+            ComPtr::from_out(CALL!(IUnknown::QueryInterface), |p: *mut *mut U| {
+                (*self.as_unknown()).QueryInterface(&U::uuidof(), p as *mut LPVOID)
+            })
+        }
+    }
+
+    fn add_ref(&self) {
+        unsafe { (*self.as_unknown()).AddRef() };
+    }
+}
+
+impl<T: Interface> Clone for ComPtr<T> {
+    fn clone(&self) -> ComPtr<T> {
+        self.add_ref();
+        ComPtr { inner: self.inner }
+    }
+}
+
+impl<T: Interface> Drop for ComPtr<T> {
+    fn drop(&mut self) {
+        unsafe { (*self.as_unknown()).Release() };
+    }
+}
+
+impl<T: Interface> Deref for ComPtr<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+//Identity comparison: two ComPtrs are equal when they hold the same 
+// interface pointer
+impl<T: Interface> PartialEq for ComPtr<T> {
+    fn eq(&self, other: &ComPtr<T>) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<T: Interface> Eq for ComPtr<T> {}
+
+impl<T: Interface> PartialOrd for ComPtr<T> {
+    fn partial_cmp(&self, other: &ComPtr<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Interface> Ord for ComPtr<T> {
+    fn cmp(&self, other: &ComPtr<T>) -> Ordering {
+        self.inner.cmp(&other.inner)
+    }
+}
+
+impl<T: Interface> fmt::Debug for ComPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ComPtr({:p})", self.inner.as_ptr())
+    }
+}
@@ -0,0 +1,151 @@
+// configuration.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICorConfiguration: v1/v2-style host callbacks, set between creating a 
+// CorRuntimeHost and starting it. The runtime keeps its own reference to 
+// each callback object, so they live as long as the runtime needs them.
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{E_POINTER, HRESULT};
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{
+    ICorConfiguration, 
+    IDebuggerThreadControl, 
+    IDebuggerThreadControlVtbl, 
+    IGCHostControl, 
+    IGCHostControlVtbl
+};
+
+use com::ComBox;
+use error::HostingError;
+#[cfg(feature = "host-managers")]
+use managers::{self, HostGcManager};
+use unwind::catch_and_translate;
+use wrappers::PtrCtr;
+
+//Told when the debugger stops and resumes the runtime's threads
+pub trait DebuggerThreadControl: Send + Sync + 'static {
+    //The calling thread is about to block for the debugger
+    fn thread_is_blocking_for_debugger(&self) {}
+
+    //The debugger let the runtime's threads go again
+    fn release_all_runtime_threads(&self) {}
+
+    //The debugger is about to stop the runtime's threads
+    fn start_blocking_for_debugger(&self) {}
+}
+
+type VirtualMemLimit = Box<dyn Fn(usize) -> usize + Send + Sync>;
+type HostControlObject = ComBox<IGCHostControlVtbl, VirtualMemLimit>;
+type DebuggerObject<D> = ComBox<IDebuggerThreadControlVtbl, D>;
+
+pub struct CorConfiguration {
+    inner: PtrCtr<ICorConfiguration>,
+}
+
+impl CorConfiguration {
+    pub(crate) fn new_from(inner: PtrCtr<ICorConfiguration>) -> CorConfiguration {
+        CorConfiguration { inner }
+    }
+
+    #[cfg(feature = "host-managers")]
+    pub fn set_gc_thread_control<G: HostGcManager>(&self, control: G) -> Result<(), HostingError> {
+        let raw = managers::gc::create_thread_control(control);
+        let hr = CHECK_HR!(ICorConfiguration::SetGCThreadControl, (*self.inner.as_const()).SetGCThreadControl(raw));
+        unsafe { (*raw).Release() };
+        hr.map(|_| ())
+    }
+
+    //The runtime asks before growing past its virtual memory limit; the 
+    // closure gets the requested limit in MB and returns the one granted
+    pub fn set_gc_host_control<F>(&self, limit: F) -> Result<(), HostingError> 
+        where F: Fn(usize) -> usize + Send + Sync + 'static
+    {
+        let vtable = IGCHostControlVtbl {
+            parent: HostControlObject::unknown_vtbl(), 
+            RequestVirtualMemLimit: request_virtual_mem_limit,
+        };
+        let raw: *mut IGCHostControl = HostControlObject::as_interface(
+            HostControlObject::new(vtable, vec![IGCHostControl::uuidof()], Box::new(limit)));
+        let hr = CHECK_HR!(ICorConfiguration::SetGCHostControl, (*self.inner.as_const()).SetGCHostControl(raw));
+        unsafe { (*raw).Release() };
+        hr.map(|_| ())
+    }
+
+    pub fn set_debugger_thread_control<D: DebuggerThreadControl>(&self, control: D) -> Result<(), HostingError> {
+        let vtable = IDebuggerThreadControlVtbl {
+            parent: DebuggerObject::<D>::unknown_vtbl(), 
+            ThreadIsBlockingForDebugger: thread_is_blocking_for_debugger::<D>, 
+            ReleaseAllRuntimeThreads: release_all_runtime_threads::<D>, 
+            StartBlockingForDebugger: start_blocking_for_debugger::<D>,
+        };
+        let raw: *mut IDebuggerThreadControl = DebuggerObject::as_interface(
+            DebuggerObject::new(vtable, vec![IDebuggerThreadControl::uuidof()], control));
+        let hr = CHECK_HR!(ICorConfiguration::SetDebuggerThreadControl, (*self.inner.as_const()).SetDebuggerThreadControl(raw));
+        unsafe { (*raw).Release() };
+        hr.map(|_| ())
+    }
+
+    //A host thread the debugger must leave running when it stops the 
+    // runtime, typically one servicing the debugger's own UI or transport
+    pub fn add_debugger_special_thread(&self, thread_id: DWORD) -> Result<(), HostingError> {
+        CHECK_HR!(ICorConfiguration::AddDebuggerSpecialThread, (*self.inner.as_const()).AddDebuggerSpecialThread(thread_id)).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(CorConfiguration);
+
+unsafe extern "system" fn request_virtual_mem_limit(this: *mut IGCHostControl, max_mb: SIZE_T, new_max_mb: *mut SIZE_T) -> HRESULT {
+    if new_max_mb.is_null() {
+        return E_POINTER;
+    }
+    let limit = &HostControlObject::from_this(this).value;
+    catch_and_translate(|| {
+        *new_max_mb = limit(max_mb);
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn thread_is_blocking_for_debugger<D: DebuggerThreadControl>(this: *mut IDebuggerThreadControl) -> HRESULT {
+    let control = &DebuggerObject::<D>::from_this(this).value;
+    catch_and_translate(|| {
+        control.thread_is_blocking_for_debugger();
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn release_all_runtime_threads<D: DebuggerThreadControl>(this: *mut IDebuggerThreadControl) -> HRESULT {
+    let control = &DebuggerObject::<D>::from_this(this).value;
+    catch_and_translate(|| {
+        control.release_all_runtime_threads();
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn start_blocking_for_debugger<D: DebuggerThreadControl>(this: *mut IDebuggerThreadControl, _unused: DWORD) -> HRESULT {
+    let control = &DebuggerObject::<D>::from_this(this).value;
+    catch_and_translate(|| {
+        control.start_blocking_for_debugger();
+        Ok(())
+    })
+}
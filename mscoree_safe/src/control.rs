@@ -0,0 +1,62 @@
+// control.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRControl: the gateway to the runtime-side managers (policy, GC, debug, 
+// tasks...). Most managers only accept configuration before Start.
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::ICLRControl;
+
+use error::HostingError;
+use wrappers::PtrCtr;
+
+pub struct ClrControl {
+    inner: PtrCtr<ICLRControl>,
+}
+
+impl ClrControl {
+    pub(crate) fn new_from(inner: PtrCtr<ICLRControl>) -> ClrControl {
+        ClrControl { inner }
+    }
+
+    //GetCLRManager for any ICLR*Manager interface; the caller owns the reference
+    pub(crate) fn manager<T: Interface>(&self) -> Result<PtrCtr<T>, HostingError> {
+        let mut p: *mut T = ptr::null_mut();
+        CHECK_HR!(ICLRControl::GetCLRManager, (*self.inner.as_const()).GetCLRManager(&T::uuidof(), &mut p as *mut *mut T as *mut *mut c_void))?;
+        PtrCtr::new_checked(p).map_err(|_| HostingError::null_pointer(CALL!(ICLRControl::GetCLRManager)))
+    }
+
+    //An AppDomainManager subclass the runtime instantiates in every domain 
+    // it creates, the default domain included. assembly_name is a display 
+    // name resolvable by normal probing, e.g. "Bootstrap, Version=1.0.0.0, 
+    // Culture=neutral, PublicKeyToken=null". Only takes effect before Start.
+    pub fn set_app_domain_manager(&self, assembly_name: &str, type_name: &str) -> Result<(), HostingError> {
+        let assembly: Vec<u16> = assembly_name.encode_utf16().chain(Some(0)).collect();
+        let ty: Vec<u16> = type_name.encode_utf16().chain(Some(0)).collect();
+        CHECK_HR!(ICLRControl::SetAppDomainManagerType, (*self.inner.as_const()).SetAppDomainManagerType(assembly.as_ptr(), ty.as_ptr())).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(ClrControl, ICLRControl);
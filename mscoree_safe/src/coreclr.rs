@@ -0,0 +1,433 @@
+// coreclr.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Hosting .NET Core and .NET 5+ through hostfxr, the modern counterpart of 
+// ICLRMetaHost. nethost.dll (shipped next to the host executable) finds the 
+// hostfxr.dll of the installed runtime; hostfxr then initializes a runtime 
+// from an app's runtimeconfig.json and hands out delegates for calling 
+// into managed code. Neither library is ever unloaded: a .NET Core runtime 
+// can't be torn down once it has started, and its delegates point into it.
+// CoreClrHost is the older route, loading coreclr.dll directly and driving 
+// it through ICLRRuntimeHost2/4 the way the desktop hosting API does.
+use std::ffi::{OsStr, OsString};
+use std::marker::PhantomData;
+use std::mem;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::basetsd::{INT_PTR, SIZE_T};
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::{DWORD, FALSE, HMODULE, TRUE};
+use winapi::shared::ntdef::{LPCWSTR, ULONGLONG};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::hostfxr::*;
+use mscoree_sys::mscoree::{ICLRRuntimeHost, ICLRRuntimeHost2, ICLRRuntimeHost4};
+
+use buffer::{wide, wide_str};
+use comptr::ComPtr;
+use error::{Call, HostingError, last_error};
+
+//Where to start looking for hostfxr. With no assembly path and no root, 
+// nethost uses the global install (DOTNET_ROOT, then Program Files).
+#[derive(Clone, Debug, Default)]
+pub struct HostFxrSearch {
+    assembly_path: Option<PathBuf>, 
+    dotnet_root: Option<PathBuf>,
+}
+
+impl HostFxrSearch {
+    pub fn new() -> HostFxrSearch {
+        HostFxrSearch::default()
+    }
+
+    //The component being hosted; an app-local runtime next to it wins
+    pub fn assembly_path<P: AsRef<Path>>(mut self, path: P) -> HostFxrSearch {
+        self.assembly_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Skips the search and looks only under this .NET install
+    pub fn dotnet_root<P: AsRef<Path>>(mut self, path: P) -> HostFxrSearch {
+        self.dotnet_root = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Full path to hostfxr.dll as reported by nethost's get_hostfxr_path
+    pub fn locate(&self) -> Result<PathBuf, HostingError> {
+        let nethost = load_library(OsStr::new("nethost.dll"))?;
+        let get_hostfxr_path: get_hostfxr_path_fn = unsafe { 
+            mem::transmute(symbol(nethost, "get_hostfxr_path\0", CALL!(nethost::get_hostfxr_path))?) 
+        };
+        let assembly_path = self.assembly_path.as_ref().map(|p| wide(p.as_os_str()));
+        let dotnet_root = self.dotnet_root.as_ref().map(|p| wide(p.as_os_str()));
+        let parameters = get_hostfxr_parameters {
+            size: mem::size_of::<get_hostfxr_parameters>() as SIZE_T, 
+            assembly_path: assembly_path.as_ref().map_or(ptr::null(), |p| p.as_ptr()), 
+            dotnet_root: dotnet_root.as_ref().map_or(ptr::null(), |p| p.as_ptr()),
+        };
+        let mut buffer: Vec<char_t> = vec![0; 260];
+        loop {
+            let mut size = buffer.len() as SIZE_T;
+            let rc = get_hostfxr_path(buffer.as_mut_ptr(), &mut size, &parameters);
+            if rc == HostApiBufferTooSmall {
+                buffer = vec![0; size as usize];
+                continue;
+            }
+            check(rc, CALL!(nethost::get_hostfxr_path))?;
+            let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+            return Ok(PathBuf::from(OsString::from_wide(&buffer[..len])));
+        }
+    }
+}
+
+pub struct HostFxr {
+    initialize_for_runtime_config: hostfxr_initialize_for_runtime_config_fn, 
+    get_runtime_delegate: hostfxr_get_runtime_delegate_fn, 
+    get_runtime_property_value: hostfxr_get_runtime_property_value_fn, 
+    set_runtime_property_value: hostfxr_set_runtime_property_value_fn, 
+    close: hostfxr_close_fn,
+}
+
+impl HostFxr {
+    //hostfxr from the global .NET install
+    pub fn load() -> Result<HostFxr, HostingError> {
+        HostFxr::load_from(&HostFxrSearch::new().locate()?)
+    }
+
+    pub fn load_from(path: &Path) -> Result<HostFxr, HostingError> {
+        let module = load_library(path.as_os_str())?;
+        unsafe {
+            Ok(HostFxr {
+                initialize_for_runtime_config: mem::transmute(symbol(module, "hostfxr_initialize_for_runtime_config\0", 
+                    CALL!(hostfxr::hostfxr_initialize_for_runtime_config))?), 
+                get_runtime_delegate: mem::transmute(symbol(module, "hostfxr_get_runtime_delegate\0", 
+                    CALL!(hostfxr::hostfxr_get_runtime_delegate))?), 
+                get_runtime_property_value: mem::transmute(symbol(module, "hostfxr_get_runtime_property_value\0", 
+                    CALL!(hostfxr::hostfxr_get_runtime_property_value))?), 
+                set_runtime_property_value: mem::transmute(symbol(module, "hostfxr_set_runtime_property_value\0", 
+                    CALL!(hostfxr::hostfxr_set_runtime_property_value))?), 
+                close: mem::transmute(symbol(module, "hostfxr_close\0", CALL!(hostfxr::hostfxr_close))?),
+            })
+        }
+    }
+
+    //Only one runtime can be loaded per process. A second initialization 
+    // for a compatible config succeeds against the running one, which 
+    // already_initialized() reports.
+    pub fn initialize_for_runtime_config<P: AsRef<Path>>(&self, runtime_config: P) -> Result<HostContext, HostingError> {
+        let config = wide(runtime_config.as_ref().as_os_str());
+        let mut handle: hostfxr_handle = ptr::null_mut();
+        let rc = (self.initialize_for_runtime_config)(config.as_ptr(), ptr::null(), &mut handle);
+        let call = CALL!(hostfxr::hostfxr_initialize_for_runtime_config);
+        if rc < 0 {
+            //A handle can come back even on failure and still needs closing
+            if !handle.is_null() {
+                (self.close)(handle);
+            }
+            return Err(HostingError::from_hresult(rc, call));
+        }
+        if handle.is_null() {
+            return Err(HostingError::null_pointer(call));
+        }
+        Ok(HostContext { fxr: self, handle, status: rc })
+    }
+}
+
+//An initialized host context; closed on drop. The runtime itself stays 
+// loaded after the context is closed.
+pub struct HostContext<'f> {
+    fxr: &'f HostFxr, 
+    handle: hostfxr_handle, 
+    status: i32,
+}
+
+impl<'f> HostContext<'f> {
+    pub fn already_initialized(&self) -> bool {
+        self.status == Success_HostAlreadyInitialized || self.status == Success_DifferentRuntimeProperties
+    }
+
+    //True when the running runtime was started with properties that differ 
+    // from the ones in this config
+    pub fn properties_differ(&self) -> bool {
+        self.status == Success_DifferentRuntimeProperties
+    }
+
+    //None when the property isn't set
+    pub fn runtime_property(&self, name: &str) -> Result<Option<String>, HostingError> {
+        let name = wide(OsStr::new(name));
+        let mut value: *const char_t = ptr::null();
+        let rc = (self.fxr.get_runtime_property_value)(self.handle, name.as_ptr(), &mut value);
+        if rc == HostPropertyNotFound {
+            return Ok(None);
+        }
+        check(rc, CALL!(hostfxr::hostfxr_get_runtime_property_value))?;
+        Ok(Some(unsafe { wide_str(value) }))
+    }
+
+    //Only allowed before the first delegate is requested
+    pub fn set_runtime_property(&self, name: &str, value: &str) -> Result<(), HostingError> {
+        let name = wide(OsStr::new(name));
+        let value = wide(OsStr::new(value));
+        let rc = (self.fxr.set_runtime_property_value)(self.handle, name.as_ptr(), value.as_ptr());
+        check(rc, CALL!(hostfxr::hostfxr_set_runtime_property_value)).map(|_| ())
+    }
+
+    //Starts the runtime if it isn't running yet
+    pub fn assembly_loader(&self) -> Result<AssemblyLoader, HostingError> {
+        let mut delegate: *mut c_void = ptr::null_mut();
+        let call = CALL!(hostfxr::hostfxr_get_runtime_delegate);
+        check((self.fxr.get_runtime_delegate)(self.handle, hdt_load_assembly_and_get_function_pointer, &mut delegate), call)?;
+        if delegate.is_null() {
+            return Err(HostingError::null_pointer(call));
+        }
+        Ok(AssemblyLoader {
+            load: unsafe { mem::transmute::<*mut c_void, load_assembly_and_get_function_pointer_fn>(delegate) }, 
+            _context: PhantomData,
+        })
+    }
+}
+
+impl<'f> Drop for HostContext<'f> {
+    fn drop(&mut self) {
+        (self.fxr.close)(self.handle);
+    }
+}
+
+//The hdt_load_assembly_and_get_function_pointer delegate. Assemblies are 
+// loaded into an isolated AssemblyLoadContext per assembly path.
+pub struct AssemblyLoader<'c> {
+    load: load_assembly_and_get_function_pointer_fn, 
+    _context: PhantomData<&'c ()>,
+}
+
+//How the managed method is exposed to native code
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DelegateType<'a> {
+    //public static int M(IntPtr args, int sizeBytes)
+    ComponentEntryPoint, 
+    //[UnmanagedCallersOnly] static methods, any signature
+    UnmanagedCallersOnly, 
+    //Assembly-qualified name of a custom delegate type
+    Named(&'a str),
+}
+
+impl<'c> AssemblyLoader<'c> {
+    //type_name is assembly-qualified, e.g. "App.Lib, App". The result must 
+    // be transmuted to the matching extern fn type by the caller.
+    pub fn function_pointer(&self, assembly: &Path, type_name: &str, method: &str, delegate: DelegateType) -> Result<*mut c_void, HostingError> {
+        let assembly = wide(assembly.as_os_str());
+        let type_name = wide(OsStr::new(type_name));
+        let method = wide(OsStr::new(method));
+        let delegate_name = match delegate {
+            DelegateType::Named(name) => Some(wide(OsStr::new(name))), 
+            _ => None,
+        };
+        let delegate_ptr = match delegate {
+            DelegateType::ComponentEntryPoint => ptr::null(), 
+            DelegateType::UnmanagedCallersOnly => UNMANAGEDCALLERSONLY_METHOD, 
+            DelegateType::Named(_) => delegate_name.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+        };
+        let mut fp: *mut c_void = ptr::null_mut();
+        let call = CALL!(hostfxr::load_assembly_and_get_function_pointer);
+        check((self.load)(assembly.as_ptr(), type_name.as_ptr(), method.as_ptr(), delegate_ptr, ptr::null_mut(), &mut fp), call)?;
+        if fp.is_null() {
+            return Err(HostingError::null_pointer(call));
+        }
+        Ok(fp)
+    }
+
+    //The default delegate shape, already typed
+    pub fn entry_point(&self, assembly: &Path, type_name: &str, method: &str) -> Result<component_entry_point_fn, HostingError> {
+        self.function_pointer(assembly, type_name, method, DelegateType::ComponentEntryPoint)
+            .map(|fp| unsafe { mem::transmute::<*mut c_void, component_entry_point_fn>(fp) })
+    }
+}
+
+//The key coreclr.dll expects in Authenticate before it will start
+pub const CORECLR_HOST_AUTHENTICATION_KEY: ULONGLONG = 0x01C6_CA6F_9402_5800;
+
+type GetClrRuntimeHostFn = unsafe extern "system" fn(REFIID, *mut *mut IUnknown) -> HRESULT;
+
+//ICLRRuntimeHost2 as exported by coreclr.dll's GetCLRRuntimeHost. As with 
+// hostfxr the module stays loaded for the life of the process.
+pub struct CoreClrHost {
+    inner: ComPtr<ICLRRuntimeHost2>, 
+    host4: Option<ComPtr<ICLRRuntimeHost4>>,
+}
+
+impl CoreClrHost {
+    //coreclr.dll on the DLL search path
+    pub fn load() -> Result<CoreClrHost, HostingError> {
+        CoreClrHost::load_from(Path::new("coreclr.dll"))
+    }
+
+    pub fn load_from(path: &Path) -> Result<CoreClrHost, HostingError> {
+        let module = load_library(path.as_os_str())?;
+        let get_host: GetClrRuntimeHostFn = unsafe {
+            mem::transmute(symbol(module, "GetCLRRuntimeHost\0", CALL!(coreclr::GetCLRRuntimeHost))?)
+        };
+        let inner: ComPtr<ICLRRuntimeHost2> = unsafe {
+            ComPtr::from_out(CALL!(coreclr::GetCLRRuntimeHost), |p: *mut *mut ICLRRuntimeHost2| {
+                get_host(&ICLRRuntimeHost2::uuidof(), p as *mut *mut IUnknown)
+            })?
+        };
+        let host4 = inner.query_interface::<ICLRRuntimeHost4>().ok();
+        Ok(CoreClrHost { inner, host4 })
+    }
+
+    //Both of these must happen before start
+    pub fn set_startup_flags(&self, flags: DWORD) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeHost2::SetStartupFlags, self.inner.SetStartupFlags(flags)).map(|_| ())
+    }
+
+    pub fn authenticate(&self, key: ULONGLONG) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeHost2::Authenticate, self.inner.Authenticate(key)).map(|_| ())
+    }
+
+    pub fn start(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeHost::Start, self.inner.Start()).map(|_| ())
+    }
+
+    pub fn stop(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeHost::Stop, self.inner.Stop()).map(|_| ())
+    }
+
+    //flags are APPDOMAIN_* values. CoreCLR takes its probing setup as 
+    // properties here (TRUSTED_PLATFORM_ASSEMBLIES, APP_PATHS, ...) rather 
+    // than from a config file. Either manager name may be None.
+    pub fn create_app_domain_with_manager(&self, friendly_name: &str, flags: DWORD, manager_assembly: Option<&str>, 
+        manager_type: Option<&str>, properties: &[(&str, &str)]) -> Result<DWORD, HostingError> 
+    {
+        let name = wide(friendly_name);
+        let manager_assembly = manager_assembly.map(wide);
+        let manager_type = manager_type.map(wide);
+        let keys: Vec<Vec<u16>> = properties.iter().map(|&(k, _)| wide(k)).collect();
+        let values: Vec<Vec<u16>> = properties.iter().map(|&(_, v)| wide(v)).collect();
+        let mut key_ptrs: Vec<LPCWSTR> = keys.iter().map(|w| w.as_ptr()).collect();
+        let mut value_ptrs: Vec<LPCWSTR> = values.iter().map(|w| w.as_ptr()).collect();
+
+        let mut domain_id: DWORD = 0;
+        CHECK_HR!(ICLRRuntimeHost2::CreateAppDomainWithManager, self.inner.CreateAppDomainWithManager(
+            name.as_ptr(), 
+            flags, 
+            manager_assembly.as_ref().map_or(ptr::null(), |w| w.as_ptr()), 
+            manager_type.as_ref().map_or(ptr::null(), |w| w.as_ptr()), 
+            key_ptrs.len() as i32, 
+            if key_ptrs.is_empty() { ptr::null_mut() } else { key_ptrs.as_mut_ptr() }, 
+            if value_ptrs.is_empty() { ptr::null_mut() } else { value_ptrs.as_mut_ptr() }, 
+            &mut domain_id
+        ))?;
+        Ok(domain_id)
+    }
+
+    //Runs the assembly's entry point in the given domain and returns its 
+    // exit code
+    pub fn execute_assembly<P: AsRef<Path>>(&self, domain_id: DWORD, assembly_path: P, args: &[&str]) -> Result<DWORD, HostingError> {
+        let path = wide(assembly_path.as_ref());
+        let args: Vec<Vec<u16>> = args.iter().map(|a| wide(*a)).collect();
+        let mut arg_ptrs: Vec<LPCWSTR> = args.iter().map(|w| w.as_ptr()).collect();
+        let mut ret: DWORD = 0;
+        CHECK_HR!(ICLRRuntimeHost2::ExecuteAssembly, self.inner.ExecuteAssembly(
+            domain_id, 
+            path.as_ptr(), 
+            arg_ptrs.len() as i32, 
+            if arg_ptrs.is_empty() { ptr::null_mut() } else { arg_ptrs.as_mut_ptr() }, 
+            &mut ret
+        ))?;
+        Ok(ret)
+    }
+
+    //A native-callable pointer to a static method, resolved once so it can 
+    // be called repeatedly without going back through the host. The method 
+    // may not be generic and its parameters must be blittable. Unsafe 
+    // because F has to match the managed signature exactly.
+    pub unsafe fn delegate<F: Copy>(&self, domain_id: DWORD, assembly_name: &str, type_name: &str, method: &str) -> Result<F, HostingError> {
+        let (assembly, ty, method) = (wide(assembly_name), wide(type_name), wide(method));
+        let mut fp: INT_PTR = 0;
+        CHECK_HR!(ICLRRuntimeHost2::CreateDelegate, self.inner.CreateDelegate(
+            domain_id, 
+            assembly.as_ptr(), 
+            ty.as_ptr(), 
+            method.as_ptr(), 
+            &mut fp
+        ))?;
+        if fp == 0 {
+            return Err(HostingError::null_pointer(CALL!(ICLRRuntimeHost2::CreateDelegate)));
+        }
+        Ok(typed_fn(fp as *mut c_void))
+    }
+
+    //CoreCLR can only unload the domain it was started with, on shutdown. 
+    // With ICLRRuntimeHost4 the latched exit code comes back too.
+    pub fn unload_app_domain(&self, domain_id: DWORD, wait_until_done: bool) -> Result<Option<i32>, HostingError> {
+        let wait = if wait_until_done { TRUE } else { FALSE };
+        match self.host4 {
+            Some(ref host4) => {
+                let mut exit_code: i32 = 0;
+                CHECK_HR!(ICLRRuntimeHost4::UnloadAppDomain2, host4.UnloadAppDomain2(domain_id, wait, &mut exit_code))?;
+                Ok(Some(exit_code))
+            }, 
+            None => {
+                CHECK_HR!(ICLRRuntimeHost::UnloadAppDomain, self.inner.UnloadAppDomain(domain_id, wait))?;
+                Ok(None)
+            },
+        }
+    }
+
+    pub fn supports_host4(&self) -> bool {
+        self.host4.is_some()
+    }
+}
+
+//F must be a plain fn pointer type; anything else is a bug at the call site
+pub(crate) unsafe fn typed_fn<F: Copy>(fp: *mut c_void) -> F {
+    assert_eq!(mem::size_of::<F>(), mem::size_of::<*mut c_void>(), "delegate type must be a function pointer");
+    mem::transmute_copy(&fp)
+}
+
+fn check(rc: i32, call: Call) -> Result<i32, HostingError> {
+    if rc < 0 { Err(HostingError::from_hresult(rc, call)) } else { Ok(rc) }
+}
+
+fn load_library(name: &OsStr) -> Result<HMODULE, HostingError> {
+    let name = wide(name);
+    let module = unsafe { LoadLibraryW(name.as_ptr()) };
+    if module.is_null() {
+        return Err(HostingError::from_hresult(last_error(), CALL!(kernel32::LoadLibraryW)));
+    }
+    Ok(module)
+}
+
+//name must be NUL-terminated
+fn symbol(module: HMODULE, name: &str, call: Call) -> Result<*mut c_void, HostingError> {
+    let proc = unsafe { GetProcAddress(module, name.as_ptr() as *const i8) };
+    if proc.is_null() {
+        return Err(HostingError::from_hresult(last_error(), call));
+    }
+    Ok(proc as *mut c_void)
+}
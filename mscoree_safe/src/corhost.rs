@@ -0,0 +1,434 @@
+// corhost.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{REFCLSID, REFIID};
+use winapi::shared::minwindef::LPVOID;
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::winerror::S_OK;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscorlib_safe::BString;
+use mscorlib_sys::system::{IAppDomainSetup, _AppDomain};
+
+use mscoree_sys::mscoree::{CLSID_CorRuntimeHost, HDOMNAINENUM, ICorConfiguration, ICorRuntimeHost, ICorThreadPool, IID_ICorRuntimeHost};
+
+use configuration::CorConfiguration;
+use error::{Call, HostingError};
+use gchost::GcHost;
+use metahost::{HostInterface, QueryInterface, RuntimeInfo};
+use reflection::{AppDomain, ManagedAssembly, ManagedObject, ManagedType};
+use threadpool::ThreadPool;
+use variant::{ClrObject, ClrValue, OsBstr};
+use wrappers::PtrCtr;
+
+//Safe wrapper over ICorRuntimeHost, the v1-style hosting interface that 
+// hands out _AppDomain pointers usable from unmanaged code.
+pub struct CorRuntimeHost {
+    inner: PtrCtr<ICorRuntimeHost>,
+}
+
+impl HostInterface for CorRuntimeHost {
+    fn clsid() -> REFCLSID {
+        &CLSID_CorRuntimeHost
+    }
+
+    fn iid() -> REFIID {
+        &IID_ICorRuntimeHost
+    }
+
+    unsafe fn from_interface(p: LPVOID) -> CorRuntimeHost {
+        let inner = PtrCtr::new_checked(p as *mut ICorRuntimeHost)
+            .expect("GetInterface pointers are never null");
+        CorRuntimeHost { inner }
+    }
+}
+
+impl CorRuntimeHost {
+    pub fn new(runtime: &dyn RuntimeInfo) -> Result<CorRuntimeHost, HostingError> {
+        runtime.query::<CorRuntimeHost>()
+    }
+
+    //Host callbacks; only honoured before start
+    pub fn configuration(&self) -> Result<CorConfiguration, HostingError> {
+        let mut config: *mut ICorConfiguration = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::GetConfiguration, (*self.inner.as_const()).GetConfiguration(&mut config))?;
+        PtrCtr::new_checked(config)
+            .map(CorConfiguration::new_from)
+            .map_err(|_| HostingError::null_pointer(CALL!(ICorRuntimeHost::GetConfiguration)))
+    }
+
+    pub fn start(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICorRuntimeHost::Start, (*self.inner.as_const()).Start()).map(|_| ())
+    }
+
+    pub fn stop(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICorRuntimeHost::Stop, (*self.inner.as_const()).Stop()).map(|_| ())
+    }
+
+    //The domain the runtime starts with; it lives until the process exits, 
+    // which makes it the safe anchor for loading through reflection
+    pub fn default_domain(&self) -> Result<AppDomain, HostingError> {
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::GetDefaultDomain, (*self.inner.as_const()).GetDefaultDomain(&mut unk))?;
+        domain_from_unknown(unk, CALL!(ICorRuntimeHost::GetDefaultDomain))
+    }
+
+    //The domain the calling thread is in. Outside of a callback from 
+    // managed code that is the default domain.
+    pub fn current_domain(&self) -> Result<AppDomain, HostingError> {
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::CurrentDomain, (*self.inner.as_const()).CurrentDomain(&mut unk))?;
+        domain_from_unknown(unk, CALL!(ICorRuntimeHost::CurrentDomain))
+    }
+
+    //Every domain alive right now, the default domain first
+    pub fn domains(&self) -> Result<Domains, HostingError> {
+        let mut handle: HDOMNAINENUM = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::EnumDomains, (*self.inner.as_const()).EnumDomains(&mut handle))?;
+        Ok(Domains { host: self, handle })
+    }
+
+    //Legacy GC control; IGCHost2 is picked up too when available
+    pub fn gc_host(&self) -> Result<GcHost, HostingError> {
+        GcHost::from_unknown(self.inner.as_const() as *mut IUnknown)
+    }
+
+    pub fn thread_pool(&self) -> Result<ThreadPool, HostingError> {
+        let mut pool: *mut ICorThreadPool = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::QueryInterface, (*self.inner.as_const()).QueryInterface(
+            &ICorThreadPool::uuidof(), 
+            &mut pool as *mut *mut ICorThreadPool as *mut *mut c_void
+        ))?;
+        PtrCtr::new_checked(pool)
+            .map(ThreadPool::new_from)
+            .map_err(|_| HostingError::null_pointer(CALL!(ICorRuntimeHost::QueryInterface)))
+    }
+
+    pub fn create_domain(&self, friendly_name: &str) -> Result<AppDomain, HostingError> {
+        let name = BString::from(friendly_name);
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::CreateDomain, (*self.inner.as_const()).CreateDomain(name.as_sys() as LPCWSTR, ptr::null_mut(), &mut unk))?;
+        domain_from_unknown(unk, CALL!(ICorRuntimeHost::CreateDomain))
+    }
+
+    //A domain with its own base directory, config file and probing paths
+    pub fn create_domain_with(&self, friendly_name: &str, config: &DomainConfig) -> Result<AppDomain, HostingError> {
+        self.create_domain_ex(friendly_name, Some(config), None)
+    }
+
+    //CreateDomainEx in full. Evidence decides the permission set the 
+    // domain's code gets under legacy CAS policy; without any the domain 
+    // runs with the host's.
+    pub fn create_domain_ex(&self, friendly_name: &str, config: Option<&DomainConfig>, evidence: Option<&DomainEvidence>) -> Result<AppDomain, HostingError> {
+        let setup = match config {
+            Some(config) => {
+                let setup = self.domain_setup()?;
+                if let Err(err) = config.apply(setup) {
+                    unsafe { (*setup).Release() };
+                    return Err(err);
+                }
+                setup
+            }, 
+            None => ptr::null_mut(),
+        };
+        let name = BString::from(friendly_name);
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        let hr = CHECK_HR!(ICorRuntimeHost::CreateDomainEx, (*self.inner.as_const()).CreateDomainEx(
+            name.as_sys() as LPCWSTR, 
+            setup as *mut IUnknown, 
+            evidence.map_or(ptr::null_mut(), |e| e.as_unknown()), 
+            &mut unk
+        ));
+        if !setup.is_null() {
+            unsafe { (*setup).Release() };
+        }
+        hr?;
+        domain_from_unknown(unk, CALL!(ICorRuntimeHost::CreateDomainEx))
+    }
+
+    //An empty System.Security.Policy.Evidence to fill in
+    pub fn create_evidence(&self) -> Result<DomainEvidence, HostingError> {
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::CreateEvidence, (*self.inner.as_const()).CreateEvidence(&mut unk))?;
+        if unk.is_null() {
+            return Err(HostingError::null_pointer(CALL!(ICorRuntimeHost::CreateEvidence)));
+        }
+        //The reference from CreateEvidence is handed over to the ClrObject
+        let object = ClrObject::from_borrowed(unk);
+        unsafe { (*unk).Release() };
+        let evidence = ManagedObject::from_value(ClrValue::Object(object?))?;
+        let mscorlib = self.default_domain()?.load("mscorlib")?;
+        Ok(DomainEvidence { evidence, mscorlib })
+    }
+
+    //A fresh AppDomainSetup; the caller owns the returned reference
+    fn domain_setup(&self) -> Result<*mut IAppDomainSetup, HostingError> {
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        CHECK_HR!(ICorRuntimeHost::CreateDomainSetup, (*self.inner.as_const()).CreateDomainSetup(&mut unk))?;
+        if unk.is_null() {
+            return Err(HostingError::null_pointer(CALL!(ICorRuntimeHost::CreateDomainSetup)));
+        }
+        let mut setup: *mut IAppDomainSetup = ptr::null_mut();
+        let hr = CHECK_HR!(IUnknown::QueryInterface, (*unk).QueryInterface(
+            &IAppDomainSetup::uuidof(), 
+            &mut setup as *mut *mut IAppDomainSetup as *mut *mut c_void
+        ));
+        unsafe { (*unk).Release() };
+        hr?;
+        if setup.is_null() {
+            return Err(HostingError::null_pointer(CALL!(IUnknown::QueryInterface)));
+        }
+        Ok(setup)
+    }
+
+    //Objects obtained from the domain become unusable once this returns
+    pub fn unload_domain(&self, domain: AppDomain) -> Result<(), HostingError> {
+        CHECK_HR!(ICorRuntimeHost::UnloadDomain, (*self.inner.as_const()).UnloadDomain(domain.as_unknown())).map(|_| ())
+    }
+}
+
+//The AppDomainSetup fields hosts usually care about. Anything left unset 
+// keeps the runtime's default, which for ApplicationBase is the host 
+// executable's directory.
+#[derive(Clone, Debug, Default)]
+pub struct DomainConfig {
+    application_base: Option<PathBuf>, 
+    application_name: Option<String>, 
+    configuration_file: Option<PathBuf>, 
+    private_bin_paths: Vec<PathBuf>, 
+    shadow_copy_files: bool,
+}
+
+impl DomainConfig {
+    pub fn new() -> DomainConfig {
+        DomainConfig::default()
+    }
+
+    pub fn application_base<P: AsRef<Path>>(mut self, path: P) -> DomainConfig {
+        self.application_base = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn application_name(mut self, name: &str) -> DomainConfig {
+        self.application_name = Some(name.to_string());
+        self
+    }
+
+    pub fn configuration_file<P: AsRef<Path>>(mut self, path: P) -> DomainConfig {
+        self.configuration_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Adds a probing directory; the runtime only honours ones under the 
+    // application base
+    pub fn private_bin_path<P: AsRef<Path>>(mut self, path: P) -> DomainConfig {
+        self.private_bin_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Loads assemblies from a shadow copy so the originals can be replaced 
+    // while the domain runs
+    pub fn shadow_copy_files(mut self, enabled: bool) -> DomainConfig {
+        self.shadow_copy_files = enabled;
+        self
+    }
+
+    //PrivateBinPath takes the directories as one semicolon-separated list
+    pub fn private_bin_path_list(&self) -> Option<OsString> {
+        if self.private_bin_paths.is_empty() {
+            return None;
+        }
+        let mut list = OsString::new();
+        for (i, path) in self.private_bin_paths.iter().enumerate() {
+            if i > 0 {
+                list.push(";");
+            }
+            list.push(path);
+        }
+        Some(list)
+    }
+
+    fn apply(&self, setup: *mut IAppDomainSetup) -> Result<(), HostingError> {
+        if let Some(ref base) = self.application_base {
+            let bs = OsBstr::new(base)?;
+            CHECK_HR!(IAppDomainSetup::put_ApplicationBase, (*setup).put_ApplicationBase(bs.as_sys()))?;
+        }
+        if let Some(ref name) = self.application_name {
+            let bs = BString::from(name.as_str());
+            CHECK_HR!(IAppDomainSetup::put_ApplicationName, (*setup).put_ApplicationName(bs.as_sys()))?;
+        }
+        if let Some(ref file) = self.configuration_file {
+            let bs = OsBstr::new(file)?;
+            CHECK_HR!(IAppDomainSetup::put_ConfigurationFile, (*setup).put_ConfigurationFile(bs.as_sys()))?;
+        }
+        if let Some(list) = self.private_bin_path_list() {
+            let bs = OsBstr::new(&list)?;
+            CHECK_HR!(IAppDomainSetup::put_PrivateBinPath, (*setup).put_PrivateBinPath(bs.as_sys()))?;
+        }
+        if self.shadow_copy_files {
+            let bs = BString::from("true");
+            CHECK_HR!(IAppDomainSetup::put_ShadowCopyFiles, (*setup).put_ShadowCopyFiles(bs.as_sys()))?;
+        }
+        Ok(())
+    }
+}
+
+//Walks NextDomain until it reports S_FALSE; an error also ends the walk. 
+// The enumeration handle is closed on drop or once the walk ends.
+pub struct Domains<'h> {
+    host: &'h CorRuntimeHost, 
+    handle: HDOMNAINENUM,
+}
+
+impl<'h> Domains<'h> {
+    fn close(&mut self) {
+        if !self.handle.is_null() {
+            let _ = CHECK_HR!(ICorRuntimeHost::CloseEnum, (*self.host.inner.as_const()).CloseEnum(self.handle));
+            self.handle = ptr::null_mut();
+        }
+    }
+}
+
+impl<'h> Iterator for Domains<'h> {
+    type Item = AppDomain;
+
+    fn next(&mut self) -> Option<AppDomain> {
+        if self.handle.is_null() {
+            return None;
+        }
+        let mut unk: *mut IUnknown = ptr::null_mut();
+        let hr = CHECK_HR!(ICorRuntimeHost::NextDomain, (*self.host.inner.as_const()).NextDomain(self.handle, &mut unk));
+        match hr {
+            Ok(S_OK) if !unk.is_null() => domain_from_unknown(unk, CALL!(ICorRuntimeHost::NextDomain)).ok(), 
+            _ => {
+                self.close();
+                None
+            },
+        }
+    }
+}
+
+impl<'h> Drop for Domains<'h> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+//System.Security.SecurityZone
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SecurityZone {
+    MyComputer = 0, 
+    Intranet = 1, 
+    Trusted = 2, 
+    Internet = 3, 
+    Untrusted = 4,
+}
+
+//Host evidence for a new domain. zone and site are the two the default 
+// CAS policy keys on; anything else can be added as a managed object.
+pub struct DomainEvidence {
+    evidence: ManagedObject, 
+    mscorlib: ManagedAssembly,
+}
+
+impl DomainEvidence {
+    pub fn add_zone(&self, zone: SecurityZone) -> Result<(), HostingError> {
+        let zone = self.policy_type("Zone")?.create_instance(&[ClrValue::I4(zone as i32)])?;
+        self.add(&zone)
+    }
+
+    //A site name such as "www.example.com"
+    pub fn add_site(&self, site: &str) -> Result<(), HostingError> {
+        let site = self.policy_type("Site")?.create_instance(&[ClrValue::from(site)])?;
+        self.add(&site)
+    }
+
+    //Zone, URL and, for non-file URLs, site evidence derived from one URL, 
+    // the way the runtime does for assemblies it downloads
+    pub fn add_url(&self, url: &str) -> Result<(), HostingError> {
+        let arg = [ClrValue::from(url)];
+        let zone = self.policy_type("Zone")?.invoke_static("CreateFromUrl", &arg)?;
+        self.add(&ManagedObject::from_value(zone)?)?;
+        self.add(&self.policy_type("Url")?.create_instance(&arg)?)?;
+        //Site.CreateFromUrl throws for file: URLs, which have no site
+        if !url.to_ascii_lowercase().starts_with("file:") {
+            let site = self.policy_type("Site")?.invoke_static("CreateFromUrl", &arg)?;
+            self.add(&ManagedObject::from_value(site)?)?;
+        }
+        Ok(())
+    }
+
+    //Evidence.AddHost; obsolete in .NET 4 but the only non-generic way in
+    pub fn add(&self, evidence: &ManagedObject) -> Result<(), HostingError> {
+        self.evidence.invoke("AddHost", &[evidence.to_value()])?;
+        Ok(())
+    }
+
+    pub fn as_object(&self) -> &ManagedObject {
+        &self.evidence
+    }
+
+    fn as_unknown(&self) -> *mut IUnknown {
+        self.evidence.as_unknown()
+    }
+
+    fn policy_type(&self, name: &str) -> Result<ManagedType, HostingError> {
+        self.mscorlib.get_type(&format!("System.Security.Policy.{}", name))
+    }
+}
+
+//Takes ownership of the IUnknown reference handed out by `call`
+pub(crate) fn domain_from_unknown(unk: *mut IUnknown, call: Call) -> Result<AppDomain, HostingError> {
+    if unk.is_null() {
+        return Err(HostingError::null_pointer(call));
+    }
+    let mut domain: *mut _AppDomain = ptr::null_mut();
+    let hr = CHECK_HR!(IUnknown::QueryInterface, (*unk).QueryInterface(
+        &_AppDomain::uuidof(), 
+        &mut domain as *mut *mut _AppDomain as *mut *mut c_void
+    ));
+    unsafe { (*unk).Release() };
+    hr?;
+    PtrCtr::new_checked(domain)
+        .map(AppDomain::new_from)
+        .map_err(|_| HostingError::null_pointer(CALL!(IUnknown::QueryInterface)))
+}
+
+COM_WRAPPER!(CorRuntimeHost, ICorRuntimeHost);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn private_bin_paths_join_with_semicolons() {
+        assert_eq!(DomainConfig::new().private_bin_path_list(), None);
+        let config = DomainConfig::new().private_bin_path("bin").private_bin_path("plugins\\net40");
+        assert_eq!(config.private_bin_path_list(), Some(OsString::from("bin;plugins\\net40")));
+    }
+}
@@ -0,0 +1,110 @@
+// debugging.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRDebugManager: lets hosts that multiplex logical connections over 
+// threads (SQL-style hosts) name those connections for the debugger, and 
+// control who may attach via the debugger DACL.
+use std::mem;
+use std::ptr;
+
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::um::winnt::PACL;
+
+use mscoree_sys::mscoree::{ICLRDebugManager, ICLRTask};
+
+use control::ClrControl;
+use error::HostingError;
+use tasks::ClrTask;
+use wrappers::PtrCtr;
+
+pub struct DebugManager {
+    inner: PtrCtr<ICLRDebugManager>,
+}
+
+impl DebugManager {
+    pub fn new(control: &ClrControl) -> Result<DebugManager, HostingError> {
+        control.manager::<ICLRDebugManager>().map(|inner| DebugManager { inner })
+    }
+
+    //The connection ends when the returned handle drops
+    pub fn begin_connection(&self, id: DWORD, name: &str) -> Result<DebugConnection, HostingError> {
+        let mut wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        CHECK_HR!(ICLRDebugManager::BeginConnection, (*self.inner.as_const()).BeginConnection(id, wide.as_mut_ptr()))?;
+        Ok(DebugConnection { manager: self, id })
+    }
+
+    pub fn is_debugger_attached(&self) -> Result<bool, HostingError> {
+        let mut attached: BOOL = 0;
+        CHECK_HR!(ICLRDebugManager::IsDebuggerAttached, (*self.inner.as_const()).IsDebuggerAttached(&mut attached))?;
+        Ok(attached != 0)
+    }
+
+    //The ACL is copied by the runtime
+    pub unsafe fn set_dacl(&self, acl: PACL) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRDebugManager::SetDacl, (*self.inner.as_const()).SetDacl(acl)).map(|_| ())
+    }
+
+    pub fn dacl(&self) -> Result<PACL, HostingError> {
+        let mut acl: PACL = ptr::null_mut();
+        CHECK_HR!(ICLRDebugManager::GetDacl, (*self.inner.as_const()).GetDacl(&mut acl))?;
+        Ok(acl)
+    }
+}
+
+COM_WRAPPER!(DebugManager);
+
+pub struct DebugConnection<'m> {
+    manager: &'m DebugManager, 
+    id: DWORD,
+}
+
+impl<'m> DebugConnection<'m> {
+    pub fn id(&self) -> DWORD {
+        self.id
+    }
+
+    //Replaces the set of tasks associated with the connection
+    pub fn set_tasks(&self, tasks: &[&ClrTask]) -> Result<(), HostingError> {
+        let raw: Vec<*mut ICLRTask> = tasks.iter().map(|t| t.as_raw()).collect();
+        unsafe { self.set_tasks_raw(&raw) }
+    }
+
+    //As set_tasks, for task pointers obtained elsewhere; they must be 
+    // live ICLRTask references
+    pub unsafe fn set_tasks_raw(&self, tasks: &[*mut ICLRTask]) -> Result<(), HostingError> {
+        let mut tasks = tasks.to_vec();
+        CHECK_HR!(ICLRDebugManager::SetConnectionTasks, (*self.manager.inner.as_const()).SetConnectionTasks(self.id, tasks.len() as DWORD, tasks.as_mut_ptr()))
+            .map(|_| ())
+    }
+
+    pub fn end(self) -> Result<(), HostingError> {
+        let hr = CHECK_HR!(ICLRDebugManager::EndConnection, (*self.manager.inner.as_const()).EndConnection(self.id));
+        mem::forget(self);
+        hr.map(|_| ())
+    }
+}
+
+impl<'m> Drop for DebugConnection<'m> {
+    fn drop(&mut self) {
+        unsafe { (*self.manager.inner.as_const()).EndConnection(self.id) };
+    }
+}
@@ -0,0 +1,155 @@
+// dispatch.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Late binding through the IDispatch every managed object's COM-callable 
+// wrapper exposes by default (ClassInterfaceType.AutoDispatch). Looser 
+// than ManagedObject, which goes through _Type::InvokeMember: members are 
+// looked up by name on each first use and there is no static type to 
+// resolve, so anything the CCW hands out can be driven this way.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::ptr;
+
+use winapi::shared::guiddef::IID_NULL;
+use winapi::shared::minwindef::{UINT, WORD};
+use winapi::shared::winerror::{DISP_E_EXCEPTION, DISP_E_TYPEMISMATCH};
+use winapi::shared::wtypesbase::LPOLESTR;
+use winapi::um::oaidl::{IDispatch, DISPID, DISPID_PROPERTYPUT, DISPPARAMS, EXCEPINFO, VARIANT};
+use winapi::um::oleauto::{SysFreeString, VariantClear, DISPATCH_METHOD, DISPATCH_PROPERTYGET, DISPATCH_PROPERTYPUT};
+use winapi::um::winnt::LOCALE_USER_DEFAULT;
+
+use buffer::wide;
+use comptr::ComPtr;
+use error::HostingError;
+use reflection::InvokeError;
+use variant::{self, ClrValue};
+
+pub struct DynamicObject {
+    inner: ComPtr<IDispatch>, 
+    ids: RefCell<HashMap<String, DISPID>>,
+}
+
+impl DynamicObject {
+    //DISP_E_TYPEMISMATCH for values that aren't objects
+    pub fn from_value(value: &ClrValue) -> Result<DynamicObject, HostingError> {
+        match *value {
+            ClrValue::Object(ref object) => {
+                let unknown = unsafe { ComPtr::from_borrowed(object.as_unknown()) }
+                    .expect("ClrObject pointers are never null");
+                let inner = unknown.query_interface::<IDispatch>()?;
+                Ok(DynamicObject { inner, ids: RefCell::new(HashMap::new()) })
+            }, 
+            _ => Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(IDispatch::QueryInterface))),
+        }
+    }
+
+    pub fn get(&self, property: &str) -> Result<ClrValue, InvokeError> {
+        self.invoke(property, DISPATCH_PROPERTYGET, &[], false).map_err(InvokeError::capture)
+    }
+
+    pub fn set(&self, property: &str, value: ClrValue) -> Result<(), HostingError> {
+        self.invoke(property, DISPATCH_PROPERTYPUT, &[value], true).map(|_| ())
+    }
+
+    pub fn call(&self, method: &str, args: &[ClrValue]) -> Result<ClrValue, InvokeError> {
+        self.invoke(method, DISPATCH_METHOD, args, false).map_err(InvokeError::capture)
+    }
+
+    //Results that are objects again, for walking property chains
+    pub fn get_dynamic(&self, property: &str) -> Result<DynamicObject, HostingError> {
+        DynamicObject::from_value(&self.get(property)?)
+    }
+
+    fn id(&self, name: &str) -> Result<DISPID, HostingError> {
+        if let Some(&id) = self.ids.borrow().get(name) {
+            return Ok(id);
+        }
+        let mut wide_name = wide(name);
+        let mut names: [LPOLESTR; 1] = [wide_name.as_mut_ptr()];
+        let mut id: DISPID = 0;
+        CHECK_HR!(IDispatch::GetIDsOfNames, self.inner.GetIDsOfNames(&IID_NULL, names.as_mut_ptr(), 1, LOCALE_USER_DEFAULT, &mut id))?;
+        self.ids.borrow_mut().insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    fn invoke(&self, name: &str, flags: WORD, args: &[ClrValue], property_put: bool) -> Result<ClrValue, HostingError> {
+        let id = self.id(name)?;
+        //IDispatch takes its arguments last to first
+        let mut variants = Vec::with_capacity(args.len());
+        for arg in args.iter().rev() {
+            match arg.to_variant() {
+                Ok(v) => variants.push(v), 
+                Err(err) => {
+                    clear_all(&mut variants);
+                    return Err(err);
+                },
+            }
+        }
+        let mut put_id: DISPID = DISPID_PROPERTYPUT;
+        let mut params = DISPPARAMS {
+            rgvarg: if variants.is_empty() { ptr::null_mut() } else { variants.as_mut_ptr() }, 
+            rgdispidNamedArgs: if property_put { &mut put_id } else { ptr::null_mut() }, 
+            cArgs: variants.len() as UINT, 
+            cNamedArgs: if property_put { 1 } else { 0 },
+        };
+        let mut result = variant::empty();
+        let mut exception: EXCEPINFO = unsafe { mem::zeroed() };
+        let mut arg_error: UINT = 0;
+        let hr = CHECK_HR!(IDispatch::Invoke, self.inner.Invoke(
+            id, 
+            &IID_NULL, 
+            LOCALE_USER_DEFAULT, 
+            flags, 
+            &mut params, 
+            if property_put { ptr::null_mut() } else { &mut result }, 
+            &mut exception, 
+            &mut arg_error
+        ));
+        clear_all(&mut variants);
+        let scode = exception.scode;
+        free_exception(&mut exception);
+        match hr {
+            //The managed exception's HRESULT says more than DISP_E_EXCEPTION
+            Err(ref err) if err.hresult() == DISP_E_EXCEPTION && scode < 0 => {
+                Err(HostingError::from_hresult(scode, CALL!(IDispatch::Invoke)))
+            }, 
+            Err(err) => Err(err), 
+            Ok(_) => ClrValue::from_owned_variant(result),
+        }
+    }
+}
+
+fn clear_all(variants: &mut Vec<VARIANT>) {
+    for v in variants.iter_mut() {
+        unsafe { VariantClear(v) };
+    }
+    variants.clear();
+}
+
+fn free_exception(exception: &mut EXCEPINFO) {
+    unsafe {
+        SysFreeString(exception.bstrSource);
+        SysFreeString(exception.bstrDescription);
+        SysFreeString(exception.bstrHelpFile);
+    }
+}
@@ -0,0 +1,275 @@
+// error.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Error type for the public hosting API. Each failure records the COM call 
+// that produced it, and the raw HRESULT is exposed as the error's source 
+// so it shows up when the chain is walked.
+use std::error::Error;
+use std::fmt;
+use std::ptr;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{CLASS_E_CLASSNOTAVAILABLE, E_NOINTERFACE, E_POINTER, HRESULT, HRESULT_FROM_WIN32, REGDB_E_CLASSNOTREG};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winbase::{FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS};
+
+use mscoree_sys::corerror::*;
+use mscoree_sys::hostfxr::{CoreHostLibMissingFailure, FrameworkMissingFailure, HostInvalidState};
+
+//The interface method that failed, e.g. ICLRRuntimeHost::Start
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Call {
+    pub interface: &'static str, 
+    pub method: &'static str,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}::{}", self.interface, self.method)
+    }
+}
+
+//A bare HRESULT. Display leads with the symbolic name for the hosting 
+// codes we know and appends the system message, falling back to our own 
+// description where Windows has none.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Hresult(pub HRESULT);
+
+impl Hresult {
+    //e.g. "HOST_E_CLRNOTAVAILABLE"; None for codes outside CORERROR_TABLE
+    pub fn name(&self) -> Option<&'static str> {
+        known(self.0).map(|&(_, name, _)| name)
+    }
+
+    pub fn message(&self) -> Option<String> {
+        system_message(self.0).or_else(|| known(self.0).map(|&(_, _, description)| description.to_string()))
+    }
+}
+
+impl fmt::Display for Hresult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} (0x{:08X})", name, self.0 as u32)?, 
+            None => write!(f, "HRESULT 0x{:08X}", self.0 as u32)?,
+        }
+        match self.message() {
+            Some(msg) => write!(f, ": {}", msg), 
+            None => Ok(()),
+        }
+    }
+}
+
+//The hosting codes from corerror.h, which FormatMessage mostly can't 
+// describe since their text lives in the runtime's resources
+const CORERROR_TABLE: &[(HRESULT, &str, &str)] = &[
+    (COR_E_APPDOMAINUNLOADED, "COR_E_APPDOMAINUNLOADED", "The application domain has been unloaded"), 
+    (COR_E_CANNOTUNLOADAPPDOMAIN, "COR_E_CANNOTUNLOADAPPDOMAIN", "The application domain cannot be unloaded"), 
+    (COR_E_ASSEMBLYEXPECTED, "COR_E_ASSEMBLYEXPECTED", "The module was expected to contain an assembly manifest"), 
+    (COR_E_NEWER_RUNTIME, "COR_E_NEWER_RUNTIME", "The assembly was built by a newer runtime than the one loaded"), 
+    (COR_E_EXCEPTION, "COR_E_EXCEPTION", "Managed code threw an exception"), 
+    (COR_E_SYSTEM, "COR_E_SYSTEM", "Managed code threw a SystemException"), 
+    (COR_E_INVALIDOPERATION, "COR_E_INVALIDOPERATION", "The operation is not valid for the object's current state"), 
+    (COR_E_MISSINGFIELD, "COR_E_MISSINGFIELD", "The field does not exist"), 
+    (COR_E_MISSINGMEMBER, "COR_E_MISSINGMEMBER", "The member does not exist"), 
+    (COR_E_MISSINGMETHOD, "COR_E_MISSINGMETHOD", "The method does not exist"), 
+    (COR_E_TYPELOAD, "COR_E_TYPELOAD", "The type could not be loaded"), 
+    (COR_E_THREADABORTED, "COR_E_THREADABORTED", "The thread was aborted"), 
+    (COR_E_TARGETINVOCATION, "COR_E_TARGETINVOCATION", "The invoked member threw an exception"), 
+    (CLDB_E_FILE_CORRUPT, "CLDB_E_FILE_CORRUPT", "The metadata is corrupt"), 
+    (CLDB_E_RECORD_NOTFOUND, "CLDB_E_RECORD_NOTFOUND", "The metadata record was not found"), 
+    (META_E_BAD_SIGNATURE, "META_E_BAD_SIGNATURE", "The signature blob is malformed"), 
+    (META_E_CA_INVALID_BLOB, "META_E_CA_INVALID_BLOB", "The custom attribute blob is malformed"), 
+    (HOST_E_DEADLOCK, "HOST_E_DEADLOCK", "The host detected a deadlock"), 
+    (HOST_E_INTERRUPTED, "HOST_E_INTERRUPTED", "The wait was interrupted"), 
+    (HOST_E_INVALIDOPERATION, "HOST_E_INVALIDOPERATION", "The operation is not valid in the runtime's current state"), 
+    (HOST_E_CLRNOTAVAILABLE, "HOST_E_CLRNOTAVAILABLE", "CLR not loaded or no longer able to run managed code"), 
+    (HOST_E_TIMEOUT, "HOST_E_TIMEOUT", "The operation timed out"), 
+    (HOST_E_NOT_OWNER, "HOST_E_NOT_OWNER", "The caller does not own the lock"), 
+    (HOST_E_ABANDONED, "HOST_E_ABANDONED", "An event was cancelled while a thread was waiting on it"), 
+    (HOST_E_EXITPROCESS_THREADABORT, "HOST_E_EXITPROCESS_THREADABORT", "The process exited on a thread abort, per policy"), 
+    (HOST_E_EXITPROCESS_ADUNLOAD, "HOST_E_EXITPROCESS_ADUNLOAD", "The process exited on a domain unload, per policy"), 
+    (HOST_E_EXITPROCESS_TIMEOUT, "HOST_E_EXITPROCESS_TIMEOUT", "The process exited on a timeout, per policy"), 
+    (HOST_E_EXITPROCESS_OUTOFMEMORY, "HOST_E_EXITPROCESS_OUTOFMEMORY", "The process exited on an out-of-memory condition, per policy"), 
+    (CLR_E_SHIM_RUNTIMELOAD, "CLR_E_SHIM_RUNTIMELOAD", "The requested runtime version could not be loaded"), 
+    (CLR_E_SHIM_RUNTIMEEXPORT, "CLR_E_SHIM_RUNTIMEEXPORT", "The runtime does not export the requested function"), 
+    (CLR_E_SHIM_INSTALLROOT, "CLR_E_SHIM_INSTALLROOT", "The .NET Framework install root is missing or invalid"), 
+    (CLR_E_SHIM_INSTALLCOMP, "CLR_E_SHIM_INSTALLCOMP", "A required .NET Framework component is missing"), 
+    (CLR_E_SHIM_LEGACYRUNTIMEALREADYBOUND, "CLR_E_SHIM_LEGACYRUNTIMEALREADYBOUND", "A different runtime is already bound to the legacy APIs"), 
+    (CLR_E_SHIM_SHUTDOWNINPROGRESS, "CLR_E_SHIM_SHUTDOWNINPROGRESS", "The shim is shutting down"),
+];
+
+fn known(hr: HRESULT) -> Option<&'static (HRESULT, &'static str, &'static str)> {
+    CORERROR_TABLE.iter().find(|&&(code, _, _)| code == hr)
+}
+
+impl Error for Hresult {}
+
+//HRESULT for the calling thread's last Win32 error
+pub(crate) fn last_error() -> HRESULT {
+    HRESULT_FROM_WIN32(unsafe { GetLastError() })
+}
+
+fn system_message(hr: HRESULT) -> Option<String> {
+    let mut buffer = [0u16; 512];
+    let len = unsafe {
+        FormatMessageW(
+            FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS, 
+            ptr::null(), 
+            hr as DWORD, 
+            0, 
+            buffer.as_mut_ptr(), 
+            buffer.len() as DWORD, 
+            ptr::null_mut()
+        )
+    };
+    if len == 0 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&buffer[..len as usize]).trim_end().to_string())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HostingError {
+    NotInstalled { call: Call, source: Hresult }, 
+    VersionNotFound { call: Call, source: Hresult }, 
+    InterfaceNotSupported { call: Call, source: Hresult }, 
+    RuntimeAlreadyStarted { call: Call, source: Hresult }, 
+    Hresult { call: Call, source: Hresult },
+}
+
+impl HostingError {
+    //Picks the variant from the well-known shim and hosting codes
+    pub fn from_hresult(hr: HRESULT, call: Call) -> HostingError {
+        let source = Hresult(hr);
+        match hr {
+            CLR_E_SHIM_INSTALLROOT | CLR_E_SHIM_INSTALLCOMP | CoreHostLibMissingFailure => HostingError::NotInstalled { call, source }, 
+            CLR_E_SHIM_RUNTIMELOAD | FrameworkMissingFailure => HostingError::VersionNotFound { call, source }, 
+            E_NOINTERFACE | CLASS_E_CLASSNOTAVAILABLE | REGDB_E_CLASSNOTREG => HostingError::InterfaceNotSupported { call, source }, 
+            HostInvalidState => HostingError::RuntimeAlreadyStarted { call, source }, 
+            _ => HostingError::Hresult { call, source },
+        }
+    }
+
+    //A call that reported success but left its out pointer null
+    pub fn null_pointer(call: Call) -> HostingError {
+        HostingError::from_hresult(E_POINTER, call)
+    }
+
+    pub fn call(&self) -> Call {
+        match *self {
+            HostingError::NotInstalled { call, .. } | 
+            HostingError::VersionNotFound { call, .. } | 
+            HostingError::InterfaceNotSupported { call, .. } | 
+            HostingError::RuntimeAlreadyStarted { call, .. } | 
+            HostingError::Hresult { call, .. } => call,
+        }
+    }
+
+    pub fn hresult(&self) -> HRESULT {
+        match *self {
+            HostingError::NotInstalled { source, .. } | 
+            HostingError::VersionNotFound { source, .. } | 
+            HostingError::InterfaceNotSupported { source, .. } | 
+            HostingError::RuntimeAlreadyStarted { source, .. } | 
+            HostingError::Hresult { source, .. } => source.0,
+        }
+    }
+}
+
+impl fmt::Display for HostingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match *self {
+            HostingError::NotInstalled { .. } => "the .NET Framework is not installed", 
+            HostingError::VersionNotFound { .. } => "the requested runtime version is not installed", 
+            HostingError::InterfaceNotSupported { .. } => "the interface is not supported", 
+            HostingError::RuntimeAlreadyStarted { .. } => "the runtime has already been started", 
+            HostingError::Hresult { .. } => "call failed",
+        };
+        write!(f, "{}: {}: {}", self.call(), what, Hresult(self.hresult()))
+    }
+}
+
+impl Error for HostingError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            HostingError::NotInstalled { ref source, .. } | 
+            HostingError::VersionNotFound { ref source, .. } | 
+            HostingError::InterfaceNotSupported { ref source, .. } | 
+            HostingError::RuntimeAlreadyStarted { ref source, .. } | 
+            HostingError::Hresult { ref source, .. } => Some(source),
+        }
+    }
+}
+
+//Lets host-side callbacks, which answer the runtime in HRESULTs, use ? on 
+// the public API
+impl From<HostingError> for HRESULT {
+    fn from(err: HostingError) -> HRESULT {
+        err.hresult()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use winapi::shared::winerror::E_FAIL;
+
+    const START: Call = Call { interface: "ICLRRuntimeHost", method: "Start" };
+
+    #[test]
+    fn classifies_known_codes() {
+        assert_eq!(HostingError::from_hresult(CLR_E_SHIM_RUNTIMELOAD, START), 
+            HostingError::VersionNotFound { call: START, source: Hresult(CLR_E_SHIM_RUNTIMELOAD) });
+        assert_eq!(HostingError::from_hresult(E_NOINTERFACE, START), 
+            HostingError::InterfaceNotSupported { call: START, source: Hresult(E_NOINTERFACE) });
+        assert_eq!(HostingError::from_hresult(HostInvalidState, START), 
+            HostingError::RuntimeAlreadyStarted { call: START, source: Hresult(HostInvalidState) });
+        assert_eq!(HostingError::from_hresult(E_FAIL, START), 
+            HostingError::Hresult { call: START, source: Hresult(E_FAIL) });
+    }
+
+    #[test]
+    fn leaves_ambiguous_codes_generic() {
+        //Neither code means the runtime is running: one is any bad host call, 
+        // the other a legacy shim binding
+        assert_eq!(HostingError::from_hresult(HOST_E_INVALIDOPERATION, START), 
+            HostingError::Hresult { call: START, source: Hresult(HOST_E_INVALIDOPERATION) });
+        assert_eq!(HostingError::from_hresult(CLR_E_SHIM_LEGACYRUNTIMEALREADYBOUND, START), 
+            HostingError::Hresult { call: START, source: Hresult(CLR_E_SHIM_LEGACYRUNTIMEALREADYBOUND) });
+    }
+
+    #[test]
+    fn keeps_call_and_code() {
+        let err = HostingError::from_hresult(HOST_E_INVALIDOPERATION, START);
+        assert_eq!(err.call().to_string(), "ICLRRuntimeHost::Start");
+        assert_eq!(HRESULT::from(err), HOST_E_INVALIDOPERATION);
+    }
+
+    #[test]
+    fn display_names_hosting_codes() {
+        let err = HostingError::from_hresult(HOST_E_CLRNOTAVAILABLE, START);
+        let text = err.to_string();
+        assert!(text.starts_with("ICLRRuntimeHost::Start: call failed: HOST_E_CLRNOTAVAILABLE (0x80131023): "), "{}", text);
+        assert_eq!(Hresult(E_FAIL).name(), None);
+        assert!(Hresult(E_FAIL).to_string().starts_with("HRESULT 0x80004005"));
+    }
+}
@@ -0,0 +1,126 @@
+// errormode.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Keeps unattended hosts from blocking on modal UI: the critical-error and 
+// GP fault boxes raised by the OS, and the WER "stopped working" dialog 
+// raised when managed code crashes during a hosting call.
+use std::env;
+
+use winapi::shared::minwindef::{BOOL, FALSE, UINT};
+use winapi::shared::ntdef::PCWSTR;
+use winapi::shared::winerror::{E_FAIL, HRESULT, HRESULT_FROM_WIN32};
+use winapi::um::errhandlingapi::SetErrorMode;
+use winapi::um::winbase::{SEM_FAILCRITICALERRORS, SEM_NOGPFAULTERRORBOX, SEM_NOOPENFILEERRORBOX};
+
+use buffer::wide;
+use error::HostingError;
+
+#[link(name = "wer")]
+extern "system" {
+    fn WerAddExcludedApplication(pwzExeName: PCWSTR, bAllUsers: BOOL) -> HRESULT;
+    fn WerRemoveExcludedApplication(pwzExeName: PCWSTR, bAllUsers: BOOL) -> HRESULT;
+}
+
+#[derive(Clone, Debug)]
+pub struct DialogSuppression {
+    error_mode: UINT, 
+    exclude_from_wer: bool,
+}
+
+impl DialogSuppression {
+    pub fn new() -> DialogSuppression {
+        DialogSuppression {
+            error_mode: SEM_FAILCRITICALERRORS | SEM_NOGPFAULTERRORBOX | SEM_NOOPENFILEERRORBOX, 
+            exclude_from_wer: false,
+        }
+    }
+
+    //Overrides the SEM_* flags passed to SetErrorMode
+    pub fn error_mode(mut self, mode: UINT) -> DialogSuppression {
+        self.error_mode = mode;
+        self
+    }
+
+    //Opt-in: also exclude the current executable from WER reporting 
+    // for the lifetime of the guard (per-user, not all users).
+    pub fn exclude_from_wer(mut self, exclude: bool) -> DialogSuppression {
+        self.exclude_from_wer = exclude;
+        self
+    }
+
+    pub fn enter(&self) -> Result<ErrorModeGuard, HostingError> {
+        let wer_exe = if self.exclude_from_wer {
+            let exe = env::current_exe().map_err(|err| {
+                let hr = match err.raw_os_error() {
+                    Some(code) => HRESULT_FROM_WIN32(code as u32), 
+                    None => E_FAIL,
+                };
+                HostingError::from_hresult(hr, CALL!(kernel32::GetModuleFileNameW))
+            })?;
+            let path = wide(&exe);
+            CHECK_HR!(wer::WerAddExcludedApplication, WerAddExcludedApplication(path.as_ptr(), FALSE))?;
+            Some(path)
+        } else {
+            None
+        };
+        let previous = unsafe { SetErrorMode(self.error_mode) };
+        Ok(ErrorModeGuard { previous, wer_exe })
+    }
+
+    //Runs f with dialogs suppressed, restoring the previous settings after
+    pub fn run<F, R>(&self, f: F) -> Result<R, HostingError> 
+        where F: FnOnce() -> R
+    {
+        let _guard = self.enter()?;
+        Ok(f())
+    }
+}
+
+impl Default for DialogSuppression {
+    fn default() -> DialogSuppression {
+        DialogSuppression::new()
+    }
+}
+
+//Restores the previous error mode, and removes the WER exclusion if one 
+// was added, when dropped. SetErrorMode is process-wide, so guards should 
+// not be interleaved across threads.
+pub struct ErrorModeGuard {
+    previous: UINT, 
+    wer_exe: Option<Vec<u16>>,
+}
+
+impl ErrorModeGuard {
+    pub fn previous_mode(&self) -> UINT {
+        self.previous
+    }
+}
+
+impl Drop for ErrorModeGuard {
+    fn drop(&mut self) {
+        unsafe { SetErrorMode(self.previous) };
+        if let Some(ref wide) = self.wer_exe {
+            //Nothing useful to do with a failure while dropping
+            let _ = unsafe { WerRemoveExcludedApplication(wide.as_ptr(), FALSE) };
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// errorreporting.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRErrorReportingManager: the Watson bucket for the exception currently 
+// in flight, and host-initiated custom dumps.
+use std::mem;
+use std::ptr;
+
+use winapi::shared::winerror::S_OK;
+
+use mscoree_sys::mscoree::{
+    BucketParameters as RawBucketParameters, 
+    ECustomDumpFlavor, 
+    ICLRErrorReportingManager, 
+    DUMP_FLAVOR_CriticalCLRState, 
+    DUMP_FLAVOR_Mini, 
+    DUMP_FLAVOR_NonHeapCLRState
+};
+
+use control::ClrControl;
+use error::HostingError;
+use wrappers::PtrCtr;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DumpFlavor {
+    Mini, 
+    CriticalClrState, 
+    NonHeapClrState,
+}
+
+impl DumpFlavor {
+    fn raw(self) -> ECustomDumpFlavor {
+        match self {
+            DumpFlavor::Mini => DUMP_FLAVOR_Mini, 
+            DumpFlavor::CriticalClrState => DUMP_FLAVOR_CriticalCLRState, 
+            DumpFlavor::NonHeapClrState => DUMP_FLAVOR_NonHeapCLRState,
+        }
+    }
+}
+
+//Watson bucketing data: the event type (e.g. "CLR20r3") and up to ten 
+// positional parameters, with trailing empty ones dropped
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BucketParameters {
+    pub event_type: String, 
+    pub parameters: Vec<String>,
+}
+
+impl BucketParameters {
+    fn from_raw(raw: &RawBucketParameters) -> BucketParameters {
+        let mut parameters: Vec<String> = raw.pszParams.iter().map(|p| wide_field(p)).collect();
+        while parameters.last().map_or(false, |p| p.is_empty()) {
+            parameters.pop();
+        }
+        BucketParameters { event_type: wide_field(&raw.pszEventTypeName), parameters }
+    }
+}
+
+pub struct ErrorReportingManager {
+    inner: PtrCtr<ICLRErrorReportingManager>,
+}
+
+impl ErrorReportingManager {
+    pub fn new(control: &ClrControl) -> Result<ErrorReportingManager, HostingError> {
+        control.manager::<ICLRErrorReportingManager>().map(|inner| ErrorReportingManager { inner })
+    }
+
+    //None when there is no managed exception on this thread to bucket
+    pub fn bucket_parameters_for_current_exception(&self) -> Result<Option<BucketParameters>, HostingError> {
+        let mut raw: RawBucketParameters = unsafe { mem::zeroed() };
+        let hr = CHECK_HR!(ICLRErrorReportingManager::GetBucketParametersForCurrentException, (*self.inner.as_const()).GetBucketParametersForCurrentException(&mut raw))?;
+        if hr != S_OK || raw.fInited == 0 {
+            return Ok(None);
+        }
+        Ok(Some(BucketParameters::from_raw(&raw)))
+    }
+
+    //The dump is ended when the returned guard drops
+    pub fn begin_custom_dump(&self, flavor: DumpFlavor) -> Result<CustomDump, HostingError> {
+        CHECK_HR!(ICLRErrorReportingManager::BeginCustomDump, (*self.inner.as_const()).BeginCustomDump(flavor.raw(), 0, ptr::null_mut(), 0))?;
+        Ok(CustomDump { manager: self })
+    }
+}
+
+COM_WRAPPER!(ErrorReportingManager);
+
+pub struct CustomDump<'m> {
+    manager: &'m ErrorReportingManager,
+}
+
+impl<'m> CustomDump<'m> {
+    pub fn end(self) -> Result<(), HostingError> {
+        let hr = CHECK_HR!(ICLRErrorReportingManager::EndCustomDump, (*self.manager.inner.as_const()).EndCustomDump());
+        mem::forget(self);
+        hr.map(|_| ())
+    }
+}
+
+impl<'m> Drop for CustomDump<'m> {
+    fn drop(&mut self) {
+        unsafe { (*self.manager.inner.as_const()).EndCustomDump() };
+    }
+}
+
+fn wide_field(field: &[u16]) -> String {
+    let len = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    String::from_utf16_lossy(&field[..len])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fill(field: &mut [u16], s: &str) {
+        for (dst, src) in field.iter_mut().zip(s.encode_utf16()) {
+            *dst = src;
+        }
+    }
+
+    #[test]
+    fn raw_parameters_are_trimmed() {
+        let mut raw: RawBucketParameters = unsafe { mem::zeroed() };
+        raw.fInited = 1;
+        fill(&mut raw.pszEventTypeName, "CLR20r3");
+        fill(&mut raw.pszParams[0], "host.exe");
+        fill(&mut raw.pszParams[2], "System.InvalidOperationException");
+        let params = BucketParameters::from_raw(&raw);
+        assert_eq!(params.event_type, "CLR20r3");
+        assert_eq!(params.parameters, vec![String::from("host.exe"), String::new(), String::from("System.InvalidOperationException")]);
+    }
+}
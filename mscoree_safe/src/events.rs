@@ -0,0 +1,175 @@
+// events.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLROnEventManager: run Rust callbacks when the runtime raises one of 
+// its host events (domain unload, runtime disabled, MDA, stack overflow). 
+// The callback is exposed to the runtime as an IActionOnCLREvent object.
+
+use winapi::shared::ntdef::PVOID;
+use winapi::shared::winerror::HRESULT;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{
+    EClrEvent, 
+    IActionOnCLREvent, 
+    IActionOnCLREventVtbl, 
+    ICLROnEventManager, 
+    MDAInfo, 
+    StackOverflowInfo, 
+    Event_ClrDisabled, 
+    Event_DomainUnload, 
+    Event_MDAFired, 
+    Event_StackOverflow, 
+    SO_ClrEngine, 
+    SO_Managed
+};
+
+use buffer::wide_str;
+use com::ComBox;
+use control::ClrControl;
+use error::HostingError;
+use unwind::catch_and_translate;
+use wrappers::PtrCtr;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ClrEvent {
+    DomainUnload, 
+    ClrDisabled, 
+    MdaFired, 
+    StackOverflow,
+}
+
+impl ClrEvent {
+    fn raw(self) -> EClrEvent {
+        match self {
+            ClrEvent::DomainUnload => Event_DomainUnload, 
+            ClrEvent::ClrDisabled => Event_ClrDisabled, 
+            ClrEvent::MdaFired => Event_MDAFired, 
+            ClrEvent::StackOverflow => Event_StackOverflow,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StackOverflowKind {
+    Managed, 
+    ClrEngine, 
+    Other,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ClrEventData {
+    DomainUnload(u32), 
+    ClrDisabled, 
+    MdaFired { caption: String, message: String }, 
+    StackOverflow(StackOverflowKind), 
+    Unknown(EClrEvent),
+}
+
+impl ClrEventData {
+    unsafe fn decode(event: EClrEvent, data: PVOID) -> ClrEventData {
+        if event == Event_DomainUnload {
+            //The domain id is passed in the pointer itself
+            ClrEventData::DomainUnload(data as usize as u32)
+        } else if event == Event_ClrDisabled {
+            ClrEventData::ClrDisabled
+        } else if event == Event_MDAFired && !data.is_null() {
+            let info = &*(data as *const MDAInfo);
+            ClrEventData::MdaFired { caption: wide_str(info.lpMDACaption), message: wide_str(info.lpMDAMessage) }
+        } else if event == Event_StackOverflow && !data.is_null() {
+            let info = &*(data as *const StackOverflowInfo);
+            ClrEventData::StackOverflow(match info.soType {
+                t if t == SO_Managed => StackOverflowKind::Managed, 
+                t if t == SO_ClrEngine => StackOverflowKind::ClrEngine, 
+                _ => StackOverflowKind::Other,
+            })
+        } else {
+            ClrEventData::Unknown(event)
+        }
+    }
+}
+
+type Action = Box<dyn Fn(ClrEventData) + Send + Sync>;
+type ActionObject = ComBox<IActionOnCLREventVtbl, Action>;
+
+unsafe extern "system" fn on_event(this: *mut IActionOnCLREvent, event: EClrEvent, data: PVOID) -> HRESULT {
+    let object = ActionObject::from_this(this);
+    let decoded = ClrEventData::decode(event, data);
+    catch_and_translate(|| {
+        (object.value)(decoded);
+        Ok(())
+    })
+}
+
+pub struct EventManager {
+    inner: PtrCtr<ICLROnEventManager>,
+}
+
+impl EventManager {
+    pub fn new(control: &ClrControl) -> Result<EventManager, HostingError> {
+        control.manager::<ICLROnEventManager>().map(|inner| EventManager { inner })
+    }
+
+    //The callback may run on any runtime thread, including while the 
+    // runtime is being torn down, so it should do as little as possible
+    pub fn register<F>(&self, event: ClrEvent, action: F) -> Result<EventRegistration, HostingError> 
+        where F: Fn(ClrEventData) + Send + Sync + 'static
+    {
+        let vtable = IActionOnCLREventVtbl {
+            parent: ActionObject::unknown_vtbl(), 
+            OnEvent: on_event,
+        };
+        let raw = ActionObject::new(vtable, vec![IActionOnCLREvent::uuidof()], Box::new(action));
+        let hr = CHECK_HR!(ICLROnEventManager::RegisterActionOnEvent, (*self.inner.as_const()).RegisterActionOnEvent(event.raw(), ActionObject::as_interface(raw)));
+        match hr {
+            Ok(_) => Ok(EventRegistration { manager: self, event, action: raw }), 
+            Err(hr) => {
+                unsafe { ActionObject::release(raw) };
+                Err(hr)
+            }
+        }
+    }
+}
+
+COM_WRAPPER!(EventManager);
+
+//Unregisters the callback when dropped
+pub struct EventRegistration<'m> {
+    manager: &'m EventManager, 
+    event: ClrEvent, 
+    action: *mut ActionObject,
+}
+
+impl<'m> EventRegistration<'m> {
+    pub fn event(&self) -> ClrEvent {
+        self.event
+    }
+}
+
+impl<'m> Drop for EventRegistration<'m> {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.manager.inner.as_const()).UnRegisterActionOnEvent(self.event.raw(), ActionObject::as_interface(self.action));
+            ActionObject::release(self.action);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// exception.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Managed exceptions behind failed calls. When managed code throws across 
+// a COM boundary the CCW leaves the exception object behind as the 
+// thread's IErrorInfo; capture() picks it up and reads it into plain 
+// data, so the message and stack survive past the call that failed.
+use std::error::Error;
+use std::fmt;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::oaidl::IErrorInfo;
+use winapi::um::oleauto::GetErrorInfo;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscorlib_sys::system::_Exception;
+
+use error::HostingError;
+use reflection::ManagedObject;
+use variant::{ClrObject, ClrValue};
+
+//Deeper chains than this are cut off rather than followed
+const MAX_DEPTH: usize = 16;
+
+const TARGET_INVOCATION: &str = "System.Reflection.TargetInvocationException";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManagedException {
+    //Full name, e.g. "System.IO.FileNotFoundException"
+    pub type_name: String, 
+    pub message: String, 
+    pub hresult: HRESULT, 
+    pub stack_trace: Option<String>, 
+    pub inner: Option<Box<ManagedException>>,
+}
+
+impl ManagedException {
+    //Takes the calling thread's pending exception, if the last failed call 
+    // left one. InvokeMember wraps whatever the target threw in a 
+    // TargetInvocationException; that wrapper is dropped in favour of 
+    // the exception underneath.
+    pub fn capture() -> Option<ManagedException> {
+        let mut info: *mut IErrorInfo = ptr::null_mut();
+        let hr = unsafe { GetErrorInfo(0, &mut info) };
+        if hr != S_OK || info.is_null() {
+            return None;
+        }
+        let mut exception: *mut _Exception = ptr::null_mut();
+        let hr = unsafe {
+            (*info).QueryInterface(&_Exception::uuidof(), &mut exception as *mut *mut _Exception as *mut *mut c_void)
+        };
+        unsafe { (*info).Release() };
+        if hr != S_OK || exception.is_null() {
+            return None;
+        }
+        let object = ClrObject::from_borrowed(exception as *mut IUnknown);
+        unsafe { (*exception).Release() };
+        let captured = ManagedException::from_object(&ManagedObject::from_value(ClrValue::Object(object.ok()?)).ok()?).ok()?;
+        Some(captured.unwrap_invocation())
+    }
+
+    pub fn from_object(exception: &ManagedObject) -> Result<ManagedException, HostingError> {
+        read(exception, 0)
+    }
+
+    //The innermost exception, usually where the problem started
+    pub fn root_cause(&self) -> &ManagedException {
+        let mut current = self;
+        while let Some(ref inner) = current.inner {
+            current = inner;
+        }
+        current
+    }
+
+    pub fn chain(&self) -> Chain {
+        Chain { next: Some(self) }
+    }
+
+    fn unwrap_invocation(self) -> ManagedException {
+        match self.inner {
+            Some(inner) if self.type_name == TARGET_INVOCATION => *inner, 
+            inner => ManagedException { inner, ..self },
+        }
+    }
+}
+
+impl fmt::Display for ManagedException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.type_name, self.message)
+    }
+}
+
+impl Error for ManagedException {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        self.inner.as_ref().map(|inner| &**inner as &(Error + 'static))
+    }
+}
+
+//The exception followed by its inner exceptions, outermost first
+pub struct Chain<'e> {
+    next: Option<&'e ManagedException>,
+}
+
+impl<'e> Iterator for Chain<'e> {
+    type Item = &'e ManagedException;
+
+    fn next(&mut self) -> Option<&'e ManagedException> {
+        let current = self.next.take()?;
+        self.next = current.inner.as_ref().map(|inner| &**inner);
+        Some(current)
+    }
+}
+
+fn read(exception: &ManagedObject, depth: usize) -> Result<ManagedException, HostingError> {
+    let type_name = exception.managed_type().full_name()?;
+    let message = text(exception.property("Message")?);
+    //HResult only became public in .NET 4.5
+    let hresult = match exception.property("HResult") {
+        Ok(ClrValue::I4(hr)) => hr, 
+        _ => 0,
+    };
+    let stack_trace = match exception.property("StackTrace")? {
+        ClrValue::String(trace) => Some(trace), 
+        _ => None,
+    };
+    let inner = match exception.property("InnerException")? {
+        ClrValue::Null => None, 
+        _ if depth + 1 >= MAX_DEPTH => None, 
+        value => Some(Box::new(read(&ManagedObject::from_value(value)?, depth + 1)?)),
+    };
+    Ok(ManagedException { type_name, message, hresult, stack_trace, inner })
+}
+
+fn text(value: ClrValue) -> String {
+    match value {
+        ClrValue::String(s) => s, 
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use corhost::CorRuntimeHost;
+    use metahost::{MetaHost, MetaHostImpl, RuntimeVersion};
+
+    fn exception(type_name: &str, inner: Option<ManagedException>) -> ManagedException {
+        ManagedException {
+            type_name: type_name.to_string(), 
+            message: String::new(), 
+            hresult: 0, 
+            stack_trace: None, 
+            inner: inner.map(Box::new),
+        }
+    }
+
+    #[test]
+    fn invocation_wrapper_is_dropped() {
+        let thrown = exception("System.IO.IOException", Some(exception("System.UnauthorizedAccessException", None)));
+        let wrapped = exception(TARGET_INVOCATION, Some(thrown.clone()));
+        assert_eq!(wrapped.unwrap_invocation(), thrown);
+        assert_eq!(thrown.root_cause().type_name, "System.UnauthorizedAccessException");
+        assert_eq!(thrown.chain().count(), 2);
+    }
+
+    //Starts the installed v4 runtime, like host::test::load_test
+    #[test]
+    fn captures_through_invoke_member() {
+        let metahost = MetaHostImpl::create().unwrap();
+        let runtime = metahost.runtime(RuntimeVersion::V4).unwrap();
+        let host = CorRuntimeHost::new(&*runtime).unwrap();
+        host.start().unwrap();
+        let mscorlib = host.default_domain().unwrap().load("mscorlib").unwrap();
+
+        //InvokeMember reports what Parse threw wrapped in a TargetInvocationException
+        let err = mscorlib.get_type("System.Int32").unwrap()
+            .invoke_static("Parse", &[ClrValue::from("x")])
+            .unwrap_err();
+        let thrown = err.exception.expect("the thrown exception is captured");
+        assert_eq!(thrown.type_name, "System.FormatException");
+        assert!(thrown.stack_trace.is_some());
+
+        //A chain built in managed code: only the outermost wrapper goes
+        let root = mscorlib.create_instance("System.FormatException").unwrap();
+        let middle = mscorlib.get_type("System.InvalidOperationException").unwrap()
+            .create_instance(&[ClrValue::from("middle"), root.to_value()])
+            .unwrap();
+        let wrapper = mscorlib.get_type(TARGET_INVOCATION).unwrap()
+            .create_instance(&[middle.to_value()])
+            .unwrap();
+        let read = ManagedException::from_object(&wrapper).unwrap().unwrap_invocation();
+        assert_eq!(read.type_name, "System.InvalidOperationException");
+        assert_eq!(read.message, "middle");
+        assert_eq!(read.root_cause().type_name, "System.FormatException");
+        assert_eq!(read.chain().count(), 2);
+    }
+}
@@ -0,0 +1,151 @@
+// framework.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Which 4.x release of the .NET Framework is installed. Every 4.x release 
+// replaces the same v4.0.30319 runtime in place, so GetVersionString can't 
+// tell them apart; the setup key's Release value can.
+use std::fmt;
+use std::ptr;
+
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::winerror::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, HRESULT_FROM_WIN32};
+use winapi::um::winreg::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD};
+
+use buffer::wide;
+use error::HostingError;
+
+const NDP_V4_FULL: &str = "SOFTWARE\\Microsoft\\NET Framework Setup\\NDP\\v4\\Full";
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FrameworkRelease {
+    Net45, 
+    Net451, 
+    Net452, 
+    Net46, 
+    Net461, 
+    Net462, 
+    Net47, 
+    Net471, 
+    Net472, 
+    Net48, 
+    Net481,
+}
+
+//Smallest Release value each version installs with. Values differ by OS 
+// (Windows 10 updates ship their own), so lookups go by range.
+const RELEASES: &[(DWORD, FrameworkRelease)] = &[
+    (378389, FrameworkRelease::Net45), 
+    (378675, FrameworkRelease::Net451), 
+    (379893, FrameworkRelease::Net452), 
+    (393295, FrameworkRelease::Net46), 
+    (394254, FrameworkRelease::Net461), 
+    (394802, FrameworkRelease::Net462), 
+    (460798, FrameworkRelease::Net47), 
+    (461308, FrameworkRelease::Net471), 
+    (461808, FrameworkRelease::Net472), 
+    (528040, FrameworkRelease::Net48), 
+    (533320, FrameworkRelease::Net481),
+];
+
+impl FrameworkRelease {
+    //None below 4.5, which didn't write a Release value. Anything newer 
+    // than the last known release maps to it.
+    pub fn from_release_key(release: DWORD) -> Option<FrameworkRelease> {
+        RELEASES.iter()
+            .take_while(|&&(min, _)| min <= release)
+            .last()
+            .map(|&(_, version)| version)
+    }
+
+    pub fn min_release_key(&self) -> DWORD {
+        RELEASES.iter()
+            .find(|&&(_, version)| version == *self)
+            .map(|&(min, _)| min)
+            .expect("every release is listed")
+    }
+}
+
+impl fmt::Display for FrameworkRelease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            FrameworkRelease::Net45 => "4.5", 
+            FrameworkRelease::Net451 => "4.5.1", 
+            FrameworkRelease::Net452 => "4.5.2", 
+            FrameworkRelease::Net46 => "4.6", 
+            FrameworkRelease::Net461 => "4.6.1", 
+            FrameworkRelease::Net462 => "4.6.2", 
+            FrameworkRelease::Net47 => "4.7", 
+            FrameworkRelease::Net471 => "4.7.1", 
+            FrameworkRelease::Net472 => "4.7.2", 
+            FrameworkRelease::Net48 => "4.8", 
+            FrameworkRelease::Net481 => "4.8.1",
+        };
+        f.write_str(s)
+    }
+}
+
+//The raw Release DWORD, None when 4.5 or later isn't installed
+pub fn framework_release_key() -> Result<Option<DWORD>, HostingError> {
+    let subkey = wide(NDP_V4_FULL);
+    let value = wide("Release");
+    let mut release: DWORD = 0;
+    let mut size = ::std::mem::size_of::<DWORD>() as DWORD;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE, 
+            subkey.as_ptr(), 
+            value.as_ptr(), 
+            RRF_RT_REG_DWORD, 
+            ptr::null_mut(), 
+            &mut release as *mut DWORD as LPVOID, 
+            &mut size
+        )
+    };
+    match status as DWORD {
+        ERROR_SUCCESS => Ok(Some(release)), 
+        ERROR_FILE_NOT_FOUND => Ok(None), 
+        code => Err(HostingError::from_hresult(HRESULT_FROM_WIN32(code), CALL!(advapi32::RegGetValueW))),
+    }
+}
+
+//Hosts that need e.g. 4.7.2 compare against it: 
+// framework_release()? >= Some(FrameworkRelease::Net472)
+pub fn framework_release() -> Result<Option<FrameworkRelease>, HostingError> {
+    framework_release_key().map(|key| key.and_then(FrameworkRelease::from_release_key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_release_keys_by_range() {
+        assert_eq!(FrameworkRelease::from_release_key(378388), None);
+        assert_eq!(FrameworkRelease::from_release_key(378389), Some(FrameworkRelease::Net45));
+        assert_eq!(FrameworkRelease::from_release_key(461814), Some(FrameworkRelease::Net472));
+        assert_eq!(FrameworkRelease::from_release_key(528449), Some(FrameworkRelease::Net48));
+        assert_eq!(FrameworkRelease::from_release_key(999999), Some(FrameworkRelease::Net481));
+        assert!(FrameworkRelease::Net48 > FrameworkRelease::Net472);
+        assert_eq!(FrameworkRelease::Net462.min_release_key(), 394802);
+        assert_eq!(FrameworkRelease::Net472.to_string(), "4.7.2");
+    }
+}
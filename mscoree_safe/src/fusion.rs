@@ -0,0 +1,294 @@
+// fusion.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//The global assembly cache through fusion.dll. fusion.dll sits in the 
+// runtime directory, so it's loaded with LoadLibraryShim and its two 
+// factory functions resolved by hand. Identities come back as 
+// AssemblyIdentity, which wraps the IAssemblyName fusion hands out and 
+// converts to the plain assembly::AssemblyName for comparisons.
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+use winapi::shared::minwindef::{DWORD, HMODULE, LPVOID};
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, HRESULT_FROM_WIN32, S_OK};
+use winapi::um::libloaderapi::GetProcAddress;
+
+use mscoree_sys::fusion::{
+    ASM_CACHE_GAC, 
+    ASM_CMPF_ALL, 
+    ASM_DISPLAYF_CULTURE, 
+    ASM_DISPLAYF_FULL, 
+    ASM_DISPLAYF_PUBLIC_KEY_TOKEN, 
+    ASM_DISPLAYF_VERSION, 
+    ASM_NAME_CULTURE, 
+    ASM_NAME_PUBLIC_KEY_TOKEN, 
+    CANOF_PARSE_DISPLAY_NAME, 
+    CreateAssemblyEnumFn, 
+    CreateAssemblyNameObjectFn, 
+    IAssemblyEnum, 
+    IAssemblyName,
+};
+use mscoree_sys::mscoree::LoadLibraryShim;
+
+use assembly::{AssemblyName, AssemblyVersion};
+use buffer::wide;
+use comptr::ComPtr;
+use error::{Call, HostingError, last_error};
+use metahost::RuntimeVersion;
+
+pub struct Fusion {
+    create_enum: CreateAssemblyEnumFn, 
+    create_name: CreateAssemblyNameObjectFn,
+}
+
+impl Fusion {
+    //fusion.dll of the runtime already in the process, or the latest installed one
+    pub fn load() -> Result<Fusion, HostingError> {
+        Fusion::load_shim(None)
+    }
+
+    pub fn load_for(version: &RuntimeVersion) -> Result<Fusion, HostingError> {
+        Fusion::load_shim(Some(version))
+    }
+
+    fn load_shim(version: Option<&RuntimeVersion>) -> Result<Fusion, HostingError> {
+        let name = wide("fusion.dll");
+        let version = version.map(|v| wide(&v.to_string()));
+        let mut module: HMODULE = ptr::null_mut();
+        let hr = unsafe {
+            LoadLibraryShim(name.as_ptr(), version.as_ref().map_or(ptr::null(), |v| v.as_ptr()), ptr::null_mut(), &mut module)
+        };
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(mscoree::LoadLibraryShim)));
+        }
+        unsafe {
+            Ok(Fusion {
+                create_enum: mem::transmute(symbol(module, "CreateAssemblyEnum\0", CALL!(fusion::CreateAssemblyEnum))?), 
+                create_name: mem::transmute(symbol(module, "CreateAssemblyNameObject\0", CALL!(fusion::CreateAssemblyNameObject))?),
+            })
+        }
+    }
+
+    //Everything in the GAC, or only what matches a partial display name 
+    // such as "System.Data" or "System.Data, Version=4.0.0.0"
+    pub fn gac_assemblies(&self, partial_name: Option<&str>) -> Result<GacAssemblies, HostingError> {
+        let filter = match partial_name {
+            Some(name) => Some(self.name_object(name)?), 
+            None => None,
+        };
+        let filter = filter.as_ref().map_or(ptr::null_mut(), |name| name.as_raw());
+        let create_enum = self.create_enum;
+        let mut p: *mut IAssemblyEnum = ptr::null_mut();
+        let hr = create_enum(&mut p, ptr::null_mut(), filter, ASM_CACHE_GAC, ptr::null_mut());
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(fusion::CreateAssemblyEnum)));
+        }
+        //S_FALSE and a null enumerator mean nothing matched
+        Ok(GacAssemblies { enumerator: unsafe { ComPtr::from_raw(p) } })
+    }
+
+    //Parses a display name the way the loader does, e.g. 
+    // "System.Data, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089"
+    pub fn parse(&self, display_name: &str) -> Result<AssemblyIdentity, HostingError> {
+        self.name_object(display_name).map(|inner| AssemblyIdentity { inner })
+    }
+
+    pub(crate) fn name_object(&self, display_name: &str) -> Result<ComPtr<IAssemblyName>, HostingError> {
+        let display_name = wide(display_name);
+        let create_name = self.create_name;
+        unsafe {
+            ComPtr::from_out(CALL!(fusion::CreateAssemblyNameObject), |p: *mut *mut IAssemblyName| {
+                create_name(p, display_name.as_ptr(), CANOF_PARSE_DISPLAY_NAME, ptr::null_mut())
+            })
+        }
+    }
+}
+
+//Lazy: each assembly is fetched from the enumerator as it's asked for
+pub struct GacAssemblies {
+    enumerator: Option<ComPtr<IAssemblyEnum>>,
+}
+
+impl Iterator for GacAssemblies {
+    type Item = AssemblyIdentity;
+
+    fn next(&mut self) -> Option<AssemblyIdentity> {
+        let next = match self.enumerator {
+            Some(ref enumerator) => {
+                let mut p: *mut IAssemblyName = ptr::null_mut();
+                let hr = unsafe { enumerator.GetNextAssembly(ptr::null_mut(), &mut p, 0) };
+                if hr == S_OK { unsafe { ComPtr::from_raw(p) } } else { None }
+            }, 
+            None => return None,
+        };
+        match next {
+            Some(inner) => Some(AssemblyIdentity { inner }), 
+            None => {
+                self.enumerator = None;
+                None
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AssemblyIdentity {
+    inner: ComPtr<IAssemblyName>,
+}
+
+impl AssemblyIdentity {
+    //Shorthand for Fusion::load()?.parse(..)
+    pub fn parse(display_name: &str) -> Result<AssemblyIdentity, HostingError> {
+        Fusion::load()?.parse(display_name)
+    }
+
+    pub fn name(&self) -> Result<String, HostingError> {
+        let mut len: DWORD = 0;
+        let _ = unsafe { self.inner.GetName(&mut len, ptr::null_mut()) };
+        let mut buffer = vec![0u16; len as usize];
+        let hr = unsafe { self.inner.GetName(&mut len, buffer.as_mut_ptr()) };
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(IAssemblyName::GetName)));
+        }
+        Ok(from_wide(&buffer))
+    }
+
+    //None for names without a version, e.g. partial references
+    pub fn version(&self) -> Option<AssemblyVersion> {
+        let (mut hi, mut lo): (DWORD, DWORD) = (0, 0);
+        let hr = unsafe { self.inner.GetVersion(&mut hi, &mut lo) };
+        if hr != S_OK {
+            return None;
+        }
+        Some(AssemblyVersion::new((hi >> 16) as u16, hi as u16, (lo >> 16) as u16, lo as u16))
+    }
+
+    //None for culture-neutral assemblies
+    pub fn culture(&self) -> Result<Option<String>, HostingError> {
+        let buffer = self.property(ASM_NAME_CULTURE)?;
+        let wchars: Vec<u16> = buffer.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        let culture = from_wide(&wchars);
+        Ok(if culture.is_empty() { None } else { Some(culture) })
+    }
+
+    pub fn public_key_token(&self) -> Result<Option<[u8; 8]>, HostingError> {
+        let buffer = self.property(ASM_NAME_PUBLIC_KEY_TOKEN)?;
+        if buffer.len() != 8 {
+            return Ok(None);
+        }
+        let mut token = [0u8; 8];
+        token.copy_from_slice(&buffer);
+        Ok(Some(token))
+    }
+
+    //Full display name, processor architecture and retargeting included
+    pub fn display_name(&self) -> Result<String, HostingError> {
+        self.display_name_with(ASM_DISPLAYF_FULL)
+    }
+
+    //flags is a combination of the ASM_DISPLAYF_* values
+    pub fn display_name_with(&self, flags: DWORD) -> Result<String, HostingError> {
+        let mut len: DWORD = 0;
+        let hr = unsafe { self.inner.GetDisplayName(ptr::null_mut(), &mut len, flags) };
+        if hr != HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) && hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(IAssemblyName::GetDisplayName)));
+        }
+        let mut buffer = vec![0u16; len as usize];
+        let hr = unsafe { self.inner.GetDisplayName(buffer.as_mut_ptr(), &mut len, flags) };
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(IAssemblyName::GetDisplayName)));
+        }
+        Ok(from_wide(&buffer))
+    }
+
+    //Name, version, culture and token in fusion's own spelling, so two 
+    // display names for the same assembly come out identical
+    pub fn canonical_name(&self) -> Result<String, HostingError> {
+        self.display_name_with(ASM_DISPLAYF_VERSION | ASM_DISPLAYF_CULTURE | ASM_DISPLAYF_PUBLIC_KEY_TOKEN)
+    }
+
+    //IAssemblyName::IsEqual; flags is a combination of the ASM_CMPF_* values 
+    // naming the parts that have to agree
+    pub fn matches(&self, other: &AssemblyIdentity, flags: DWORD) -> bool {
+        unsafe { self.inner.IsEqual(other.as_raw(), flags) == S_OK }
+    }
+
+    //The plain-data identity, for comparisons and hashing
+    pub fn to_assembly_name(&self) -> Result<AssemblyName, HostingError> {
+        let mut name = AssemblyName::new(&self.name()?);
+        name.version = self.version();
+        name.culture = Some(self.culture()?.unwrap_or_default());
+        name.public_key_token = self.public_key_token()?;
+        Ok(name)
+    }
+
+    pub fn as_raw(&self) -> *mut IAssemblyName {
+        self.inner.as_raw()
+    }
+
+    //Raw property bytes; unset properties come back empty
+    fn property(&self, id: DWORD) -> Result<Vec<u8>, HostingError> {
+        let mut len: DWORD = 0;
+        let hr = unsafe { self.inner.GetProperty(id, ptr::null_mut(), &mut len) };
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if hr != HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) && hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(IAssemblyName::GetProperty)));
+        }
+        let mut buffer = vec![0u8; len as usize];
+        let hr = unsafe { self.inner.GetProperty(id, buffer.as_mut_ptr() as LPVOID, &mut len) };
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(IAssemblyName::GetProperty)));
+        }
+        buffer.truncate(len as usize);
+        Ok(buffer)
+    }
+}
+
+impl PartialEq for AssemblyIdentity {
+    fn eq(&self, other: &AssemblyIdentity) -> bool {
+        self.matches(other, ASM_CMPF_ALL)
+    }
+}
+
+impl fmt::Display for AssemblyIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.display_name().map_err(|_| fmt::Error)?;
+        write!(f, "{}", name)
+    }
+}
+
+fn from_wide(buffer: &[u16]) -> String {
+    let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..end])
+}
+
+//name must be NUL-terminated
+fn symbol(module: HMODULE, name: &str, call: Call) -> Result<LPVOID, HostingError> {
+    let proc = unsafe { GetProcAddress(module, name.as_ptr() as *const i8) };
+    if proc.is_null() {
+        return Err(HostingError::from_hresult(last_error(), call));
+    }
+    Ok(proc as LPVOID)
+}
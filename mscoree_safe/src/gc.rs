@@ -0,0 +1,205 @@
+// gc.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRGCManager: trigger collections, read statistics and set the segment 
+// and gen0 budgets. Startup limits only take effect before Start. The 
+// pointer-sized limits of ICLRGCManager2 (v4+) are picked up when the 
+// runtime offers them.
+use std::mem;
+use std::thread;
+use std::time::Duration;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::E_NOINTERFACE;
+
+use mscoree_sys::gchost::{COR_GC_COUNTS, COR_GC_MEMORYUSAGE, COR_GC_STATS};
+use mscoree_sys::mscoree::{ICLRGCManager, ICLRGCManager2};
+
+use comptr::ComPtr;
+use control::ClrControl;
+use error::HostingError;
+use wrappers::PtrCtr;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Generation {
+    Gen0, 
+    Gen1, 
+    Gen2, 
+    //Every generation, same as GC.Collect()
+    All,
+}
+
+impl Generation {
+    pub(crate) fn raw(self) -> i32 {
+        match self {
+            Generation::Gen0 => 0, 
+            Generation::Gen1 => 1, 
+            Generation::Gen2 => 2, 
+            Generation::All => -1,
+        }
+    }
+}
+
+//COR_GC_STATS decoded, with sizes converted from the KB the runtime 
+// reports to bytes
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct GcStats {
+    pub gen0_collections: usize, 
+    pub gen1_collections: usize, 
+    pub gen2_collections: usize, 
+    //GC.Collect() and friends, included in the per-generation counts
+    pub explicit_collections: usize, 
+    pub committed: u64, 
+    pub reserved: u64, 
+    //All generations plus the large object heap
+    pub heap_size: u64,
+}
+
+impl From<COR_GC_STATS> for GcStats {
+    fn from(stats: COR_GC_STATS) -> GcStats {
+        let bytes = |kb: usize| kb as u64 * 1024;
+        GcStats {
+            gen0_collections: stats.GenCollectionsTaken[0], 
+            gen1_collections: stats.GenCollectionsTaken[1], 
+            gen2_collections: stats.GenCollectionsTaken[2], 
+            explicit_collections: stats.ExplicitGCCount, 
+            committed: bytes(stats.CommittedKBytes), 
+            reserved: bytes(stats.ReservedKBytes), 
+            heap_size: bytes(stats.Gen0HeapSizeKBytes) 
+                + bytes(stats.Gen1HeapSizeKBytes) 
+                + bytes(stats.Gen2HeapSizeKBytes) 
+                + bytes(stats.LargeObjectHeapSizeKBytes),
+        }
+    }
+}
+
+pub struct GcManager {
+    inner: PtrCtr<ICLRGCManager>,
+}
+
+impl GcManager {
+    pub fn new(control: &ClrControl) -> Result<GcManager, HostingError> {
+        control.manager::<ICLRGCManager>().map(|inner| GcManager { inner })
+    }
+
+    pub fn collect(&self, generation: Generation) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRGCManager::Collect, (*self.inner.as_const()).Collect(generation.raw())).map(|_| ())
+    }
+
+    //Both collection counts and memory usage
+    pub fn get_stats(&self) -> Result<COR_GC_STATS, HostingError> {
+        let mut stats: COR_GC_STATS = unsafe { mem::zeroed() };
+        stats.Flags = COR_GC_COUNTS | COR_GC_MEMORYUSAGE;
+        CHECK_HR!(ICLRGCManager::GetStats, (*self.inner.as_const()).GetStats(&mut stats))?;
+        Ok(stats)
+    }
+
+    pub fn stats(&self) -> Result<GcStats, HostingError> {
+        self.get_stats().map(GcStats::from)
+    }
+
+    //Endless: one sample straight away, then one per interval. The 
+    // sleeping happens in next(), so take() or a break ends it.
+    pub fn poll(&self, interval: Duration) -> GcStatsPoll {
+        GcStatsPoll { manager: self, interval, first: true }
+    }
+
+    //Sizes in bytes; the segment size must be a multiple of 1MB and at 
+    // least 4MB. Zero keeps the runtime default.
+    pub fn set_gc_startup_limits(&self, segment_size: DWORD, gen0_size: DWORD) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRGCManager::SetGCStartupLimits, (*self.inner.as_const()).SetGCStartupLimits(segment_size, gen0_size)).map(|_| ())
+    }
+
+    //Same rules, but sizes above 4GB fit on 64-bit. E_NOINTERFACE on 
+    // runtimes without ICLRGCManager2.
+    pub fn set_gc_startup_limits_ex(&self, segment_size: usize, gen0_size: usize) -> Result<(), HostingError> {
+        let v2 = self.v2().ok_or_else(|| HostingError::from_hresult(E_NOINTERFACE, CALL!(ICLRGCManager2::SetGCStartupLimitsEx)))?;
+        CHECK_HR!(ICLRGCManager2::SetGCStartupLimitsEx, v2.SetGCStartupLimitsEx(segment_size, gen0_size)).map(|_| ())
+    }
+
+    pub fn supports_large_limits(&self) -> bool {
+        self.v2().is_some()
+    }
+
+    //Whichever call fits: the DWORD one when both sizes allow it, so 
+    // pre-v4 runtimes keep working for small limits
+    pub fn set_gc_startup_limits_any(&self, segment_size: usize, gen0_size: usize) -> Result<(), HostingError> {
+        let max = DWORD::max_value() as usize;
+        if segment_size <= max && gen0_size <= max {
+            self.set_gc_startup_limits(segment_size as DWORD, gen0_size as DWORD)
+        } else {
+            self.set_gc_startup_limits_ex(segment_size, gen0_size)
+        }
+    }
+
+    //Asked for on each use rather than held, so GcManager stays one reference
+    fn v2(&self) -> Option<ComPtr<ICLRGCManager2>> {
+        let v1 = unsafe { ComPtr::from_borrowed(self.inner.as_const() as *mut ICLRGCManager) }?;
+        v1.query_interface::<ICLRGCManager2>().ok()
+    }
+}
+
+COM_WRAPPER!(GcManager);
+
+pub struct GcStatsPoll<'m> {
+    manager: &'m GcManager, 
+    interval: Duration, 
+    first: bool,
+}
+
+impl<'m> Iterator for GcStatsPoll<'m> {
+    type Item = Result<GcStats, HostingError>;
+
+    fn next(&mut self) -> Option<Result<GcStats, HostingError>> {
+        if self.first {
+            self.first = false;
+        } else {
+            thread::sleep(self.interval);
+        }
+        Some(self.manager.stats())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stats_are_decoded_into_bytes() {
+        let mut raw: COR_GC_STATS = unsafe { mem::zeroed() };
+        raw.GenCollectionsTaken = [7, 3, 1];
+        raw.ExplicitGCCount = 2;
+        raw.CommittedKBytes = 4;
+        raw.ReservedKBytes = 16;
+        raw.Gen0HeapSizeKBytes = 1;
+        raw.Gen1HeapSizeKBytes = 1;
+        raw.Gen2HeapSizeKBytes = 1;
+        raw.LargeObjectHeapSizeKBytes = 1;
+        let stats = GcStats::from(raw);
+        assert_eq!(stats.gen0_collections, 7);
+        assert_eq!(stats.gen2_collections, 1);
+        assert_eq!(stats.explicit_collections, 2);
+        assert_eq!(stats.committed, 4096);
+        assert_eq!(stats.reserved, 16384);
+        assert_eq!(stats.heap_size, 4096);
+    }
+}
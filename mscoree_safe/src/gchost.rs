@@ -0,0 +1,107 @@
+// gchost.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IGCHost/IGCHost2, the pre-ICLRControl GC control surface reached through 
+// ICorRuntimeHost. Still the only way to get per-thread allocation stats 
+// and to cap virtual memory.
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{E_NOINTERFACE, S_OK};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::gchost::{
+    COR_GC_COUNTS, 
+    COR_GC_MEMORYUSAGE, 
+    COR_GC_STATS, 
+    COR_GC_THREAD_STATS, 
+    IGCHost, 
+    IGCHost2
+};
+
+use error::HostingError;
+use gc::Generation;
+use wrappers::PtrCtr;
+
+pub struct GcHost {
+    inner: PtrCtr<IGCHost>, 
+    v2: Option<PtrCtr<IGCHost2>>,
+}
+
+impl GcHost {
+    //QueryInterface off the runtime host; the caller keeps its reference
+    pub(crate) fn from_unknown(unk: *mut IUnknown) -> Result<GcHost, HostingError> {
+        let mut p: *mut IGCHost = ptr::null_mut();
+        CHECK_HR!(IUnknown::QueryInterface, (*unk).QueryInterface(&IGCHost::uuidof(), &mut p as *mut *mut IGCHost as *mut *mut c_void))?;
+        let inner = PtrCtr::new_checked(p).map_err(|_| HostingError::null_pointer(CALL!(IUnknown::QueryInterface)))?;
+        let mut p2: *mut IGCHost2 = ptr::null_mut();
+        let hr = unsafe { (*unk).QueryInterface(&IGCHost2::uuidof(), &mut p2 as *mut *mut IGCHost2 as *mut *mut c_void) };
+        let v2 = if hr == S_OK { PtrCtr::new_checked(p2).ok() } else { None };
+        Ok(GcHost { inner, v2 })
+    }
+
+    pub fn collect(&self, generation: Generation) -> Result<(), HostingError> {
+        CHECK_HR!(IGCHost::Collect, (*self.inner.as_const()).Collect(generation.raw())).map(|_| ())
+    }
+
+    pub fn get_stats(&self) -> Result<COR_GC_STATS, HostingError> {
+        let mut stats: COR_GC_STATS = unsafe { mem::zeroed() };
+        stats.Flags = COR_GC_COUNTS | COR_GC_MEMORYUSAGE;
+        CHECK_HR!(IGCHost::GetStats, (*self.inner.as_const()).GetStats(&mut stats))?;
+        Ok(stats)
+    }
+
+    //Stats for the calling thread
+    pub fn thread_stats(&self) -> Result<COR_GC_THREAD_STATS, HostingError> {
+        let mut stats: COR_GC_THREAD_STATS = unsafe { mem::zeroed() };
+        CHECK_HR!(IGCHost::GetThreadStats, (*self.inner.as_const()).GetThreadStats(ptr::null_mut(), &mut stats))?;
+        Ok(stats)
+    }
+
+    pub fn set_gc_startup_limits(&self, segment_size: DWORD, gen0_size: DWORD) -> Result<(), HostingError> {
+        CHECK_HR!(IGCHost::SetGCStartupLimits, (*self.inner.as_const()).SetGCStartupLimits(segment_size, gen0_size)).map(|_| ())
+    }
+
+    //IGCHost2 only; E_NOINTERFACE on runtimes that don't provide it
+    pub fn set_gc_startup_limits_ex(&self, segment_size: usize, gen0_size: usize) -> Result<(), HostingError> {
+        match self.v2 {
+            Some(ref v2) => CHECK_HR!(IGCHost2::SetGCStartupLimitsEx, (*v2.as_const()).SetGCStartupLimitsEx(segment_size, gen0_size)).map(|_| ()), 
+            None => Err(HostingError::from_hresult(E_NOINTERFACE, CALL!(IGCHost2::SetGCStartupLimitsEx))),
+        }
+    }
+
+    pub fn set_virtual_mem_limit(&self, max_mb: usize) -> Result<(), HostingError> {
+        CHECK_HR!(IGCHost::SetVirtualMemLimit, (*self.inner.as_const()).SetVirtualMemLimit(max_mb)).map(|_| ())
+    }
+}
+
+impl Drop for GcHost {
+    fn drop(&mut self) {
+        if let Some(ref v2) = self.v2 {
+            unsafe { (*v2.as_const()).Release() };
+        }
+        unsafe { (*self.inner.as_const()).Release() };
+    }
+}
@@ -0,0 +1,150 @@
+// hostprotection.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRHostProtectionManager: keep partially trusted code loaded into the 
+// runtime away from whole categories of framework APIs, the ones marked 
+// with HostProtectionAttribute. Like the other runtime managers this only 
+// takes effect before Start.
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign, Sub};
+
+use mscoree_sys::mscoree::{
+    eAll, 
+    eExternalProcessMgmt, 
+    eExternalThreading, 
+    eMayLeakOnAbort, 
+    eNoChecks, 
+    eSecurityInfrastructure, 
+    eSelfAffectingProcessMgmt, 
+    eSelfAffectingThreading, 
+    eSharedState, 
+    eSynchronization, 
+    eUI, 
+    EApiCategories, 
+    ICLRHostProtectionManager,
+};
+
+use control::ClrControl;
+use error::HostingError;
+use wrappers::PtrCtr;
+
+//A set of EApiCategories, combined with |
+#[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+pub struct HostProtectionCategories(EApiCategories);
+
+impl HostProtectionCategories {
+    pub const NONE: HostProtectionCategories = HostProtectionCategories(eNoChecks);
+    pub const SYNCHRONIZATION: HostProtectionCategories = HostProtectionCategories(eSynchronization);
+    pub const SHARED_STATE: HostProtectionCategories = HostProtectionCategories(eSharedState);
+    pub const EXTERNAL_PROCESS_MGMT: HostProtectionCategories = HostProtectionCategories(eExternalProcessMgmt);
+    pub const SELF_AFFECTING_PROCESS_MGMT: HostProtectionCategories = HostProtectionCategories(eSelfAffectingProcessMgmt);
+    pub const EXTERNAL_THREADING: HostProtectionCategories = HostProtectionCategories(eExternalThreading);
+    pub const SELF_AFFECTING_THREADING: HostProtectionCategories = HostProtectionCategories(eSelfAffectingThreading);
+    pub const SECURITY_INFRASTRUCTURE: HostProtectionCategories = HostProtectionCategories(eSecurityInfrastructure);
+    pub const UI: HostProtectionCategories = HostProtectionCategories(eUI);
+    pub const MAY_LEAK_ON_ABORT: HostProtectionCategories = HostProtectionCategories(eMayLeakOnAbort);
+    pub const ALL: HostProtectionCategories = HostProtectionCategories(eAll);
+
+    //Bits the runtime doesn't define are dropped
+    pub fn from_bits(bits: EApiCategories) -> HostProtectionCategories {
+        HostProtectionCategories(bits & eAll)
+    }
+
+    pub fn bits(self) -> EApiCategories {
+        self.0
+    }
+
+    pub fn contains(self, other: HostProtectionCategories) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == eNoChecks
+    }
+}
+
+impl BitOr for HostProtectionCategories {
+    type Output = HostProtectionCategories;
+
+    fn bitor(self, rhs: HostProtectionCategories) -> HostProtectionCategories {
+        HostProtectionCategories(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for HostProtectionCategories {
+    fn bitor_assign(&mut self, rhs: HostProtectionCategories) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl Sub for HostProtectionCategories {
+    type Output = HostProtectionCategories;
+
+    fn sub(self, rhs: HostProtectionCategories) -> HostProtectionCategories {
+        HostProtectionCategories(self.0 & !rhs.0)
+    }
+}
+
+impl fmt::Debug for HostProtectionCategories {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostProtectionCategories({:#x})", self.0)
+    }
+}
+
+pub struct HostProtectionManager {
+    inner: PtrCtr<ICLRHostProtectionManager>,
+}
+
+impl HostProtectionManager {
+    pub fn new(control: &ClrControl) -> Result<HostProtectionManager, HostingError> {
+        control.manager::<ICLRHostProtectionManager>().map(|inner| HostProtectionManager { inner })
+    }
+
+    //Code without full trust gets a HostProtectionException when it 
+    // reaches an API in any of these categories
+    pub fn set_protected_categories(&self, categories: HostProtectionCategories) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRHostProtectionManager::SetProtectedCategories, (*self.inner.as_const()).SetProtectedCategories(categories.bits())).map(|_| ())
+    }
+
+    //Resolve grant sets when an assembly loads instead of on first demand, 
+    // so nothing that blocks on a lock runs inside protected code later
+    pub fn set_eager_serialize_grant_sets(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRHostProtectionManager::SetEagerSerializeGrantSets, (*self.inner.as_const()).SetEagerSerializeGrantSets()).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(HostProtectionManager);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn categories_combine_and_subtract() {
+        let blocked = HostProtectionCategories::SYNCHRONIZATION | HostProtectionCategories::SHARED_STATE;
+        assert!(blocked.contains(HostProtectionCategories::SHARED_STATE));
+        assert!(!blocked.contains(HostProtectionCategories::UI));
+        assert_eq!(blocked - HostProtectionCategories::SHARED_STATE, HostProtectionCategories::SYNCHRONIZATION);
+        assert!((blocked - blocked).is_empty());
+        assert_eq!(HostProtectionCategories::from_bits(0xffff_ffff), HostProtectionCategories::ALL);
+    }
+}
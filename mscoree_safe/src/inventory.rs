@@ -0,0 +1,177 @@
+// inventory.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Plain-data view of the installed runtimes for inventory and diagnostics 
+// tooling. Nothing here holds a COM reference, so descriptors can be kept, 
+// compared and (with the serde feature) written out as JSON.
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use winapi::shared::minwindef::DWORD;
+
+use error::HostingError;
+use metahost::{MetaHost, RuntimeInfo, RuntimeVersion};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Bitness {
+    X86, 
+    X64,
+}
+
+impl Bitness {
+    //Framework64 holds the 64-bit runtimes, Framework the 32-bit ones
+    pub fn from_directory(directory: &Path) -> Bitness {
+        let is_64 = directory.components().any(|c| match c {
+            Component::Normal(name) => name.to_string_lossy().eq_ignore_ascii_case("Framework64"), 
+            _ => false,
+        });
+        if is_64 { Bitness::X64 } else { Bitness::X86 }
+    }
+}
+
+//Everything RuntimeInfo can report about a runtime, read once and detached 
+// from the COM pointer, so it can cross threads or be serialized
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuntimeDescriptor {
+    pub version: String, 
+    pub directory: PathBuf, 
+    pub loaded: bool, 
+    pub loadable: bool, 
+    pub started: bool, 
+    pub bitness: Bitness,
+}
+
+impl RuntimeDescriptor {
+    pub fn from_runtime<R: RuntimeInfo + ?Sized>(runtime: &R) -> Result<RuntimeDescriptor, HostingError> {
+        let directory = runtime.directory()?;
+        Ok(RuntimeDescriptor {
+            version: runtime.version().to_string(), 
+            bitness: Bitness::from_directory(&directory), 
+            directory, 
+            loaded: runtime.loaded(), 
+            loadable: runtime.loadable(), 
+            started: runtime.started(),
+        })
+    }
+
+    //Asks the runtime rather than trusting its cache, so the descriptor is 
+    // current as of this call
+    pub fn from_runtime_uncached<R: RuntimeInfo + ?Sized>(runtime: &R) -> Result<RuntimeDescriptor, HostingError> {
+        let directory = runtime.directory()?;
+        Ok(RuntimeDescriptor {
+            version: runtime.version().to_string(), 
+            bitness: Bitness::from_directory(&directory), 
+            directory, 
+            loaded: runtime.loaded_uncached(), 
+            loadable: runtime.loadable_uncached(), 
+            started: runtime.started_uncached(),
+        })
+    }
+
+    pub fn runtime_version(&self) -> RuntimeVersion {
+        RuntimeVersion::from(self.version.clone())
+    }
+}
+
+//A runtime's configuration and state in one place, for diagnostics dumps. 
+// Display writes it out one field per line.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuntimeReport {
+    pub version: String, 
+    pub directory: PathBuf, 
+    pub default_startup_flags: DWORD, 
+    //The host config file registered with SetDefaultStartupFlags
+    pub config_file: Option<PathBuf>, 
+    pub loadable: bool, 
+    pub loaded: bool, 
+    pub started: bool, 
+    //What the runtime was actually started with
+    pub startup_flags: Option<DWORD>, 
+    //Whether CorBindToRuntimeEx and friends resolve to this runtime
+    pub legacy_v2_bound: bool,
+}
+
+impl RuntimeReport {
+    pub fn from_runtime<R: RuntimeInfo + ?Sized>(runtime: &R) -> Result<RuntimeReport, HostingError> {
+        let (default_startup_flags, config_file) = runtime.default_startup_config()?;
+        Ok(RuntimeReport {
+            version: runtime.version().to_string(), 
+            directory: runtime.directory()?, 
+            default_startup_flags, 
+            config_file, 
+            loadable: runtime.loadable_uncached(), 
+            loaded: runtime.loaded_uncached(), 
+            started: runtime.started_uncached(), 
+            startup_flags: runtime.startup_flags(), 
+            legacy_v2_bound: runtime.is_legacy_v2_bound()?,
+        })
+    }
+}
+
+impl fmt::Display for RuntimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "directory: {}", self.directory.display())?;
+        writeln!(f, "default startup flags: {:#x}", self.default_startup_flags)?;
+        match self.config_file {
+            Some(ref path) => writeln!(f, "config file: {}", path.display())?, 
+            None => writeln!(f, "config file: none")?,
+        }
+        writeln!(f, "loadable: {}", self.loadable)?;
+        writeln!(f, "loaded: {}", self.loaded)?;
+        match self.startup_flags {
+            Some(flags) => writeln!(f, "started: true (flags {:#x})", flags)?, 
+            None => writeln!(f, "started: {}", self.started)?,
+        }
+        write!(f, "legacy v2 bound: {}", self.legacy_v2_bound)
+    }
+}
+
+//Every runtime the metahost knows about, oldest first
+pub fn inventory(metahost: &dyn MetaHost) -> Result<Vec<RuntimeDescriptor>, HostingError> {
+    let mut runtimes: Vec<_> = metahost.runtimes().into_iter().collect();
+    runtimes.sort_by(|a, b| a.0.cmp(&b.0));
+    runtimes.into_iter()
+        .map(|(_, runtime)| RuntimeDescriptor::from_runtime(&*runtime))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn descriptor_is_send_sync() {
+        assert_send_sync::<RuntimeDescriptor>();
+    }
+
+    #[test]
+    fn bitness_from_directory() {
+        assert_eq!(Bitness::from_directory(Path::new(r"C:\Windows\Microsoft.NET\Framework64\v4.0.30319")), Bitness::X64);
+        assert_eq!(Bitness::from_directory(Path::new(r"C:\Windows\Microsoft.NET\Framework\v2.0.50727")), Bitness::X86);
+    }
+}
@@ -0,0 +1,327 @@
+// legacy.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Activation through the pre-v4 shim (CorBindToRuntimeEx and friends) for 
+// machines that only have .NET 2.0/3.5, where mscoree.dll doesn't export 
+// CLRCreateInstance. The same MetaHost/RuntimeInfo traits are implemented 
+// over it, but the old shim can't answer much about runtimes it hasn't 
+// bound: loaded/started reflect what this host bound, and there is no 
+// ICLRRuntimeInfo behind as_raw. Since this crate imports CLRCreateInstance, 
+// a binary meant for such machines has to delay-load mscoree.dll.
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use winapi::shared::guiddef::{IsEqualGUID, REFCLSID, REFIID};
+use winapi::shared::minwindef::{DWORD, HMODULE, LPVOID};
+use winapi::shared::winerror::{E_NOTIMPL, ERROR_INSUFFICIENT_BUFFER, HRESULT, HRESULT_FROM_WIN32, S_OK};
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+use winapi::um::processthreadsapi::{GetCurrentProcessId, GetProcessId};
+
+use mscoree_sys::metahost::{ICLRMetaHost, ICLRRuntimeInfo};
+use mscoree_sys::mscoree::{
+    CLSID_CLRRuntimeHost, 
+    CLSID_CorRuntimeHost, 
+    CorBindToRuntimeEx, 
+    GetRequestedRuntimeInfo, 
+    LoadLibraryShim, 
+    RUNTIME_INFO_DONT_SHOW_ERROR_DIALOG, 
+    RUNTIME_INFO_IGNORE_ERROR_MODE, 
+};
+
+use buffer::wide;
+use error::HostingError;
+use metahost::{MetaHost, MetaHostImpl, Process, RuntimeInfo, RuntimeRef, RuntimeVersion};
+
+//Versions the old shim is asked about; v4 answers too on machines that have it
+const PROBED: &[RuntimeVersion] = &[
+    RuntimeVersion::V1_0, 
+    RuntimeVersion::V1_1, 
+    RuntimeVersion::V2, 
+    RuntimeVersion::V4,
+];
+
+//The runtime this host bound, shared by the metahost and its runtimes. 
+// The shim allows one per process, so binding a second version fails.
+#[derive(Debug, Default)]
+struct Binding {
+    version: RefCell<Option<RuntimeVersion>>, 
+    flags: Cell<DWORD>, 
+    started: Cell<bool>,
+}
+
+#[derive(Debug)]
+pub struct LegacyRuntime {
+    version: RuntimeVersion, 
+    directory: PathBuf, 
+    binding: Rc<Binding>, 
+    default_startup_flags: Cell<DWORD>,
+}
+
+impl LegacyRuntime {
+    fn is_bound(&self) -> bool {
+        self.binding.version.borrow().as_ref() == Some(&self.version)
+    }
+
+    //Whatever was created through the shim counts as starting the runtime, 
+    // since hosts start what they bind straight away
+    fn bind(&self, clsid: REFCLSID, iid: REFIID) -> Result<LPVOID, HostingError> {
+        let version = wide(&self.version.to_string());
+        let flags = self.default_startup_flags.get();
+        let mut p: LPVOID = ptr::null_mut();
+        CHECK_HR!(mscoree::CorBindToRuntimeEx, CorBindToRuntimeEx(version.as_ptr(), ptr::null(), flags, clsid, iid, &mut p))?;
+        if self.binding.version.borrow().is_none() {
+            *self.binding.version.borrow_mut() = Some(self.version.clone());
+            self.binding.flags.set(flags);
+            self.binding.started.set(true);
+        }
+        if p.is_null() {
+            return Err(HostingError::null_pointer(CALL!(mscoree::CorBindToRuntimeEx)));
+        }
+        Ok(p)
+    }
+}
+
+impl RuntimeInfo for LegacyRuntime {
+    fn version(&self) -> RuntimeVersion {
+        self.version.clone()
+    }
+
+    fn loaded(&self) -> bool {
+        self.is_bound()
+    }
+
+    //False once another version has been bound
+    fn loadable(&self) -> bool {
+        match *self.binding.version.borrow() {
+            Some(ref bound) => *bound == self.version, 
+            None => true,
+        }
+    }
+
+    fn started(&self) -> bool {
+        self.is_bound() && self.binding.started.get()
+    }
+
+    fn loaded_uncached(&self) -> bool {
+        self.loaded()
+    }
+
+    fn loadable_uncached(&self) -> bool {
+        self.loadable()
+    }
+
+    fn started_uncached(&self) -> bool {
+        self.started()
+    }
+
+    fn invalidate(&self) {}
+
+    fn startup_flags(&self) -> Option<DWORD> {
+        if self.started() { Some(self.binding.flags.get()) } else { None }
+    }
+
+    fn directory(&self) -> Result<PathBuf, HostingError> {
+        Ok(self.directory.clone())
+    }
+
+    fn default_startup_flags(&self) -> Result<DWORD, HostingError> {
+        Ok(self.default_startup_flags.get())
+    }
+
+    //The old shim has no host config file parameter on this path
+    fn set_default_startup_flags(&self, flags: DWORD, host_config: Option<&Path>) -> Result<(), HostingError> {
+        if host_config.is_some() {
+            return Err(HostingError::from_hresult(E_NOTIMPL, CALL!(mscoree::CorBindToRuntimeEx)));
+        }
+        self.default_startup_flags.set(flags);
+        Ok(())
+    }
+
+    fn load_library(&self, dll_name: &OsStr) {
+        let name = wide(dll_name);
+        let version = wide(&self.version.to_string());
+        let mut module: HMODULE = ptr::null_mut();
+        let _hr = unsafe { LoadLibraryShim(name.as_ptr(), version.as_ptr(), ptr::null_mut(), &mut module) };
+    }
+
+    //Only the two host objects go through the binding; anything else the 
+    // v4 GetInterface offers has no pre-v4 equivalent here
+    fn get_interface(&self, clsid: REFCLSID, iid: REFIID) -> Result<LPVOID, HostingError> {
+        let clsid_ref = unsafe { &*clsid };
+        if IsEqualGUID(clsid_ref, &CLSID_CorRuntimeHost) || IsEqualGUID(clsid_ref, &CLSID_CLRRuntimeHost) {
+            self.bind(clsid, iid)
+        } else {
+            Err(HostingError::from_hresult(E_NOTIMPL, CALL!(mscoree::CorBindToRuntimeEx)))
+        }
+    }
+
+    fn is_debugger_attached(&self) -> Result<bool, HostingError> {
+        Err(HostingError::from_hresult(E_NOTIMPL, CALL!(ICLRRuntimeInfo::IsDebuggerAttached)))
+    }
+
+    //Whatever the old shim binds is the legacy runtime already
+    fn bind_as_legacy_v2_runtime(&self) -> Result<(), HostingError> {
+        Ok(())
+    }
+
+    //No ICLRRuntimeInfo exists on this path
+    fn as_raw(&self) -> *mut ICLRRuntimeInfo {
+        ptr::null_mut()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LegacyMetaHost {
+    binding: Rc<Binding>, 
+    runtimes: RefCell<HashMap<RuntimeVersion, Rc<LegacyRuntime>>>,
+}
+
+impl LegacyMetaHost {
+    pub fn new() -> LegacyMetaHost {
+        LegacyMetaHost::default()
+    }
+
+    //Fails when the version isn't installed
+    fn probe(&self, version: &RuntimeVersion) -> Result<Rc<LegacyRuntime>, HostingError> {
+        if let Some(ri) = self.runtimes.borrow().get(version) {
+            return Ok(ri.clone());
+        }
+        let directory = requested_runtime_directory(version)?;
+        let ri = Rc::new(LegacyRuntime {
+            version: version.clone(), 
+            directory, 
+            binding: self.binding.clone(), 
+            default_startup_flags: Cell::new(0),
+        });
+        self.runtimes.borrow_mut().insert(version.clone(), ri.clone());
+        Ok(ri)
+    }
+}
+
+impl MetaHost for LegacyMetaHost {
+    fn runtime(&self, version: RuntimeVersion) -> Result<RuntimeRef, HostingError> {
+        self.probe(&version).map(|ri| RuntimeRef::new(ri))
+    }
+
+    fn runtimes(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        PROBED.iter()
+            .filter_map(|version| self.probe(version).ok().map(|ri| (version.clone(), RuntimeRef::new(ri))))
+            .collect()
+    }
+
+    fn loaded_runtimes(&self) -> HashMap<RuntimeVersion, bool> {
+        self.runtimes().into_iter()
+            .map(|(version, _)| {
+                let loaded = self.binding.version.borrow().as_ref() == Some(&version);
+                (version, loaded)
+            })
+            .collect()
+    }
+
+    fn runtimes_uncached(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        self.runtimes()
+    }
+
+    fn loaded_runtimes_uncached(&self) -> HashMap<RuntimeVersion, bool> {
+        self.loaded_runtimes()
+    }
+
+    fn invalidate(&self) {}
+
+    //The old shim can't look into other processes
+    fn loaded_runtimes_in(&self, process: Process) -> Result<HashMap<RuntimeVersion, bool>, HostingError> {
+        let pid = match process {
+            Process::Id(pid) => pid, 
+            Process::Handle(handle) => unsafe { GetProcessId(handle) },
+        };
+        if pid != unsafe { GetCurrentProcessId() } {
+            return Err(HostingError::from_hresult(E_NOTIMPL, CALL!(ICLRMetaHost::EnumerateLoadedRuntimes)));
+        }
+        Ok(self.loaded_runtimes())
+    }
+
+    fn legacy_v2_bound_runtime(&self) -> Result<Option<RuntimeVersion>, HostingError> {
+        Ok(self.binding.version.borrow().clone())
+    }
+
+    fn as_raw(&self) -> *mut ICLRMetaHost {
+        ptr::null_mut()
+    }
+}
+
+//True when the installed mscoree.dll is the v4 shim
+pub fn clr_create_instance_available() -> bool {
+    let name = wide("mscoree.dll");
+    unsafe {
+        let module = LoadLibraryW(name.as_ptr());
+        !module.is_null() && !GetProcAddress(module, "CLRCreateInstance\0".as_ptr() as *const i8).is_null()
+    }
+}
+
+//The v4 metahost when the shim has one, the legacy one otherwise
+pub fn metahost() -> Result<Box<dyn MetaHost>, HostingError> {
+    if clr_create_instance_available() {
+        return MetaHostImpl::create().map(|host| Box::new(host) as Box<dyn MetaHost>);
+    }
+    Ok(Box::new(LegacyMetaHost::new()))
+}
+
+//GetRequestedRuntimeInfo reports the framework root and the version 
+// separately; the runtime lives in root\version
+fn requested_runtime_directory(version: &RuntimeVersion) -> Result<PathBuf, HostingError> {
+    let wversion = wide(&version.to_string());
+    let flags = RUNTIME_INFO_DONT_SHOW_ERROR_DIALOG | RUNTIME_INFO_IGNORE_ERROR_MODE;
+    let call = |dir: &mut Vec<u16>, dir_len: &mut DWORD, ver: &mut Vec<u16>, ver_len: &mut DWORD| -> HRESULT {
+        unsafe {
+            GetRequestedRuntimeInfo(
+                ptr::null(), 
+                wversion.as_ptr(), 
+                ptr::null(), 
+                0, 
+                flags, 
+                dir.as_mut_ptr(), 
+                dir.len() as DWORD, 
+                dir_len, 
+                ver.as_mut_ptr(), 
+                ver.len() as DWORD, 
+                ver_len
+            )
+        }
+    };
+    let (mut dir, mut ver) = (vec![0u16; 260], vec![0u16; 64]);
+    let (mut dir_len, mut ver_len): (DWORD, DWORD) = (0, 0);
+    let mut hr = call(&mut dir, &mut dir_len, &mut ver, &mut ver_len);
+    if hr == HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) {
+        dir = vec![0; dir_len as usize];
+        ver = vec![0; ver_len as usize];
+        hr = call(&mut dir, &mut dir_len, &mut ver, &mut ver_len);
+    }
+    if hr != S_OK {
+        return Err(HostingError::from_hresult(hr, CALL!(mscoree::GetRequestedRuntimeInfo)));
+    }
+    let trim = |buffer: &[u16]| String::from_utf16_lossy(&buffer[..buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len())]);
+    Ok(PathBuf::from(trim(&dir)).join(trim(&ver)))
+}
@@ -21,7 +21,14 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
+extern crate once_cell;
 extern crate winapi;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "serde")]
+#[macro_use] extern crate serde;
+#[cfg(feature = "tracing")]
+#[macro_use] extern crate tracing;
 
 extern crate mscorlib_safe;
 extern crate mscorlib_sys;
@@ -29,8 +36,100 @@ extern crate mscoree_sys;
 
 #[macro_use] mod macros;
 
+pub mod assembly;
+pub mod apartment;
+#[cfg(feature = "host-managers")]
+pub mod bridge;
+pub mod buffer;
+#[cfg(feature = "hosting")]
+pub mod builder;
+#[cfg(feature = "hosting")]
+pub mod callback;
+#[cfg(feature = "metahost")]
+pub mod clr;
+#[cfg(feature = "hosting")]
+pub mod clrhost;
+#[cfg(feature = "hosting")]
+mod com;
+pub mod comptr;
+#[cfg(feature = "hosting")]
+pub mod configuration;
+#[cfg(feature = "hosting")]
+pub mod control;
+#[cfg(feature = "hosting")]
+pub mod coreclr;
+#[cfg(feature = "hosting")]
+pub mod corhost;
+#[cfg(feature = "debugging")]
+pub mod debugging;
+#[cfg(feature = "hosting")]
+pub mod dispatch;
+pub mod error;
+pub mod errormode;
+#[cfg(feature = "hosting")]
+pub mod errorreporting;
+#[cfg(feature = "hosting")]
+pub mod events;
+#[cfg(feature = "hosting")]
+pub mod exception;
+pub mod framework;
+#[cfg(feature = "metahost")]
+pub mod fusion;
+#[cfg(feature = "hosting")]
+pub mod gc;
+#[cfg(feature = "hosting")]
+pub mod gchost;
 pub mod host;
+#[cfg(feature = "hosting")]
+pub mod hostprotection;
+#[cfg(feature = "metahost")]
+pub mod inventory;
+#[cfg(feature = "legacy")]
+pub mod legacy;
+#[cfg(feature = "metahost")]
+pub mod loadevents;
+#[cfg(feature = "host-managers")]
+pub mod managers;
+#[cfg(feature = "hosting")]
+mod marshal;
+#[cfg(feature = "metadata")]
+pub mod metadata;
+#[cfg(feature = "metahost")]
+pub mod manifest;
+#[cfg(feature = "metahost")]
 pub mod metahost;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "hosting")]
+pub mod monitor;
+#[cfg(feature = "hosting")]
+pub mod policy;
+pub mod prelude;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+#[cfg(feature = "debugging")]
+pub mod publish;
+#[cfg(feature = "hosting")]
+pub mod reflection;
+#[cfg(feature = "hosting")]
+pub mod runtimehost;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "hosting")]
+pub mod sidebyside;
+#[cfg(feature = "hosting")]
+pub mod tasks;
+#[cfg(feature = "hosting")]
+pub mod threadpool;
+#[cfg(feature = "metahost")]
+pub mod tools;
+mod trace;
+#[cfg(feature = "metahost")]
+mod unwind;
+#[cfg(feature = "hosting")]
+pub mod validator;
+#[cfg(feature = "hosting")]
+pub mod variant;
 pub mod wrappers;
 
 /*
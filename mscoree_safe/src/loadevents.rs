@@ -0,0 +1,120 @@
+// loadevents.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Runtime-load notifications delivered over channels. The shim only takes a 
+// bare function pointer, so one trampoline is registered per process and 
+// fans each notification out to every live subscriber. Subscribers that 
+// have dropped their receiver are pruned on the next notification.
+//
+// The callback runs on whichever thread is loading the runtime. Sending on 
+// a channel never blocks, so the thread set/unset callbacks the shim hands 
+// over (for hosts that must wait on other threads) aren't needed here.
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(feature = "async")]
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+use once_cell::sync::Lazy;
+
+use mscoree_sys::metahost::{CallbackThreadSetFnPtr, CallbackThreadUnsetFnPtr, ICLRMetaHost, ICLRRuntimeInfo};
+
+use comptr::ComPtr;
+use error::HostingError;
+use metahost::{RuntimeInfo, RuntimeInfoImpl, RuntimeVersion};
+use unwind::catch_silently;
+
+//A runtime that was just loaded into this process, by this host or anyone else
+#[derive(Clone, Debug)]
+pub struct RuntimeLoadEvent {
+    pub version: RuntimeVersion, 
+    pub runtime: RuntimeInfoImpl,
+}
+
+#[cfg(feature = "async")]
+pub type RuntimeLoadStream = UnboundedReceiver<RuntimeLoadEvent>;
+
+enum Subscriber {
+    Channel(Sender<RuntimeLoadEvent>), 
+    #[cfg(feature = "async")]
+    Stream(UnboundedSender<RuntimeLoadEvent>),
+}
+
+impl Subscriber {
+    //false once the receiving end is gone
+    fn send(&self, event: RuntimeLoadEvent) -> bool {
+        match self {
+            Subscriber::Channel(tx) => tx.send(event).is_ok(), 
+            #[cfg(feature = "async")]
+            Subscriber::Stream(tx) => tx.unbounded_send(event).is_ok(),
+        }
+    }
+}
+
+struct Subscribers {
+    registered: bool, 
+    subscribers: Vec<Subscriber>,
+}
+
+static SUBSCRIBERS: Lazy<Mutex<Subscribers>> = Lazy::new(|| Mutex::new(Subscribers {
+    registered: false, 
+    subscribers: Vec::new(),
+}));
+
+pub(crate) fn channel(metahost: &ICLRMetaHost) -> Result<Receiver<RuntimeLoadEvent>, HostingError> {
+    let (tx, rx) = mpsc::channel();
+    subscribe(metahost, Subscriber::Channel(tx))?;
+    Ok(rx)
+}
+
+#[cfg(feature = "async")]
+pub(crate) fn stream(metahost: &ICLRMetaHost) -> Result<RuntimeLoadStream, HostingError> {
+    let (tx, rx) = unbounded();
+    subscribe(metahost, Subscriber::Stream(tx))?;
+    Ok(rx)
+}
+
+//Registration is process-wide, so whichever metahost subscribes first 
+// registers the trampoline. Only a successful registration is remembered.
+fn subscribe(metahost: &ICLRMetaHost, subscriber: Subscriber) -> Result<(), HostingError> {
+    let mut state = SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if !state.registered {
+        CHECK_HR!(ICLRMetaHost::RequestRuntimeLoadedNotification, metahost.RequestRuntimeLoadedNotification(on_runtime_loaded))?;
+        state.registered = true;
+    }
+    state.subscribers.push(subscriber);
+    Ok(())
+}
+
+extern fn on_runtime_loaded(info: *mut ICLRRuntimeInfo, _thread_set: CallbackThreadSetFnPtr, _thread_unset: CallbackThreadUnsetFnPtr) {
+    catch_silently(|| {
+        //The shim keeps its reference; each event owns one of its own
+        let runtime = unsafe { ComPtr::from_borrowed(info).and_then(|info| RuntimeInfoImpl::from_raw(info.into_raw())) };
+        let runtime = match runtime {
+            Some(runtime) => runtime, 
+            None => return,
+        };
+        let event = RuntimeLoadEvent { version: runtime.version(), runtime };
+        let mut state = SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner);
+        state.subscribers.retain(|subscriber| subscriber.send(event.clone()));
+    });
+}
@@ -0,0 +1,95 @@
+// macros.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Evaluates an HRESULT-returning FFI call: Ok(hr) for success codes 
+// (S_OK, S_FALSE...), Err(hr) for failure codes.
+macro_rules! CHECK_HR {
+    //Named form for the public API: Err carries a HostingError recording 
+    // which interface method failed. This is also the form that gets traced.
+    ($intf:ident :: $method:ident, $call:expr) => {{
+        let call = CALL!($intf::$method);
+        let hr: ::winapi::shared::winerror::HRESULT = $crate::trace::com_call(call, || unsafe { $call });
+        if hr < 0 {
+            Err($crate::error::HostingError::from_hresult(hr, call))
+        } else {
+            Ok(hr)
+        }
+    }};
+    ($call:expr) => {{
+        let hr: ::winapi::shared::winerror::HRESULT = unsafe { $call };
+        if hr < 0 { Err(hr) } else { Ok(hr) }
+    }};
+}
+
+//The Call value for an interface method, for errors raised outside CHECK_HR
+macro_rules! CALL {
+    ($intf:ident :: $method:ident) => {
+        $crate::error::Call { interface: stringify!($intf), method: stringify!($method) }
+    };
+}
+
+//Sealed + RefCounted + Release-on-drop for a wrapper whose `inner` field 
+// is a PtrCtr over some IUnknown-derived interface.
+macro_rules! COM_WRAPPER {
+    ($wrapper:ident) => {
+        impl $crate::wrappers::Sealed for $wrapper {}
+        impl $crate::wrappers::RefCounted for $wrapper {
+            fn increment(&self) -> bool {
+                unsafe {(*self.inner.as_const()).AddRef()};
+                true
+            }
+            fn decrement(&self) -> bool {
+                unsafe {(*self.inner.as_const()).Release()};
+                true
+            }
+        }
+        impl Drop for $wrapper {
+            fn drop(&mut self) {
+                $crate::wrappers::RefCounted::decrement(self);
+            }
+        }
+    };
+    //Also adds raw-pointer conversions for interop with other COM bindings. 
+    // as_raw borrows: no AddRef, valid while the wrapper lives. into_raw 
+    // hands the wrapper's reference to the caller, who must Release it. 
+    // from_raw takes over one reference the caller already owns, None for null.
+    ($wrapper:ident, $intf:ty) => {
+        COM_WRAPPER!($wrapper);
+        impl $wrapper {
+            pub fn as_raw(&self) -> *mut $intf {
+                self.inner.as_const() as *mut $intf
+            }
+
+            pub fn into_raw(self) -> *mut $intf {
+                let p = self.as_raw();
+                ::std::mem::forget(self);
+                p
+            }
+
+            pub unsafe fn from_raw(p: *mut $intf) -> Option<$wrapper> {
+                $crate::wrappers::PtrCtr::new_checked(p)
+                    .ok()
+                    .map(|inner| $wrapper { inner })
+            }
+        }
+    };
+}
@@ -0,0 +1,259 @@
+// assembly.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IHostAssemblyManager/IHostAssemblyStore: serve assembly images from the 
+// host (embedded resources, encrypted archives) instead of disk. The store 
+// is consulted first for every bind except the assemblies listed by 
+// non_host_store_assemblies; answering None falls back to normal probing.
+use std::ptr;
+
+use winapi::shared::basetsd::UINT64;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::winerror::{ERROR_FILE_NOT_FOUND, E_OUTOFMEMORY, E_POINTER, HRESULT, HRESULT_FROM_WIN32, S_OK};
+use winapi::um::objidlbase::IStream;
+use winapi::um::shlwapi::SHCreateMemStream;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{
+    AssemblyBindInfo, 
+    GetCLRIdentityManager, 
+    ICLRAssemblyIdentityManager, 
+    ICLRAssemblyReferenceList, 
+    IHostAssemblyManager, 
+    IHostAssemblyManagerVtbl, 
+    IHostAssemblyStore, 
+    IHostAssemblyStoreVtbl, 
+    ModuleBindInfo
+};
+
+use assembly::{AssemblyName, AssemblyNameError};
+use buffer::wide_str;
+use com::ComBox;
+use unwind::catch_and_translate;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssemblyRequest {
+    pub app_domain_id: DWORD, 
+    //The identity as written in the referencing assembly
+    pub referenced: String, 
+    //The identity after binding policy was applied; this is what to serve
+    pub post_policy: String,
+}
+
+impl AssemblyRequest {
+    pub fn identity(&self) -> Result<AssemblyName, AssemblyNameError> {
+        self.post_policy.parse()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModuleRequest {
+    pub app_domain_id: DWORD, 
+    pub assembly: String, 
+    pub module: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProvidedAssembly {
+    //Must be the same for every request served with the same image
+    pub id: u64, 
+    pub image: Vec<u8>, 
+    pub pdb: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProvidedModule {
+    pub id: DWORD, 
+    pub image: Vec<u8>, 
+    pub pdb: Option<Vec<u8>>,
+}
+
+pub trait HostAssemblyStore: Send + Sync + 'static {
+    fn provide_assembly(&self, request: &AssemblyRequest) -> Result<Option<ProvidedAssembly>, HRESULT>;
+
+    fn provide_module(&self, _request: &ModuleRequest) -> Result<Option<ProvidedModule>, HRESULT> {
+        Ok(None)
+    }
+
+    //Display names the runtime should always bind itself, read once at 
+    // registration. The framework assemblies are the usual candidates.
+    fn non_host_store_assemblies(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+type StoreObject<S> = ComBox<IHostAssemblyStoreVtbl, S>;
+
+struct AssemblyManager {
+    store: *mut IHostAssemblyStore, 
+    excluded: Vec<String>,
+}
+
+impl Drop for AssemblyManager {
+    fn drop(&mut self) {
+        unsafe { (*self.store).Release() };
+    }
+}
+
+type ManagerObject = ComBox<IHostAssemblyManagerVtbl, AssemblyManager>;
+
+//A new IHostAssemblyManager object owned by the caller
+pub(crate) fn create<S: HostAssemblyStore>(store: S) -> *mut IUnknown {
+    let excluded = store.non_host_store_assemblies();
+    let store_vtable = IHostAssemblyStoreVtbl {
+        parent: StoreObject::<S>::unknown_vtbl(), 
+        ProvideAssembly: provide_assembly::<S>, 
+        ProvideModule: provide_module::<S>,
+    };
+    let store = StoreObject::as_interface(StoreObject::new(store_vtable, vec![IHostAssemblyStore::uuidof()], store));
+    let vtable = IHostAssemblyManagerVtbl {
+        parent: ManagerObject::unknown_vtbl(), 
+        GetNonHostStoreAssemblies: get_non_host_store_assemblies, 
+        GetAssemblyStore: get_assembly_store,
+    };
+    ManagerObject::as_interface(ManagerObject::new(vtable, vec![IHostAssemblyManager::uuidof()], AssemblyManager { store, excluded }))
+}
+
+unsafe extern "system" fn get_non_host_store_assemblies(this: *mut IHostAssemblyManager, ppv: *mut *mut ICLRAssemblyReferenceList) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    let manager = &ManagerObject::from_this(this).value;
+    //A null list means every bind goes to the store first
+    if manager.excluded.is_empty() {
+        return S_OK;
+    }
+    catch_and_translate(|| {
+        *ppv = reference_list(&manager.excluded)?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn get_assembly_store(this: *mut IHostAssemblyManager, ppv: *mut *mut IHostAssemblyStore) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    let manager = &ManagerObject::from_this(this).value;
+    (*manager.store).AddRef();
+    *ppv = manager.store;
+    S_OK
+}
+
+unsafe extern "system" fn provide_assembly<S: HostAssemblyStore>(this: *mut IHostAssemblyStore, info: *mut AssemblyBindInfo, 
+    assembly_id: *mut UINT64, context: *mut UINT64, image: *mut *mut IStream, pdb: *mut *mut IStream) -> HRESULT 
+{
+    if info.is_null() || assembly_id.is_null() || image.is_null() {
+        return E_POINTER;
+    }
+    *image = ptr::null_mut();
+    if !pdb.is_null() {
+        *pdb = ptr::null_mut();
+    }
+    let store = &StoreObject::<S>::from_this(this).value;
+    let request = AssemblyRequest {
+        app_domain_id: (*info).dwAppDomainId, 
+        referenced: wide_str((*info).lpReferencedIdentity), 
+        post_policy: wide_str((*info).lpPostPolicyIdentity),
+    };
+    catch_and_translate(|| {
+        let provided = store.provide_assembly(&request)?.ok_or_else(not_found)?;
+        *assembly_id = provided.id;
+        if !context.is_null() {
+            *context = 0;
+        }
+        write_streams(&provided.image, provided.pdb.as_ref(), image, pdb)
+    })
+}
+
+unsafe extern "system" fn provide_module<S: HostAssemblyStore>(this: *mut IHostAssemblyStore, info: *mut ModuleBindInfo, 
+    module_id: *mut DWORD, image: *mut *mut IStream, pdb: *mut *mut IStream) -> HRESULT 
+{
+    if info.is_null() || module_id.is_null() || image.is_null() {
+        return E_POINTER;
+    }
+    *image = ptr::null_mut();
+    if !pdb.is_null() {
+        *pdb = ptr::null_mut();
+    }
+    let store = &StoreObject::<S>::from_this(this).value;
+    let request = ModuleRequest {
+        app_domain_id: (*info).dwAppDomainId, 
+        assembly: wide_str((*info).lpAssemblyIdentity), 
+        module: wide_str((*info).lpModuleName),
+    };
+    catch_and_translate(|| {
+        let provided = store.provide_module(&request)?.ok_or_else(not_found)?;
+        *module_id = provided.id;
+        write_streams(&provided.image, provided.pdb.as_ref(), image, pdb)
+    })
+}
+
+//The runtime treats file-not-found as "not in the store"
+fn not_found() -> HRESULT {
+    HRESULT_FROM_WIN32(ERROR_FILE_NOT_FOUND)
+}
+
+unsafe fn write_streams(image: &[u8], pdb: Option<&Vec<u8>>, image_out: *mut *mut IStream, pdb_out: *mut *mut IStream) -> Result<(), HRESULT> {
+    *image_out = memory_stream(image)?;
+    if let (Some(pdb), false) = (pdb, pdb_out.is_null()) {
+        *pdb_out = memory_stream(pdb)?;
+    }
+    Ok(())
+}
+
+//SHCreateMemStream copies the bytes, so the stream outlives the slice
+fn memory_stream(bytes: &[u8]) -> Result<*mut IStream, HRESULT> {
+    let stream = unsafe { SHCreateMemStream(bytes.as_ptr(), bytes.len() as u32) };
+    if stream.is_null() { Err(E_OUTOFMEMORY) } else { Ok(stream) }
+}
+
+fn reference_list(names: &[String]) -> Result<*mut ICLRAssemblyReferenceList, HRESULT> {
+    let mut identity: *mut IUnknown = ptr::null_mut();
+    CHECK_HR!(GetCLRIdentityManager(&ICLRAssemblyIdentityManager::uuidof(), &mut identity))?;
+    let identity = identity as *mut ICLRAssemblyIdentityManager;
+    let wide: Vec<Vec<u16>> = names.iter().map(|n| n.encode_utf16().chain(Some(0)).collect()).collect();
+    let mut ptrs: Vec<LPCWSTR> = wide.iter().map(|w| w.as_ptr()).collect();
+    let mut list: *mut ICLRAssemblyReferenceList = ptr::null_mut();
+    let hr = CHECK_HR!((*identity).GetCLRAssemblyReferenceList(ptrs.as_mut_ptr(), ptrs.len() as DWORD, &mut list));
+    unsafe { (*identity).Release() };
+    hr.map(|_| list)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn request_identity_uses_post_policy() {
+        let request = AssemblyRequest {
+            app_domain_id: 1, 
+            referenced: "Plugin, Version=1.0.0.0, Culture=neutral, PublicKeyToken=null".to_string(), 
+            post_policy: "Plugin, Version=2.0.0.0, Culture=neutral, PublicKeyToken=null".to_string(),
+        };
+        let identity = request.identity().unwrap();
+        assert_eq!(identity.name, "Plugin");
+        assert_eq!(identity.version.unwrap().major, 2);
+    }
+}
@@ -0,0 +1,95 @@
+// gc.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IHostGCManager: notifications around the runtime suspending managed 
+// threads for a collection. They run on the thread driving the GC (or the 
+// thread about to block), with the runtime in a fragile state: don't call 
+// back into managed code, and keep them short.
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{ICGThreadControl, ICGThreadControlVtbl, IHostGCManager, IHostGCManagerVtbl};
+
+use com::ComBox;
+use unwind::catch_and_translate;
+
+pub trait HostGcManager: Send + Sync + 'static {
+    //The calling thread is about to block until the GC finishes
+    fn thread_is_blocking_for_suspension(&self) {}
+
+    //The runtime is starting to suspend threads for a collection
+    fn suspension_starting(&self) {}
+
+    //Threads are resuming; `generation` is the generation that was collected
+    fn suspension_ending(&self, _generation: u32) {}
+}
+
+type GcObject<G> = ComBox<IHostGCManagerVtbl, G>;
+type ThreadControlObject<G> = ComBox<ICGThreadControlVtbl, G>;
+
+//A new IHostGCManager object owned by the caller
+pub(crate) fn create<G: HostGcManager>(manager: G) -> *mut IUnknown {
+    let vtable = IHostGCManagerVtbl {
+        parent: GcObject::<G>::unknown_vtbl(), 
+        ThreadIsBlockingForSuspension: thread_is_blocking_for_suspension::<IHostGCManagerVtbl, G, IHostGCManager>, 
+        SuspensionStarting: suspension_starting::<IHostGCManagerVtbl, G, IHostGCManager>, 
+        SuspensionEnding: suspension_ending::<IHostGCManagerVtbl, G, IHostGCManager>,
+    };
+    GcObject::as_interface(GcObject::new(vtable, vec![IHostGCManager::uuidof()], manager))
+}
+
+//The v1/v2 IGCThreadControl has the same three notifications
+pub(crate) fn create_thread_control<G: HostGcManager>(manager: G) -> *mut ICGThreadControl {
+    let vtable = ICGThreadControlVtbl {
+        parent: ThreadControlObject::<G>::unknown_vtbl(), 
+        ThreadIsBlockingForSuspension: thread_is_blocking_for_suspension::<ICGThreadControlVtbl, G, ICGThreadControl>, 
+        SuspensionStarting: suspension_starting::<ICGThreadControlVtbl, G, ICGThreadControl>, 
+        SuspensionEnding: suspension_ending::<ICGThreadControlVtbl, G, ICGThreadControl>,
+    };
+    ThreadControlObject::as_interface(ThreadControlObject::new(vtable, vec![ICGThreadControl::uuidof()], manager))
+}
+
+unsafe extern "system" fn thread_is_blocking_for_suspension<V, G: HostGcManager, I>(this: *mut I) -> HRESULT {
+    let manager = &ComBox::<V, G>::from_this(this).value;
+    catch_and_translate(|| {
+        manager.thread_is_blocking_for_suspension();
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn suspension_starting<V, G: HostGcManager, I>(this: *mut I) -> HRESULT {
+    let manager = &ComBox::<V, G>::from_this(this).value;
+    catch_and_translate(|| {
+        manager.suspension_starting();
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn suspension_ending<V, G: HostGcManager, I>(this: *mut I, generation: DWORD) -> HRESULT {
+    let manager = &ComBox::<V, G>::from_this(this).value;
+    catch_and_translate(|| {
+        manager.suspension_ending(generation);
+        Ok(())
+    })
+}
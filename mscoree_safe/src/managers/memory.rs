@@ -0,0 +1,395 @@
+// memory.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IHostMemoryManager/IHostMalloc: route the runtime's virtual memory and 
+// heap allocations through the host. Every hook defaults to the plain OS 
+// call, so an implementation only overrides what it wants to meter or cap; 
+// returning E_OUTOFMEMORY from a hook is how a host refuses an allocation.
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::{c_char, c_int, c_void};
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::ntdef::HANDLE;
+use winapi::shared::winerror::{E_OUTOFMEMORY, E_POINTER, HRESULT};
+use winapi::um::heapapi::{HeapAlloc, HeapCreate, HeapDestroy, HeapFree};
+use winapi::um::memoryapi;
+use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winnt::{HEAP_CREATE_ENABLE_EXECUTE, HEAP_NO_SERIALIZE};
+use winapi::Interface;
+
+use mscoree_sys::mscoree::*;
+
+use com::ComBox;
+use error::{HostingError, last_error};
+use unwind::catch_and_translate;
+use wrappers::{PtrCtr, RefCounted};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CriticalLevel {
+    //Failure only affects the requesting task
+    Task, 
+    AppDomain, 
+    //Failure takes the process down; refuse these only as a last resort
+    Process,
+}
+
+impl CriticalLevel {
+    pub(crate) fn from_raw(raw: EMemoryCriticalLevel) -> CriticalLevel {
+        match raw {
+            eTaskCritical => CriticalLevel::Task, 
+            eAppDomainCritical => CriticalLevel::AppDomain, 
+            _ => CriticalLevel::Process,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryAvailable {
+    Low, 
+    Neutral, 
+    High,
+}
+
+impl MemoryAvailable {
+    pub(crate) fn raw(self) -> EMemoryAvailable {
+        match self {
+            MemoryAvailable::Low => eMemoryAvailableLow, 
+            MemoryAvailable::Neutral => eMemoryAvailableNeutral, 
+            MemoryAvailable::High => eMemoryAvailableHigh,
+        }
+    }
+}
+
+//Requirements for a heap handed out by CreateMalloc
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MallocKind {
+    pub thread_safe: bool, 
+    pub executable: bool,
+}
+
+impl MallocKind {
+    pub(crate) fn from_raw(raw: DWORD) -> MallocKind {
+        MallocKind {
+            thread_safe: raw & MALLOC_THREADSAFE != 0, 
+            executable: raw & MALLOC_EXECUTABLE != 0,
+        }
+    }
+}
+
+//The runtime's memory-pressure callback; signal it when the host's own 
+// accounting says memory is running low so the GC can react
+pub struct MemoryNotification {
+    inner: PtrCtr<ICLRMemoryNotificationCallback>,
+}
+
+unsafe impl Send for MemoryNotification {}
+unsafe impl Sync for MemoryNotification {}
+
+impl MemoryNotification {
+    fn from_borrowed(p: *mut ICLRMemoryNotificationCallback) -> Result<MemoryNotification, HostingError> {
+        let notification = PtrCtr::new_checked(p)
+            .map(|inner| MemoryNotification { inner })
+            .map_err(|_| HostingError::null_pointer(CALL!(IHostMemoryManager::RegisterMemoryNotificationCallback)))?;
+        notification.increment();
+        Ok(notification)
+    }
+
+    pub fn notify(&self, available: MemoryAvailable) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRMemoryNotificationCallback::OnMemoryNotification, (*self.inner.as_const()).OnMemoryNotification(available.raw())).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(MemoryNotification);
+
+pub trait HostMalloc: Send + Sync {
+    fn alloc(&self, size: usize, level: CriticalLevel) -> Result<*mut c_void, HRESULT>;
+    fn free(&self, mem: *mut c_void) -> Result<(), HRESULT>;
+}
+
+pub trait HostMemoryManager: Send + Sync + 'static {
+    fn create_malloc(&self, kind: MallocKind) -> Result<Box<dyn HostMalloc>, HRESULT> {
+        HeapMalloc::new(kind).map(|heap| Box::new(heap) as Box<dyn HostMalloc>)
+    }
+
+    fn virtual_alloc(&self, address: *mut c_void, size: usize, allocation_type: DWORD, protect: DWORD, _level: CriticalLevel) 
+        -> Result<*mut c_void, HRESULT> 
+    {
+        let mem = unsafe { memoryapi::VirtualAlloc(address, size, allocation_type, protect) };
+        if mem.is_null() { Err(E_OUTOFMEMORY) } else { Ok(mem) }
+    }
+
+    fn virtual_free(&self, address: *mut c_void, size: usize, free_type: DWORD) -> Result<(), HRESULT> {
+        match unsafe { memoryapi::VirtualFree(address, size, free_type) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    //Returns the number of bytes written to the buffer
+    fn virtual_query(&self, address: *mut c_void, buffer: *mut c_void, length: usize) -> Result<usize, HRESULT> {
+        match unsafe { memoryapi::VirtualQuery(address, buffer as *mut _, length) } {
+            0 => Err(last_error()), 
+            written => Ok(written),
+        }
+    }
+
+    //Returns the previous protection
+    fn virtual_protect(&self, address: *mut c_void, size: usize, protect: DWORD) -> Result<DWORD, HRESULT> {
+        let mut old: DWORD = 0;
+        match unsafe { memoryapi::VirtualProtect(address, size, protect, &mut old) } {
+            0 => Err(last_error()), 
+            _ => Ok(old),
+        }
+    }
+
+    //Memory load as a percentage, and available bytes
+    fn memory_load(&self) -> Result<(DWORD, usize), HRESULT> {
+        let mut status: MEMORYSTATUSEX = unsafe { mem::zeroed() };
+        status.dwLength = mem::size_of::<MEMORYSTATUSEX>() as DWORD;
+        match unsafe { GlobalMemoryStatusEx(&mut status) } {
+            0 => Err(last_error()), 
+            _ => Ok((status.dwMemoryLoad, status.ullAvailPhys as usize)),
+        }
+    }
+
+    //Called once by the runtime; keep the notification to report pressure
+    fn register_notification(&self, _notification: MemoryNotification) {}
+
+    //Advisory: the runtime is about to reserve (or has reserved) a range
+    fn needs_virtual_address_space(&self, _address: *mut c_void, _size: usize) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn acquired_virtual_address_space(&self, _address: *mut c_void, _size: usize) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn released_virtual_address_space(&self, _address: *mut c_void) -> Result<(), HRESULT> {
+        Ok(())
+    }
+}
+
+//A private Win32 heap, the default CreateMalloc answer
+pub struct HeapMalloc {
+    heap: HANDLE,
+}
+
+unsafe impl Send for HeapMalloc {}
+unsafe impl Sync for HeapMalloc {}
+
+impl HeapMalloc {
+    pub fn new(kind: MallocKind) -> Result<HeapMalloc, HRESULT> {
+        let mut options = 0;
+        if !kind.thread_safe {
+            options |= HEAP_NO_SERIALIZE;
+        }
+        if kind.executable {
+            options |= HEAP_CREATE_ENABLE_EXECUTE;
+        }
+        let heap = unsafe { HeapCreate(options, 0, 0) };
+        if heap.is_null() { Err(last_error()) } else { Ok(HeapMalloc { heap }) }
+    }
+}
+
+impl HostMalloc for HeapMalloc {
+    fn alloc(&self, size: usize, _level: CriticalLevel) -> Result<*mut c_void, HRESULT> {
+        let mem = unsafe { HeapAlloc(self.heap, 0, size) };
+        if mem.is_null() { Err(E_OUTOFMEMORY) } else { Ok(mem) }
+    }
+
+    fn free(&self, mem: *mut c_void) -> Result<(), HRESULT> {
+        match unsafe { HeapFree(self.heap, 0, mem) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for HeapMalloc {
+    fn drop(&mut self) {
+        unsafe { HeapDestroy(self.heap) };
+    }
+}
+
+type MallocObject = ComBox<IHostMallocVtbl, Box<dyn HostMalloc>>;
+type MemoryObject<M> = ComBox<IHostMemoryManagerVtbl, M>;
+
+//A new IHostMemoryManager object owned by the caller
+pub(crate) fn create<M: HostMemoryManager>(manager: M) -> *mut IUnknown {
+    let vtable = IHostMemoryManagerVtbl {
+        parent: MemoryObject::<M>::unknown_vtbl(), 
+        CreateMalloc: create_malloc::<M>, 
+        VirtualAlloc: virtual_alloc::<M>, 
+        VirtualFree: virtual_free::<M>, 
+        VirtualQuery: virtual_query::<M>, 
+        VirtualProtect: virtual_protect::<M>, 
+        GetMemoryLoad: get_memory_load::<M>, 
+        RegisterMemoryNotificationCallback: register_notification::<M>, 
+        NeedsVirtualAddressSpace: needs_virtual_address_space::<M>, 
+        AcquiredVirtualAddressSpace: acquired_virtual_address_space::<M>, 
+        ReleasedVirtualAddressSpace: released_virtual_address_space::<M>,
+    };
+    MemoryObject::as_interface(MemoryObject::new(vtable, vec![IHostMemoryManager::uuidof()], manager))
+}
+
+unsafe extern "system" fn create_malloc<M: HostMemoryManager>(this: *mut IHostMemoryManager, kind: DWORD, ppv: *mut *mut IHostMalloc) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        let malloc = manager.create_malloc(MallocKind::from_raw(kind))?;
+        let vtable = IHostMallocVtbl {
+            parent: MallocObject::unknown_vtbl(), 
+            Alloc: malloc_alloc, 
+            DebugAlloc: malloc_debug_alloc, 
+            Free: malloc_free,
+        };
+        *ppv = MallocObject::as_interface(MallocObject::new(vtable, vec![IHostMalloc::uuidof()], malloc));
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn virtual_alloc<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: *mut c_void, size: SIZE_T, 
+    allocation_type: DWORD, protect: DWORD, level: EMemoryCriticalLevel, ppv: *mut *mut c_void) -> HRESULT 
+{
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *ppv = manager.virtual_alloc(address, size, allocation_type, protect, CriticalLevel::from_raw(level))?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn virtual_free<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: LPVOID, size: SIZE_T, free_type: DWORD) -> HRESULT {
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.virtual_free(address, size, free_type))
+}
+
+unsafe extern "system" fn virtual_query<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: *mut c_void, buffer: *mut c_void, 
+    length: SIZE_T, result: *mut SIZE_T) -> HRESULT 
+{
+    if result.is_null() {
+        return E_POINTER;
+    }
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *result = manager.virtual_query(address, buffer, length)?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn virtual_protect<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: *mut c_void, size: SIZE_T, 
+    protect: DWORD, old_protect: *mut DWORD) -> HRESULT 
+{
+    if old_protect.is_null() {
+        return E_POINTER;
+    }
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *old_protect = manager.virtual_protect(address, size, protect)?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn get_memory_load<M: HostMemoryManager>(this: *mut IHostMemoryManager, load: *mut DWORD, available: *mut SIZE_T) -> HRESULT {
+    if load.is_null() || available.is_null() {
+        return E_POINTER;
+    }
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        let (percent, bytes) = manager.memory_load()?;
+        *load = percent;
+        *available = bytes;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn register_notification<M: HostMemoryManager>(this: *mut IHostMemoryManager, callback: *mut ICLRMemoryNotificationCallback) -> HRESULT {
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        manager.register_notification(MemoryNotification::from_borrowed(callback)?);
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn needs_virtual_address_space<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: LPVOID, size: SIZE_T) -> HRESULT {
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.needs_virtual_address_space(address, size))
+}
+
+unsafe extern "system" fn acquired_virtual_address_space<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: LPVOID, size: SIZE_T) -> HRESULT {
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.acquired_virtual_address_space(address, size))
+}
+
+unsafe extern "system" fn released_virtual_address_space<M: HostMemoryManager>(this: *mut IHostMemoryManager, address: LPVOID) -> HRESULT {
+    let manager = &MemoryObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.released_virtual_address_space(address))
+}
+
+unsafe extern "system" fn malloc_alloc(this: *mut IHostMalloc, size: SIZE_T, level: EMemoryCriticalLevel, ppv: *mut *mut c_void) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    let malloc = &MallocObject::from_this(this).value;
+    catch_and_translate(|| {
+        *ppv = malloc.alloc(size, CriticalLevel::from_raw(level))?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn malloc_debug_alloc(this: *mut IHostMalloc, size: SIZE_T, level: EMemoryCriticalLevel, _file: *mut c_char, 
+    _line: c_int, ppv: *mut *mut c_void) -> HRESULT 
+{
+    malloc_alloc(this, size, level, ppv)
+}
+
+unsafe extern "system" fn malloc_free(this: *mut IHostMalloc, mem: *mut c_void) -> HRESULT {
+    let malloc = &MallocObject::from_this(this).value;
+    catch_and_translate(|| malloc.free(mem))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn malloc_kind_from_raw() {
+        assert_eq!(MallocKind::from_raw(0), MallocKind::default());
+        assert_eq!(MallocKind::from_raw(MALLOC_THREADSAFE | MALLOC_EXECUTABLE), MallocKind { thread_safe: true, executable: true });
+        assert_eq!(MallocKind::from_raw(MALLOC_EXECUTABLE), MallocKind { thread_safe: false, executable: true });
+    }
+
+    #[test]
+    fn critical_level_from_raw() {
+        assert_eq!(CriticalLevel::from_raw(eTaskCritical), CriticalLevel::Task);
+        assert_eq!(CriticalLevel::from_raw(eAppDomainCritical), CriticalLevel::AppDomain);
+        assert_eq!(CriticalLevel::from_raw(eProcessCritical), CriticalLevel::Process);
+    }
+}
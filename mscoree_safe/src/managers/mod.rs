@@ -0,0 +1,194 @@
+// mod.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Host-side managers: traits a host implements in Rust, turned into the 
+// IHost*Manager COM objects the runtime asks for through IHostControl. 
+// Build a HostControl with the managers you want and hand it to 
+// ClrRuntimeHost::set_host_control before Start; the runtime keeps the 
+// objects alive for its own lifetime.
+use std::ptr;
+use std::sync::Arc;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualGUID, GUID, REFIID};
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{E_NOINTERFACE, E_POINTER, HRESULT, S_OK, WAIT_TIMEOUT};
+use winapi::um::winbase::{WAIT_ABANDONED, WAIT_IO_COMPLETION, WAIT_OBJECT_0};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::corerror::{HOST_E_ABANDONED, HOST_E_INTERRUPTED, HOST_E_TIMEOUT};
+use mscoree_sys::mscoree::{IHostAssemblyManager, IHostControl, IHostControlVtbl, IHostGCManager, IHostMemoryManager, IHostSecurityManager, IHostSyncManager, IHostTaskManager};
+
+use bridge::{ManagedBridge, Managers};
+use com::ComBox;
+use error::last_error;
+use unwind::catch_and_translate;
+
+pub mod assembly;
+pub mod gc;
+pub mod memory;
+pub mod security;
+pub mod sync;
+pub mod task;
+
+pub use self::assembly::{AssemblyRequest, HostAssemblyStore, ModuleRequest, ProvidedAssembly, ProvidedModule};
+pub use self::gc::HostGcManager;
+pub use self::memory::{CriticalLevel, HeapMalloc, HostMalloc, HostMemoryManager, MallocKind, MemoryAvailable, MemoryNotification};
+pub use self::security::{ContextType, HostSecurityContext, HostSecurityManager, SecurityContext};
+pub use self::sync::{ClrSyncManager, HostAutoEvent, HostCrst, HostManualEvent, HostSemaphore, HostSyncManager, Win32Crst, Win32Event, Win32Semaphore};
+pub use self::task::{current_thread_task, HostTask, HostTaskHandle, HostTaskManager, ThreadStart, ThreadTask, WaitOption};
+
+//One reference to a manager object, released with the HostControl
+struct Registered {
+    iid: GUID, 
+    object: *mut IUnknown,
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        unsafe { (*self.object).Release() };
+    }
+}
+
+pub struct HostControl {
+    managers: Vec<Registered>, 
+    app_domain_managers: Option<Arc<Managers>>,
+}
+
+type HostControlObject = ComBox<IHostControlVtbl, HostControl>;
+
+impl HostControl {
+    pub fn new() -> HostControl {
+        HostControl { managers: Vec::new(), app_domain_managers: None }
+    }
+
+    pub fn memory_manager<M: HostMemoryManager>(mut self, manager: M) -> HostControl {
+        self.register(IHostMemoryManager::uuidof(), memory::create(manager));
+        self
+    }
+
+    pub fn assembly_store<S: HostAssemblyStore>(mut self, store: S) -> HostControl {
+        self.register(IHostAssemblyManager::uuidof(), assembly::create(store));
+        self
+    }
+
+    pub fn gc_manager<G: HostGcManager>(mut self, manager: G) -> HostControl {
+        self.register(IHostGCManager::uuidof(), gc::create(manager));
+        self
+    }
+
+    pub fn security_manager<S: HostSecurityManager>(mut self, manager: S) -> HostControl {
+        self.register(IHostSecurityManager::uuidof(), security::create(manager));
+        self
+    }
+
+    pub fn task_manager<M: HostTaskManager>(mut self, manager: M) -> HostControl {
+        self.register(IHostTaskManager::uuidof(), task::create(manager));
+        self
+    }
+
+    pub fn sync_manager<S: HostSyncManager>(mut self, manager: S) -> HostControl {
+        self.register(IHostSyncManager::uuidof(), sync::create(manager));
+        self
+    }
+
+    //Hands every AppDomainManager the runtime reports to the bridge, see 
+    // ManagedBridge::attach
+    pub fn app_domain_managers(mut self, bridge: &ManagedBridge) -> HostControl {
+        self.app_domain_managers = Some(bridge.managers());
+        self
+    }
+
+    //Takes over the caller's reference; a later registration for the 
+    // same interface replaces the earlier one
+    pub(crate) fn register(&mut self, iid: GUID, object: *mut IUnknown) {
+        self.managers.retain(|m| !IsEqualGUID(&m.iid, &iid));
+        self.managers.push(Registered { iid, object });
+    }
+
+    //A new IHostControl object owned by the caller
+    pub(crate) fn into_raw(self) -> *mut IHostControl {
+        let vtable = IHostControlVtbl {
+            parent: HostControlObject::unknown_vtbl(), 
+            GetHostManager: get_host_manager, 
+            SetAppDomainManager: set_app_domain_manager,
+        };
+        HostControlObject::as_interface(HostControlObject::new(vtable, vec![IHostControl::uuidof()], self))
+    }
+}
+
+impl Default for HostControl {
+    fn default() -> HostControl {
+        HostControl::new()
+    }
+}
+
+unsafe extern "system" fn get_host_manager(this: *mut IHostControl, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    let control = &HostControlObject::from_this(this).value;
+    //E_NOINTERFACE tells the runtime to use its own implementation
+    match control.managers.iter().find(|m| IsEqualGUID(&m.iid, &*riid)) {
+        Some(m) => {
+            (*m.object).AddRef();
+            *ppv = m.object as *mut c_void;
+            S_OK
+        }, 
+        None => E_NOINTERFACE,
+    }
+}
+
+//Without a bridge the manager is simply not kept
+unsafe extern "system" fn set_app_domain_manager(this: *mut IHostControl, domain_id: DWORD, manager: *mut IUnknown) -> HRESULT {
+    let control = &HostControlObject::from_this(this).value;
+    catch_and_translate(|| match control.app_domain_managers {
+        Some(ref managers) => managers.record(domain_id, manager).map_err(|err| err.hresult()), 
+        None => Ok(()),
+    })
+}
+
+//Maps a Win32 wait return code to what the runtime expects from a host wait
+pub(crate) fn wait_result(ret: DWORD) -> Result<(), HRESULT> {
+    match ret {
+        WAIT_OBJECT_0 => Ok(()), 
+        WAIT_TIMEOUT => Err(HOST_E_TIMEOUT), 
+        WAIT_IO_COMPLETION => Err(HOST_E_INTERRUPTED), 
+        WAIT_ABANDONED => Err(HOST_E_ABANDONED), 
+        _ => Err(last_error()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wait_result_maps_codes() {
+        assert_eq!(wait_result(WAIT_OBJECT_0), Ok(()));
+        assert_eq!(wait_result(WAIT_TIMEOUT), Err(HOST_E_TIMEOUT));
+        assert_eq!(wait_result(WAIT_IO_COMPLETION), Err(HOST_E_INTERRUPTED));
+        assert_eq!(wait_result(WAIT_ABANDONED), Err(HOST_E_ABANDONED));
+    }
+}
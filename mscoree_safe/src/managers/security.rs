@@ -0,0 +1,268 @@
+// security.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IHostSecurityManager/IHostSecurityContext: let the host own impersonation 
+// and flow its own security context across the runtime's async points 
+// (thread pool work items, finalizers, I/O completions). The token hooks 
+// default to the Win32 calls the runtime would otherwise make itself.
+use std::ptr;
+
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::shared::ntdef::HANDLE;
+use winapi::shared::winerror::{E_POINTER, HRESULT};
+use winapi::um::processthreadsapi::{self, GetCurrentThread};
+use winapi::um::securitybaseapi;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{
+    EContextType, 
+    IHostSecurityContext, 
+    IHostSecurityContextVtbl, 
+    IHostSecurityManager, 
+    IHostSecurityManagerVtbl, 
+    eRestrictedContext
+};
+
+use com::ComBox;
+use error::last_error;
+use unwind::catch_and_translate;
+use wrappers::{PtrCtr, RefCounted};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContextType {
+    //The context of the running code
+    Current, 
+    //A reduced context used for finalizers and similar runtime callouts
+    Restricted,
+}
+
+impl ContextType {
+    pub(crate) fn from_raw(raw: EContextType) -> ContextType {
+        if raw == eRestrictedContext { ContextType::Restricted } else { ContextType::Current }
+    }
+}
+
+pub trait HostSecurityContext: Send + Sync + 'static {
+    //A snapshot the runtime can carry to another thread
+    fn capture(&self) -> Result<Box<dyn HostSecurityContext>, HRESULT>;
+}
+
+//A context handed to us by the runtime. It is one the host produced 
+// earlier, and host_context gets back to the Rust implementation.
+pub struct SecurityContext {
+    inner: PtrCtr<IHostSecurityContext>,
+}
+
+unsafe impl Send for SecurityContext {}
+unsafe impl Sync for SecurityContext {}
+
+impl SecurityContext {
+    fn from_borrowed(p: *mut IHostSecurityContext) -> Option<SecurityContext> {
+        PtrCtr::new_checked(p).ok().map(|inner| {
+            let context = SecurityContext { inner };
+            context.increment();
+            context
+        })
+    }
+
+    pub fn host_context(&self) -> Option<&dyn HostSecurityContext> {
+        let raw = self.inner.as_const() as *mut IHostSecurityContext;
+        unsafe {
+            //Ours if the vtable points at our Capture entry
+            if (*(*raw).lpVtbl).Capture as usize == context_capture as usize {
+                Some(&*ContextObject::from_this(raw).value)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+COM_WRAPPER!(SecurityContext);
+
+pub trait HostSecurityManager: Send + Sync + 'static {
+    fn impersonate_logged_on_user(&self, token: HANDLE) -> Result<(), HRESULT> {
+        match unsafe { securitybaseapi::ImpersonateLoggedOnUser(token) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    fn revert_to_self(&self) -> Result<(), HRESULT> {
+        match unsafe { securitybaseapi::RevertToSelf() } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    //The returned handle is owned by the caller
+    fn open_thread_token(&self, desired_access: DWORD, open_as_self: bool) -> Result<HANDLE, HRESULT> {
+        let mut token: HANDLE = ptr::null_mut();
+        match unsafe { processthreadsapi::OpenThreadToken(GetCurrentThread(), desired_access, open_as_self as BOOL, &mut token) } {
+            0 => Err(last_error()), 
+            _ => Ok(token),
+        }
+    }
+
+    //A null token stops impersonation on the current thread
+    fn set_thread_token(&self, token: HANDLE) -> Result<(), HRESULT> {
+        match unsafe { processthreadsapi::SetThreadToken(ptr::null_mut(), token) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    //None tells the runtime there is no host context to flow
+    fn security_context(&self, _kind: ContextType) -> Result<Option<Box<dyn HostSecurityContext>>, HRESULT> {
+        Ok(None)
+    }
+
+    //Called when the runtime restores a previously captured context; 
+    // None clears it
+    fn set_security_context(&self, _kind: ContextType, _context: Option<SecurityContext>) -> Result<(), HRESULT> {
+        Ok(())
+    }
+}
+
+type ContextObject = ComBox<IHostSecurityContextVtbl, Box<dyn HostSecurityContext>>;
+type SecurityObject<S> = ComBox<IHostSecurityManagerVtbl, S>;
+
+fn context_into_raw(context: Box<dyn HostSecurityContext>) -> *mut IHostSecurityContext {
+    let vtable = IHostSecurityContextVtbl {
+        parent: ContextObject::unknown_vtbl(), 
+        Capture: context_capture,
+    };
+    ContextObject::as_interface(ContextObject::new(vtable, vec![IHostSecurityContext::uuidof()], context))
+}
+
+//A new IHostSecurityManager object owned by the caller
+pub(crate) fn create<S: HostSecurityManager>(manager: S) -> *mut IUnknown {
+    let vtable = IHostSecurityManagerVtbl {
+        parent: SecurityObject::<S>::unknown_vtbl(), 
+        ImpersonateLoggedOnUser: impersonate_logged_on_user::<S>, 
+        RevertToSelf: revert_to_self::<S>, 
+        OpenThreadToken: open_thread_token::<S>, 
+        SetThreadToken: set_thread_token::<S>, 
+        GetSecurityContext: get_security_context::<S>, 
+        SetSecurityContext: set_security_context::<S>,
+    };
+    SecurityObject::as_interface(SecurityObject::new(vtable, vec![IHostSecurityManager::uuidof()], manager))
+}
+
+unsafe extern "system" fn context_capture(this: *mut IHostSecurityContext, ppv: *mut *mut IHostSecurityContext) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    let context = &ContextObject::from_this(this).value;
+    catch_and_translate(|| {
+        *ppv = context_into_raw(context.capture()?);
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn impersonate_logged_on_user<S: HostSecurityManager>(this: *mut IHostSecurityManager, token: HANDLE) -> HRESULT {
+    let manager = &SecurityObject::<S>::from_this(this).value;
+    catch_and_translate(|| manager.impersonate_logged_on_user(token))
+}
+
+unsafe extern "system" fn revert_to_self<S: HostSecurityManager>(this: *mut IHostSecurityManager) -> HRESULT {
+    let manager = &SecurityObject::<S>::from_this(this).value;
+    catch_and_translate(|| manager.revert_to_self())
+}
+
+unsafe extern "system" fn open_thread_token<S: HostSecurityManager>(this: *mut IHostSecurityManager, desired_access: DWORD, 
+    open_as_self: BOOL, token: *mut HANDLE) -> HRESULT 
+{
+    if token.is_null() {
+        return E_POINTER;
+    }
+    let manager = &SecurityObject::<S>::from_this(this).value;
+    catch_and_translate(|| {
+        *token = manager.open_thread_token(desired_access, open_as_self != 0)?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn set_thread_token<S: HostSecurityManager>(this: *mut IHostSecurityManager, token: HANDLE) -> HRESULT {
+    let manager = &SecurityObject::<S>::from_this(this).value;
+    catch_and_translate(|| manager.set_thread_token(token))
+}
+
+unsafe extern "system" fn get_security_context<S: HostSecurityManager>(this: *mut IHostSecurityManager, kind: EContextType, 
+    ppv: *mut *mut IHostSecurityContext) -> HRESULT 
+{
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    let manager = &SecurityObject::<S>::from_this(this).value;
+    catch_and_translate(|| {
+        if let Some(context) = manager.security_context(ContextType::from_raw(kind))? {
+            *ppv = context_into_raw(context);
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn set_security_context<S: HostSecurityManager>(this: *mut IHostSecurityManager, kind: EContextType, 
+    context: *mut IHostSecurityContext) -> HRESULT 
+{
+    let manager = &SecurityObject::<S>::from_this(this).value;
+    let context = SecurityContext::from_borrowed(context);
+    catch_and_translate(|| manager.set_security_context(ContextType::from_raw(kind), context))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use winapi::shared::winerror::S_OK;
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl HostSecurityContext for Counted {
+        fn capture(&self) -> Result<Box<dyn HostSecurityContext>, HRESULT> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(Counted(self.0.clone())))
+        }
+    }
+
+    #[test]
+    fn captured_context_round_trips() {
+        let captures = Arc::new(AtomicUsize::new(0));
+        let raw = context_into_raw(Box::new(Counted(captures.clone())));
+        let mut cloned: *mut IHostSecurityContext = ptr::null_mut();
+        unsafe {
+            assert_eq!(((*(*raw).lpVtbl).Capture)(raw, &mut cloned), S_OK);
+            let context = SecurityContext::from_borrowed(cloned).unwrap();
+            assert!(context.host_context().is_some());
+            drop(context);
+            (*cloned).Release();
+            (*raw).Release();
+        }
+        assert_eq!(captures.load(Ordering::SeqCst), 1);
+        assert_eq!(Arc::strong_count(&captures), 1);
+    }
+}
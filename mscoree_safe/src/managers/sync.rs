@@ -0,0 +1,531 @@
+// sync.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IHostSyncManager: host-provided locks, events and semaphores, for hosts 
+// that schedule tasks cooperatively and must know when one blocks. The 
+// defaults are the Win32 primitives, so a host overrides only the kinds 
+// it needs to see. Message pumping waits are treated as plain alertable 
+// waits by the defaults.
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_long;
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::shared::ntdef::HANDLE;
+use winapi::shared::winerror::{E_POINTER, HRESULT};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::minwinbase::CRITICAL_SECTION;
+use winapi::um::synchapi::{
+    CreateEventW, 
+    CreateSemaphoreW, 
+    DeleteCriticalSection, 
+    EnterCriticalSection, 
+    InitializeCriticalSectionAndSpinCount, 
+    LeaveCriticalSection, 
+    ReleaseSemaphore, 
+    ResetEvent, 
+    SetCriticalSectionSpinCount, 
+    SetEvent, 
+    TryEnterCriticalSection, 
+    WaitForSingleObjectEx
+};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{
+    ICLRSyncManager, 
+    IHostAutoEvent, 
+    IHostAutoEventVtbl, 
+    IHostCrst, 
+    IHostCrstVtbl, 
+    IHostManualEvent, 
+    IHostManualEventVtbl, 
+    IHostSemaphore, 
+    IHostSemaphoreVtbl, 
+    IHostSyncManager, 
+    IHostSyncManagerVtbl, 
+    IHostTask
+};
+
+use com::ComBox;
+use error::{HostingError, last_error};
+use managers::wait_result;
+use managers::task::{HostTaskHandle, WaitOption};
+use unwind::catch_and_translate;
+use wrappers::{PtrCtr, RefCounted};
+
+pub trait HostCrst: Send + Sync + 'static {
+    fn enter(&self, option: WaitOption) -> Result<(), HRESULT>;
+    fn leave(&self) -> Result<(), HRESULT>;
+    fn try_enter(&self, option: WaitOption) -> Result<bool, HRESULT>;
+
+    fn set_spin_count(&self, _spin_count: u32) -> Result<(), HRESULT> {
+        Ok(())
+    }
+}
+
+pub trait HostAutoEvent: Send + Sync + 'static {
+    fn wait(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT>;
+    fn set(&self) -> Result<(), HRESULT>;
+}
+
+pub trait HostManualEvent: Send + Sync + 'static {
+    fn wait(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT>;
+    fn reset(&self) -> Result<(), HRESULT>;
+    fn set(&self) -> Result<(), HRESULT>;
+}
+
+pub trait HostSemaphore: Send + Sync + 'static {
+    fn wait(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT>;
+    //Returns the previous count
+    fn release(&self, count: i32) -> Result<i32, HRESULT>;
+}
+
+pub trait HostSyncManager: Send + Sync + 'static {
+    //None for the default spin count
+    fn create_crst(&self, spin_count: Option<u32>) -> Result<Box<dyn HostCrst>, HRESULT> {
+        Ok(Box::new(Win32Crst::new(spin_count.unwrap_or(0))?))
+    }
+
+    fn create_auto_event(&self) -> Result<Box<dyn HostAutoEvent>, HRESULT> {
+        Ok(Box::new(Win32Event::new(false, false)?))
+    }
+
+    fn create_manual_event(&self, initial_state: bool) -> Result<Box<dyn HostManualEvent>, HRESULT> {
+        Ok(Box::new(Win32Event::new(true, initial_state)?))
+    }
+
+    //Backs a Monitor; `cookie` identifies it to ClrSyncManager::monitor_owner
+    fn create_monitor_event(&self, _cookie: usize) -> Result<Box<dyn HostAutoEvent>, HRESULT> {
+        self.create_auto_event()
+    }
+
+    //Back a ReaderWriterLock; `cookie` identifies it to 
+    // ClrSyncManager::rw_lock_owners
+    fn create_rw_lock_writer_event(&self, _cookie: usize) -> Result<Box<dyn HostAutoEvent>, HRESULT> {
+        self.create_auto_event()
+    }
+
+    fn create_rw_lock_reader_event(&self, initial_state: bool, _cookie: usize) -> Result<Box<dyn HostManualEvent>, HRESULT> {
+        self.create_manual_event(initial_state)
+    }
+
+    fn create_semaphore(&self, initial: u32, max: u32) -> Result<Box<dyn HostSemaphore>, HRESULT> {
+        Ok(Box::new(Win32Semaphore::new(initial, max)?))
+    }
+
+    //Called once; keep the manager to ask who owns a lock (deadlock detection)
+    fn set_clr_sync_manager(&self, _manager: ClrSyncManager) {}
+}
+
+//ICLRSyncManager: which tasks own the runtime's monitors and 
+// reader-writer locks
+pub struct ClrSyncManager {
+    inner: PtrCtr<ICLRSyncManager>,
+}
+
+unsafe impl Send for ClrSyncManager {}
+unsafe impl Sync for ClrSyncManager {}
+
+impl ClrSyncManager {
+    fn from_borrowed(p: *mut ICLRSyncManager) -> Result<ClrSyncManager, HostingError> {
+        let manager = PtrCtr::new_checked(p)
+            .map(|inner| ClrSyncManager { inner })
+            .map_err(|_| HostingError::null_pointer(CALL!(IHostSyncManager::SetCLRSyncManager)))?;
+        manager.increment();
+        Ok(manager)
+    }
+
+    pub fn monitor_owner(&self, cookie: usize) -> Result<Option<HostTaskHandle>, HostingError> {
+        let mut owner: *mut IHostTask = ptr::null_mut();
+        CHECK_HR!(ICLRSyncManager::GetMonitorOwner, (*self.inner.as_const()).GetMonitorOwner(cookie, &mut owner))?;
+        Ok(if owner.is_null() { None } else { Some(unsafe { HostTaskHandle::from_raw(owner) }) })
+    }
+
+    pub fn rw_lock_owners(&self, cookie: usize) -> Result<Vec<HostTaskHandle>, HostingError> {
+        let mut iterator: SIZE_T = 0;
+        CHECK_HR!(ICLRSyncManager::CreateRWLockOwnerIterator, (*self.inner.as_const()).CreateRWLockOwnerIterator(cookie, &mut iterator))?;
+        let mut owners = Vec::new();
+        let result = loop {
+            let mut owner: *mut IHostTask = ptr::null_mut();
+            if let Err(hr) = CHECK_HR!(ICLRSyncManager::GetRWLockOwnerNext, (*self.inner.as_const()).GetRWLockOwnerNext(iterator, &mut owner)) {
+                break Err(hr);
+            }
+            if owner.is_null() {
+                break Ok(());
+            }
+            owners.push(unsafe { HostTaskHandle::from_raw(owner) });
+        };
+        unsafe { (*self.inner.as_const()).DeleteRWLockOwnerIterator(iterator) };
+        result.map(|_| owners)
+    }
+}
+
+COM_WRAPPER!(ClrSyncManager);
+
+//The default critical section; boxed so it never moves once initialized
+pub struct Win32Crst {
+    section: Box<UnsafeCell<CRITICAL_SECTION>>,
+}
+
+unsafe impl Send for Win32Crst {}
+unsafe impl Sync for Win32Crst {}
+
+impl Win32Crst {
+    pub fn new(spin_count: u32) -> Result<Win32Crst, HRESULT> {
+        let section: Box<UnsafeCell<CRITICAL_SECTION>> = Box::new(UnsafeCell::new(unsafe { mem::zeroed() }));
+        match unsafe { InitializeCriticalSectionAndSpinCount(section.get(), spin_count) } {
+            0 => Err(last_error()), 
+            _ => Ok(Win32Crst { section }),
+        }
+    }
+}
+
+impl HostCrst for Win32Crst {
+    fn enter(&self, _option: WaitOption) -> Result<(), HRESULT> {
+        unsafe { EnterCriticalSection(self.section.get()) };
+        Ok(())
+    }
+
+    fn leave(&self) -> Result<(), HRESULT> {
+        unsafe { LeaveCriticalSection(self.section.get()) };
+        Ok(())
+    }
+
+    fn try_enter(&self, _option: WaitOption) -> Result<bool, HRESULT> {
+        Ok(unsafe { TryEnterCriticalSection(self.section.get()) } != 0)
+    }
+
+    fn set_spin_count(&self, spin_count: u32) -> Result<(), HRESULT> {
+        unsafe { SetCriticalSectionSpinCount(self.section.get(), spin_count) };
+        Ok(())
+    }
+}
+
+impl Drop for Win32Crst {
+    fn drop(&mut self) {
+        unsafe { DeleteCriticalSection(self.section.get()) };
+    }
+}
+
+//The default auto- and manual-reset event
+pub struct Win32Event {
+    event: HANDLE,
+}
+
+unsafe impl Send for Win32Event {}
+unsafe impl Sync for Win32Event {}
+
+impl Win32Event {
+    pub fn new(manual_reset: bool, initial_state: bool) -> Result<Win32Event, HRESULT> {
+        let event = unsafe { CreateEventW(ptr::null_mut(), manual_reset as BOOL, initial_state as BOOL, ptr::null()) };
+        if event.is_null() { Err(last_error()) } else { Ok(Win32Event { event }) }
+    }
+
+    fn wait_event(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+        wait_handle(self.event, timeout_ms, option)
+    }
+
+    fn set_event(&self) -> Result<(), HRESULT> {
+        match unsafe { SetEvent(self.event) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+}
+
+impl HostAutoEvent for Win32Event {
+    fn wait(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+        self.wait_event(timeout_ms, option)
+    }
+
+    fn set(&self) -> Result<(), HRESULT> {
+        self.set_event()
+    }
+}
+
+impl HostManualEvent for Win32Event {
+    fn wait(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+        self.wait_event(timeout_ms, option)
+    }
+
+    fn reset(&self) -> Result<(), HRESULT> {
+        match unsafe { ResetEvent(self.event) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    fn set(&self) -> Result<(), HRESULT> {
+        self.set_event()
+    }
+}
+
+impl Drop for Win32Event {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.event) };
+    }
+}
+
+pub struct Win32Semaphore {
+    semaphore: HANDLE,
+}
+
+unsafe impl Send for Win32Semaphore {}
+unsafe impl Sync for Win32Semaphore {}
+
+impl Win32Semaphore {
+    pub fn new(initial: u32, max: u32) -> Result<Win32Semaphore, HRESULT> {
+        let semaphore = unsafe { CreateSemaphoreW(ptr::null_mut(), initial as c_long, max as c_long, ptr::null()) };
+        if semaphore.is_null() { Err(last_error()) } else { Ok(Win32Semaphore { semaphore }) }
+    }
+}
+
+impl HostSemaphore for Win32Semaphore {
+    fn wait(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+        wait_handle(self.semaphore, timeout_ms, option)
+    }
+
+    fn release(&self, count: i32) -> Result<i32, HRESULT> {
+        let mut previous: c_long = 0;
+        match unsafe { ReleaseSemaphore(self.semaphore, count, &mut previous) } {
+            0 => Err(last_error()), 
+            _ => Ok(previous),
+        }
+    }
+}
+
+impl Drop for Win32Semaphore {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.semaphore) };
+    }
+}
+
+fn wait_handle(handle: HANDLE, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+    let alertable = option.alertable || option.msg_pump;
+    wait_result(unsafe { WaitForSingleObjectEx(handle, timeout_ms, alertable as BOOL) })
+}
+
+type CrstObject = ComBox<IHostCrstVtbl, Box<dyn HostCrst>>;
+type AutoEventObject = ComBox<IHostAutoEventVtbl, Box<dyn HostAutoEvent>>;
+type ManualEventObject = ComBox<IHostManualEventVtbl, Box<dyn HostManualEvent>>;
+type SemaphoreObject = ComBox<IHostSemaphoreVtbl, Box<dyn HostSemaphore>>;
+type SyncObject<S> = ComBox<IHostSyncManagerVtbl, S>;
+
+fn crst_into_raw(crst: Box<dyn HostCrst>) -> *mut IHostCrst {
+    let vtable = IHostCrstVtbl {
+        parent: CrstObject::unknown_vtbl(), 
+        Enter: crst_enter, 
+        Leave: crst_leave, 
+        TryEnter: crst_try_enter, 
+        SetSpinCount: crst_set_spin_count,
+    };
+    CrstObject::as_interface(CrstObject::new(vtable, vec![IHostCrst::uuidof()], crst))
+}
+
+fn auto_event_into_raw(event: Box<dyn HostAutoEvent>) -> *mut IHostAutoEvent {
+    let vtable = IHostAutoEventVtbl {
+        parent: AutoEventObject::unknown_vtbl(), 
+        Wait: auto_event_wait, 
+        Set: auto_event_set,
+    };
+    AutoEventObject::as_interface(AutoEventObject::new(vtable, vec![IHostAutoEvent::uuidof()], event))
+}
+
+fn manual_event_into_raw(event: Box<dyn HostManualEvent>) -> *mut IHostManualEvent {
+    let vtable = IHostManualEventVtbl {
+        parent: ManualEventObject::unknown_vtbl(), 
+        Wait: manual_event_wait, 
+        Reset: manual_event_reset, 
+        Set: manual_event_set,
+    };
+    ManualEventObject::as_interface(ManualEventObject::new(vtable, vec![IHostManualEvent::uuidof()], event))
+}
+
+fn semaphore_into_raw(semaphore: Box<dyn HostSemaphore>) -> *mut IHostSemaphore {
+    let vtable = IHostSemaphoreVtbl {
+        parent: SemaphoreObject::unknown_vtbl(), 
+        Wait: semaphore_wait, 
+        ReleaseSemaphore: semaphore_release,
+    };
+    SemaphoreObject::as_interface(SemaphoreObject::new(vtable, vec![IHostSemaphore::uuidof()], semaphore))
+}
+
+//A new IHostSyncManager object owned by the caller
+pub(crate) fn create<S: HostSyncManager>(manager: S) -> *mut IUnknown {
+    let vtable = IHostSyncManagerVtbl {
+        parent: SyncObject::<S>::unknown_vtbl(), 
+        SetCLRSyncManager: set_clr_sync_manager::<S>, 
+        CreateCrst: create_crst::<S>, 
+        CreateCrstWithSpinCount: create_crst_with_spin_count::<S>, 
+        CreateAutoEvent: create_auto_event::<S>, 
+        CreateManualEvent: create_manual_event::<S>, 
+        CreateMonitorEvent: create_monitor_event::<S>, 
+        CreateRWLockWriterEvent: create_rw_lock_writer_event::<S>, 
+        CreateRWLockReaderEvent: create_rw_lock_reader_event::<S>, 
+        CreateSemaphore: create_semaphore::<S>,
+    };
+    SyncObject::as_interface(SyncObject::new(vtable, vec![IHostSyncManager::uuidof()], manager))
+}
+
+//Fills an out-parameter, which is nulled first so failures leave it clean
+unsafe fn fill<T, F>(ppv: *mut *mut T, make: F) -> HRESULT 
+    where F: FnOnce() -> Result<*mut T, HRESULT>
+{
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    catch_and_translate(|| {
+        *ppv = make()?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn set_clr_sync_manager<S: HostSyncManager>(this: *mut IHostSyncManager, clr_manager: *mut ICLRSyncManager) -> HRESULT {
+    let manager = &SyncObject::<S>::from_this(this).value;
+    catch_and_translate(|| {
+        manager.set_clr_sync_manager(ClrSyncManager::from_borrowed(clr_manager)?);
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn create_crst<S: HostSyncManager>(this: *mut IHostSyncManager, ppv: *mut *mut IHostCrst) -> HRESULT {
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_crst(None).map(crst_into_raw))
+}
+
+unsafe extern "system" fn create_crst_with_spin_count<S: HostSyncManager>(this: *mut IHostSyncManager, spin_count: DWORD, 
+    ppv: *mut *mut IHostCrst) -> HRESULT 
+{
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_crst(Some(spin_count)).map(crst_into_raw))
+}
+
+unsafe extern "system" fn create_auto_event<S: HostSyncManager>(this: *mut IHostSyncManager, ppv: *mut *mut IHostAutoEvent) -> HRESULT {
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_auto_event().map(auto_event_into_raw))
+}
+
+unsafe extern "system" fn create_manual_event<S: HostSyncManager>(this: *mut IHostSyncManager, initial_state: BOOL, 
+    ppv: *mut *mut IHostManualEvent) -> HRESULT 
+{
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_manual_event(initial_state != 0).map(manual_event_into_raw))
+}
+
+unsafe extern "system" fn create_monitor_event<S: HostSyncManager>(this: *mut IHostSyncManager, cookie: SIZE_T, 
+    ppv: *mut *mut IHostAutoEvent) -> HRESULT 
+{
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_monitor_event(cookie).map(auto_event_into_raw))
+}
+
+unsafe extern "system" fn create_rw_lock_writer_event<S: HostSyncManager>(this: *mut IHostSyncManager, cookie: SIZE_T, 
+    ppv: *mut *mut IHostAutoEvent) -> HRESULT 
+{
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_rw_lock_writer_event(cookie).map(auto_event_into_raw))
+}
+
+unsafe extern "system" fn create_rw_lock_reader_event<S: HostSyncManager>(this: *mut IHostSyncManager, initial_state: BOOL, cookie: SIZE_T, 
+    ppv: *mut *mut IHostManualEvent) -> HRESULT 
+{
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_rw_lock_reader_event(initial_state != 0, cookie).map(manual_event_into_raw))
+}
+
+unsafe extern "system" fn create_semaphore<S: HostSyncManager>(this: *mut IHostSyncManager, initial: DWORD, max: DWORD, 
+    ppv: *mut *mut IHostSemaphore) -> HRESULT 
+{
+    let manager = &SyncObject::<S>::from_this(this).value;
+    fill(ppv, || manager.create_semaphore(initial, max).map(semaphore_into_raw))
+}
+
+unsafe extern "system" fn crst_enter(this: *mut IHostCrst, option: DWORD) -> HRESULT {
+    let crst = &CrstObject::from_this(this).value;
+    catch_and_translate(|| crst.enter(WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn crst_leave(this: *mut IHostCrst) -> HRESULT {
+    let crst = &CrstObject::from_this(this).value;
+    catch_and_translate(|| crst.leave())
+}
+
+unsafe extern "system" fn crst_try_enter(this: *mut IHostCrst, option: DWORD, succeeded: *mut BOOL) -> HRESULT {
+    if succeeded.is_null() {
+        return E_POINTER;
+    }
+    let crst = &CrstObject::from_this(this).value;
+    catch_and_translate(|| {
+        *succeeded = crst.try_enter(WaitOption::from_raw(option))? as BOOL;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn crst_set_spin_count(this: *mut IHostCrst, spin_count: DWORD) -> HRESULT {
+    let crst = &CrstObject::from_this(this).value;
+    catch_and_translate(|| crst.set_spin_count(spin_count))
+}
+
+unsafe extern "system" fn auto_event_wait(this: *mut IHostAutoEvent, ms: DWORD, option: DWORD) -> HRESULT {
+    let event = &AutoEventObject::from_this(this).value;
+    catch_and_translate(|| event.wait(ms, WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn auto_event_set(this: *mut IHostAutoEvent) -> HRESULT {
+    let event = &AutoEventObject::from_this(this).value;
+    catch_and_translate(|| event.set())
+}
+
+unsafe extern "system" fn manual_event_wait(this: *mut IHostManualEvent, ms: DWORD, option: DWORD) -> HRESULT {
+    let event = &ManualEventObject::from_this(this).value;
+    catch_and_translate(|| event.wait(ms, WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn manual_event_reset(this: *mut IHostManualEvent) -> HRESULT {
+    let event = &ManualEventObject::from_this(this).value;
+    catch_and_translate(|| event.reset())
+}
+
+unsafe extern "system" fn manual_event_set(this: *mut IHostManualEvent) -> HRESULT {
+    let event = &ManualEventObject::from_this(this).value;
+    catch_and_translate(|| event.set())
+}
+
+unsafe extern "system" fn semaphore_wait(this: *mut IHostSemaphore, ms: DWORD, option: DWORD) -> HRESULT {
+    let semaphore = &SemaphoreObject::from_this(this).value;
+    catch_and_translate(|| semaphore.wait(ms, WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn semaphore_release(this: *mut IHostSemaphore, count: c_long, previous: *mut c_long) -> HRESULT {
+    let semaphore = &SemaphoreObject::from_this(this).value;
+    catch_and_translate(|| {
+        let count = semaphore.release(count)?;
+        if !previous.is_null() {
+            *previous = count;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,574 @@
+// task.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IHostTaskManager/IHostTask: the host owns the threads the runtime runs 
+// on. Every hook has a default, and the defaults amount to plain OS 
+// threads, so a host can start from HostTaskManager with no overrides and 
+// take over pieces (thread creation, sleeping, priorities) one at a time.
+use std::cell::RefCell;
+use std::mem;
+use std::ptr;
+use std::sync::Mutex;
+
+use winapi::ctypes::c_int;
+use winapi::shared::basetsd::{SIZE_T, ULONG_PTR};
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPVOID, ULONG};
+use winapi::shared::ntdef::{HANDLE, LCID, PVOID};
+use winapi::shared::winerror::{E_NOTIMPL, E_POINTER, HRESULT};
+use winapi::um::handleapi::{CloseHandle, DuplicateHandle};
+use winapi::um::minwinbase::LPTHREAD_START_ROUTINE;
+use winapi::um::processthreadsapi::{
+    CreateThread, 
+    GetCurrentProcess, 
+    GetCurrentThread, 
+    GetThreadPriority, 
+    QueueUserAPC, 
+    ResumeThread, 
+    SetThreadPriority, 
+    SwitchToThread
+};
+use winapi::um::synchapi::{SleepEx, WaitForSingleObjectEx};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winbase::{CREATE_SUSPENDED, THREAD_PRIORITY_ERROR_RETURN};
+use winapi::um::winnt::DUPLICATE_SAME_ACCESS;
+use winapi::Interface;
+
+use mscoree_sys::mscoree::{
+    ICLRTask, 
+    ICLRTaskManager, 
+    IHostTask, 
+    IHostTaskManager, 
+    IHostTaskManagerVtbl, 
+    IHostTaskVtbl, 
+    WAIT_ALERTABLE, 
+    WAIT_MSGPUMP, 
+    WAIT_NOTINDEADLOCK
+};
+
+use com::ComBox;
+use error::last_error;
+use managers::wait_result;
+use tasks::{ClrTask, TaskManager};
+use unwind::catch_and_translate;
+
+pub type ThreadStart = unsafe extern "system" fn(LPVOID) -> DWORD;
+
+//The WAIT_OPTION flags passed to every blocking host call
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WaitOption {
+    //Pump messages while waiting (STA threads)
+    pub msg_pump: bool, 
+    //Return HOST_E_INTERRUPTED if the task is alerted
+    pub alertable: bool, 
+    //The runtime guarantees this wait can't take part in a deadlock
+    pub not_in_deadlock: bool,
+}
+
+impl WaitOption {
+    pub(crate) fn from_raw(raw: DWORD) -> WaitOption {
+        WaitOption {
+            msg_pump: raw & WAIT_MSGPUMP != 0, 
+            alertable: raw & WAIT_ALERTABLE != 0, 
+            not_in_deadlock: raw & WAIT_NOTINDEADLOCK != 0,
+        }
+    }
+}
+
+pub trait HostTask: Send + Sync + 'static {
+    //Begin running a task created by HostTaskManager::create_task
+    fn start(&self) -> Result<(), HRESULT>;
+
+    //Wake the task out of an alertable wait
+    fn alert(&self) -> Result<(), HRESULT>;
+
+    fn join(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT>;
+
+    fn set_priority(&self, priority: i32) -> Result<(), HRESULT>;
+
+    fn priority(&self) -> Result<i32, HRESULT>;
+
+    //The runtime's side of the task; None when it is detached
+    fn set_clr_task(&self, _task: Option<ClrTask>) {}
+}
+
+type TaskObject = ComBox<IHostTaskVtbl, Box<dyn HostTask>>;
+
+//The runtime matches tasks by identity, so one HostTask gets exactly one 
+// COM object; clones share it
+pub struct HostTaskHandle {
+    raw: *mut IHostTask,
+}
+
+unsafe impl Send for HostTaskHandle {}
+unsafe impl Sync for HostTaskHandle {}
+
+impl HostTaskHandle {
+    pub fn new<T: HostTask>(task: T) -> HostTaskHandle {
+        let vtable = IHostTaskVtbl {
+            parent: TaskObject::unknown_vtbl(), 
+            Start: task_start, 
+            //Alert in the SDK headers
+            Stop: task_alert, 
+            Join: task_join, 
+            SetPriority: task_set_priority, 
+            GetPriority: task_get_priority, 
+            SetCLRTask: task_set_clr_task,
+        };
+        let boxed: Box<dyn HostTask> = Box::new(task);
+        HostTaskHandle { raw: TaskObject::as_interface(TaskObject::new(vtable, vec![IHostTask::uuidof()], boxed)) }
+    }
+
+    //Takes ownership of a reference to one of our own task objects
+    pub(crate) unsafe fn from_raw(raw: *mut IHostTask) -> HostTaskHandle {
+        HostTaskHandle { raw }
+    }
+
+    pub fn task(&self) -> &dyn HostTask {
+        unsafe { &*TaskObject::from_this(self.raw).value }
+    }
+
+    //A new reference for an out-parameter
+    fn into_raw(self) -> *mut IHostTask {
+        let raw = self.raw;
+        mem::forget(self);
+        raw
+    }
+}
+
+impl Clone for HostTaskHandle {
+    fn clone(&self) -> HostTaskHandle {
+        unsafe { (*self.raw).AddRef() };
+        HostTaskHandle { raw: self.raw }
+    }
+}
+
+impl Drop for HostTaskHandle {
+    fn drop(&mut self) {
+        unsafe { (*self.raw).Release() };
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<HostTaskHandle>> = RefCell::new(None);
+}
+
+//The task for the calling thread, adopting threads the host didn't create
+pub fn current_thread_task() -> Result<HostTaskHandle, HRESULT> {
+    if let Some(handle) = CURRENT.with(|c| c.borrow().clone()) {
+        return Ok(handle);
+    }
+    let handle = HostTaskHandle::new(ThreadTask::for_current_thread()?);
+    CURRENT.with(|c| *c.borrow_mut() = Some(handle.clone()));
+    Ok(handle)
+}
+
+//The default task: an OS thread, created suspended until start
+pub struct ThreadTask {
+    thread: HANDLE, 
+    clr_task: Mutex<Option<ClrTask>>,
+}
+
+unsafe impl Send for ThreadTask {}
+unsafe impl Sync for ThreadTask {}
+
+struct Launch {
+    start: ThreadStart, 
+    param: LPVOID, 
+    handle: Option<HostTaskHandle>,
+}
+
+unsafe extern "system" fn launch(param: LPVOID) -> DWORD {
+    let block = Box::from_raw(param as *mut Launch);
+    CURRENT.with(|c| *c.borrow_mut() = block.handle.clone());
+    (block.start)(block.param)
+}
+
+unsafe extern "system" fn wake(_param: ULONG_PTR) {}
+
+impl ThreadTask {
+    pub fn spawn(stack_size: usize, start: ThreadStart, param: LPVOID) -> Result<HostTaskHandle, HRESULT> {
+        let launch_block = Box::into_raw(Box::new(Launch { start, param, handle: None }));
+        let thread = unsafe { CreateThread(ptr::null_mut(), stack_size, Some(launch), launch_block as LPVOID, CREATE_SUSPENDED, ptr::null_mut()) };
+        if thread.is_null() {
+            let hr = last_error();
+            drop(unsafe { Box::from_raw(launch_block) });
+            return Err(hr);
+        }
+        let handle = HostTaskHandle::new(ThreadTask { thread, clr_task: Mutex::new(None) });
+        //Still suspended, so the launch block isn't being read yet
+        unsafe { (*launch_block).handle = Some(handle.clone()) };
+        Ok(handle)
+    }
+
+    fn for_current_thread() -> Result<ThreadTask, HRESULT> {
+        let mut thread: HANDLE = ptr::null_mut();
+        let ok = unsafe {
+            let process = GetCurrentProcess();
+            DuplicateHandle(process, GetCurrentThread(), process, &mut thread, 0, FALSE, DUPLICATE_SAME_ACCESS)
+        };
+        if ok == 0 {
+            return Err(last_error());
+        }
+        Ok(ThreadTask { thread, clr_task: Mutex::new(None) })
+    }
+
+    pub fn clr_task(&self) -> Option<ClrTask> {
+        self.clr_task.lock().ok().and_then(|task| task.as_ref().and_then(|t| ClrTask::from_borrowed(t.as_raw()).ok()))
+    }
+}
+
+impl HostTask for ThreadTask {
+    fn start(&self) -> Result<(), HRESULT> {
+        match unsafe { ResumeThread(self.thread) } {
+            0xFFFF_FFFF => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    fn alert(&self) -> Result<(), HRESULT> {
+        match unsafe { QueueUserAPC(Some(wake), self.thread, 0) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    fn join(&self, timeout_ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+        wait_result(unsafe { WaitForSingleObjectEx(self.thread, timeout_ms, option.alertable as BOOL) })
+    }
+
+    fn set_priority(&self, priority: i32) -> Result<(), HRESULT> {
+        match unsafe { SetThreadPriority(self.thread, priority) } {
+            0 => Err(last_error()), 
+            _ => Ok(()),
+        }
+    }
+
+    fn priority(&self) -> Result<i32, HRESULT> {
+        match unsafe { GetThreadPriority(self.thread) } {
+            p if p == THREAD_PRIORITY_ERROR_RETURN as c_int => Err(last_error()), 
+            p => Ok(p),
+        }
+    }
+
+    fn set_clr_task(&self, task: Option<ClrTask>) {
+        if let Ok(mut slot) = self.clr_task.lock() {
+            *slot = task;
+        }
+    }
+}
+
+impl Drop for ThreadTask {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.thread) };
+    }
+}
+
+pub trait HostTaskManager: Send + Sync + 'static {
+    fn current_task(&self) -> Result<HostTaskHandle, HRESULT> {
+        current_thread_task()
+    }
+
+    fn create_task(&self, stack_size: usize, start: ThreadStart, param: LPVOID) -> Result<HostTaskHandle, HRESULT> {
+        ThreadTask::spawn(stack_size, start, param)
+    }
+
+    fn sleep(&self, ms: DWORD, option: WaitOption) -> Result<(), HRESULT> {
+        match unsafe { SleepEx(ms, option.alertable as BOOL) } {
+            0 => Ok(()), 
+            ret => wait_result(ret),
+        }
+    }
+
+    //Yield to another task
+    fn switch_to_task(&self, _option: WaitOption) -> Result<(), HRESULT> {
+        unsafe { SwitchToThread() };
+        Ok(())
+    }
+
+    //The runtime changed the current task's locales
+    fn set_ui_locale(&self, _lcid: LCID) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn set_locale(&self, _lcid: LCID) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    //Whether a P/Invoke to `target` should go through the leave/enter hooks
+    fn call_needs_host_hook(&self, _target: usize) -> bool {
+        false
+    }
+
+    //Code is leaving the runtime for native code at `target`, and back
+    fn leave_runtime(&self, _target: usize) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn enter_runtime(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    //Native code is calling into managed code, and returning
+    fn reverse_leave_runtime(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn reverse_enter_runtime(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    //Aborts on the current task must wait until the matching end call
+    fn begin_delay_abort(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn end_delay_abort(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    //The current task must not be moved to another OS thread until the 
+    // matching end call
+    fn begin_thread_affinity(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn end_thread_affinity(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    fn set_stack_guarantee(&self, _bytes: u32) -> Result<(), HRESULT> {
+        Err(E_NOTIMPL)
+    }
+
+    fn stack_guarantee(&self) -> Result<u32, HRESULT> {
+        Err(E_NOTIMPL)
+    }
+
+    //Called once; keep the manager to create or look up runtime tasks
+    fn set_clr_task_manager(&self, _manager: TaskManager) {}
+}
+
+type ManagerObject<M> = ComBox<IHostTaskManagerVtbl, M>;
+
+//A new IHostTaskManager object owned by the caller
+pub(crate) fn create<M: HostTaskManager>(manager: M) -> *mut IUnknown {
+    let vtable = IHostTaskManagerVtbl {
+        parent: ManagerObject::<M>::unknown_vtbl(), 
+        GetCurentTask: get_current_task::<M>, 
+        CreateTask: create_task::<M>, 
+        Sleep: sleep::<M>, 
+        SwitchToTask: switch_to_task::<M>, 
+        SetUILocale: set_ui_locale::<M>, 
+        SetLocale: set_locale::<M>, 
+        CallNeedsHostHook: call_needs_host_hook::<M>, 
+        LeaveRuntime: leave_runtime::<M>, 
+        EnterRuntime: enter_runtime::<M>, 
+        ReverseLeaveRuntime: reverse_leave_runtime::<M>, 
+        ReverseEnterRuntime: reverse_enter_runtime::<M>, 
+        BeginDelayAbort: begin_delay_abort::<M>, 
+        EndDelayAbort: end_delay_abort::<M>, 
+        BeginThreadAffinity: begin_thread_affinity::<M>, 
+        EndThreadAffinity: end_thread_affinity::<M>, 
+        SetStackGuarantee: set_stack_guarantee::<M>, 
+        GetStackGuarantee: get_stack_guarantee::<M>, 
+        SetCLRTaskManager: set_clr_task_manager::<M>,
+    };
+    ManagerObject::as_interface(ManagerObject::new(vtable, vec![IHostTaskManager::uuidof()], manager))
+}
+
+unsafe fn task_of<'a>(this: *mut IHostTask) -> &'a dyn HostTask {
+    &*TaskObject::from_this(this).value
+}
+
+unsafe extern "system" fn task_start(this: *mut IHostTask) -> HRESULT {
+    let task = task_of(this);
+    catch_and_translate(|| task.start())
+}
+
+unsafe extern "system" fn task_alert(this: *mut IHostTask) -> HRESULT {
+    let task = task_of(this);
+    catch_and_translate(|| task.alert())
+}
+
+unsafe extern "system" fn task_join(this: *mut IHostTask, ms: DWORD, option: DWORD) -> HRESULT {
+    let task = task_of(this);
+    catch_and_translate(|| task.join(ms, WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn task_set_priority(this: *mut IHostTask, priority: c_int) -> HRESULT {
+    let task = task_of(this);
+    catch_and_translate(|| task.set_priority(priority))
+}
+
+unsafe extern "system" fn task_get_priority(this: *mut IHostTask, priority: *mut c_int) -> HRESULT {
+    if priority.is_null() {
+        return E_POINTER;
+    }
+    let task = task_of(this);
+    catch_and_translate(|| {
+        *priority = task.priority()?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn task_set_clr_task(this: *mut IHostTask, clr_task: *mut ICLRTask) -> HRESULT {
+    let task = task_of(this);
+    catch_and_translate(|| {
+        let clr_task = if clr_task.is_null() { None } else { Some(ClrTask::from_borrowed(clr_task)?) };
+        task.set_clr_task(clr_task);
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn get_current_task<M: HostTaskManager>(this: *mut IHostTaskManager, ppv: *mut *mut IHostTask) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *ppv = manager.current_task()?.into_raw();
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn create_task<M: HostTaskManager>(this: *mut IHostTaskManager, stack_size: DWORD, start: LPTHREAD_START_ROUTINE, 
+    param: PVOID, ppv: *mut *mut IHostTask) -> HRESULT 
+{
+    let start = match start {
+        Some(start) if !ppv.is_null() => start, 
+        _ => return E_POINTER,
+    };
+    *ppv = ptr::null_mut();
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *ppv = manager.create_task(stack_size as usize, start, param)?.into_raw();
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn sleep<M: HostTaskManager>(this: *mut IHostTaskManager, ms: DWORD, option: DWORD) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.sleep(ms, WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn switch_to_task<M: HostTaskManager>(this: *mut IHostTaskManager, option: DWORD) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.switch_to_task(WaitOption::from_raw(option)))
+}
+
+unsafe extern "system" fn set_ui_locale<M: HostTaskManager>(this: *mut IHostTaskManager, lcid: LCID) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.set_ui_locale(lcid))
+}
+
+unsafe extern "system" fn set_locale<M: HostTaskManager>(this: *mut IHostTaskManager, lcid: LCID) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.set_locale(lcid))
+}
+
+unsafe extern "system" fn call_needs_host_hook<M: HostTaskManager>(this: *mut IHostTaskManager, target: SIZE_T, needs_hook: *mut BOOL) -> HRESULT {
+    if needs_hook.is_null() {
+        return E_POINTER;
+    }
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *needs_hook = manager.call_needs_host_hook(target) as BOOL;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn leave_runtime<M: HostTaskManager>(this: *mut IHostTaskManager, target: SIZE_T) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.leave_runtime(target))
+}
+
+unsafe extern "system" fn enter_runtime<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.enter_runtime())
+}
+
+unsafe extern "system" fn reverse_leave_runtime<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.reverse_leave_runtime())
+}
+
+unsafe extern "system" fn reverse_enter_runtime<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.reverse_enter_runtime())
+}
+
+unsafe extern "system" fn begin_delay_abort<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.begin_delay_abort())
+}
+
+unsafe extern "system" fn end_delay_abort<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.end_delay_abort())
+}
+
+unsafe extern "system" fn begin_thread_affinity<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.begin_thread_affinity())
+}
+
+unsafe extern "system" fn end_thread_affinity<M: HostTaskManager>(this: *mut IHostTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.end_thread_affinity())
+}
+
+unsafe extern "system" fn set_stack_guarantee<M: HostTaskManager>(this: *mut IHostTaskManager, guarantee: ULONG) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| manager.set_stack_guarantee(guarantee))
+}
+
+unsafe extern "system" fn get_stack_guarantee<M: HostTaskManager>(this: *mut IHostTaskManager, guarantee: *mut ULONG) -> HRESULT {
+    if guarantee.is_null() {
+        return E_POINTER;
+    }
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        *guarantee = manager.stack_guarantee()?;
+        Ok(())
+    })
+}
+
+unsafe extern "system" fn set_clr_task_manager<M: HostTaskManager>(this: *mut IHostTaskManager, clr_manager: *mut ICLRTaskManager) -> HRESULT {
+    let manager = &ManagerObject::<M>::from_this(this).value;
+    catch_and_translate(|| {
+        manager.set_clr_task_manager(TaskManager::from_borrowed(clr_manager)?);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wait_option_from_raw() {
+        assert_eq!(WaitOption::from_raw(0), WaitOption::default());
+        let option = WaitOption::from_raw(WAIT_ALERTABLE | WAIT_NOTINDEADLOCK);
+        assert!(option.alertable && option.not_in_deadlock && !option.msg_pump);
+    }
+}
@@ -0,0 +1,257 @@
+// manifest.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Reproducibility manifest for a started runtime: which CLR build actually 
+// got loaded, with what flags and which configuration files. Meant to be 
+// attached to build provenance records and support tickets.
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{BYTE, DWORD, LPVOID, UINT};
+use winapi::um::verrsrc::VS_FIXEDFILEINFO;
+use winapi::um::wincrypt::{
+    CryptAcquireContextW, 
+    CryptCreateHash, 
+    CryptDestroyHash, 
+    CryptGetHashParam, 
+    CryptHashData, 
+    CryptReleaseContext, 
+    ALG_ID, 
+    CALG_SHA_256, 
+    CRYPT_VERIFYCONTEXT, 
+    HCRYPTHASH, 
+    HCRYPTPROV, 
+    HP_HASHVAL, 
+    PROV_RSA_AES
+};
+use winapi::um::winver::{GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW};
+
+use buffer::wide;
+use error::{HostingError, last_error};
+use metahost::RuntimeInfo;
+
+//Engine binaries worth fingerprinting: clr.dll for v4, mscorwks.dll for v2
+const RUNTIME_BINARIES: &[&str] = &["clr.dll", "mscorwks.dll"];
+
+#[derive(Debug)]
+pub enum ManifestError {
+    NotStarted, 
+    RuntimeQuery(HostingError), 
+    Io(PathBuf, io::Error), 
+    Hash(PathBuf, HostingError),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ManifestError::NotStarted => write!(f, "the runtime has not been started"), 
+            ManifestError::RuntimeQuery(ref err) => write!(f, "querying the runtime failed: {}", err), 
+            ManifestError::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err), 
+            ManifestError::Hash(ref path, ref err) => write!(f, "hashing {} failed: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for ManifestError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            ManifestError::NotStarted => None, 
+            ManifestError::Io(_, ref err) => Some(err), 
+            ManifestError::RuntimeQuery(ref err) | 
+            ManifestError::Hash(_, ref err) => Some(err),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileFingerprint {
+    pub path: PathBuf, 
+    pub file_version: Option<String>, 
+    pub sha256: String,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HostingManifest {
+    pub runtime_version: String, 
+    pub runtime_directory: PathBuf, 
+    pub startup_flags: u32, 
+    pub runtime_files: Vec<FileFingerprint>, 
+    pub host_config: Option<FileFingerprint>, 
+    pub config_inputs: Vec<FileFingerprint>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ManifestBuilder {
+    host_config: Option<PathBuf>, 
+    config_inputs: Vec<PathBuf>,
+}
+
+impl ManifestBuilder {
+    pub fn new() -> ManifestBuilder {
+        ManifestBuilder::default()
+    }
+
+    pub fn host_config<P: AsRef<Path>>(mut self, path: P) -> ManifestBuilder {
+        self.host_config = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Any other file that influences runtime behavior (app.config, policy files...)
+    pub fn config_input<P: AsRef<Path>>(mut self, path: P) -> ManifestBuilder {
+        self.config_inputs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    //Must be called after the runtime has been started, since the startup 
+    // flags are read back from the runtime itself rather than trusted from input.
+    pub fn capture(self, runtime: &dyn RuntimeInfo) -> Result<HostingManifest, ManifestError> {
+        let startup_flags = match runtime.startup_flags() {
+            Some(flags) => flags, 
+            None => return Err(ManifestError::NotStarted),
+        };
+        let runtime_directory = runtime.directory().map_err(ManifestError::RuntimeQuery)?;
+        let runtime_version = runtime.version().to_string();
+
+        let mut runtime_files = Vec::new();
+        for name in RUNTIME_BINARIES {
+            let path = runtime_directory.join(name);
+            if path.is_file() {
+                runtime_files.push(fingerprint(&path)?);
+            }
+        }
+        let host_config = match self.host_config {
+            Some(ref path) => Some(fingerprint(path)?), 
+            None => None,
+        };
+        let config_inputs = self.config_inputs.iter()
+            .map(|path| fingerprint(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(HostingManifest {
+            runtime_version, 
+            runtime_directory, 
+            startup_flags, 
+            runtime_files, 
+            host_config, 
+            config_inputs,
+        })
+    }
+}
+
+fn fingerprint(path: &Path) -> Result<FileFingerprint, ManifestError> {
+    let data = fs::read(path).map_err(|err| ManifestError::Io(path.to_path_buf(), err))?;
+    let digest = sha256(&data).map_err(|hr| ManifestError::Hash(path.to_path_buf(), hr))?;
+    Ok(FileFingerprint {
+        path: path.to_path_buf(), 
+        file_version: file_version(path), 
+        sha256: to_hex(&digest),
+    })
+}
+
+//Reads VS_FIXEDFILEINFO, None for files without a version resource
+fn file_version(path: &Path) -> Option<String> {
+    let wpath = wide(path.as_os_str());
+    let mut handle: DWORD = 0;
+    let size = unsafe { GetFileVersionInfoSizeW(wpath.as_ptr(), &mut handle) };
+    if size == 0 {
+        return None;
+    }
+    let mut block: Vec<u8> = vec![0; size as usize];
+    let ok = unsafe { GetFileVersionInfoW(wpath.as_ptr(), 0, size, block.as_mut_ptr() as *mut c_void) };
+    if ok == 0 {
+        return None;
+    }
+    let root = wide(OsStr::new("\\"));
+    let mut info: LPVOID = ptr::null_mut();
+    let mut len: UINT = 0;
+    let ok = unsafe { VerQueryValueW(block.as_ptr() as *const c_void, root.as_ptr(), &mut info, &mut len) };
+    if ok == 0 || info.is_null() || (len as usize) < ::std::mem::size_of::<VS_FIXEDFILEINFO>() {
+        return None;
+    }
+    let info = unsafe { &*(info as *const VS_FIXEDFILEINFO) };
+    Some(format!("{}.{}.{}.{}", 
+        info.dwFileVersionMS >> 16, 
+        info.dwFileVersionMS & 0xffff, 
+        info.dwFileVersionLS >> 16, 
+        info.dwFileVersionLS & 0xffff))
+}
+
+fn sha256(data: &[u8]) -> Result<Vec<u8>, HostingError> {
+    digest(CALG_SHA_256, data)
+}
+
+//Any CryptoAPI hash; the metadata module needs SHA-1 for public key tokens
+pub(crate) fn digest(algorithm: ALG_ID, data: &[u8]) -> Result<Vec<u8>, HostingError> {
+    let mut prov: HCRYPTPROV = 0;
+    let ok = unsafe { CryptAcquireContextW(&mut prov, ptr::null(), ptr::null(), PROV_RSA_AES, CRYPT_VERIFYCONTEXT) };
+    if ok == 0 {
+        return Err(HostingError::from_hresult(last_error(), CALL!(advapi32::CryptAcquireContextW)));
+    }
+    let mut hash: HCRYPTHASH = 0;
+    let result = unsafe {
+        if CryptCreateHash(prov, algorithm, 0, 0, &mut hash) == 0 {
+            Err(HostingError::from_hresult(last_error(), CALL!(advapi32::CryptCreateHash)))
+        } else {
+            let mut digest: Vec<BYTE> = vec![0; 64];
+            let mut len = digest.len() as DWORD;
+            let r = if CryptHashData(hash, data.as_ptr(), data.len() as DWORD, 0) == 0 {
+                Err(HostingError::from_hresult(last_error(), CALL!(advapi32::CryptHashData)))
+            } else if CryptGetHashParam(hash, HP_HASHVAL, digest.as_mut_ptr(), &mut len, 0) == 0 {
+                Err(HostingError::from_hresult(last_error(), CALL!(advapi32::CryptGetHashParam)))
+            } else {
+                digest.truncate(len as usize);
+                Ok(digest)
+            };
+            CryptDestroyHash(hash);
+            r
+        }
+    };
+    unsafe { CryptReleaseContext(prov, 0) };
+    result
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn sha256_known_value() {
+        let digest = sha256(b"abc").unwrap();
+        assert_eq!(to_hex(&digest), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
@@ -0,0 +1,141 @@
+// marshal.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Strings coming back out of .NET. A BSTR is either borrowed (inside a 
+// VARIANT or SAFEARRAY someone else clears) or owned (an out-param we 
+// must SysFreeString); the functions here say which in their names so 
+// every call site frees exactly once.
+use std::ptr;
+use std::slice;
+
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::DISP_E_TYPEMISMATCH;
+use winapi::shared::wtypes::{BSTR, VARTYPE, VT_BSTR};
+use winapi::um::oaidl::SAFEARRAY;
+use winapi::um::oleauto::{
+    SafeArrayAccessData, 
+    SafeArrayDestroy, 
+    SafeArrayGetLBound, 
+    SafeArrayGetUBound, 
+    SafeArrayGetVartype, 
+    SafeArrayUnaccessData, 
+    SysFreeString, 
+    SysStringLen
+};
+
+use error::HostingError;
+
+//Copies a BSTR that stays owned by the caller. Null reads as "", which is 
+// how COM represents an empty BSTR.
+pub(crate) unsafe fn bstr_to_string(bstr: BSTR) -> String {
+    if bstr.is_null() {
+        return String::new();
+    }
+    let wide = slice::from_raw_parts(bstr, SysStringLen(bstr) as usize);
+    String::from_utf16_lossy(wide)
+}
+
+//Copies a BSTR we were handed ownership of, then frees it
+pub(crate) unsafe fn take_bstr(bstr: BSTR) -> String {
+    let s = bstr_to_string(bstr);
+    SysFreeString(bstr);
+    s
+}
+
+//A BSTR out-parameter that is freed however the call turns out:
+//    let mut name = BstrOut::new();
+//    CHECK_HR!(I::GetName, (*p).GetName(name.as_out()))?;
+//    let name = name.into_string();
+pub(crate) struct BstrOut {
+    inner: BSTR,
+}
+
+impl BstrOut {
+    pub fn new() -> BstrOut {
+        BstrOut { inner: ptr::null_mut() }
+    }
+
+    pub fn as_out(&mut self) -> *mut BSTR {
+        &mut self.inner
+    }
+
+    pub fn into_string(self) -> String {
+        unsafe { bstr_to_string(self.inner) }
+    }
+}
+
+impl Default for BstrOut {
+    fn default() -> BstrOut {
+        BstrOut::new()
+    }
+}
+
+impl Drop for BstrOut {
+    fn drop(&mut self) {
+        unsafe { SysFreeString(self.inner) };
+    }
+}
+
+//Copies a one-dimensional SAFEARRAY of BSTR that stays owned by the caller
+pub(crate) unsafe fn strings_from_safearray(psa: *mut SAFEARRAY) -> Result<Vec<String>, HostingError> {
+    if psa.is_null() {
+        return Ok(Vec::new());
+    }
+    let mut vt: VARTYPE = 0;
+    CHECK_HR!(oleaut32::SafeArrayGetVartype, SafeArrayGetVartype(psa, &mut vt))?;
+    if vt != VT_BSTR as VARTYPE {
+        return Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(oleaut32::SafeArrayGetVartype)));
+    }
+    let (mut lower, mut upper) = (0i32, -1i32);
+    CHECK_HR!(oleaut32::SafeArrayGetLBound, SafeArrayGetLBound(psa, 1, &mut lower))?;
+    CHECK_HR!(oleaut32::SafeArrayGetUBound, SafeArrayGetUBound(psa, 1, &mut upper))?;
+    let len = (upper - lower + 1).max(0) as usize;
+    let mut data: *mut c_void = ptr::null_mut();
+    CHECK_HR!(oleaut32::SafeArrayAccessData, SafeArrayAccessData(psa, &mut data))?;
+    let strings = slice::from_raw_parts(data as *const BSTR, len).iter().map(|&b| bstr_to_string(b)).collect();
+    SafeArrayUnaccessData(psa);
+    Ok(strings)
+}
+
+//As strings_from_safearray for an array we own; SafeArrayDestroy frees 
+// the BSTRs along with it
+pub(crate) unsafe fn take_string_array(psa: *mut SAFEARRAY) -> Result<Vec<String>, HostingError> {
+    let strings = strings_from_safearray(psa);
+    if !psa.is_null() {
+        SafeArrayDestroy(psa);
+    }
+    strings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use winapi::um::oleauto::SysAllocStringLen;
+
+    #[test]
+    fn take_bstr_copies_before_freeing() {
+        let text: Vec<u16> = "héllo".encode_utf16().collect();
+        let bstr = unsafe { SysAllocStringLen(text.as_ptr(), text.len() as u32) };
+        assert_eq!(unsafe { take_bstr(bstr) }, "héllo");
+        assert_eq!(BstrOut::new().into_string(), "");
+    }
+}
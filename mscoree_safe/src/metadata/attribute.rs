@@ -0,0 +1,357 @@
+// attribute.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Custom attribute values (ECMA-335 II.23.3). The blob only says how many 
+// constructor arguments there are, not their types, so decoding reads the 
+// constructor's signature first. Enum arguments carry no width either; it 
+// comes from the enum's value__ field when the enum is defined in the same 
+// module, and int32 (the width of nearly every enum) otherwise.
+use mscoree_sys::corerror::META_E_CA_INVALID_BLOB;
+use mscoree_sys::corhdr::*;
+
+use error::HostingError;
+use metadata::blob::BlobReader;
+use metadata::signature::{TypeSig, MAX_NESTING};
+use metadata::{token_type, MetadataScope};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeValue {
+    Bool(bool), 
+    //A UTF-16 code unit, which needn't be a valid char on its own
+    Char(u16), 
+    I1(i8), 
+    U1(u8), 
+    I2(i16), 
+    U2(u16), 
+    I4(i32), 
+    U4(u32), 
+    I8(i64), 
+    U8(u64), 
+    R4(f32), 
+    R8(f64), 
+    String(Option<String>), 
+    //typeof(T) arguments, as the assembly-qualified name the compiler wrote
+    Type(Option<String>), 
+    Enum { type_name: String, value: i64 }, 
+    Array(Option<Vec<AttributeValue>>),
+}
+
+impl AttributeValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match *self { AttributeValue::String(Some(ref s)) => Some(s), _ => None }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self { AttributeValue::Bool(b) => Some(b), _ => None }
+    }
+
+    //Any integer or enum value, widened
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            AttributeValue::I1(i) => Some(i64::from(i)), 
+            AttributeValue::U1(u) => Some(i64::from(u)), 
+            AttributeValue::I2(i) => Some(i64::from(i)), 
+            AttributeValue::U2(u) | AttributeValue::Char(u) => Some(i64::from(u)), 
+            AttributeValue::I4(i) => Some(i64::from(i)), 
+            AttributeValue::U4(u) => Some(i64::from(u)), 
+            AttributeValue::I8(i) => Some(i), 
+            AttributeValue::U8(u) => Some(u as i64), 
+            AttributeValue::Enum { value, .. } => Some(value), 
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NamedArgumentKind {
+    Field, 
+    Property,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedArgument {
+    pub kind: NamedArgumentKind, 
+    pub name: String, 
+    pub value: AttributeValue,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DecodedAttribute {
+    //Constructor arguments, in order
+    pub fixed: Vec<AttributeValue>, 
+    pub named: Vec<NamedArgument>,
+}
+
+impl DecodedAttribute {
+    pub fn named(&self, name: &str) -> Option<&AttributeValue> {
+        self.named.iter().find(|arg| arg.name == name).map(|arg| &arg.value)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CustomAttribute {
+    pub token: mdCustomAttribute, 
+    //What the attribute is applied to
+    pub owner: mdToken, 
+    //MethodDef or MemberRef of the attribute's constructor
+    pub constructor: mdToken, 
+    pub type_name: String, 
+    pub blob: Vec<u8>,
+}
+
+impl CustomAttribute {
+    //Constructor and named arguments as typed values. The scope is the one 
+    // the attribute was read from, used to resolve parameter types.
+    pub fn decode(&self, scope: &MetadataScope) -> Result<DecodedAttribute, HostingError> {
+        let invalid = || HostingError::from_hresult(META_E_CA_INVALID_BLOB, CALL!(IMetaDataImport::GetCustomAttributeProps));
+        let params = constructor_params(scope, self.constructor).ok_or_else(invalid)?;
+        decode_blob(&self.blob, &params, &|name: &str| enum_by_name(scope, name)).ok_or_else(invalid)
+    }
+}
+
+//The shapes a custom attribute argument can take
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ArgType {
+    Bool, 
+    Char, 
+    I1, 
+    U1, 
+    I2, 
+    U2, 
+    I4, 
+    U4, 
+    I8, 
+    U8, 
+    R4, 
+    R8, 
+    String, 
+    Type, 
+    //Boxed: the value is preceded by its own type
+    Object, 
+    //Type name and underlying integer type
+    Enum(String, Box<ArgType>), 
+    Array(Box<ArgType>),
+}
+
+fn primitive(element: u8) -> Option<ArgType> {
+    Some(match u32::from(element) {
+        ELEMENT_TYPE_BOOLEAN => ArgType::Bool, 
+        ELEMENT_TYPE_CHAR => ArgType::Char, 
+        ELEMENT_TYPE_I1 => ArgType::I1, 
+        ELEMENT_TYPE_U1 => ArgType::U1, 
+        ELEMENT_TYPE_I2 => ArgType::I2, 
+        ELEMENT_TYPE_U2 => ArgType::U2, 
+        ELEMENT_TYPE_I4 => ArgType::I4, 
+        ELEMENT_TYPE_U4 => ArgType::U4, 
+        ELEMENT_TYPE_I8 => ArgType::I8, 
+        ELEMENT_TYPE_U8 => ArgType::U8, 
+        ELEMENT_TYPE_R4 => ArgType::R4, 
+        ELEMENT_TYPE_R8 => ArgType::R8, 
+        ELEMENT_TYPE_STRING => ArgType::String, 
+        _ => return None,
+    })
+}
+
+fn constructor_params(scope: &MetadataScope, constructor: mdToken) -> Option<Vec<ArgType>> {
+    let sig = scope.method_signature(constructor).ok()?;
+    sig.params.iter().map(|ty| arg_type(scope, ty)).collect()
+}
+
+fn arg_type(scope: &MetadataScope, ty: &TypeSig) -> Option<ArgType> {
+    Some(match *ty.unmodified() {
+        TypeSig::Bool => ArgType::Bool, 
+        TypeSig::Char => ArgType::Char, 
+        TypeSig::I1 => ArgType::I1, 
+        TypeSig::U1 => ArgType::U1, 
+        TypeSig::I2 => ArgType::I2, 
+        TypeSig::U2 => ArgType::U2, 
+        TypeSig::I4 => ArgType::I4, 
+        TypeSig::U4 => ArgType::U4, 
+        TypeSig::I8 => ArgType::I8, 
+        TypeSig::U8 => ArgType::U8, 
+        TypeSig::R4 => ArgType::R4, 
+        TypeSig::R8 => ArgType::R8, 
+        TypeSig::String => ArgType::String, 
+        TypeSig::Object => ArgType::Object, 
+        TypeSig::SzArray(ref element) => ArgType::Array(Box::new(arg_type(scope, element)?)), 
+        //System.Type is the only class allowed here
+        TypeSig::Class(_) => ArgType::Type, 
+        TypeSig::ValueType(token) => {
+            let name = scope.type_name(token).ok()?;
+            let underlying = enum_underlying(scope, token).unwrap_or(ArgType::I4);
+            ArgType::Enum(name, Box::new(underlying))
+        }, 
+        _ => return None,
+    })
+}
+
+fn enum_by_name(scope: &MetadataScope, name: &str) -> ArgType {
+    //A named argument's enum is written assembly-qualified; the type 
+    // name is what's before the first comma
+    let type_name = name.split(',').next().unwrap_or(name).trim();
+    let underlying = scope.find_type_def(type_name, None).ok()
+        .and_then(|td| td)
+        .and_then(|td| enum_underlying(scope, td))
+        .unwrap_or(ArgType::I4);
+    ArgType::Enum(type_name.to_string(), Box::new(underlying))
+}
+
+//Read from the value__ field, which only a TypeDef in this scope has
+fn enum_underlying(scope: &MetadataScope, token: mdToken) -> Option<ArgType> {
+    if token_type(token) != mdtTypeDef {
+        return None;
+    }
+    let field = *scope.fields_named(token, "value__").ok()?.first()?;
+    match arg_type(scope, &scope.field_type(field).ok()?)? {
+        ArgType::Enum(..) | ArgType::Array(_) => None, 
+        underlying => Some(underlying),
+    }
+}
+
+fn decode_blob(blob: &[u8], params: &[ArgType], enums: &dyn Fn(&str) -> ArgType) -> Option<DecodedAttribute> {
+    let mut r = BlobReader::new(blob);
+    if r.u16()? != 0x0001 {
+        return None;
+    }
+    let fixed = params.iter().map(|ty| value(&mut r, ty, enums, 0)).collect::<Option<Vec<_>>>()?;
+    let count = r.u16()?;
+    let mut named = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let kind = match u32::from(r.u8()?) {
+            SERIALIZATION_TYPE_FIELD => NamedArgumentKind::Field, 
+            SERIALIZATION_TYPE_PROPERTY => NamedArgumentKind::Property, 
+            _ => return None,
+        };
+        let ty = ser_type(&mut r, enums, 0)?;
+        let name = r.ser_string()??;
+        let value = value(&mut r, &ty, enums, 0)?;
+        named.push(NamedArgument { kind, name, value });
+    }
+    Some(DecodedAttribute { fixed, named })
+}
+
+//FieldOrPropType: how named arguments and boxed values state their type
+fn ser_type(r: &mut BlobReader, enums: &dyn Fn(&str) -> ArgType, depth: usize) -> Option<ArgType> {
+    if depth > MAX_NESTING {
+        return None;
+    }
+    let tag = r.u8()?;
+    if let Some(ty) = primitive(tag) {
+        return Some(ty);
+    }
+    match u32::from(tag) {
+        SERIALIZATION_TYPE_TYPE => Some(ArgType::Type), 
+        SERIALIZATION_TYPE_TAGGED_OBJECT => Some(ArgType::Object), 
+        ELEMENT_TYPE_SZARRAY => ser_type(r, enums, depth + 1).map(|ty| ArgType::Array(Box::new(ty))), 
+        SERIALIZATION_TYPE_ENUM => Some(enums(&r.ser_string()??)), 
+        _ => None,
+    }
+}
+
+//Boxed values can hold arrays of boxed values, so this nests as deeply 
+// as the blob says; depth bounds it like signature parsing
+fn value(r: &mut BlobReader, ty: &ArgType, enums: &dyn Fn(&str) -> ArgType, depth: usize) -> Option<AttributeValue> {
+    if depth > MAX_NESTING {
+        return None;
+    }
+    Some(match *ty {
+        ArgType::Bool => AttributeValue::Bool(r.u8()? != 0), 
+        ArgType::Char => AttributeValue::Char(r.u16()?), 
+        ArgType::I1 => AttributeValue::I1(r.u8()? as i8), 
+        ArgType::U1 => AttributeValue::U1(r.u8()?), 
+        ArgType::I2 => AttributeValue::I2(r.u16()? as i16), 
+        ArgType::U2 => AttributeValue::U2(r.u16()?), 
+        ArgType::I4 => AttributeValue::I4(r.u32()? as i32), 
+        ArgType::U4 => AttributeValue::U4(r.u32()?), 
+        ArgType::I8 => AttributeValue::I8(r.u64()? as i64), 
+        ArgType::U8 => AttributeValue::U8(r.u64()?), 
+        ArgType::R4 => AttributeValue::R4(f32::from_bits(r.u32()?)), 
+        ArgType::R8 => AttributeValue::R8(f64::from_bits(r.u64()?)), 
+        ArgType::String => AttributeValue::String(r.ser_string()?), 
+        ArgType::Type => AttributeValue::Type(r.ser_string()?), 
+        ArgType::Object => {
+            let boxed = ser_type(r, enums, depth + 1)?;
+            return value(r, &boxed, enums, depth + 1);
+        }, 
+        ArgType::Enum(ref type_name, ref underlying) => {
+            let value = value(r, underlying, enums, depth + 1)?.as_i64()?;
+            AttributeValue::Enum { type_name: type_name.clone(), value }
+        }, 
+        ArgType::Array(ref element) => {
+            let len = r.u32()?;
+            if len == 0xFFFF_FFFF {
+                AttributeValue::Array(None)
+            } else {
+                let values = (0..len).map(|_| value(r, element, enums, depth + 1)).collect::<Option<Vec<_>>>()?;
+                AttributeValue::Array(Some(values))
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_fixed_and_named_arguments() {
+        //[Obsolete("gone", true, DiagnosticId = "X1")], plus an int[] 
+        // and a boxed enum to cover the nested encodings
+        let mut blob = vec![0x01, 0x00, 0x04];
+        blob.extend_from_slice(b"gone");
+        blob.extend_from_slice(&[0x01, 0x02, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        blob.extend_from_slice(&[0x02, 0x00, 0x54, 0x0E, 0x0C]);
+        blob.extend_from_slice(b"DiagnosticId");
+        blob.extend_from_slice(&[0x02]);
+        blob.extend_from_slice(b"X1");
+        blob.extend_from_slice(&[0x53, 0x51, 0x01]);
+        blob.extend_from_slice(b"F");
+        blob.extend_from_slice(&[0x55, 0x01]);
+        blob.extend_from_slice(b"E");
+        blob.extend_from_slice(&[0x05, 0x00, 0x00, 0x00]);
+
+        let params = [ArgType::String, ArgType::Bool, ArgType::Array(Box::new(ArgType::I4))];
+        let decoded = decode_blob(&blob, &params, &|name: &str| ArgType::Enum(name.to_string(), Box::new(ArgType::I4))).unwrap();
+        assert_eq!(decoded.fixed[0].as_str(), Some("gone"));
+        assert_eq!(decoded.fixed[1].as_bool(), Some(true));
+        assert_eq!(decoded.fixed[2], AttributeValue::Array(Some(vec![AttributeValue::I4(7), AttributeValue::I4(-1)])));
+        assert_eq!(decoded.named("DiagnosticId").and_then(AttributeValue::as_str), Some("X1"));
+        assert_eq!(decoded.named("F"), Some(&AttributeValue::Enum { type_name: "E".to_string(), value: 5 }));
+    }
+
+    #[test]
+    fn rejects_runaway_nesting() {
+        let enums = |name: &str| ArgType::Enum(name.to_string(), Box::new(ArgType::I4));
+        //A named field whose type is an array of arrays of arrays...
+        let mut blob = vec![0x01, 0x00, 0x01, 0x00, 0x53];
+        blob.extend(vec![0x1D; 100_000]);
+        blob.push(0x08);
+        assert!(decode_blob(&blob, &[], &enums).is_none());
+        //A boxed object holding a one-element object[] holding a boxed 
+        // object[]...
+        let mut blob = vec![0x01, 0x00];
+        for _ in 0..100_000 {
+            blob.extend_from_slice(&[0x1D, 0x51, 0x01, 0x00, 0x00, 0x00]);
+        }
+        assert!(decode_blob(&blob, &[ArgType::Object], &enums).is_none());
+    }
+}
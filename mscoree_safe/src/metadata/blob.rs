@@ -0,0 +1,142 @@
+// blob.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Cursor over a metadata blob: the compressed integers and coded tokens of 
+// signatures (ECMA-335 II.23.2), plus the little-endian fixed-size values 
+// custom attribute blobs are made of. Every read is None once the data 
+// runs out, so malformed blobs surface as a failed parse, never a panic.
+use std::str;
+
+use mscoree_sys::corhdr::{mdToken, mdtTypeDef, mdtTypeRef, mdtTypeSpec};
+
+#[derive(Clone, Debug)]
+pub(crate) struct BlobReader<'b> {
+    data: &'b [u8], 
+    pos: usize,
+}
+
+impl<'b> BlobReader<'b> {
+    pub(crate) fn new(data: &'b [u8]) -> BlobReader<'b> {
+        BlobReader { data, pos: 0 }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).cloned()
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Option<&'b [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from(b[0]) | u16::from(b[1]) << 8)
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from(self.u16()?) | u32::from(self.u16()?) << 16)
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from(self.u32()?) | u64::from(self.u32()?) << 32)
+    }
+
+    //One, two or four bytes, big-endian, length in the top bits
+    pub(crate) fn compressed(&mut self) -> Option<u32> {
+        let first = self.u8()?;
+        if first & 0x80 == 0 {
+            Some(u32::from(first))
+        } else if first & 0xC0 == 0x80 {
+            Some(u32::from(first & 0x3F) << 8 | u32::from(self.u8()?))
+        } else if first & 0xE0 == 0xC0 {
+            let rest = self.bytes(3)?;
+            Some(u32::from(first & 0x1F) << 24 | u32::from(rest[0]) << 16 | u32::from(rest[1]) << 8 | u32::from(rest[2]))
+        } else {
+            None
+        }
+    }
+
+    //Signed compressed integers (array lower bounds) keep the sign in bit 0 
+    // and are rotated by the width they were encoded in
+    pub(crate) fn compressed_signed(&mut self) -> Option<i32> {
+        let width = match self.peek()? {
+            b if b & 0x80 == 0 => 7, 
+            b if b & 0xC0 == 0x80 => 14, 
+            _ => 29,
+        };
+        let raw = self.compressed()?;
+        let value = (raw >> 1) as i32;
+        Some(if raw & 1 == 0 { value } else { value - (1 << (width - 1)) })
+    }
+
+    //TypeDefOrRefOrSpecEncoded: a compressed row number tagged with its table
+    pub(crate) fn type_token(&mut self) -> Option<mdToken> {
+        let coded = self.compressed()?;
+        let table = match coded & 0x3 {
+            0 => mdtTypeDef, 
+            1 => mdtTypeRef, 
+            2 => mdtTypeSpec, 
+            _ => return None,
+        };
+        Some(table | coded >> 2)
+    }
+
+    //SerString: 0xFF for null, otherwise a compressed length and UTF-8
+    pub(crate) fn ser_string(&mut self) -> Option<Option<String>> {
+        if self.peek()? == 0xFF {
+            self.pos += 1;
+            return Some(None);
+        }
+        let len = self.compressed()? as usize;
+        let bytes = self.bytes(len)?;
+        str::from_utf8(bytes).ok().map(|s| Some(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compressed_integers() {
+        let mut r = BlobReader::new(&[0x03, 0x80, 0x80, 0xC0, 0x00, 0x40, 0x00, 0x06, 0x7B, 0x49, 0xC0]);
+        assert_eq!(r.compressed(), Some(0x03));
+        assert_eq!(r.compressed(), Some(0x80));
+        assert_eq!(r.compressed(), Some(0x4000));
+        assert_eq!(r.compressed_signed(), Some(3));
+        assert_eq!(r.compressed_signed(), Some(-3));
+        assert_eq!(r.type_token(), Some(mdtTypeRef | 0x12));
+        assert_eq!(r.compressed(), None);
+    }
+}
@@ -0,0 +1,307 @@
+// dependency.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Assembly identities from the manifest tables, and a reference graph built 
+// from them. Resolving a reference to a file is left to probe callbacks, 
+// since only the caller knows the application base and binding policy; 
+// probe_directory and probe_gac cover the usual cases.
+use std::collections::HashMap;
+use std::env;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
+
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{DWORD, ULONG};
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::winerror::S_OK;
+use winapi::um::wincrypt::CALG_SHA1;
+
+use mscoree_sys::cor::ASSEMBLYMETADATA;
+use mscoree_sys::corerror::CLDB_E_RECORD_NOTFOUND;
+use mscoree_sys::corhdr::*;
+
+use assembly::{AssemblyName, AssemblyVersion};
+use error::HostingError;
+use manifest::digest;
+use metadata::{read_string, MetadataDispenser, MetadataScope};
+
+//Longer than any culture name
+const LOCALE_CHARS: usize = 64;
+
+impl MetadataScope {
+    //The assembly this module is the manifest of; None for a netmodule
+    pub fn assembly_name(&self) -> Result<Option<AssemblyName>, HostingError> {
+        let mut assembly: mdAssembly = mdTokenNil;
+        match unsafe { self.assembly.GetAssemblyFromScope(&mut assembly) } {
+            S_OK => {}, 
+            CLDB_E_RECORD_NOTFOUND => return Ok(None), 
+            hr => return Err(HostingError::from_hresult(hr, CALL!(IMetaDataAssemblyImport::GetAssemblyFromScope))),
+        }
+        let mut key: *const c_void = ptr::null();
+        let mut key_len: ULONG = 0;
+        let mut flags: DWORD = 0;
+        let mut locale = [0u16; LOCALE_CHARS];
+        let mut metadata = locale_metadata(&mut locale);
+        let name = read_string(CALL!(IMetaDataAssemblyImport::GetAssemblyProps), |buffer, len, needed| unsafe {
+            metadata.cbLocale = LOCALE_CHARS as ULONG;
+            self.assembly.GetAssemblyProps(assembly, &mut key, &mut key_len, ptr::null_mut(), buffer, len, needed, &mut metadata, &mut flags)
+        })?;
+        //A definition always carries the full key, when it has one
+        identity(name, &metadata, &locale, unsafe { borrow(key, key_len) }, true).map(Some)
+    }
+
+    pub fn assembly_refs(&self) -> Result<Vec<mdAssemblyRef>, HostingError> {
+        self.assembly_tokens(CALL!(IMetaDataAssemblyImport::EnumAssemblyRefs), |henum, tokens, max, fetched| unsafe {
+            self.assembly.EnumAssemblyRefs(henum, tokens, max, fetched)
+        })
+    }
+
+    pub fn assembly_ref(&self, assembly_ref: mdAssemblyRef) -> Result<AssemblyName, HostingError> {
+        let mut key: *const c_void = ptr::null();
+        let mut key_len: ULONG = 0;
+        let mut flags: DWORD = 0;
+        let mut locale = [0u16; LOCALE_CHARS];
+        let mut metadata = locale_metadata(&mut locale);
+        let name = read_string(CALL!(IMetaDataAssemblyImport::GetAssemblyRefProps), |buffer, len, needed| unsafe {
+            metadata.cbLocale = LOCALE_CHARS as ULONG;
+            self.assembly.GetAssemblyRefProps(
+                assembly_ref, &mut key, &mut key_len, buffer, len, needed, &mut metadata, ptr::null_mut(), ptr::null_mut(), &mut flags
+            )
+        })?;
+        identity(name, &metadata, &locale, unsafe { borrow(key, key_len) }, flags & afPublicKey != 0)
+    }
+}
+
+fn locale_metadata(locale: &mut [u16; LOCALE_CHARS]) -> ASSEMBLYMETADATA {
+    let mut metadata: ASSEMBLYMETADATA = unsafe { mem::zeroed() };
+    metadata.szLocale = locale.as_mut_ptr() as LPCWSTR;
+    metadata.cbLocale = LOCALE_CHARS as ULONG;
+    metadata
+}
+
+unsafe fn borrow<'k>(key: *const c_void, len: ULONG) -> &'k [u8] {
+    if key.is_null() || len == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(key as *const u8, len as usize)
+}
+
+fn identity(name: String, metadata: &ASSEMBLYMETADATA, locale: &[u16], key: &[u8], full_key: bool) -> Result<AssemblyName, HostingError> {
+    let mut identity = AssemblyName::new(&name);
+    identity.version = Some(AssemblyVersion::new(
+        metadata.usMajorVersion, metadata.usMinorVersion, metadata.usBuildNumber, metadata.usRevisionNumber
+    ));
+    let culture_len = locale.iter().position(|&c| c == 0).unwrap_or(locale.len());
+    identity.culture = Some(String::from_utf16_lossy(&locale[..culture_len]));
+    identity.public_key_token = match key.len() {
+        0 => None, 
+        _ if full_key => Some(public_key_token(key)?), 
+        8 => {
+            let mut token = [0u8; 8];
+            token.copy_from_slice(key);
+            Some(token)
+        }, 
+        _ => None,
+    };
+    Ok(identity)
+}
+
+//The last eight bytes of the key's SHA-1, reversed
+fn public_key_token(key: &[u8]) -> Result<[u8; 8], HostingError> {
+    let hash = digest(CALG_SHA1, key)?;
+    let mut token = [0u8; 8];
+    for (t, h) in token.iter_mut().zip(hash.iter().rev()) {
+        *t = *h;
+    }
+    Ok(token)
+}
+
+#[derive(Clone, Debug)]
+pub struct AssemblyNode {
+    pub name: AssemblyName, 
+    //None when no probe found a file whose identity satisfies the reference
+    pub path: Option<PathBuf>, 
+    //Indices into DependencyGraph::nodes
+    pub references: Vec<usize>,
+}
+
+//One assembly referenced at more than one version
+#[derive(Clone, Debug)]
+pub struct VersionConflict {
+    pub name: String, 
+    //The nodes involved, one per version, oldest first
+    pub nodes: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DependencyGraph {
+    pub nodes: Vec<AssemblyNode>, 
+    //Indices of the assemblies the graph was built from
+    pub roots: Vec<usize>, 
+    pub conflicts: Vec<VersionConflict>,
+}
+
+impl DependencyGraph {
+    //Walks the AssemblyRefs of every path, then of everything the probe 
+    // resolves, until nothing new turns up. The probe gets the reference 
+    // and the directory of the assembly making it. Failing to open a root 
+    // is an error; a probed file that can't be opened, or turns out to 
+    // be a different assembly, just leaves the reference unresolved.
+    pub fn build<I, P, F>(dispenser: &MetadataDispenser, paths: I, mut probe: F) -> Result<DependencyGraph, HostingError> 
+        where I: IntoIterator<Item = P>, P: AsRef<Path>, F: FnMut(&AssemblyName, &Path) -> Option<PathBuf>
+    {
+        let mut graph = DependencyGraph { nodes: Vec::new(), roots: Vec::new(), conflicts: Vec::new() };
+        let mut index: HashMap<AssemblyName, usize> = HashMap::new();
+        let mut pending: Vec<(usize, MetadataScope)> = Vec::new();
+        for path in paths {
+            let scope = dispenser.open(path.as_ref())?;
+            let name = match scope.assembly_name()? {
+                Some(name) => name, 
+                None => continue,
+            };
+            let node = graph.node(&mut index, name);
+            graph.nodes[node].path = Some(path.as_ref().to_path_buf());
+            graph.roots.push(node);
+            pending.push((node, scope));
+        }
+        while let Some((from, scope)) = pending.pop() {
+            let directory = scope.path().and_then(Path::parent).map(Path::to_path_buf).unwrap_or_default();
+            for assembly_ref in scope.assembly_refs()? {
+                let reference = scope.assembly_ref(assembly_ref)?;
+                let known = index.contains_key(&reference);
+                let to = graph.node(&mut index, reference);
+                if !graph.nodes[from].references.contains(&to) {
+                    graph.nodes[from].references.push(to);
+                }
+                if known {
+                    continue;
+                }
+                let candidate = probe(&graph.nodes[to].name, &directory)
+                    .and_then(|path| dispenser.open(&path).ok().map(|scope| (path, scope)));
+                if let Some((path, scope)) = candidate {
+                    let satisfies = match scope.assembly_name() {
+                        Ok(Some(ref found)) => found.satisfies(&graph.nodes[to].name), 
+                        _ => false,
+                    };
+                    if satisfies {
+                        graph.nodes[to].path = Some(path);
+                        pending.push((to, scope));
+                    }
+                }
+            }
+        }
+        graph.conflicts = conflicts(&graph.nodes);
+        Ok(graph)
+    }
+
+    fn node(&mut self, index: &mut HashMap<AssemblyName, usize>, name: AssemblyName) -> usize {
+        let nodes = &mut self.nodes;
+        *index.entry(name.clone()).or_insert_with(|| {
+            nodes.push(AssemblyNode { name, path: None, references: Vec::new() });
+            nodes.len() - 1
+        })
+    }
+
+    pub fn unresolved(&self) -> impl Iterator<Item = &AssemblyNode> {
+        self.nodes.iter().filter(|node| node.path.is_none())
+    }
+
+    //The nodes that reference `node`
+    pub fn referrers(&self, node: usize) -> Vec<usize> {
+        (0..self.nodes.len()).filter(|&i| self.nodes[i].references.contains(&node)).collect()
+    }
+}
+
+//Same name, token and culture at different versions
+fn conflicts(nodes: &[AssemblyNode]) -> Vec<VersionConflict> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        let mut unversioned = node.name.clone();
+        unversioned.version = None;
+        match groups.iter_mut().find(|group| {
+            let mut other = nodes[group[0]].name.clone();
+            other.version = None;
+            other == unversioned
+        }) {
+            Some(group) => group.push(i), 
+            None => groups.push(vec![i]),
+        }
+    }
+    groups.into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_by_key(|&i| nodes[i].name.version);
+            VersionConflict { name: nodes[group[0]].name.name.clone(), nodes: group }
+        })
+        .collect()
+}
+
+//`<directory>\<name>.dll`, then `.exe`, as the loader probes the 
+// application base
+pub fn probe_directory(directory: &Path, reference: &AssemblyName) -> Option<PathBuf> {
+    ["dll", "exe"].iter()
+        .map(|extension| directory.join(format!("{}.{}", reference.name, extension)))
+        .find(|path| path.is_file())
+}
+
+//The v4 GAC, then the v2 one. Only strong-named references with a 
+// version can be in there.
+pub fn probe_gac(reference: &AssemblyName) -> Option<PathBuf> {
+    let version = reference.version?;
+    let token: String = reference.public_key_token?.iter().map(|b| format!("{:02x}", b)).collect();
+    let culture = reference.normalized_culture().unwrap_or_default();
+    let windows = PathBuf::from(env::var_os("WINDIR")?);
+    let file = format!("{}.dll", reference.name);
+    let v4 = format!("v4.0_{}_{}_{}", version, culture, token);
+    let v2 = format!("{}_{}_{}", version, culture, token);
+    ["GAC_MSIL", "GAC_64", "GAC_32"].iter()
+        .map(|gac| windows.join("Microsoft.NET").join("assembly").join(gac).join(&reference.name).join(&v4).join(&file))
+        .chain(["GAC_MSIL", "GAC_64", "GAC_32", "GAC"].iter()
+            .map(|gac| windows.join("assembly").join(gac).join(&reference.name).join(&v2).join(&file)))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node(name: &str) -> AssemblyNode {
+        AssemblyNode { name: name.parse().unwrap(), path: None, references: Vec::new() }
+    }
+
+    #[test]
+    fn flags_versions_of_the_same_identity() {
+        let nodes = vec![
+            node("Newtonsoft.Json, Version=12.0.0.0, Culture=neutral, PublicKeyToken=30ad4fe6b2a6aeed"), 
+            node("mscorlib, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089"), 
+            node("Newtonsoft.Json, Version=9.0.0.0, Culture=neutral, PublicKeyToken=30ad4fe6b2a6aeed"), 
+            //Different token, so a different assembly altogether
+            node("Newtonsoft.Json, Version=6.0.0.0, Culture=neutral, PublicKeyToken=null"),
+        ];
+        let found = conflicts(&nodes);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "Newtonsoft.Json");
+        assert_eq!(found[0].nodes, vec![2, 0]);
+    }
+}
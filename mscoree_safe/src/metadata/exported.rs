@@ -0,0 +1,151 @@
+// exported.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ExportedType rows. In a multi-file assembly they list the public types 
+// of the other modules; with tdForwarder set they are type forwarders, 
+// pointing at the assembly the type moved to. Facades are made of little 
+// else.
+use std::ptr;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::S_OK;
+
+use mscoree_sys::corerror::{CLDB_E_FILE_CORRUPT, CLDB_E_RECORD_NOTFOUND};
+use mscoree_sys::corhdr::*;
+
+use buffer::wide;
+use error::HostingError;
+use metadata::{read_string, token_type, MetadataScope};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TypeImplementation {
+    //Another module of this assembly
+    File(mdFile), 
+    Assembly(mdAssemblyRef), 
+    //Nested in the given exported type, which says where both live
+    Nested(mdExportedType),
+}
+
+impl TypeImplementation {
+    fn from_token(token: mdToken) -> Option<TypeImplementation> {
+        match token_type(token) {
+            mdtFile => Some(TypeImplementation::File(token)), 
+            mdtAssemblyRef => Some(TypeImplementation::Assembly(token)), 
+            mdtExportedType => Some(TypeImplementation::Nested(token)), 
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExportedType {
+    pub token: mdExportedType, 
+    //Namespace-qualified, nested types written Outer+Inner
+    pub name: String, 
+    pub implementation: TypeImplementation, 
+    //The TypeDef in the implementing module; only a hint, and nil for 
+    // forwarders
+    pub type_def: mdTypeDef, 
+    //CorTypeAttr
+    pub flags: DWORD,
+}
+
+impl ExportedType {
+    pub fn is_forwarder(&self) -> bool {
+        self.flags & tdForwarder != 0
+    }
+}
+
+impl MetadataScope {
+    pub fn exported_types(&self) -> Result<Vec<ExportedType>, HostingError> {
+        let tokens = self.assembly_tokens(CALL!(IMetaDataAssemblyImport::EnumExportedTypes), |henum, tokens, max, fetched| unsafe {
+            self.assembly.EnumExportedTypes(henum, tokens, max, fetched)
+        })?;
+        tokens.into_iter().map(|token| self.exported_type(token)).collect()
+    }
+
+    pub fn exported_type(&self, token: mdExportedType) -> Result<ExportedType, HostingError> {
+        let mut implementation: mdToken = mdTokenNil;
+        let mut type_def: mdTypeDef = mdTokenNil;
+        let mut flags: DWORD = 0;
+        let name = read_string(CALL!(IMetaDataAssemblyImport::GetExportedTypeProps), |buffer, len, needed| unsafe {
+            self.assembly.GetExportedTypeProps(token, buffer, len, needed, &mut implementation, &mut type_def, &mut flags)
+        })?;
+        let implementation = TypeImplementation::from_token(implementation)
+            .ok_or_else(|| HostingError::from_hresult(CLDB_E_FILE_CORRUPT, CALL!(IMetaDataAssemblyImport::GetExportedTypeProps)))?;
+        let name = match implementation {
+            TypeImplementation::Nested(outer) => format!("{}+{}", self.exported_type(outer)?.name, name), 
+            _ => name,
+        };
+        Ok(ExportedType { token, name, implementation, type_def, flags })
+    }
+
+    //Top-level when `enclosing` is None; nested names are looked up one 
+    // level at a time, as with find_type_def
+    pub fn find_exported_type(&self, name: &str, enclosing: Option<mdExportedType>) -> Result<Option<mdExportedType>, HostingError> {
+        let name = wide(name);
+        let mut token: mdExportedType = mdTokenNil;
+        let hr = unsafe { self.assembly.FindExportedTypeByName(name.as_ptr(), enclosing.unwrap_or(mdTokenNil), &mut token) };
+        match hr {
+            S_OK => Ok(Some(token)), 
+            hr if hr < 0 && hr != CLDB_E_RECORD_NOTFOUND => Err(HostingError::from_hresult(hr, CALL!(IMetaDataAssemblyImport::FindExportedTypeByName))), 
+            _ => Ok(None),
+        }
+    }
+
+    //Where a forwarder sends the type, following nested types out to the 
+    // outermost one. None for types exported from another module.
+    pub fn forwarded_to(&self, exported: &ExportedType) -> Result<Option<mdAssemblyRef>, HostingError> {
+        if !exported.is_forwarder() {
+            return Ok(None);
+        }
+        let mut implementation = exported.implementation;
+        loop {
+            match implementation {
+                TypeImplementation::Assembly(assembly_ref) => return Ok(Some(assembly_ref)), 
+                TypeImplementation::File(_) => return Ok(None), 
+                TypeImplementation::Nested(outer) => implementation = self.exported_type(outer)?.implementation,
+            }
+        }
+    }
+
+    //The simple name of a referenced assembly, e.g. "mscorlib"
+    pub fn assembly_ref_name(&self, assembly_ref: mdAssemblyRef) -> Result<String, HostingError> {
+        read_string(CALL!(IMetaDataAssemblyImport::GetAssemblyRefProps), |buffer, len, needed| unsafe {
+            self.assembly.GetAssemblyRefProps(
+                assembly_ref, ptr::null_mut(), ptr::null_mut(), buffer, len, needed, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn implementation_tables() {
+        assert_eq!(TypeImplementation::from_token(mdtAssemblyRef | 2), Some(TypeImplementation::Assembly(mdtAssemblyRef | 2)));
+        assert_eq!(TypeImplementation::from_token(mdtExportedType | 1), Some(TypeImplementation::Nested(mdtExportedType | 1)));
+        assert_eq!(TypeImplementation::from_token(mdtTypeDef | 1), None);
+    }
+}
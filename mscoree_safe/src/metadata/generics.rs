@@ -0,0 +1,168 @@
+// generics.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Generic parameters of types and methods, their constraints, and the 
+// MethodSpec rows that instantiate generic methods, through 
+// IMetaDataImport2. Instantiated types need nothing extra: they are 
+// TypeSpecs, and TypeSig::GenericInst already describes them.
+use std::ptr;
+
+use winapi::shared::minwindef::{DWORD, ULONG};
+
+use mscoree_sys::corerror::META_E_BAD_SIGNATURE;
+use mscoree_sys::corhdr::*;
+
+use error::HostingError;
+use metadata::{borrow_blob, read_string, MetadataScope, Signature, TypeSig};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Variance {
+    None, 
+    //out T
+    Covariant, 
+    //in T
+    Contravariant,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GenericParam {
+    pub token: mdGenericParam, 
+    //The TypeDef or MethodDef declaring it
+    pub owner: mdToken, 
+    //Position in the parameter list; what Var(n) and MVar(n) refer to
+    pub index: u32, 
+    pub name: String, 
+    //CorGenericParamAttr
+    pub flags: DWORD, 
+    //TypeDef, TypeRef or TypeSpec tokens of the types it must derive from 
+    // or implement
+    pub constraints: Vec<mdToken>,
+}
+
+impl GenericParam {
+    pub fn variance(&self) -> Variance {
+        match self.flags & gpVarianceMask {
+            gpCovariant => Variance::Covariant, 
+            gpContravariant => Variance::Contravariant, 
+            _ => Variance::None,
+        }
+    }
+
+    //where T : class
+    pub fn is_reference_type(&self) -> bool {
+        self.flags & gpReferenceTypeConstraint != 0
+    }
+
+    //where T : struct
+    pub fn is_value_type(&self) -> bool {
+        self.flags & gpNotNullableValueTypeConstraint != 0
+    }
+
+    //where T : new()
+    pub fn has_default_constructor(&self) -> bool {
+        self.flags & gpDefaultConstructorConstraint != 0
+    }
+}
+
+//A generic method instantiation, e.g. Enumerable.Cast<string>
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodSpec {
+    pub token: mdMethodSpec, 
+    //The generic MethodDef or MemberRef being instantiated
+    pub method: mdToken, 
+    pub arguments: Vec<TypeSig>,
+}
+
+impl MetadataScope {
+    //In declaration order; empty for a non-generic type or method
+    pub fn generic_params(&self, owner: mdToken) -> Result<Vec<GenericParam>, HostingError> {
+        let tokens = self.tokens(CALL!(IMetaDataImport2::EnumGenericParams), |henum, tokens, max, fetched| unsafe {
+            self.import2.EnumGenericParams(henum, owner, tokens, max, fetched)
+        })?;
+        let mut params = tokens.into_iter().map(|token| self.generic_param(token)).collect::<Result<Vec<_>, _>>()?;
+        params.sort_by_key(|param| param.index);
+        Ok(params)
+    }
+
+    pub fn generic_param(&self, token: mdGenericParam) -> Result<GenericParam, HostingError> {
+        let mut index: ULONG = 0;
+        let mut flags: DWORD = 0;
+        let mut owner: mdToken = mdTokenNil;
+        let name = read_string(CALL!(IMetaDataImport2::GetGenericParamProps), |buffer, len, needed| unsafe {
+            self.import2.GetGenericParamProps(token, &mut index, &mut flags, &mut owner, ptr::null_mut(), buffer, len, needed)
+        })?;
+        let constraints = self.generic_param_constraints(token)?;
+        Ok(GenericParam { token, owner, index, name, flags, constraints })
+    }
+
+    pub fn generic_param_constraints(&self, param: mdGenericParam) -> Result<Vec<mdToken>, HostingError> {
+        let tokens = self.tokens(CALL!(IMetaDataImport2::EnumGenericParamConstraints), |henum, tokens, max, fetched| unsafe {
+            self.import2.EnumGenericParamConstraints(henum, param, tokens, max, fetched)
+        })?;
+        tokens.into_iter()
+            .map(|constraint| {
+                let mut ty: mdToken = mdTokenNil;
+                CHECK_HR!(IMetaDataImport2::GetGenericParamConstraintProps, self.import2.GetGenericParamConstraintProps(
+                    constraint, ptr::null_mut(), &mut ty
+                ))?;
+                Ok(ty)
+            })
+            .collect()
+    }
+
+    //Every instantiation of a generic MethodDef or MemberRef in this module
+    pub fn method_specs(&self, method: mdToken) -> Result<Vec<MethodSpec>, HostingError> {
+        let tokens = self.tokens(CALL!(IMetaDataImport2::EnumMethodSpecs), |henum, tokens, max, fetched| unsafe {
+            self.import2.EnumMethodSpecs(henum, method, tokens, max, fetched)
+        })?;
+        tokens.into_iter().map(|token| self.method_spec(token)).collect()
+    }
+
+    pub fn method_spec(&self, token: mdMethodSpec) -> Result<MethodSpec, HostingError> {
+        let mut method: mdToken = mdTokenNil;
+        let mut sig: PCCOR_SIGNATURE = ptr::null();
+        let mut len: ULONG = 0;
+        CHECK_HR!(IMetaDataImport2::GetMethodSpecProps, self.import2.GetMethodSpecProps(token, &mut method, &mut sig, &mut len))?;
+        let arguments = method_spec_arguments(unsafe { borrow_blob(sig, len) })?;
+        Ok(MethodSpec { token, method, arguments })
+    }
+}
+
+fn method_spec_arguments(blob: &[u8]) -> Result<Vec<TypeSig>, HostingError> {
+    match Signature::parse(blob)? {
+        Signature::GenericInst(arguments) => Ok(arguments), 
+        _ => Err(HostingError::from_hresult(META_E_BAD_SIGNATURE, CALL!(IMetaDataImport2::GetMethodSpecProps))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn method_spec_blob() {
+        //GENERICINST, two arguments: string and !!0
+        assert_eq!(method_spec_arguments(&[0x0A, 0x02, 0x0E, 0x1E, 0x00]).unwrap(), vec![TypeSig::String, TypeSig::MVar(0)]);
+        //A field signature is not an instantiation
+        assert!(method_spec_arguments(&[0x06, 0x08]).is_err());
+    }
+}
@@ -0,0 +1,418 @@
+// mod.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Read-only access to assembly metadata through the unmanaged metadata API 
+// (IMetaDataDispenser / IMetaDataImport). The dispenser parses the file 
+// itself; nothing is loaded into a runtime, so assemblies built for other 
+// frameworks or bitnesses can be inspected as freely as our own.
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{REFCLSID, REFIID};
+use winapi::shared::minwindef::{DWORD, LPCVOID, LPVOID, ULONG};
+use winapi::shared::ntdef::LPWSTR;
+use winapi::shared::winerror::{E_INVALIDARG, HRESULT, S_OK};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::cor::{
+    CLSID_CorMetaDataDispenser, 
+    IID_IMetaDataDispenser, 
+    IMetaDataAssemblyImport, 
+    IMetaDataDispenser, 
+    IMetaDataImport, 
+    IMetaDataImport2
+};
+use mscoree_sys::corerror::{CLDB_E_RECORD_NOTFOUND, META_E_BAD_SIGNATURE};
+use mscoree_sys::corhdr::*;
+
+use buffer::{double_call_string, wide};
+use comptr::ComPtr;
+use error::{Call, HostingError};
+use metahost::{HostInterface, QueryInterface, RuntimeInfo};
+
+mod attribute;
+mod blob;
+mod dependency;
+mod exported;
+mod generics;
+mod pe;
+mod pinvoke;
+mod resource;
+mod signature;
+mod tables;
+#[cfg(feature = "hosting")]
+mod validate;
+
+pub use self::attribute::{AttributeValue, CustomAttribute, DecodedAttribute, NamedArgument, NamedArgumentKind};
+pub use self::dependency::{probe_directory, probe_gac, AssemblyNode, DependencyGraph, VersionConflict};
+pub use self::exported::{ExportedType, TypeImplementation};
+pub use self::generics::{GenericParam, MethodSpec, Variance};
+pub use self::pinvoke::{NativeCallConv, NativeCharSet, PinvokeMap};
+pub use self::resource::{resource_from_image, ManifestResource, ResourceLocation};
+pub use self::signature::{CallingConvention, MethodSig, PropertySig, Signature, TypeSig};
+pub use self::tables::{CodedTokenInfo, ColumnInfo, ColumnType, Heap, HeapSizes, MetadataTables, TableInfo};
+#[cfg(feature = "hosting")]
+pub use self::validate::{MetadataValidationError, ModuleKind};
+
+//How many tokens each Enum* call asks for
+const ENUM_BATCH: usize = 64;
+
+//The table a token points into, e.g. mdtTypeDef
+pub fn token_type(token: mdToken) -> CorTokenType {
+    token & 0xFF00_0000
+}
+
+pub struct MetadataDispenser {
+    inner: ComPtr<IMetaDataDispenser>,
+}
+
+impl HostInterface for MetadataDispenser {
+    fn clsid() -> REFCLSID {
+        &CLSID_CorMetaDataDispenser
+    }
+
+    fn iid() -> REFIID {
+        &IID_IMetaDataDispenser
+    }
+
+    unsafe fn from_interface(p: LPVOID) -> MetadataDispenser {
+        let inner = ComPtr::from_raw(p as *mut IMetaDataDispenser)
+            .expect("GetInterface pointers are never null");
+        MetadataDispenser { inner }
+    }
+}
+
+impl MetadataDispenser {
+    //The dispenser of the given runtime; any v2+ runtime reads metadata of 
+    // every version
+    pub fn new(runtime: &dyn RuntimeInfo) -> Result<MetadataDispenser, HostingError> {
+        runtime.query::<MetadataDispenser>()
+    }
+
+    //Read-only. The file stays mapped, and locked, until the scope is dropped.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<MetadataScope, HostingError> {
+        let name = wide(path.as_ref());
+        let unknown = unsafe {
+            ComPtr::from_out(CALL!(IMetaDataDispenser::OpenScope), |p: *mut *mut IUnknown| {
+                self.inner.OpenScope(name.as_ptr(), ofRead, &IMetaDataImport::uuidof(), p)
+            })?
+        };
+        MetadataScope::from_unknown(unknown, Image::File(path.as_ref().to_path_buf()))
+    }
+
+    //Read-only, over a private copy of the image, so nothing touches the 
+    // disk and the buffer can be dropped straight away
+    pub fn open_memory(&self, image: &[u8]) -> Result<MetadataScope, HostingError> {
+        if image.len() > ULONG::max_value() as usize {
+            return Err(HostingError::from_hresult(E_INVALIDARG, CALL!(IMetaDataDispenser::OpenScopeOnMemory)));
+        }
+        let image = image.to_vec();
+        let unknown = unsafe {
+            ComPtr::from_out(CALL!(IMetaDataDispenser::OpenScopeOnMemory), |p: *mut *mut IUnknown| {
+                self.inner.OpenScopeOnMemory(image.as_ptr() as LPCVOID, image.len() as ULONG, ofRead, &IMetaDataImport::uuidof(), p)
+            })?
+        };
+        MetadataScope::from_unknown(unknown, Image::Memory(image))
+    }
+}
+
+//Where a scope's metadata lives. The metadata engine reads a Memory 
+// image in place rather than copying it.
+enum Image {
+    File(PathBuf), 
+    Memory(Vec<u8>),
+}
+
+//One opened module. Blobs borrowed from a scope (signatures, attribute 
+// values) point into the mapped file or copied image and live as long as it does.
+pub struct MetadataScope {
+    import: ComPtr<IMetaDataImport>, 
+    //Generic parameters, constraints and MethodSpecs
+    import2: ComPtr<IMetaDataImport2>, 
+    //Manifest tables: assembly refs, files, resources, exported types
+    assembly: ComPtr<IMetaDataAssemblyImport>, 
+    //Embedded resources are read back out of the image itself. Declared 
+    // last so the interfaces are released before a Memory image is freed.
+    image: Image,
+}
+
+impl MetadataScope {
+    fn from_unknown(unknown: ComPtr<IUnknown>, image: Image) -> Result<MetadataScope, HostingError> {
+        Ok(MetadataScope { 
+            import: unknown.query_interface::<IMetaDataImport>()?, 
+            import2: unknown.query_interface::<IMetaDataImport2>()?, 
+            assembly: unknown.query_interface::<IMetaDataAssemblyImport>()?, 
+            image,
+        })
+    }
+
+    //None for scopes opened with open_memory
+    pub fn path(&self) -> Option<&Path> {
+        match self.image {
+            Image::File(ref path) => Some(path), 
+            Image::Memory(_) => None,
+        }
+    }
+
+    //Every type defined in the module, except the <Module> pseudo-type
+    pub fn type_defs(&self) -> Result<Vec<mdTypeDef>, HostingError> {
+        self.tokens(CALL!(IMetaDataImport::EnumTypeDefs), |henum, tokens, max, fetched| unsafe {
+            self.import.EnumTypeDefs(henum, tokens, max, fetched)
+        })
+    }
+
+    //Methods declared by the type; mdTokenNil gives the module's global 
+    // functions
+    pub fn methods(&self, owner: mdTypeDef) -> Result<Vec<mdMethodDef>, HostingError> {
+        self.tokens(CALL!(IMetaDataImport::EnumMethods), |henum, tokens, max, fetched| unsafe {
+            self.import.EnumMethods(henum, owner, tokens, max, fetched)
+        })
+    }
+
+    pub fn method_name(&self, method: mdMethodDef) -> Result<String, HostingError> {
+        read_string(CALL!(IMetaDataImport::GetMethodProps), |buffer, len, needed| unsafe {
+            self.import.GetMethodProps(method, ptr::null_mut(), buffer, len, needed, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+        })
+    }
+
+    //CorMethodAttr flags, and the declaring type (mdTokenNil for globals)
+    pub fn method_attributes(&self, method: mdMethodDef) -> Result<(DWORD, mdTypeDef), HostingError> {
+        let mut attributes: DWORD = 0;
+        let mut owner: mdTypeDef = mdTokenNil;
+        CHECK_HR!(IMetaDataImport::GetMethodProps, self.import.GetMethodProps(
+            method, &mut owner, ptr::null_mut(), 0, ptr::null_mut(), &mut attributes, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut()
+        ))?;
+        Ok((attributes, owner))
+    }
+
+    pub fn find_type_def(&self, name: &str, enclosing: Option<mdTypeDef>) -> Result<Option<mdTypeDef>, HostingError> {
+        let name = wide(name);
+        let mut td: mdTypeDef = mdTokenNil;
+        let hr = unsafe { self.import.FindTypeDefByName(name.as_ptr(), enclosing.unwrap_or(mdTokenNil), &mut td) };
+        match hr {
+            S_OK => Ok(Some(td)), 
+            hr if hr < 0 && hr != CLDB_E_RECORD_NOTFOUND => Err(HostingError::from_hresult(hr, CALL!(IMetaDataImport::FindTypeDefByName))), 
+            _ => Ok(None),
+        }
+    }
+
+    //Namespace-qualified, with nested types written Outer+Inner as 
+    // reflection does. Accepts TypeDef and TypeRef tokens.
+    pub fn type_name(&self, token: mdToken) -> Result<String, HostingError> {
+        match token_type(token) {
+            mdtTypeDef => {
+                let name = read_string(CALL!(IMetaDataImport::GetTypeDefProps), |buffer, len, needed| unsafe {
+                    self.import.GetTypeDefProps(token, buffer, len, needed, ptr::null_mut(), ptr::null_mut())
+                })?;
+                let mut enclosing: mdTypeDef = mdTokenNil;
+                //Fails with CLDB_E_RECORD_NOTFOUND for types that aren't nested
+                match unsafe { self.import.GetNestedClassProps(token, &mut enclosing) } {
+                    S_OK if enclosing != mdTokenNil => Ok(format!("{}+{}", self.type_name(enclosing)?, name)), 
+                    _ => Ok(name),
+                }
+            }, 
+            mdtTypeRef => {
+                let mut scope: mdToken = mdTokenNil;
+                let name = read_string(CALL!(IMetaDataImport::GetTypeRefProps), |buffer, len, needed| unsafe {
+                    self.import.GetTypeRefProps(token, &mut scope, buffer, len, needed)
+                })?;
+                if token_type(scope) == mdtTypeRef {
+                    Ok(format!("{}+{}", self.type_name(scope)?, name))
+                } else {
+                    Ok(name)
+                }
+            }, 
+            _ => Err(HostingError::from_hresult(E_INVALIDARG, CALL!(IMetaDataImport::GetTypeRefProps))),
+        }
+    }
+
+    //Attributes applied to any token: types, members, parameters, the 
+    // assembly itself
+    pub fn custom_attributes(&self, owner: mdToken) -> Result<Vec<CustomAttribute>, HostingError> {
+        let tokens = self.tokens(CALL!(IMetaDataImport::EnumCustomAttributes), |henum, tokens, max, fetched| unsafe {
+            self.import.EnumCustomAttributes(henum, owner, mdTokenNil, tokens, max, fetched)
+        })?;
+        tokens.into_iter().map(|token| self.custom_attribute(token)).collect()
+    }
+
+    //The first attribute of the given type, e.g. "System.ObsoleteAttribute"
+    pub fn find_custom_attribute(&self, owner: mdToken, type_name: &str) -> Result<Option<CustomAttribute>, HostingError> {
+        Ok(self.custom_attributes(owner)?.into_iter().find(|attribute| attribute.type_name == type_name))
+    }
+
+    fn custom_attribute(&self, token: mdCustomAttribute) -> Result<CustomAttribute, HostingError> {
+        let (mut owner, mut constructor): (mdToken, mdToken) = (mdTokenNil, mdTokenNil);
+        let mut blob: *const c_void = ptr::null();
+        let mut len: ULONG = 0;
+        CHECK_HR!(IMetaDataImport::GetCustomAttributeProps, self.import.GetCustomAttributeProps(token, &mut owner, &mut constructor, &mut blob, &mut len))?;
+        let (parent, _) = self.member_parent(constructor)?;
+        Ok(CustomAttribute {
+            token, 
+            owner, 
+            constructor, 
+            type_name: self.type_name(parent)?, 
+            blob: unsafe { borrow_blob(blob as *const u8, len) }.to_vec(),
+        })
+    }
+
+    //The declaring type and signature of a MethodDef or MemberRef
+    pub(crate) fn member_parent(&self, member: mdToken) -> Result<(mdToken, &[u8]), HostingError> {
+        let mut parent: mdToken = mdTokenNil;
+        let mut sig: PCCOR_SIGNATURE = ptr::null();
+        let mut len: ULONG = 0;
+        match token_type(member) {
+            mdtMethodDef => {
+                CHECK_HR!(IMetaDataImport::GetMethodProps, self.import.GetMethodProps(
+                    member, &mut parent, ptr::null_mut(), 0, ptr::null_mut(), ptr::null_mut(), &mut sig, &mut len, ptr::null_mut(), ptr::null_mut()
+                ))?;
+            }, 
+            mdtMemberRef => {
+                CHECK_HR!(IMetaDataImport::GetMemberRefProps, self.import.GetMemberRefProps(
+                    member, &mut parent, ptr::null_mut(), 0, ptr::null_mut(), &mut sig, &mut len
+                ))?;
+            }, 
+            _ => return Err(HostingError::from_hresult(E_INVALIDARG, CALL!(IMetaDataImport::GetMemberProps))),
+        }
+        Ok((parent, unsafe { borrow_blob(sig, len) }))
+    }
+
+    //MethodDef or MemberRef. MemberRefs to fields come back as an error; 
+    // member_signature takes either.
+    pub fn method_signature(&self, method: mdToken) -> Result<MethodSig, HostingError> {
+        self.member_signature(method)?.into_method()
+            .ok_or_else(|| HostingError::from_hresult(META_E_BAD_SIGNATURE, CALL!(IMetaDataImport::GetMemberProps)))
+    }
+
+    pub fn member_signature(&self, member: mdToken) -> Result<Signature, HostingError> {
+        Signature::parse(self.member_parent(member)?.1)
+    }
+
+    pub fn field_type(&self, field: mdFieldDef) -> Result<TypeSig, HostingError> {
+        match Signature::parse(self.field_blob(field)?)? {
+            Signature::Field(ty) => Ok(ty), 
+            _ => Err(HostingError::from_hresult(META_E_BAD_SIGNATURE, CALL!(IMetaDataImport::GetFieldProps))),
+        }
+    }
+
+    pub fn property_signature(&self, property: mdProperty) -> Result<PropertySig, HostingError> {
+        let mut sig: PCCOR_SIGNATURE = ptr::null();
+        let mut len: ULONG = 0;
+        CHECK_HR!(IMetaDataImport::GetPropertyProps, self.import.GetPropertyProps(
+            property, ptr::null_mut(), ptr::null_mut(), 0, ptr::null_mut(), ptr::null_mut(), &mut sig, &mut len, 
+            ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), 0, ptr::null_mut()
+        ))?;
+        match Signature::parse(unsafe { borrow_blob(sig, len) })? {
+            Signature::Property(sig) => Ok(sig), 
+            _ => Err(HostingError::from_hresult(META_E_BAD_SIGNATURE, CALL!(IMetaDataImport::GetPropertyProps))),
+        }
+    }
+
+    //The type a TypeSpec token stands for, e.g. a generic instantiation
+    pub fn type_spec(&self, spec: mdTypeSpec) -> Result<TypeSig, HostingError> {
+        let mut sig: PCCOR_SIGNATURE = ptr::null();
+        let mut len: ULONG = 0;
+        CHECK_HR!(IMetaDataImport::GetTypeSpecFromToken, self.import.GetTypeSpecFromToken(spec, &mut sig, &mut len))?;
+        TypeSig::parse(unsafe { borrow_blob(sig, len) })
+    }
+
+    //Stand-alone signatures: method locals and calli sites
+    pub fn standalone_signature(&self, token: mdSignature) -> Result<Signature, HostingError> {
+        let mut sig: PCCOR_SIGNATURE = ptr::null();
+        let mut len: ULONG = 0;
+        CHECK_HR!(IMetaDataImport::GetSigFromToken, self.import.GetSigFromToken(token, &mut sig, &mut len))?;
+        Signature::parse(unsafe { borrow_blob(sig, len) })
+    }
+
+    //Signature of a FieldDef: FIELD followed by the field's type
+    pub(crate) fn field_blob(&self, field: mdFieldDef) -> Result<&[u8], HostingError> {
+        let mut sig: PCCOR_SIGNATURE = ptr::null();
+        let mut len: ULONG = 0;
+        CHECK_HR!(IMetaDataImport::GetFieldProps, self.import.GetFieldProps(
+            field, ptr::null_mut(), ptr::null_mut(), 0, ptr::null_mut(), ptr::null_mut(), &mut sig, &mut len, ptr::null_mut(), ptr::null_mut(), ptr::null_mut()
+        ))?;
+        Ok(unsafe { borrow_blob(sig, len) })
+    }
+
+    pub(crate) fn fields_named(&self, owner: mdTypeDef, name: &str) -> Result<Vec<mdFieldDef>, HostingError> {
+        let name = wide(name);
+        self.tokens(CALL!(IMetaDataImport::EnumFieldsWithName), |henum, tokens, max, fetched| unsafe {
+            self.import.EnumFieldsWithName(henum, owner, name.as_ptr(), tokens, max, fetched)
+        })
+    }
+
+    fn tokens<F>(&self, call: Call, next: F) -> Result<Vec<mdToken>, HostingError> 
+        where F: FnMut(*mut HCORENUM, *mut mdToken, ULONG, *mut ULONG) -> HRESULT
+    {
+        enumerate(call, next, |henum| unsafe { self.import.CloseEnum(henum) })
+    }
+
+    //Manifest enumerations are closed through the interface that opened them
+    fn assembly_tokens<F>(&self, call: Call, next: F) -> Result<Vec<mdToken>, HostingError> 
+        where F: FnMut(*mut HCORENUM, *mut mdToken, ULONG, *mut ULONG) -> HRESULT
+    {
+        enumerate(call, next, |henum| unsafe { self.assembly.CloseEnum(henum) })
+    }
+}
+
+//Drives an HCORENUM enumeration to the end, ENUM_BATCH tokens per call. 
+// The handle is created by the first call and closed however the loop ends.
+fn enumerate<F, C>(call: Call, mut next: F, close: C) -> Result<Vec<mdToken>, HostingError> 
+    where F: FnMut(*mut HCORENUM, *mut mdToken, ULONG, *mut ULONG) -> HRESULT, C: FnOnce(HCORENUM)
+{
+    let mut henum: HCORENUM = ptr::null_mut();
+    let mut batch = [mdTokenNil; ENUM_BATCH];
+    let mut tokens = Vec::new();
+    let result = loop {
+        let mut fetched: ULONG = 0;
+        let hr = next(&mut henum, batch.as_mut_ptr(), ENUM_BATCH as ULONG, &mut fetched);
+        if hr < 0 {
+            break Err(HostingError::from_hresult(hr, call));
+        }
+        tokens.extend_from_slice(&batch[..(fetched as usize).min(ENUM_BATCH)]);
+        //S_FALSE once nothing is left
+        if hr != S_OK || fetched == 0 {
+            break Ok(tokens);
+        }
+    };
+    if !henum.is_null() {
+        close(henum);
+    }
+    result
+}
+
+//Names come back through the usual size-then-fill pair of calls
+fn read_string<F>(call: Call, mut f: F) -> Result<String, HostingError> 
+    where F: FnMut(LPWSTR, ULONG, *mut ULONG) -> HRESULT
+{
+    double_call_string(|buffer, len: *mut DWORD| f(buffer, unsafe { *len }, len))
+        .map_err(|hr| HostingError::from_hresult(hr, call))
+}
+
+unsafe fn borrow_blob<'s>(data: *const u8, len: ULONG) -> &'s [u8] {
+    if data.is_null() || len == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(data, len as usize)
+}
@@ -0,0 +1,161 @@
+// pe.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Just enough of the PE/COFF layout (ECMA-335 II.25) to find the CLI 
+// header's resources directory in an image read from disk. Anything that 
+// doesn't add up is None; the caller reports it as a corrupt file.
+use metadata::blob::BlobReader;
+
+//Index of the CLI header in the optional header's data directories
+const COM_DESCRIPTOR: usize = 14;
+
+#[derive(Clone, Copy, Debug)]
+struct Section {
+    virtual_address: u32, 
+    virtual_size: u32, 
+    raw_offset: u32, 
+    raw_size: u32,
+}
+
+fn reader_at(image: &[u8], offset: usize) -> Option<BlobReader> {
+    image.get(offset..).map(BlobReader::new)
+}
+
+fn sections(image: &[u8]) -> Option<(usize, Vec<Section>)> {
+    let mut dos = reader_at(image, 0)?;
+    if dos.bytes(2)? != b"MZ" {
+        return None;
+    }
+    let pe = reader_at(image, 0x3C)?.u32()? as usize;
+    let mut coff = reader_at(image, pe)?;
+    if coff.bytes(4)? != b"PE\0\0" {
+        return None;
+    }
+    coff.u16()?;
+    let count = coff.u16()?;
+    coff.bytes(12)?;
+    let optional_size = coff.u16()? as usize;
+    let optional = pe + 24;
+    let mut table = reader_at(image, optional + optional_size)?;
+    let mut sections = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        table.bytes(8)?;
+        let virtual_size = table.u32()?;
+        let virtual_address = table.u32()?;
+        let raw_size = table.u32()?;
+        let raw_offset = table.u32()?;
+        table.bytes(16)?;
+        sections.push(Section { virtual_address, virtual_size, raw_offset, raw_size });
+    }
+    Some((optional, sections))
+}
+
+//File offset of an RVA, through the section that maps it
+fn file_offset(sections: &[Section], rva: u32) -> Option<usize> {
+    sections.iter()
+        .find(|s| rva >= s.virtual_address && rva - s.virtual_address < s.virtual_size.max(s.raw_size))
+        .and_then(|s| {
+            let delta = rva - s.virtual_address;
+            if delta < s.raw_size { Some((s.raw_offset + delta) as usize) } else { None }
+        })
+}
+
+//The (RVA, size) of a data directory, PE32 and PE32+ alike
+fn data_directory(image: &[u8], optional: usize, index: usize) -> Option<(u32, u32)> {
+    let mut header = reader_at(image, optional)?;
+    let (count_at, dirs_at) = match header.u16()? {
+        0x10B => (92, 96), 
+        0x20B => (108, 112), 
+        _ => return None,
+    };
+    if (reader_at(image, optional + count_at)?.u32()? as usize) <= index {
+        return None;
+    }
+    let mut dir = reader_at(image, optional + dirs_at + index * 8)?;
+    Some((dir.u32()?, dir.u32()?))
+}
+
+//An embedded resource: a u32 length followed by the data, at `offset` 
+// into the CLI header's resources directory
+pub(crate) fn embedded_resource(image: &[u8], offset: u32) -> Option<&[u8]> {
+    let (optional, sections) = sections(image)?;
+    let (cli_rva, _) = data_directory(image, optional, COM_DESCRIPTOR)?;
+    let mut cli = reader_at(image, file_offset(&sections, cli_rva)?)?;
+    cli.bytes(24)?;
+    let resources_rva = cli.u32()?;
+    let resources_size = cli.u32()?;
+    if offset.checked_add(4)? > resources_size {
+        return None;
+    }
+    let mut resource = reader_at(image, file_offset(&sections, resources_rva.checked_add(offset)?)?)?;
+    let len = resource.u32()?;
+    if u64::from(offset) + 4 + u64::from(len) > u64::from(resources_size) {
+        return None;
+    }
+    resource.bytes(len as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn put(image: &mut Vec<u8>, at: usize, bytes: &[u8]) {
+        if image.len() < at + bytes.len() {
+            image.resize(at + bytes.len(), 0);
+        }
+        image[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn le(v: u32) -> [u8; 4] {
+        [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]
+    }
+
+    #[test]
+    fn finds_embedded_resource() {
+        //PE32, one section at RVA 0x2000 mapped to file offset 0x200, the 
+        // CLI header at its start and the resources right after it
+        let mut image = Vec::new();
+        put(&mut image, 0, b"MZ");
+        put(&mut image, 0x3C, &le(0x80));
+        put(&mut image, 0x80, b"PE\0\0");
+        put(&mut image, 0x86, &[1, 0]);
+        put(&mut image, 0x94, &[0xE0, 0]);
+        put(&mut image, 0x98, &[0x0B, 0x01]);
+        put(&mut image, 0x98 + 92, &le(16));
+        put(&mut image, 0x98 + 96 + 14 * 8, &le(0x2000));
+        put(&mut image, 0x98 + 96 + 14 * 8 + 4, &le(72));
+        let section = 0x98 + 0xE0;
+        put(&mut image, section + 8, &le(0x1000));
+        put(&mut image, section + 12, &le(0x2000));
+        put(&mut image, section + 16, &le(0x200));
+        put(&mut image, section + 20, &le(0x200));
+        put(&mut image, 0x200 + 24, &le(0x2048));
+        put(&mut image, 0x200 + 28, &le(16));
+        put(&mut image, 0x248, &le(0));
+        put(&mut image, 0x24C, &le(3));
+        put(&mut image, 0x250, b"abc");
+        put(&mut image, 0x3FF, &[0]);
+        assert_eq!(embedded_resource(&image, 4), Some(&b"abc"[..]));
+        assert_eq!(embedded_resource(&image, 0), Some(&b""[..]));
+        assert_eq!(embedded_resource(&image, 13), None);
+    }
+}
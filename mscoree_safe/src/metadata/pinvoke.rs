@@ -0,0 +1,150 @@
+// pinvoke.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//P/Invoke declarations: [DllImport] never becomes a custom attribute, it 
+// is stored as an ImplMap row, read here through GetPinvokeMap.
+use winapi::shared::minwindef::DWORD;
+
+use mscoree_sys::corhdr::*;
+
+use error::HostingError;
+use metadata::{read_string, MetadataScope};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NativeCharSet {
+    NotSpecified, 
+    Ansi, 
+    Unicode, 
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NativeCallConv {
+    //Whatever the platform default is; stdcall on x86 Windows
+    Winapi, 
+    Cdecl, 
+    StdCall, 
+    ThisCall, 
+    FastCall, 
+    NotSpecified,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PinvokeMap {
+    pub method: mdMethodDef, 
+    pub method_name: String, 
+    //None for global functions
+    pub declaring_type: Option<String>, 
+    //As written in the declaration, e.g. "kernel32.dll" or "user32"
+    pub module: String, 
+    pub entry_point: String, 
+    //CorPinvokeMap flags; the accessors below pick them apart
+    pub flags: DWORD,
+}
+
+impl PinvokeMap {
+    pub fn char_set(&self) -> NativeCharSet {
+        match self.flags & pmCharSetMask {
+            pmCharSetAnsi => NativeCharSet::Ansi, 
+            pmCharSetUnicode => NativeCharSet::Unicode, 
+            pmCharSetAuto => NativeCharSet::Auto, 
+            _ => NativeCharSet::NotSpecified,
+        }
+    }
+
+    pub fn call_conv(&self) -> NativeCallConv {
+        match self.flags & pmCallConvMask {
+            pmCallConvWinapi => NativeCallConv::Winapi, 
+            pmCallConvCdecl => NativeCallConv::Cdecl, 
+            pmCallConvStdcall => NativeCallConv::StdCall, 
+            pmCallConvThiscall => NativeCallConv::ThisCall, 
+            pmCallConvFastcall => NativeCallConv::FastCall, 
+            _ => NativeCallConv::NotSpecified,
+        }
+    }
+
+    //SetLastError = true
+    pub fn sets_last_error(&self) -> bool {
+        self.flags & pmSupportsLastError != 0
+    }
+
+    //ExactSpelling = true: no A/W suffix probing
+    pub fn exact_spelling(&self) -> bool {
+        self.flags & pmNoMangle != 0
+    }
+}
+
+impl MetadataScope {
+    //None for methods that aren't P/Invoke declarations
+    pub fn pinvoke_map(&self, method: mdMethodDef) -> Result<Option<PinvokeMap>, HostingError> {
+        let (attributes, owner) = self.method_attributes(method)?;
+        if attributes & mdPinvokeImpl == 0 {
+            return Ok(None);
+        }
+        let mut flags: DWORD = 0;
+        let mut module_ref: mdModuleRef = mdTokenNil;
+        let entry_point = read_string(CALL!(IMetaDataImport::GetPinvokeMap), |buffer, len, needed| unsafe {
+            self.import.GetPinvokeMap(method, &mut flags, buffer, len, needed, &mut module_ref)
+        })?;
+        let module = read_string(CALL!(IMetaDataImport::GetModuleRefProps), |buffer, len, needed| unsafe {
+            self.import.GetModuleRefProps(module_ref, buffer, len, needed)
+        })?;
+        let declaring_type = match owner {
+            mdTokenNil => None, 
+            owner => Some(self.type_name(owner)?),
+        };
+        Ok(Some(PinvokeMap { method, method_name: self.method_name(method)?, declaring_type, module, entry_point, flags }))
+    }
+
+    //Every native import declared in the module, globals first
+    pub fn pinvoke_imports(&self) -> Result<Vec<PinvokeMap>, HostingError> {
+        let mut owners = vec![mdTokenNil];
+        owners.extend(self.type_defs()?);
+        let mut imports = Vec::new();
+        for owner in owners {
+            for method in self.methods(owner)? {
+                imports.extend(self.pinvoke_map(method)?);
+            }
+        }
+        Ok(imports)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags_decode() {
+        let map = PinvokeMap {
+            method: mdtMethodDef | 1, 
+            method_name: "MessageBox".to_string(), 
+            declaring_type: None, 
+            module: "user32.dll".to_string(), 
+            entry_point: "MessageBoxW".to_string(), 
+            flags: pmNoMangle | pmCharSetUnicode | pmSupportsLastError | pmCallConvWinapi,
+        };
+        assert_eq!(map.char_set(), NativeCharSet::Unicode);
+        assert_eq!(map.call_conv(), NativeCallConv::Winapi);
+        assert!(map.sets_last_error() && map.exact_spelling());
+    }
+}
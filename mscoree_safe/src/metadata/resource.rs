@@ -0,0 +1,123 @@
+// resource.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Manifest resources: the ManifestResource table names them, and the 
+// embedded ones (typically .resources blobs) live in the image's CLI 
+// resources directory, read straight from the file so nothing has to be 
+// loaded into a runtime.
+use std::fs;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{E_FAIL, HRESULT_FROM_WIN32};
+
+use mscoree_sys::corerror::CLDB_E_FILE_CORRUPT;
+use mscoree_sys::corhdr::*;
+
+use error::HostingError;
+use metadata::pe::embedded_resource;
+use metadata::{read_string, token_type, Image, MetadataScope};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResourceLocation {
+    //In this image, at the given offset into the resources directory
+    Embedded(u32), 
+    //A separate file of the same assembly
+    File(mdFile), 
+    //Forwarded to another assembly
+    Assembly(mdAssemblyRef),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestResource {
+    pub token: mdManifestResource, 
+    pub name: String, 
+    pub location: ResourceLocation, 
+    //CorManifestResourceFlags
+    pub flags: DWORD,
+}
+
+impl ManifestResource {
+    pub fn is_public(&self) -> bool {
+        self.flags & mrVisibilityMask == mrPublic
+    }
+
+    pub fn is_embedded(&self) -> bool {
+        match self.location {
+            ResourceLocation::Embedded(_) => true, 
+            _ => false,
+        }
+    }
+}
+
+impl MetadataScope {
+    pub fn manifest_resources(&self) -> Result<Vec<ManifestResource>, HostingError> {
+        let tokens = self.assembly_tokens(CALL!(IMetaDataAssemblyImport::EnumManifestResources), |henum, tokens, max, fetched| unsafe {
+            self.assembly.EnumManifestResources(henum, tokens, max, fetched)
+        })?;
+        tokens.into_iter().map(|token| self.manifest_resource(token)).collect()
+    }
+
+    pub fn manifest_resource(&self, token: mdManifestResource) -> Result<ManifestResource, HostingError> {
+        let mut implementation: mdToken = mdTokenNil;
+        let mut offset: DWORD = 0;
+        let mut flags: DWORD = 0;
+        let name = read_string(CALL!(IMetaDataAssemblyImport::GetManifestResourceProps), |buffer, len, needed| unsafe {
+            self.assembly.GetManifestResourceProps(token, buffer, len, needed, &mut implementation, &mut offset, &mut flags)
+        })?;
+        //A nil implementation, of any table, means this file
+        let location = match token_type(implementation) {
+            _ if implementation & 0x00FF_FFFF == 0 => ResourceLocation::Embedded(offset), 
+            mdtAssemblyRef => ResourceLocation::Assembly(implementation), 
+            _ => ResourceLocation::File(implementation),
+        };
+        Ok(ManifestResource { token, name, location, flags })
+    }
+
+    //The resource's bytes, or None when it lives outside this image. A 
+    // file-backed scope reads the file again on each call; read it once and 
+    // use `resource_from_image` to extract several.
+    pub fn resource_data(&self, resource: &ManifestResource) -> Result<Option<Vec<u8>>, HostingError> {
+        if !resource.is_embedded() {
+            return Ok(None);
+        }
+        match self.image {
+            Image::File(ref path) => {
+                let image = fs::read(path).map_err(|err| {
+                    let hr = err.raw_os_error().map_or(E_FAIL, |code| HRESULT_FROM_WIN32(code as u32));
+                    HostingError::from_hresult(hr, CALL!(kernel32::ReadFile))
+                })?;
+                Ok(resource_from_image(&image, resource)?.map(<[u8]>::to_vec))
+            }, 
+            Image::Memory(ref image) => Ok(resource_from_image(image, resource)?.map(<[u8]>::to_vec)),
+        }
+    }
+}
+
+//For an image already in memory; None when the resource isn't embedded
+pub fn resource_from_image<'i>(image: &'i [u8], resource: &ManifestResource) -> Result<Option<&'i [u8]>, HostingError> {
+    match resource.location {
+        ResourceLocation::Embedded(offset) => embedded_resource(image, offset)
+            .map(Some)
+            .ok_or_else(|| HostingError::from_hresult(CLDB_E_FILE_CORRUPT, CALL!(metadata::resource_from_image))), 
+        _ => Ok(None),
+    }
+}
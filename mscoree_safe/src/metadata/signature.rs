@@ -0,0 +1,312 @@
+// signature.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Signature blobs (ECMA-335 II.23.2) as a typed tree. Type tokens are left 
+// as tokens; MetadataScope::type_name turns them into names when needed.
+use mscoree_sys::corerror::META_E_BAD_SIGNATURE;
+use mscoree_sys::corhdr::*;
+
+use error::HostingError;
+use metadata::blob::BlobReader;
+
+//How deeply types may nest (int32*** counts three) before a blob is 
+// rejected; real signatures stay in single digits, hostile ones would 
+// otherwise recurse until the stack runs out
+pub(crate) const MAX_NESTING: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeSig {
+    Void, 
+    Bool, 
+    Char, 
+    I1, 
+    U1, 
+    I2, 
+    U2, 
+    I4, 
+    U4, 
+    I8, 
+    U8, 
+    R4, 
+    R8, 
+    //IntPtr and UIntPtr
+    I, 
+    U, 
+    String, 
+    Object, 
+    TypedByRef, 
+    Class(mdToken), 
+    ValueType(mdToken), 
+    Ptr(Box<TypeSig>), 
+    ByRef(Box<TypeSig>), 
+    SzArray(Box<TypeSig>), 
+    //Multi-dimensional; sizes and lower bounds may cover fewer than rank 
+    // dimensions
+    Array { element: Box<TypeSig>, rank: u32, sizes: Vec<u32>, lower_bounds: Vec<i32> }, 
+    GenericInst { value_type: bool, definition: mdToken, arguments: Vec<TypeSig> }, 
+    //Generic parameter of the enclosing type (!n) or method (!!n)
+    Var(u32), 
+    MVar(u32), 
+    FnPtr(Box<MethodSig>), 
+    //modreq (required) or modopt, e.g. the IsVolatile on volatile fields
+    Modified { required: bool, modifier: mdToken, inner: Box<TypeSig> }, 
+    Pinned(Box<TypeSig>),
+}
+
+impl TypeSig {
+    //TypeSpec blobs are a bare type
+    pub fn parse(blob: &[u8]) -> Result<TypeSig, HostingError> {
+        let mut r = BlobReader::new(blob);
+        type_sig(&mut r, 0).ok_or_else(|| HostingError::from_hresult(META_E_BAD_SIGNATURE, CALL!(TypeSig::parse)))
+    }
+
+    //Without any custom modifiers
+    pub fn unmodified(&self) -> &TypeSig {
+        match *self {
+            TypeSig::Modified { ref inner, .. } => inner.unmodified(), 
+            ref ty => ty,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CallingConvention {
+    Default, 
+    //The unmanaged conventions, for calli and function pointers
+    C, 
+    StdCall, 
+    ThisCall, 
+    FastCall, 
+    VarArg, 
+    NativeVarArg,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodSig {
+    pub calling_convention: CallingConvention, 
+    pub has_this: bool, 
+    pub explicit_this: bool, 
+    //Number of method type parameters, 0 for non-generic methods
+    pub generic_arity: u32, 
+    pub return_type: TypeSig, 
+    pub params: Vec<TypeSig>, 
+    //For vararg call sites: index into params where the variable part starts
+    pub sentinel: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertySig {
+    pub has_this: bool, 
+    pub ty: TypeSig, 
+    //Indexer parameters
+    pub params: Vec<TypeSig>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signature {
+    Method(MethodSig), 
+    Field(TypeSig), 
+    Property(PropertySig), 
+    Locals(Vec<TypeSig>), 
+    //MethodSpec instantiation: the type arguments
+    GenericInst(Vec<TypeSig>),
+}
+
+impl Signature {
+    pub fn parse(blob: &[u8]) -> Result<Signature, HostingError> {
+        let mut r = BlobReader::new(blob);
+        signature(&mut r).ok_or_else(|| HostingError::from_hresult(META_E_BAD_SIGNATURE, CALL!(Signature::parse)))
+    }
+
+    pub fn into_method(self) -> Option<MethodSig> {
+        match self { Signature::Method(sig) => Some(sig), _ => None }
+    }
+}
+
+fn signature(r: &mut BlobReader) -> Option<Signature> {
+    let header = u32::from(r.peek()?);
+    Some(match header & IMAGE_CEE_CS_CALLCONV_MASK {
+        IMAGE_CEE_CS_CALLCONV_FIELD => {
+            r.u8()?;
+            Signature::Field(type_sig(r, 0)?)
+        }, 
+        IMAGE_CEE_CS_CALLCONV_PROPERTY => {
+            r.u8()?;
+            let count = r.compressed()?;
+            let ty = type_sig(r, 0)?;
+            let params = (0..count).map(|_| type_sig(r, 0)).collect::<Option<Vec<_>>>()?;
+            Signature::Property(PropertySig { has_this: header & IMAGE_CEE_CS_CALLCONV_HASTHIS != 0, ty, params })
+        }, 
+        IMAGE_CEE_CS_CALLCONV_LOCAL_SIG => {
+            r.u8()?;
+            let count = r.compressed()?;
+            Signature::Locals((0..count).map(|_| type_sig(r, 0)).collect::<Option<Vec<_>>>()?)
+        }, 
+        IMAGE_CEE_CS_CALLCONV_GENERICINST => {
+            r.u8()?;
+            let count = r.compressed()?;
+            Signature::GenericInst((0..count).map(|_| type_sig(r, 0)).collect::<Option<Vec<_>>>()?)
+        }, 
+        _ => Signature::Method(method_sig(r, 0)?),
+    })
+}
+
+fn method_sig(r: &mut BlobReader, depth: usize) -> Option<MethodSig> {
+    let header = u32::from(r.u8()?);
+    let calling_convention = match header & IMAGE_CEE_CS_CALLCONV_MASK {
+        IMAGE_CEE_CS_CALLCONV_DEFAULT => CallingConvention::Default, 
+        IMAGE_CEE_CS_CALLCONV_C => CallingConvention::C, 
+        IMAGE_CEE_CS_CALLCONV_STDCALL => CallingConvention::StdCall, 
+        IMAGE_CEE_CS_CALLCONV_THISCALL => CallingConvention::ThisCall, 
+        IMAGE_CEE_CS_CALLCONV_FASTCALL => CallingConvention::FastCall, 
+        IMAGE_CEE_CS_CALLCONV_VARARG => CallingConvention::VarArg, 
+        IMAGE_CEE_CS_CALLCONV_NATIVEVARARG => CallingConvention::NativeVarArg, 
+        _ => return None,
+    };
+    let generic_arity = if header & IMAGE_CEE_CS_CALLCONV_GENERIC != 0 { r.compressed()? } else { 0 };
+    let count = r.compressed()?;
+    let return_type = type_sig(r, depth)?;
+    //The count comes from the blob, so it can't size an allocation
+    let mut params = Vec::new();
+    let mut sentinel = None;
+    while params.len() < count as usize {
+        if u32::from(r.peek()?) == ELEMENT_TYPE_SENTINEL {
+            r.u8()?;
+            sentinel = Some(params.len());
+            continue;
+        }
+        params.push(type_sig(r, depth)?);
+    }
+    Some(MethodSig {
+        calling_convention, 
+        has_this: header & IMAGE_CEE_CS_CALLCONV_HASTHIS != 0, 
+        explicit_this: header & IMAGE_CEE_CS_CALLCONV_EXPLICITTHIS != 0, 
+        generic_arity, 
+        return_type, 
+        params, 
+        sentinel,
+    })
+}
+
+fn boxed(r: &mut BlobReader, depth: usize) -> Option<Box<TypeSig>> {
+    type_sig(r, depth + 1).map(Box::new)
+}
+
+fn type_sig(r: &mut BlobReader, depth: usize) -> Option<TypeSig> {
+    if depth > MAX_NESTING {
+        return None;
+    }
+    Some(match u32::from(r.u8()?) {
+        ELEMENT_TYPE_VOID => TypeSig::Void, 
+        ELEMENT_TYPE_BOOLEAN => TypeSig::Bool, 
+        ELEMENT_TYPE_CHAR => TypeSig::Char, 
+        ELEMENT_TYPE_I1 => TypeSig::I1, 
+        ELEMENT_TYPE_U1 => TypeSig::U1, 
+        ELEMENT_TYPE_I2 => TypeSig::I2, 
+        ELEMENT_TYPE_U2 => TypeSig::U2, 
+        ELEMENT_TYPE_I4 => TypeSig::I4, 
+        ELEMENT_TYPE_U4 => TypeSig::U4, 
+        ELEMENT_TYPE_I8 => TypeSig::I8, 
+        ELEMENT_TYPE_U8 => TypeSig::U8, 
+        ELEMENT_TYPE_R4 => TypeSig::R4, 
+        ELEMENT_TYPE_R8 => TypeSig::R8, 
+        ELEMENT_TYPE_I => TypeSig::I, 
+        ELEMENT_TYPE_U => TypeSig::U, 
+        ELEMENT_TYPE_STRING => TypeSig::String, 
+        ELEMENT_TYPE_OBJECT => TypeSig::Object, 
+        ELEMENT_TYPE_TYPEDBYREF => TypeSig::TypedByRef, 
+        ELEMENT_TYPE_CLASS => TypeSig::Class(r.type_token()?), 
+        ELEMENT_TYPE_VALUETYPE => TypeSig::ValueType(r.type_token()?), 
+        ELEMENT_TYPE_PTR => TypeSig::Ptr(boxed(r, depth)?), 
+        ELEMENT_TYPE_BYREF => TypeSig::ByRef(boxed(r, depth)?), 
+        ELEMENT_TYPE_SZARRAY => TypeSig::SzArray(boxed(r, depth)?), 
+        ELEMENT_TYPE_PINNED => TypeSig::Pinned(boxed(r, depth)?), 
+        ELEMENT_TYPE_ARRAY => {
+            let element = boxed(r, depth)?;
+            let rank = r.compressed()?;
+            let num_sizes = r.compressed()?;
+            let sizes = (0..num_sizes).map(|_| r.compressed()).collect::<Option<Vec<_>>>()?;
+            let num_bounds = r.compressed()?;
+            let lower_bounds = (0..num_bounds).map(|_| r.compressed_signed()).collect::<Option<Vec<_>>>()?;
+            TypeSig::Array { element, rank, sizes, lower_bounds }
+        }, 
+        ELEMENT_TYPE_GENERICINST => {
+            let value_type = match u32::from(r.u8()?) {
+                ELEMENT_TYPE_CLASS => false, 
+                ELEMENT_TYPE_VALUETYPE => true, 
+                _ => return None,
+            };
+            let definition = r.type_token()?;
+            let count = r.compressed()?;
+            let arguments = (0..count).map(|_| type_sig(r, depth + 1)).collect::<Option<Vec<_>>>()?;
+            TypeSig::GenericInst { value_type, definition, arguments }
+        }, 
+        ELEMENT_TYPE_VAR => TypeSig::Var(r.compressed()?), 
+        ELEMENT_TYPE_MVAR => TypeSig::MVar(r.compressed()?), 
+        ELEMENT_TYPE_FNPTR => TypeSig::FnPtr(Box::new(method_sig(r, depth + 1)?)), 
+        element @ ELEMENT_TYPE_CMOD_REQD | element @ ELEMENT_TYPE_CMOD_OPT => {
+            let modifier = r.type_token()?;
+            TypeSig::Modified { required: element == ELEMENT_TYPE_CMOD_REQD, modifier, inner: boxed(r, depth)? }
+        }, 
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_generic_instance_method() {
+        //instance !!0 modopt(..) M<T>(class List`1<!!0>, int32[0...,])
+        let blob = [
+            0x30, 0x01, 0x02, 
+            0x20, 0x09, 0x1e, 0x00, 
+            0x15, 0x12, 0x05, 0x01, 0x1e, 0x00, 
+            0x14, 0x08, 0x02, 0x00, 0x01, 0x00,
+        ];
+        let sig = Signature::parse(&blob).unwrap().into_method().unwrap();
+        assert!(sig.has_this);
+        assert_eq!(sig.generic_arity, 1);
+        assert_eq!(sig.return_type.unmodified(), &TypeSig::MVar(0));
+        assert_eq!(sig.params, vec![
+            TypeSig::GenericInst { value_type: false, definition: mdtTypeRef | 1, arguments: vec![TypeSig::MVar(0)] }, 
+            TypeSig::Array { element: Box::new(TypeSig::I4), rank: 2, sizes: vec![], lower_bounds: vec![0] },
+        ]);
+        assert!(Signature::parse(&blob[..6]).is_err());
+    }
+
+    #[test]
+    fn rejects_hostile_blobs() {
+        //A parameter count of 0x1FFFFFFF with nothing behind it
+        assert!(Signature::parse(&[0x00, 0xDF, 0xFF, 0xFF, 0xFF, 0x01]).is_err());
+        //Pointers to pointers, far deeper than any real type
+        let mut deep = vec![0x06];
+        deep.extend(vec![0x0F; 100_000]);
+        deep.push(0x08);
+        assert!(Signature::parse(&deep).is_err());
+        let mut nested = vec![0x06];
+        nested.extend(vec![0x0F; MAX_NESTING]);
+        nested.push(0x08);
+        assert!(Signature::parse(&nested).is_ok());
+    }
+}
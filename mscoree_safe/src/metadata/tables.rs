@@ -0,0 +1,282 @@
+// tables.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//The physical #~ stream through IMetaDataTables: table and column layout, 
+// raw rows and the heaps, for tools that need more than the logical view 
+// MetadataScope gives. Tables are numbered as in ECMA-335 II.22 (Module is 
+// 0, TypeRef 1...), and row ids start at 1. Everything borrowed points into 
+// the mapped image and lives as long as the MetadataTables does.
+use std::ffi::CStr;
+use std::ptr;
+use std::slice;
+
+use winapi::ctypes::{c_char, c_void};
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{HRESULT, S_OK};
+
+use mscoree_sys::cor::*;
+use mscoree_sys::corhdr::mdToken;
+
+use comptr::ComPtr;
+use error::{Call, HostingError};
+use metadata::{borrow_blob, MetadataScope};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeapSizes {
+    pub strings: u32, 
+    pub blobs: u32, 
+    pub guids: u32, 
+    pub user_strings: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Heap {
+    Strings, 
+    Blobs, 
+    Guids, 
+    UserStrings,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TableInfo {
+    pub index: u32, 
+    pub name: String, 
+    //Bytes per row, with this image's index widths
+    pub row_size: u32, 
+    pub rows: u32, 
+    pub columns: u32, 
+    //The column the table is sorted on, if any
+    pub key: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnType {
+    //Index into the given table
+    Rid(u32), 
+    //One of the coded token kinds; see MetadataTables::coded_token
+    CodedToken(u32), 
+    Short, 
+    UShort, 
+    Long, 
+    ULong, 
+    Byte, 
+    String, 
+    Guid, 
+    Blob, 
+    Unknown(u32),
+}
+
+impl ColumnType {
+    fn from_code(code: ULONG) -> ColumnType {
+        match code {
+            _ if code <= iRidMax => ColumnType::Rid(code), 
+            _ if code <= iCodedTokenMax => ColumnType::CodedToken(code - iCodedToken), 
+            iSHORT => ColumnType::Short, 
+            iUSHORT => ColumnType::UShort, 
+            iLONG => ColumnType::Long, 
+            iULONG => ColumnType::ULong, 
+            iBYTE => ColumnType::Byte, 
+            iSTRING => ColumnType::String, 
+            iGUID => ColumnType::Guid, 
+            iBLOB => ColumnType::Blob, 
+            _ => ColumnType::Unknown(code),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnInfo {
+    pub name: String, 
+    //Byte offset within the row
+    pub offset: u32, 
+    pub size: u32, 
+    pub kind: ColumnType,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CodedTokenInfo {
+    pub name: String, 
+    //The token types it can encode, in tag order
+    pub tables: Vec<mdToken>,
+}
+
+pub struct MetadataTables {
+    inner: ComPtr<IMetaDataTables>,
+}
+
+impl MetadataScope {
+    pub fn tables(&self) -> Result<MetadataTables, HostingError> {
+        Ok(MetadataTables { inner: self.import.query_interface::<IMetaDataTables>()? })
+    }
+}
+
+impl MetadataTables {
+    pub fn heap_sizes(&self) -> Result<HeapSizes, HostingError> {
+        let mut sizes = HeapSizes::default();
+        CHECK_HR!(IMetaDataTables::GetStringHeapSize, self.inner.GetStringHeapSize(&mut sizes.strings))?;
+        CHECK_HR!(IMetaDataTables::GetBlobHeapSize, self.inner.GetBlobHeapSize(&mut sizes.blobs))?;
+        CHECK_HR!(IMetaDataTables::GetGuidHeapSize, self.inner.GetGuidHeapSize(&mut sizes.guids))?;
+        CHECK_HR!(IMetaDataTables::GetUserStringHeapSize, self.inner.GetUserStringHeapSize(&mut sizes.user_strings))?;
+        Ok(sizes)
+    }
+
+    //Every table the format knows of, present in this image or not
+    pub fn table_count(&self) -> Result<u32, HostingError> {
+        let mut count: ULONG = 0;
+        CHECK_HR!(IMetaDataTables::GetNumTables, self.inner.GetNumTables(&mut count))?;
+        Ok(count)
+    }
+
+    //The table a token type maps to, e.g. mdtTypeDef gives 2
+    pub fn table_index(&self, token_type: mdToken) -> Result<u32, HostingError> {
+        let mut index: ULONG = 0;
+        CHECK_HR!(IMetaDataTables::GetTableIndex, self.inner.GetTableIndex(token_type, &mut index))?;
+        Ok(index)
+    }
+
+    pub fn table(&self, index: u32) -> Result<TableInfo, HostingError> {
+        let (mut row_size, mut rows, mut columns, mut key): (ULONG, ULONG, ULONG, ULONG) = (0, 0, 0, 0);
+        let mut name: *const c_char = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetTableInfo, self.inner.GetTableInfo(index, &mut row_size, &mut rows, &mut columns, &mut key, &mut name))?;
+        //Unsorted tables report -1
+        let key = if key < columns { Some(key) } else { None };
+        Ok(TableInfo { index, name: unsafe { c_string(name) }, row_size, rows, columns, key })
+    }
+
+    pub fn column(&self, table: u32, column: u32) -> Result<ColumnInfo, HostingError> {
+        let (mut offset, mut size, mut kind): (ULONG, ULONG, ULONG) = (0, 0, 0);
+        let mut name: *const c_char = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetColumnInfo, self.inner.GetColumnInfo(table, column, &mut offset, &mut size, &mut kind, &mut name))?;
+        Ok(ColumnInfo { name: unsafe { c_string(name) }, offset, size, kind: ColumnType::from_code(kind) })
+    }
+
+    pub fn coded_token(&self, index: u32) -> Result<CodedTokenInfo, HostingError> {
+        let mut count: ULONG = 0;
+        let mut tokens: *const ULONG = ptr::null();
+        let mut name: *const c_char = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetCodedTokenInfo, self.inner.GetCodedTokenInfo(index, &mut count, &mut tokens, &mut name))?;
+        let tables = if tokens.is_null() { Vec::new() } else { unsafe { slice::from_raw_parts(tokens, count as usize) }.to_vec() };
+        Ok(CodedTokenInfo { name: unsafe { c_string(name) }, tables })
+    }
+
+    //The row exactly as stored, TableInfo::row_size bytes
+    pub fn row(&self, table: u32, rid: u32) -> Result<&[u8], HostingError> {
+        let info = self.table(table)?;
+        let mut row: *const c_void = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetRow, self.inner.GetRow(table, rid, &mut row))?;
+        Ok(unsafe { borrow_blob(row as *const u8, info.row_size) })
+    }
+
+    //A decoded cell: heap offsets for heap columns, full tokens for RID and 
+    // coded token columns, the value itself otherwise
+    pub fn cell(&self, table: u32, column: u32, rid: u32) -> Result<u32, HostingError> {
+        let mut value: ULONG = 0;
+        CHECK_HR!(IMetaDataTables::GetColumn, self.inner.GetColumn(table, column, rid, &mut value))?;
+        Ok(value)
+    }
+
+    //Lossy where the heap holds invalid UTF-8
+    pub fn string(&self, index: u32) -> Result<String, HostingError> {
+        let mut s: *const c_char = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetString, self.inner.GetString(index, &mut s))?;
+        Ok(unsafe { c_string(s) })
+    }
+
+    pub fn blob(&self, index: u32) -> Result<&[u8], HostingError> {
+        let mut len: ULONG = 0;
+        let mut data: *const c_void = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetBlob, self.inner.GetBlob(index, &mut len, &mut data))?;
+        Ok(unsafe { borrow_blob(data as *const u8, len) })
+    }
+
+    //1-based, as GUID heap indices are
+    pub fn guid(&self, index: u32) -> Result<GUID, HostingError> {
+        let mut guid: *const GUID = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetGuid, self.inner.GetGuid(index, &mut guid))?;
+        if guid.is_null() {
+            return Err(HostingError::null_pointer(CALL!(IMetaDataTables::GetGuid)));
+        }
+        Ok(unsafe { *guid })
+    }
+
+    //UTF-16 followed by the one-byte "has special characters" flag
+    pub fn user_string(&self, index: u32) -> Result<&[u8], HostingError> {
+        let mut len: ULONG = 0;
+        let mut data: *const c_void = ptr::null();
+        CHECK_HR!(IMetaDataTables::GetUserString, self.inner.GetUserString(index, &mut len, &mut data))?;
+        Ok(unsafe { borrow_blob(data as *const u8, len) })
+    }
+
+    //Offsets of every entry in a heap, for walking it end to end
+    pub fn heap_offsets(&self, heap: Heap) -> Result<Vec<u32>, HostingError> {
+        let sizes = self.heap_sizes()?;
+        match heap {
+            Heap::Strings => walk(sizes.strings, CALL!(IMetaDataTables::GetNextString), |i, next| unsafe { self.inner.GetNextString(i, next) }), 
+            Heap::Blobs => walk(sizes.blobs, CALL!(IMetaDataTables::GetNextBlob), |i, next| unsafe { self.inner.GetNextBlob(i, next) }), 
+            //Sixteen bytes per entry, indexed from 1
+            Heap::Guids => Ok((1..=sizes.guids / 16).collect()), 
+            Heap::UserStrings => walk(sizes.user_strings, CALL!(IMetaDataTables::GetNextUserString), |i, next| unsafe { self.inner.GetNextUserString(i, next) }),
+        }
+    }
+}
+
+fn walk<F>(size: u32, call: Call, mut next: F) -> Result<Vec<u32>, HostingError> 
+    where F: FnMut(ULONG, *mut ULONG) -> HRESULT
+{
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        offsets.push(offset);
+        let mut following: ULONG = 0;
+        let hr = next(offset, &mut following);
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, call));
+        }
+        //S_FALSE past the last entry; never trust a step that goes backwards
+        if hr != S_OK || following <= offset {
+            break;
+        }
+        offset = following;
+    }
+    Ok(offsets)
+}
+
+unsafe fn c_string(s: *const c_char) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn column_type_codes() {
+        assert_eq!(ColumnType::from_code(2), ColumnType::Rid(2));
+        assert_eq!(ColumnType::from_code(iCodedToken + 3), ColumnType::CodedToken(3));
+        assert_eq!(ColumnType::from_code(iSTRING), ColumnType::String);
+        assert_eq!(ColumnType::from_code(200), ColumnType::Unknown(200));
+    }
+}
@@ -0,0 +1,165 @@
+// validate.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//IMetaDataValidate: the metadata half of PEVerify, run against an 
+// opened scope with no runtime started. Problems come back through an 
+// IVEHandler, as with validator::Validator, but there is no validator to 
+// format them, so the raw code and its arguments are passed on instead.
+use std::cell::{Cell, RefCell};
+
+use winapi::shared::winerror::{E_ABORT, HRESULT, S_OK};
+use winapi::um::oaidl::SAFEARRAY;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscoree_sys::cor::*;
+use mscoree_sys::ivehandler::{IVEHandler, IVEHandlerVtbl, VEContext};
+
+use com::ComBox;
+use error::{HostingError, Hresult};
+use metadata::MetadataScope;
+use unwind::catch_and_translate;
+use variant::ClrValue;
+
+//What the scope was produced as; almost always a PE file read from disk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ModuleKind {
+    Pe, 
+    Obj, 
+    //Edit-and-continue delta
+    Enc, 
+    Incremental,
+}
+
+impl ModuleKind {
+    fn raw(self) -> CorValidatorModuleType {
+        match self {
+            ModuleKind::Pe => ValidatorModuleTypePE, 
+            ModuleKind::Obj => ValidatorModuleTypeObj, 
+            ModuleKind::Enc => ValidatorModuleTypeEnc, 
+            ModuleKind::Incremental => ValidatorModuleTypeIncr,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetadataValidationError {
+    //The VLDTR_E_*/VLDTR_S_* code
+    pub code: HRESULT, 
+    //The offending record, if any
+    pub token: u32, 
+    //The values the code's message template refers to, e.g. a name or table 
+    // index
+    pub arguments: Vec<ClrValue>, 
+    pub message: String,
+}
+
+struct Handler<F> {
+    callback: RefCell<F>, 
+    errors: Cell<usize>,
+}
+
+type HandlerObject<F> = ComBox<IVEHandlerVtbl, Handler<F>>;
+
+impl MetadataScope {
+    //Returns the number of problems reported. The callback returns false to 
+    // stop validation early.
+    pub fn validate<F>(&self, kind: ModuleKind, on_error: F) -> Result<usize, HostingError> 
+        where F: FnMut(&MetadataValidationError) -> bool
+    {
+        let validate = self.import.query_interface::<IMetaDataValidate>()?;
+        let vtable = IVEHandlerVtbl {
+            parent: HandlerObject::<F>::unknown_vtbl(), 
+            VEHandler: ve_handler::<F>, 
+            SetReporterFtn: set_reporter_ftn,
+        };
+        let handler = HandlerObject::new(vtable, vec![IVEHandler::uuidof()], Handler {
+            callback: RefCell::new(on_error), 
+            errors: Cell::new(0),
+        });
+        let init = unsafe { validate.ValidatorInit(kind.raw(), HandlerObject::as_interface::<IUnknown>(handler)) };
+        let hr = if init < 0 { init } else { unsafe { validate.ValidateMetaData() } };
+        let errors = unsafe { (*handler).value.errors.get() };
+        unsafe { HandlerObject::release(handler) };
+        if init < 0 {
+            return Err(HostingError::from_hresult(init, CALL!(IMetaDataValidate::ValidatorInit)));
+        }
+        //As with ICLRValidator, the overall HRESULT reflects the errors 
+        // already reported
+        if hr < 0 && errors == 0 && hr != E_ABORT {
+            return Err(HostingError::from_hresult(hr, CALL!(IMetaDataValidate::ValidateMetaData)));
+        }
+        Ok(errors)
+    }
+}
+
+fn message(code: HRESULT, arguments: &[ClrValue]) -> String {
+    let mut message = Hresult(code).to_string();
+    if !arguments.is_empty() {
+        let arguments: Vec<String> = arguments.iter()
+            .map(|argument| match argument.as_str() {
+                Some(s) => s.to_string(), 
+                None => format!("{:?}", argument),
+            })
+            .collect();
+        message.push_str(&format!(" [{}]", arguments.join(", ")));
+    }
+    message
+}
+
+unsafe extern "system" fn ve_handler<F>(this: *mut IVEHandler, code: HRESULT, context: VEContext, psa: *mut SAFEARRAY) -> HRESULT 
+    where F: FnMut(&MetadataValidationError) -> bool
+{
+    let handler = &HandlerObject::<F>::from_this(this).value;
+    //The array is borrowed; unreadable arguments are dropped, not fatal
+    let arguments = match ClrValue::from_safearray(psa) {
+        Ok(ClrValue::Array(values)) => values, 
+        _ => Vec::new(),
+    };
+    let error = MetadataValidationError { 
+        code, 
+        token: context.Token, 
+        message: message(code, &arguments), 
+        arguments,
+    };
+    handler.errors.set(handler.errors.get() + 1);
+    //Any failure code makes the validator stop
+    catch_and_translate(|| {
+        let mut callback = handler.callback.try_borrow_mut().map_err(|_| E_ABORT)?;
+        if (&mut *callback)(&error) { Ok(()) } else { Err(E_ABORT) }
+    })
+}
+
+unsafe extern "system" fn set_reporter_ftn(_this: *mut IVEHandler, _reporter: i64) -> HRESULT {
+    S_OK
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn message_lists_arguments() {
+        let text = message(0x8013_1200u32 as HRESULT, &[ClrValue::from("Foo"), ClrValue::from(3)]);
+        assert!(text.ends_with(" [Foo, I4(3)]"), "{}", text);
+    }
+}
@@ -1,33 +1,73 @@
+use std::cell::{Cell, RefCell};
+use std::cmp;
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fmt;
+use std::fs;
 use std::fmt::Debug;
+use std::mem;
+use std::ops::Deref;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::rc::{Rc, Weak};
+use std::rc::Rc;
+use std::str::FromStr;
 use std::string::ToString;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 use winapi::shared::guiddef::{REFCLSID, REFIID};
-use winapi::shared::minwindef::{BOOL, DWORD, LPVOID, ULONG};
-use winapi::shared::ntdef::HANDLE;
-use winapi::shared::winerror::S_OK;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPVOID, ULONG};
+use winapi::shared::ntdef::{HANDLE, LPCWSTR};
+use winapi::shared::winerror::{E_FAIL, E_NOTIMPL, E_OUTOFMEMORY, ERROR_INSUFFICIENT_BUFFER, HRESULT, HRESULT_FROM_WIN32, S_OK};
 
-use winapi::um::objidlbase::{IEnumUnknown};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::objidlbase::{IEnumUnknown, IStream};
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::shlwapi::SHCreateMemStream;
 use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winnt::{PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
+use winapi::Interface;
+
+use once_cell::sync::OnceCell;
 
 use mscorlib_safe::BString;
 
-use mscoree_sys::metahost::{CLSID_CLRMetaHost, CLRCreateInstance, ICLRMetaHost, ICLRRuntimeInfo, IID_ICLRMetaHost, IID_ICLRRuntimeInfo};
+use mscoree_sys::metahost::{
+    CLSID_CLRMetaHost, 
+    CLSID_CLRMetaHostPolicy, 
+    CLRCreateInstance, 
+    ICLRMetaHost, 
+    ICLRMetaHostPolicy, 
+    ICLRRuntimeInfo, 
+    IID_ICLRMetaHost, 
+    IID_ICLRMetaHostPolicy, 
+    IID_ICLRRuntimeInfo, 
+    METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_FALSE, 
+    METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_MASK, 
+    METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_TRUE, 
+    METAHOST_POLICY_EMULATE_EXE_LAUNCH,
+};
 use mscoree_sys::mscoree::{
     CLSID_TypeNameFactory, 
-    CLSID_CLRRuntimeHost, 
     CLSID_CorRuntimeHost, 
-    ICLRRuntimeHost, 
-    ICorRuntimeHost,
-    ITypeNameFactory,
-    IID_ICLRRuntimeHost, 
+    IDebuggerInfo, 
     IID_ICorRuntimeHost, 
-    IID_ITypeNameFactory
+    IID_ITypeNameFactory, 
+    ITypeNameFactory,
 };
 
+use apartment;
+use buffer::{double_call_string, sized_call_string, wide};
+use comptr::ComPtr;
+use error::{HostingError, last_error};
+use inventory::{RuntimeDescriptor, RuntimeReport};
+#[cfg(feature = "async")]
+use loadevents::RuntimeLoadStream;
+use loadevents::{self, RuntimeLoadEvent};
+#[cfg(feature = "hosting")]
+use sidebyside::{self, SideBySideReport};
+
 extern "system" {
     pub fn GetCurrentProcess() -> HANDLE;
 }
@@ -65,13 +105,104 @@ macro_rules! ENUM_CONSTANTS {
 }
 
 ENUM_CONSTANTS!{String, 
-#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 enum RuntimeVersion {
+    V1_0 = "v1.0.3705", 
+    V1_1 = "v1.1.4322", 
     V2 = "v2.0.50727", 
     V3 = "v3.0", 
     V4 = "v4.0.30319"
 }}
 
+//The numeric parts of a "vX.Y[.Z]" version string
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct VersionNumber {
+    pub major: u32, 
+    pub minor: u32, 
+    pub build: Option<u32>,
+}
+
+impl fmt::Display for VersionNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}.{}", self.major, self.minor)?;
+        match self.build {
+            Some(build) => write!(f, ".{}", build), 
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for VersionNumber {
+    type Err = VersionParseError;
+    fn from_str(s: &str) -> Result<VersionNumber, VersionParseError> {
+        let digits = match s.trim() {
+            t if t.starts_with('v') || t.starts_with('V') => &t[1..], 
+            _ => return Err(VersionParseError::MissingPrefix(s.to_string())),
+        };
+        let mut parts = [0u32; 3];
+        let mut count = 0;
+        for part in digits.split('.') {
+            if count == 3 {
+                return Err(VersionParseError::BadNumber(s.to_string()));
+            }
+            parts[count] = part.parse().map_err(|_| VersionParseError::BadNumber(s.to_string()))?;
+            count += 1;
+        }
+        if count < 2 {
+            return Err(VersionParseError::BadNumber(s.to_string()));
+        }
+        Ok(VersionNumber {
+            major: parts[0], 
+            minor: parts[1], 
+            build: if count == 3 { Some(parts[2]) } else { None },
+        })
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum VersionParseError {
+    MissingPrefix(String), 
+    BadNumber(String),
+}
+
+impl RuntimeVersion {
+    //None for Unknown versions that aren't of the vX.Y[.Z] form
+    pub fn number(&self) -> Option<VersionNumber> {
+        self.to_string().parse().ok()
+    }
+
+    pub fn is_known(&self) -> bool {
+        match *self {
+            RuntimeVersion::Unknown(_) => false, 
+            _ => true,
+        }
+    }
+}
+
+//Strict counterpart of From<String>: rejects strings that aren't version 
+// numbers instead of wrapping them in Unknown
+impl FromStr for RuntimeVersion {
+    type Err = VersionParseError;
+    fn from_str(s: &str) -> Result<RuntimeVersion, VersionParseError> {
+        let number: VersionNumber = s.parse()?;
+        Ok(RuntimeVersion::from(number.to_string()))
+    }
+}
+
+//Numeric order; versions that don't parse sort after all that do
+impl Ord for RuntimeVersion {
+    fn cmp(&self, other: &RuntimeVersion) -> cmp::Ordering {
+        let key = |v: &RuntimeVersion| (v.number().is_none(), v.number(), v.to_string(), v.is_known());
+        key(self).cmp(&key(other))
+    }
+}
+
+impl PartialOrd for RuntimeVersion {
+    fn partial_cmp(&self, other: &RuntimeVersion) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /*CLSID_CorMetaDataDispenser	IID_IMetaDataDispenser, IID_IMetaDataDispenserEx
 CLSID_CorMetaDataDispenserRuntime	IID_IMetaDataDispenser, IID_IMetaDataDispenserEx
 CLSID_CorRuntimeHost	IID_ICorRuntimeHost
@@ -79,43 +210,97 @@ CLSID_CLRRuntimeHost	IID_ICLRRuntimeHost
 CLSID_TypeNameFactory	IID_ITypeNameFactory
 CLSID_CLRDebuggingLegacy	IID_ICorDebug
 CLSID_CLRStrongName	IID_ICLRStrongName*/
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Hash)]
-pub enum SupportedInterfaces {
-    CorRuntimeHost,
-    CLRRuntimeHost, 
-    TypeNameFactory, 
-}
-
-impl SupportedInterfaces {
-    pub fn clsid(&self) -> REFCLSID {
-        match self {
-            SupportedInterfaces::CorRuntimeHost => &CLSID_CorRuntimeHost,
-            SupportedInterfaces::CLRRuntimeHost => &CLSID_CLRRuntimeHost, 
-            SupportedInterfaces::TypeNameFactory => &CLSID_TypeNameFactory
-        }
+//Anything ICLRRuntimeInfo::GetInterface can hand out. Implementing this 
+// next to a wrapper is all it takes to make it available through query().
+pub trait HostInterface: Sized {
+    fn clsid() -> REFCLSID;
+    fn iid() -> REFIID;
+    //Takes over the one reference GetInterface returned; p is never null
+    unsafe fn from_interface(p: LPVOID) -> Self;
+}
+
+//No wrapper of its own yet, so the bare pointer is what's handed out
+impl HostInterface for ComPtr<ITypeNameFactory> {
+    fn clsid() -> REFCLSID {
+        &CLSID_TypeNameFactory
     }
 
-    pub fn iid(&self) -> REFIID {
-        match self {
-            SupportedInterfaces::CorRuntimeHost => &IID_ICorRuntimeHost,
-            SupportedInterfaces::CLRRuntimeHost => &IID_ICLRRuntimeHost, 
-            SupportedInterfaces::TypeNameFactory => &IID_ITypeNameFactory
-        }
+    fn iid() -> REFIID {
+        &IID_ITypeNameFactory
     }
+
+    unsafe fn from_interface(p: LPVOID) -> ComPtr<ITypeNameFactory> {
+        ComPtr::from_raw(p as *mut ITypeNameFactory).expect("GetInterface pointers are never null")
+    }
+}
+
+//The typed front end to RuntimeInfo::get_interface, e.g. 
+// `runtime.query::<ClrRuntimeHost>()?`. A separate trait so RuntimeInfo 
+// stays usable as a trait object.
+pub trait QueryInterface {
+    fn query<T: HostInterface>(&self) -> Result<T, HostingError>;
 }
 
-pub struct IntfCtr {
-    inner: *mut LPVOID, 
-    intf_ty: SupportedInterfaces
+impl<R: RuntimeInfo + ?Sized> QueryInterface for R {
+    fn query<T: HostInterface>(&self) -> Result<T, HostingError> {
+        let p = self.get_interface(T::clsid(), T::iid())?;
+        Ok(unsafe { T::from_interface(p) })
+    }
 }
 
 pub trait RuntimeInfo {
-    fn version(&mut self) -> RuntimeVersion;
-    fn loaded(&mut self) -> bool;
-    fn loadable(&mut self) -> bool;
-    fn started(&mut self) -> bool;
-    fn load_library(&mut self, dll_name: &str);
-    fn interface(&mut self, supported_intf: SupportedInterfaces) -> IntfCtr;
+    fn version(&self) -> RuntimeVersion;
+    fn loaded(&self) -> bool;
+    fn loadable(&self) -> bool;
+    fn started(&self) -> bool;
+    //Same queries, always asking the runtime and leaving any cache alone
+    fn loaded_uncached(&self) -> bool;
+    fn loadable_uncached(&self) -> bool;
+    fn started_uncached(&self) -> bool;
+    //Forget cached answers; the next query goes back to the runtime
+    fn invalidate(&self);
+    //Re-query everything that's cached now
+    fn refresh(&self) {
+        self.invalidate();
+        self.loaded();
+        self.loadable();
+        self.started();
+    }
+    //Owned copy of the runtime's state for other threads or serialization
+    fn snapshot(&self) -> Result<RuntimeDescriptor, HostingError> {
+        RuntimeDescriptor::from_runtime_uncached(self)
+    }
+    //Configuration and state in one pass, for diagnostics dumps
+    fn describe(&self) -> Result<RuntimeReport, HostingError> {
+        RuntimeReport::from_runtime(self)
+    }
+    fn startup_flags(&self) -> Option<DWORD>;
+    fn directory(&self) -> Result<PathBuf, HostingError>;
+    fn default_startup_flags(&self) -> Result<DWORD, HostingError>;
+    fn set_default_startup_flags(&self, flags: DWORD, host_config: Option<&Path>) -> Result<(), HostingError>;
+    //The host config file set alongside the default startup flags
+    fn host_config_file(&self) -> Result<Option<PathBuf>, HostingError> {
+        Ok(None)
+    }
+    //Flags and config file together, for callers that want both
+    fn default_startup_config(&self) -> Result<(DWORD, Option<PathBuf>), HostingError> {
+        Ok((self.default_startup_flags()?, self.host_config_file()?))
+    }
+    //Loads a DLL that ships with this runtime version, located relative 
+    // to the runtime directory
+    fn load_library(&self, dll_name: &OsStr);
+    //Owned, non-null pointer to iid on the clsid object; query() is the 
+    // typed way in
+    fn get_interface(&self, clsid: REFCLSID, iid: REFIID) -> Result<LPVOID, HostingError>;
+    fn is_debugger_attached(&self) -> Result<bool, HostingError>;
+    //Makes this pre-v4 runtime the one the legacy shim APIs 
+    // (CorBindToRuntimeEx...) resolve to
+    fn bind_as_legacy_v2_runtime(&self) -> Result<(), HostingError>;
+    fn is_legacy_v2_bound(&self) -> Result<bool, HostingError> {
+        Ok(false)
+    }
+    //Borrowed: no AddRef, valid while self is
+    fn as_raw(&self) -> *mut ICLRRuntimeInfo;
 }
 
 impl Debug for RuntimeInfo + 'static {
@@ -124,258 +309,817 @@ impl Debug for RuntimeInfo + 'static {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[derive(Debug, PartialEq, PartialOrd)]
 pub struct RuntimeInfoImpl {
-    version: RuntimeVersion,
-    inner: *mut ICLRRuntimeInfo,
-    loaded: Option<bool>, 
-    loadable: Option<bool>,
-    started: Option<bool>,
+    version: RefCell<RuntimeVersion>,
+    inner: ComPtr<ICLRRuntimeInfo>,
+    loaded: Cell<Option<bool>>, 
+    loadable: Cell<Option<bool>>,
+    started: Cell<Option<bool>>,
+}
+
+//Safe to move between threads: the interface is free-threaded and the 
+// cached flags belong to this value alone. SharedMetaHost relies on this.
+unsafe impl Send for RuntimeInfoImpl {}
+
+//A second owner of the same ICLRRuntimeInfo: ComPtr::clone AddRefs, and 
+// each copy releases its own reference. The version is carried over; the 
+// cached flags start empty so the copy queries for itself.
+impl Clone for RuntimeInfoImpl {
+    fn clone(&self) -> RuntimeInfoImpl {
+        RuntimeInfoImpl::new_from(self.version.borrow().clone(), self.inner.clone())
+    }
 }
 
 impl RuntimeInfoImpl {
-    fn version(in_ptr: *mut ICLRRuntimeInfo) -> RuntimeVersion {
-        assert!(!in_ptr.is_null());
-        let mut dw: DWORD = 0;
-        let _hr = unsafe {
-            (*in_ptr).GetVersionString(ptr::null_mut(), &mut dw)
-        };
-        //dw now contains length of required buffer
-        let mut buffer: Vec<u16> = Vec::with_capacity(dw as usize);
-        let hr = unsafe {
-            (*in_ptr).GetVersionString(buffer.as_mut_ptr(), &mut dw)
-        };
+    //Standalone lookup for callers that don't go through a MetaHost, 
+    // e.g. the startup builder. The metahost is only needed for GetRuntime.
+    pub(crate) fn from_version(version: RuntimeVersion) -> Result<RuntimeInfoImpl, HostingError> {
+        runtime_info(&create_metahost()?, &version)
+    }
 
-        if hr == S_OK {
-            let bs = BString::from_vec(buffer);
-            return RuntimeVersion::from(bs.to_string());
-        }
-        RuntimeVersion::Unknown(String::from(""))
+    //Takes over one reference the caller owns
+    pub unsafe fn from_raw(p: *mut ICLRRuntimeInfo) -> Option<RuntimeInfoImpl> {
+        ComPtr::from_raw(p).map(|inner| RuntimeInfoImpl::new_from(RuntimeInfoImpl::version(&inner), inner))
+    }
+
+    //Hands this value's reference to the caller, who must Release it
+    pub fn into_raw(self) -> *mut ICLRRuntimeInfo {
+        self.inner.into_raw()
+    }
+
+    fn new_from(version: RuntimeVersion, inner: ComPtr<ICLRRuntimeInfo>) -> RuntimeInfoImpl {
+        RuntimeInfoImpl {
+            version: RefCell::new(version), 
+            inner: inner, 
+            loaded: Cell::new(None), 
+            loadable: Cell::new(None), 
+            started: Cell::new(None) }
+    }
+
+    //An empty Unknown when the runtime won't say, so the next version() 
+    // call retries
+    fn version(in_ptr: &ICLRRuntimeInfo) -> RuntimeVersion {
+        read_version(in_ptr).unwrap_or_else(|_| RuntimeVersion::Unknown(String::new()))
     }
 }
 
 impl RuntimeInfo for RuntimeInfoImpl {
-    fn version(&mut self) -> RuntimeVersion {
-        match self.version {
-            RuntimeVersion::V2 | RuntimeVersion::V3 | RuntimeVersion::V4 => return self.version.clone(), 
-            RuntimeVersion::Unknown(_) => {}
+    fn version(&self) -> RuntimeVersion {
+        let unread = *self.version.borrow() == RuntimeVersion::Unknown(String::new());
+        if unread {
+            *self.version.borrow_mut() = RuntimeInfoImpl::version(&self.inner);
         }
+        self.version.borrow().clone()
+    }
 
-        let mut dw: DWORD = 0;
-        let _hr = unsafe {
-            (*self.inner).GetVersionString(ptr::null_mut(), &mut dw)
-        };
-        //dw now contains length of required buffer
-        let mut buffer: Vec<u16> = Vec::with_capacity(dw as usize);
-        let hr = unsafe {
-            (*self.inner).GetVersionString(buffer.as_mut_ptr(), &mut dw)
-        };
-
-        if hr == S_OK {
-            let bs = BString::from_vec(buffer);
-            self.version = RuntimeVersion::from(bs.to_string());
+    fn loaded(&self) -> bool {
+        if let Some(b) = self.loaded.get() {
+            return b;
         }
-        self.version.clone()
+        let b = self.loaded_uncached();
+        self.loaded.set(Some(b));
+        b
     }
 
-    fn loaded(&mut self) -> bool {
-        match self.loaded {
-            Some(b) => return b, 
-            None => {}
-        }
+    fn loaded_uncached(&self) -> bool {
         let handle = unsafe {GetCurrentProcess()};
         let mut vb: BOOL = 0;
         let _hr = unsafe {(*self.inner).IsLoaded(handle, &mut vb as *mut BOOL)};
-        self.loaded = Some(vb < 0);
-        vb < 0
+        vb != 0
     }
 
-    fn load_library(&mut self, dll_name: &str) {
-
+    fn load_library(&self, dll_name: &OsStr) {
+        let name = wide(dll_name);
+        let mut module: LPVOID = ptr::null_mut();
+        let _hr = unsafe {(*self.inner).LoadLibrary(name.as_ptr(), &mut module)};
     }
 
-    fn interface(&mut self, supported_intf: SupportedInterfaces) -> IntfCtr {
-        let pp_unk: *mut LPVOID = match supported_intf {
-            SupportedInterfaces::CLRRuntimeHost => {
-                let mut p: *mut ICLRRuntimeHost = ptr::null_mut();
-                &mut p as *mut _ as *mut LPVOID
-            }, 
-            SupportedInterfaces::CorRuntimeHost => {
-                let mut p: *mut ICorRuntimeHost = ptr::null_mut();
-                &mut p as *mut _ as *mut LPVOID
-            }, 
-            SupportedInterfaces::TypeNameFactory => {
-                let mut p: *mut ITypeNameFactory = ptr::null_mut();
-                &mut p as *mut _ as *mut LPVOID
-            }
-        };
-        let _hr = unsafe {
-            (*self.inner).GetInterface(supported_intf.clsid(), supported_intf.iid(), pp_unk)
-        };
-        IntfCtr {inner: pp_unk, intf_ty: supported_intf}
+    fn get_interface(&self, clsid: REFCLSID, iid: REFIID) -> Result<LPVOID, HostingError> {
+        let mut p: LPVOID = ptr::null_mut();
+        CHECK_HR!(ICLRRuntimeInfo::GetInterface, (*self.inner).GetInterface(clsid, iid, &mut p))?;
+        if p.is_null() {
+            return Err(HostingError::null_pointer(CALL!(ICLRRuntimeInfo::GetInterface)));
+        }
+        Ok(p)
     }
 
-    fn loadable(&mut self) -> bool {
-        match self.loadable {
-            Some(b) => return b, 
-            None => {}
+    fn loadable(&self) -> bool {
+        if let Some(b) = self.loadable.get() {
+            return b;
         }
+        let b = self.loadable_uncached();
+        self.loadable.set(Some(b));
+        b
+    }
+
+    fn loadable_uncached(&self) -> bool {
         let mut vb: BOOL = 0;
         let _hr = unsafe {(*self.inner).IsLoadable(&mut vb as *mut BOOL)};
-        self.loadable = Some(vb < 0);
-        vb < 0
+        vb != 0
     }
 
-    fn started(&mut self) -> bool {
-        match self.started {
-            Some(b) => return b,
-            None => {}
+    fn started(&self) -> bool {
+        if let Some(b) = self.started.get() {
+            return b;
         }
+        let b = self.started_uncached();
+        self.started.set(Some(b));
+        b
+    }
+
+    fn started_uncached(&self) -> bool {
         let mut vb: BOOL = 0;
         let _hr = unsafe {(*self.inner).IsStarted(&mut vb as *mut BOOL, &mut 0)};
-        self.started = Some(vb < 0);
-        vb < 0
+        vb != 0
+    }
+
+    fn invalidate(&self) {
+        self.loaded.set(None);
+        self.loadable.set(None);
+        self.started.set(None);
+    }
+
+    //Flags the runtime was actually started with, None if it hasn't been started
+    fn startup_flags(&self) -> Option<DWORD> {
+        let mut vb: BOOL = 0;
+        let mut flags: DWORD = 0;
+        let hr = unsafe {(*self.inner).IsStarted(&mut vb as *mut BOOL, &mut flags as *mut DWORD)};
+        if hr == S_OK && vb != 0 {
+            Some(flags)
+        } else {
+            None
+        }
+    }
+
+    fn directory(&self) -> Result<PathBuf, HostingError> {
+        let inner = &self.inner;
+        double_call_string(|buf, len| unsafe {(*inner).GetRuntimeDirectory(buf, len)})
+            .map(PathBuf::from)
+            .map_err(|hr| HostingError::from_hresult(hr, CALL!(ICLRRuntimeInfo::GetRuntimeDirectory)))
+    }
+
+    fn default_startup_flags(&self) -> Result<DWORD, HostingError> {
+        self.default_startup_config().map(|(flags, _)| flags)
+    }
+
+    fn host_config_file(&self) -> Result<Option<PathBuf>, HostingError> {
+        self.default_startup_config().map(|(_, config)| config)
+    }
+
+    //GetDefaultStartupFlags hands back both in one call
+    fn default_startup_config(&self) -> Result<(DWORD, Option<PathBuf>), HostingError> {
+        let inner = &self.inner;
+        let mut flags: DWORD = 0;
+        let config = double_call_string(|buf, len| unsafe {(*inner).GetDefaultStartupFlags(&mut flags, buf, len)})
+            .map_err(|hr| HostingError::from_hresult(hr, CALL!(ICLRRuntimeInfo::GetDefaultStartupFlags)))?;
+        Ok((flags, if config.is_empty() { None } else { Some(PathBuf::from(config)) }))
+    }
+
+    //Must be called before the runtime is started
+    fn set_default_startup_flags(&self, flags: DWORD, host_config: Option<&Path>) -> Result<(), HostingError> {
+        let config: Option<Vec<u16>> = host_config.map(|p| p.as_os_str().encode_wide().chain(Some(0)).collect());
+        let config_ptr: LPCWSTR = match config {
+            Some(ref wide) => wide.as_ptr(), 
+            None => ptr::null(),
+        };
+        CHECK_HR!(ICLRRuntimeInfo::SetDefaultStartupFlags, (*self.inner).SetDefaultStartupFlags(flags, config_ptr)).map(|_| ())
+    }
+
+    fn bind_as_legacy_v2_runtime(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeInfo::BindAsLegacyV2Runtime, (*self.inner).BindAsLegacyV2Runtime()).map(|_| ())
+    }
+
+    //Asks a metahost of its own, since the binding is process-wide
+    fn is_legacy_v2_bound(&self) -> Result<bool, HostingError> {
+        Ok(legacy_v2_binding(&create_metahost()?)? == Some(self.version.borrow().clone()))
+    }
+
+    //IDebuggerInfo hangs off the CorRuntimeHost object, so this loads the 
+    // runtime if it isn't already
+    fn is_debugger_attached(&self) -> Result<bool, HostingError> {
+        let host = self.get_interface(&CLSID_CorRuntimeHost, &IID_ICorRuntimeHost)? as *mut IUnknown;
+        let host = unsafe { ComPtr::from_raw(host) }.expect("get_interface already rejected null pointers");
+        let info = host.query_interface::<IDebuggerInfo>()?;
+        let mut attached: BOOL = 0;
+        CHECK_HR!(IDebuggerInfo::IsDebuggerAttached, info.IsDebuggerAttached(&mut attached)).map(|_| attached != 0)
+    }
+
+    fn as_raw(&self) -> *mut ICLRRuntimeInfo {
+        self.inner.as_raw()
+    }
+}
+
+//Target process for a loaded-runtime query. A pid is opened for the 
+// duration of the call; a handle is only borrowed and needs 
+// PROCESS_QUERY_INFORMATION | PROCESS_VM_READ access.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Process {
+    Id(DWORD), 
+    Handle(HANDLE),
+}
+
+struct ProcessHandle {
+    raw: HANDLE, 
+    owned: bool,
+}
+
+impl ProcessHandle {
+    fn open(pid: DWORD) -> Result<ProcessHandle, HostingError> {
+        let raw = unsafe { OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, FALSE, pid) };
+        if raw.is_null() {
+            return Err(HostingError::from_hresult(last_error(), CALL!(kernel32::OpenProcess)));
+        }
+        Ok(ProcessHandle { raw, owned: true })
+    }
+
+    fn borrowed(raw: HANDLE) -> ProcessHandle {
+        ProcessHandle { raw, owned: false }
+    }
+}
+
+impl Drop for ProcessHandle {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe { CloseHandle(self.raw) };
+        }
     }
 }
 
 pub trait MetaHost {
-    fn runtime(&mut self, version: RuntimeVersion) -> Weak<dyn RuntimeInfo>;
-    fn runtimes(&mut self) -> HashMap<RuntimeVersion, Weak<dyn RuntimeInfo>>;
-    fn loaded_runtimes(&mut self) -> HashMap<RuntimeVersion, bool>;
+    fn runtime(&self, version: RuntimeVersion) -> Result<RuntimeRef, HostingError>;
+    fn runtimes(&self) -> HashMap<RuntimeVersion, RuntimeRef>;
+    fn loaded_runtimes(&self) -> HashMap<RuntimeVersion, bool>;
+    //Re-enumerate instead of answering from the cache. Runtimes already 
+    // handed out stay valid.
+    fn runtimes_uncached(&self) -> HashMap<RuntimeVersion, RuntimeRef>;
+    fn loaded_runtimes_uncached(&self) -> HashMap<RuntimeVersion, bool>;
+    //Drops the loaded-runtime cache and invalidates every cached runtime
+    fn invalidate(&self);
+    fn refresh(&self) {
+        self.invalidate();
+        self.loaded_runtimes();
+    }
+    fn loaded_runtimes_in(&self, process: Process) -> Result<HashMap<RuntimeVersion, bool>, HostingError>;
+    fn legacy_v2_bound_runtime(&self) -> Result<Option<RuntimeVersion>, HostingError>;
+    //Starts each of `versions` that can share this process, see sidebyside
+    #[cfg(feature = "hosting")]
+    fn load_side_by_side(&self, versions: &[RuntimeVersion]) -> SideBySideReport {
+        sidebyside::load(self, versions)
+    }
+    //Every runtime loaded into this process from now on, see loadevents
+    fn runtime_load_events(&self) -> Result<Receiver<RuntimeLoadEvent>, HostingError> {
+        loadevents::channel(notifying_metahost(self)?)
+    }
+    #[cfg(feature = "async")]
+    fn runtime_load_stream(&self) -> Result<RuntimeLoadStream, HostingError> {
+        loadevents::stream(notifying_metahost(self)?)
+    }
+    //Borrowed: no AddRef, valid while self is
+    fn as_raw(&self) -> *mut ICLRMetaHost;
+}
+
+//Hosts without a real ICLRMetaHost behind them (mock, legacy) can't be 
+// notified of anything
+fn notifying_metahost<M: MetaHost + ?Sized>(host: &M) -> Result<&ICLRMetaHost, HostingError> {
+    let raw = host.as_raw();
+    if raw.is_null() {
+        return Err(HostingError::from_hresult(E_NOTIMPL, CALL!(ICLRMetaHost::RequestRuntimeLoadedNotification)));
+    }
+    Ok(unsafe { &*raw })
 }
 
+//A runtime handed out by a MetaHost, sharing ownership with the host's 
+// cache: it stays usable for as long as it's held, whether or not the host 
+// is still around.
+#[derive(Clone)]
+pub struct RuntimeRef {
+    inner: Rc<dyn RuntimeInfo>,
+}
+
+impl RuntimeRef {
+    pub(crate) fn new(inner: Rc<dyn RuntimeInfo>) -> RuntimeRef {
+        RuntimeRef { inner }
+    }
+}
+
+impl Deref for RuntimeRef {
+    type Target = dyn RuntimeInfo;
+
+    fn deref(&self) -> &(dyn RuntimeInfo + 'static) {
+        &*self.inner
+    }
+}
+
+impl Debug for RuntimeRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RuntimeRef({})", self.inner.version().to_string())
+    }
+}
+
+//An owned runtime from an enumeration, independent of any MetaHost cache
 #[derive(Clone, Debug)]
+pub struct RuntimeHandle {
+    info: RuntimeInfoImpl,
+}
+
+impl RuntimeHandle {
+    pub fn info(&self) -> &dyn RuntimeInfo {
+        &self.info
+    }
+
+    pub fn into_info(self) -> RuntimeInfoImpl {
+        self.info
+    }
+}
+
+impl Deref for RuntimeHandle {
+    type Target = RuntimeInfoImpl;
+
+    fn deref(&self) -> &RuntimeInfoImpl {
+        &self.info
+    }
+}
+
+//Walks the installed runtimes, fetched from the shim in batches. The shim 
+// reports runtimes in ascending version order; sorted() makes that explicit 
+// for callers that need to rely on it.
+pub struct InstalledRuntimes {
+    runtimes: RuntimeEnum,
+}
+
+impl InstalledRuntimes {
+    pub fn sorted(self) -> Vec<RuntimeHandle> {
+        let mut handles: Vec<RuntimeHandle> = self.collect();
+        handles.sort_by_key(|handle| handle.version());
+        handles
+    }
+}
+
+impl Iterator for InstalledRuntimes {
+    type Item = RuntimeHandle;
+
+    fn next(&mut self) -> Option<RuntimeHandle> {
+        self.runtimes.next()
+            .map(|ri| RuntimeHandle { info: RuntimeInfoImpl::new_from(RuntimeInfoImpl::version(&ri), ri) })
+    }
+}
+
+//How many runtimes each IEnumUnknown::Next call asks for
+const ENUM_BATCH: usize = 16;
+
+//Pulls runtimes out of an IEnumUnknown ENUM_BATCH at a time, skipping 
+// anything that isn't an ICLRRuntimeInfo. Pointers fetched but not yet 
+// handed out are released on drop.
+struct RuntimeEnum {
+    enumerator: Option<ComPtr<IEnumUnknown>>, 
+    batch: [*mut IUnknown; ENUM_BATCH], 
+    fetched: usize, 
+    pos: usize,
+}
+
+impl RuntimeEnum {
+    fn new(enumerator: ComPtr<IEnumUnknown>) -> RuntimeEnum {
+        RuntimeEnum { enumerator: Some(enumerator), batch: [ptr::null_mut(); ENUM_BATCH], fetched: 0, pos: 0 }
+    }
+
+    //False once the enumeration is exhausted
+    fn refill(&mut self) -> bool {
+        let mut fetched: ULONG = 0;
+        let hr = match self.enumerator {
+            Some(ref enumerator) => unsafe { enumerator.Next(ENUM_BATCH as ULONG, self.batch.as_mut_ptr(), &mut fetched) }, 
+            None => return false,
+        };
+        //S_FALSE is a short, final batch; release the enumerator as soon 
+        // as it runs dry
+        if hr != S_OK {
+            self.enumerator = None;
+        }
+        self.fetched = if hr < 0 { 0 } else { cmp::min(fetched as usize, ENUM_BATCH) };
+        self.pos = 0;
+        self.fetched > 0
+    }
+}
+
+impl Iterator for RuntimeEnum {
+    type Item = ComPtr<ICLRRuntimeInfo>;
+
+    fn next(&mut self) -> Option<ComPtr<ICLRRuntimeInfo>> {
+        loop {
+            if self.pos == self.fetched && !self.refill() {
+                return None;
+            }
+            let raw = mem::replace(&mut self.batch[self.pos], ptr::null_mut());
+            self.pos += 1;
+            if let Some(unknown) = unsafe { ComPtr::from_raw(raw) } {
+                if let Ok(ri) = unknown.query_interface::<ICLRRuntimeInfo>() {
+                    return Some(ri);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for RuntimeEnum {
+    fn drop(&mut self) {
+        for raw in &self.batch[self.pos..self.fetched] {
+            drop(unsafe { ComPtr::from_raw(*raw) });
+        }
+    }
+}
+
+//Long enough for every shipped version string ("v4.0.30319", "v2.0.50727")
+const VERSION_CAPACITY: usize = 32;
+
+//The one place GetVersionString is called
+fn read_version(runtime: &ICLRRuntimeInfo) -> Result<RuntimeVersion, HRESULT> {
+    sized_call_string(VERSION_CAPACITY, |buffer, len| unsafe { runtime.GetVersionString(buffer, len) })
+        .map(RuntimeVersion::from)
+}
+
+//Raw metahost calls shared by MetaHostImpl and SharedMetaHost
+fn create_metahost() -> Result<ComPtr<ICLRMetaHost>, HostingError> {
+    apartment::ensure_current()?;
+    unsafe {
+        ComPtr::from_out(CALL!(mscoree::CLRCreateInstance), |p: *mut *mut ICLRMetaHost| CLRCreateInstance(&CLSID_CLRMetaHost, &IID_ICLRMetaHost, p as *mut LPVOID))
+    }
+}
+
+fn runtime_info(metahost: &ICLRMetaHost, version: &RuntimeVersion) -> Result<RuntimeInfoImpl, HostingError> {
+    let bs = BString::from_str(&version.to_string());
+    let ri = unsafe {
+        ComPtr::from_out(CALL!(ICLRMetaHost::GetRuntime), |p: *mut *mut ICLRRuntimeInfo| metahost.GetRuntime(bs.as_sys(), &IID_ICLRRuntimeInfo, p as *mut LPVOID))
+    }?;
+    Ok(RuntimeInfoImpl::new_from(version.clone(), ri))
+}
+
+fn enumerate_installed(metahost: &ICLRMetaHost) -> Result<InstalledRuntimes, HostingError> {
+    let enumerator = unsafe { ComPtr::from_out(CALL!(ICLRMetaHost::EnumerateInstalledRuntimes), |p| metahost.EnumerateInstalledRuntimes(p)) }?;
+    Ok(InstalledRuntimes { runtimes: RuntimeEnum::new(enumerator) })
+}
+
+fn installed_runtimes(metahost: &ICLRMetaHost) -> Result<Vec<RuntimeInfoImpl>, HostingError> {
+    Ok(enumerate_installed(metahost)?.map(RuntimeHandle::into_info).collect())
+}
+
+fn loaded_versions(metahost: &ICLRMetaHost, process: Process) -> Result<Vec<RuntimeVersion>, HostingError> {
+    let handle = match process {
+        Process::Id(pid) => ProcessHandle::open(pid)?, 
+        Process::Handle(raw) => ProcessHandle::borrowed(raw),
+    };
+    let enumerator = unsafe { ComPtr::from_out(CALL!(ICLRMetaHost::EnumerateLoadedRuntimes), |p| metahost.EnumerateLoadedRuntimes(handle.raw, p)) }?;
+    Ok(RuntimeEnum::new(enumerator).map(|ri| RuntimeInfoImpl::version(&ri)).collect())
+}
+
+//The runtime bound to legacy v2 activation (CorBindToRuntimeEx and 
+// friends) in this process, None if nothing has been bound yet
+fn legacy_v2_binding(metahost: &ICLRMetaHost) -> Result<Option<RuntimeVersion>, HostingError> {
+    let mut ri_ptr: *mut ICLRRuntimeInfo = ptr::null_mut();
+    let hr = CHECK_HR!(ICLRMetaHost::QueryLegacyV2RuntimeBinding, metahost.QueryLegacyV2RuntimeBinding(&IID_ICLRRuntimeInfo, &mut ri_ptr as *mut _ as *mut LPVOID))?;
+    match unsafe { ComPtr::from_raw(ri_ptr) } {
+        Some(ri) if hr == S_OK => Ok(Some(RuntimeInfoImpl::version(&ri))), 
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug)]
 pub struct MetaHostImpl {
-    inner: *mut ICLRMetaHost,
-    runtimes: HashMap<RuntimeVersion, Rc<dyn RuntimeInfo>>,
-    loaded_runtimes: HashMap<RuntimeVersion, bool>,
+    inner: ComPtr<ICLRMetaHost>,
+    runtimes: RefCell<HashMap<RuntimeVersion, Rc<dyn RuntimeInfo>>>,
+    loaded_runtimes: RefCell<HashMap<RuntimeVersion, bool>>,
+}
+
+//AddRefs the metahost through ComPtr::clone. The copy gets caches of its 
+// own, so runtimes handed out by one host are never reported by the other.
+impl Clone for MetaHostImpl {
+    fn clone(&self) -> MetaHostImpl {
+        MetaHostImpl {
+            inner: self.inner.clone(), 
+            runtimes: RefCell::new(HashMap::new()), 
+            loaded_runtimes: RefCell::new(HashMap::new()),
+        }
+    }
 }
 
 impl MetaHostImpl {
     fn new() -> Box<MetaHost> {
-        let mut mh_ptr: *mut ICLRMetaHost = ptr::null_mut();
-        let hr = unsafe {
-            CLRCreateInstance(&CLSID_CLRMetaHost, &IID_ICLRMetaHost, &mut mh_ptr as *mut _ as *mut LPVOID)
-        };
-        if hr == 0 && !mh_ptr.is_null() {
-            Box::new(MetaHostImpl {
-                inner: mh_ptr, 
-                runtimes: HashMap::new(), 
-                loaded_runtimes: HashMap::new()
-            })
-        }
-        else {
-            panic!("HR = 0x{:x}", hr);
+        match create_metahost() {
+            Ok(inner) => Box::new(MetaHostImpl {
+                inner: inner, 
+                runtimes: RefCell::new(HashMap::new()), 
+                loaded_runtimes: RefCell::new(HashMap::new())
+            }), 
+            Err(err) => panic!("{}", err),
         }
     }
+
+    pub(crate) fn create() -> Result<MetaHostImpl, HostingError> {
+        create_metahost().map(|inner| MetaHostImpl {
+            inner: inner, 
+            runtimes: RefCell::new(HashMap::new()), 
+            loaded_runtimes: RefCell::new(HashMap::new())
+        })
+    }
+
+    //Takes over one reference the caller owns; caches start out empty
+    pub unsafe fn from_raw(p: *mut ICLRMetaHost) -> Option<MetaHostImpl> {
+        ComPtr::from_raw(p).map(|inner| MetaHostImpl {
+            inner: inner, 
+            runtimes: RefCell::new(HashMap::new()), 
+            loaded_runtimes: RefCell::new(HashMap::new())
+        })
+    }
+
+    //Hands this value's reference to the caller, who must Release it. 
+    // Runtimes handed out earlier keep their own references.
+    pub fn into_raw(self) -> *mut ICLRMetaHost {
+        self.inner.into_raw()
+    }
+
+    //Fresh enumeration on every call; bypasses the runtimes() cache
+    pub fn installed_runtimes(&self) -> Result<InstalledRuntimes, HostingError> {
+        enumerate_installed(&self.inner)
+    }
+
+    fn cached_runtimes(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        self.runtimes.borrow().iter()
+            .map(|(key, value)| (key.clone(), RuntimeRef::new(value.clone())))
+            .collect()
+    }
 }
 
 impl MetaHost for MetaHostImpl {
-    fn runtime(&mut self, version: RuntimeVersion) -> Weak<dyn RuntimeInfo> {
-        match self.runtimes.get(&version) {
-            Some(ri) => return Rc::downgrade(ri),
-            None => {}
+    fn runtime(&self, version: RuntimeVersion) -> Result<RuntimeRef, HostingError> {
+        if let Some(ri) = self.runtimes.borrow().get(&version) {
+            return Ok(RuntimeRef::new(ri.clone()));
         }
-        let bs = BString::from_str(&version.to_string());
-        let mut ri_ptr: *mut ICLRRuntimeInfo = ptr::null_mut();
-        let hr = unsafe {
-            (*self.inner).GetRuntime(bs.as_sys(), &IID_ICLRRuntimeInfo, &mut ri_ptr as *mut _ as *mut LPVOID)
-        };
-        if hr == 0 && !ri_ptr.is_null() {
-            let ri = RuntimeInfoImpl {
-                version: version.clone(), 
-                inner: ri_ptr, 
-                loaded: None, 
-                loadable: None, 
-                started: None };
-            let strong = Rc::new(ri);
-            let w = Rc::downgrade(&strong);
-            self.runtimes.insert(version, strong);
-            w
+        let ri: Rc<dyn RuntimeInfo> = Rc::new(runtime_info(&self.inner, &version)?);
+        self.runtimes.borrow_mut().insert(version, ri.clone());
+        Ok(RuntimeRef::new(ri))
+    }
+
+    fn runtimes(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        if self.runtimes.borrow().is_empty() {
+            return self.runtimes_uncached();
         }
-        else {
-            panic!("HR = 0x{:x}", hr);
+        self.cached_runtimes()
+    }
+
+    fn loaded_runtimes(&self) -> HashMap<RuntimeVersion, bool> {
+        if self.loaded_runtimes.borrow().is_empty() {
+            let loaded = self.loaded_runtimes_uncached();
+            *self.loaded_runtimes.borrow_mut() = loaded;
         }
+        self.loaded_runtimes.borrow().clone()
     }
 
-    fn runtimes(&mut self) -> HashMap<RuntimeVersion, Weak<dyn RuntimeInfo>> {
-        if self.runtimes.is_empty() {
-            let mut ieu_ptr: *mut IEnumUnknown = ptr::null_mut();
-            let hr = unsafe {
-                (*self.inner).EnumerateInstalledRuntimes(&mut ieu_ptr as *mut *mut IEnumUnknown)
-            };
-            if hr == 0 && !ieu_ptr.is_null() {
-                let mut next_hr = S_OK;
-                let mut hmri: HashMap<RuntimeVersion, Rc<dyn RuntimeInfo>> = HashMap::new();
-                while next_hr == S_OK {
-                    let mut iu_ptr: *mut IUnknown = ptr::null_mut();
-                    let mut cfetched: ULONG = 0;
-                    next_hr = unsafe {
-                        (*ieu_ptr).Next(1, &mut iu_ptr as *mut *mut IUnknown, &mut cfetched as *mut ULONG)
-                    };
-                    if next_hr == S_OK {
-                        let mut ri_ptr: *mut ICLRRuntimeInfo = ptr::null_mut();
-                        let inner_hr = unsafe { (*iu_ptr).QueryInterface(&IID_ICLRRuntimeInfo, &mut ri_ptr as *mut _ as *mut LPVOID )};
-                        if inner_hr == S_OK && !ri_ptr.is_null() {
-                            let mut ri = RuntimeInfoImpl { 
-                                version: RuntimeVersion::Unknown(String::from("")), 
-                                inner: ri_ptr, 
-                                loaded: None, 
-                                loadable: None,
-                                started: None };
-                            let v = ri.version();
-                            hmri.insert(v, Rc::new(ri));
-                        }
-                    }
-                }
-                self.runtimes = hmri;
+    //Newly installed runtimes are added; cached ones are kept so every 
+    // RuntimeRef for a version shares one cache
+    fn runtimes_uncached(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        if let Ok(installed) = installed_runtimes(&self.inner) {
+            let mut runtimes = self.runtimes.borrow_mut();
+            for ri in installed {
+                let v = ri.version.borrow().clone();
+                runtimes.entry(v).or_insert_with(|| Rc::new(ri));
             }
         }
-        let mut weak_map = HashMap::new();
-        self.runtimes.iter().for_each(|(key, value)| {
-            weak_map.insert(key.clone(), Rc::downgrade(&value));
+        self.cached_runtimes()
+    }
+
+    fn loaded_runtimes_uncached(&self) -> HashMap<RuntimeVersion, bool> {
+        let handle = unsafe { GetCurrentProcess() };
+        self.loaded_runtimes_in(Process::Handle(handle)).unwrap_or_default()
+    }
+
+    fn invalidate(&self) {
+        self.loaded_runtimes.borrow_mut().clear();
+        self.runtimes.borrow().values().for_each(|ri| ri.invalidate());
+    }
+
+    //Installed runtimes that aren't loaded in the target are reported as false
+    fn loaded_runtimes_in(&self, process: Process) -> Result<HashMap<RuntimeVersion, bool>, HostingError> {
+        let mut loaded: HashMap<RuntimeVersion, bool> = loaded_versions(&self.inner, process)?
+            .into_iter()
+            .map(|v| (v, true))
+            .collect();
+        self.runtimes().keys().for_each(|key| {
+            loaded.entry(key.clone()).or_insert(false);
         });
-        weak_map
-    }
-
-    fn loaded_runtimes(&mut self) -> HashMap<RuntimeVersion, bool> {
-        if self.loaded_runtimes.is_empty() {
-            let mut ieu_ptr: *mut IEnumUnknown = ptr::null_mut();
-            let hr = unsafe {
-                let handle = GetCurrentProcess();
-                (*self.inner).EnumerateLoadedRuntimes(handle, &mut ieu_ptr as *mut *mut IEnumUnknown)
-            };
-            if hr == 0 && !ieu_ptr.is_null() {
-                let mut next_hr = S_OK;
-                let mut hmri: HashMap<RuntimeVersion, bool> = HashMap::new();
-                while next_hr == S_OK {
-                    let mut iu_ptr: *mut IUnknown = ptr::null_mut();
-                    let mut cfetched: ULONG = 0;
-                    next_hr = unsafe {
-                        (*ieu_ptr).Next(1, &mut iu_ptr as *mut *mut IUnknown, &mut cfetched as *mut ULONG)
-                    };
-                    if next_hr == S_OK {
-                        let mut ri_ptr: *mut ICLRRuntimeInfo = ptr::null_mut();
-                        let inner_hr = unsafe { (*iu_ptr).QueryInterface(&IID_ICLRRuntimeInfo, &mut ri_ptr as *mut _ as *mut LPVOID )};
-                        if inner_hr == S_OK && !ri_ptr.is_null() {
-                            let v = RuntimeInfoImpl::version(ri_ptr);
-                            hmri.insert(v, true);
-                        }
-                    }
-                }
-                self.runtimes().iter().for_each(|(key, _value)|{
-                    if !hmri.contains_key(key) {
-                        hmri.insert(key.clone(), false);
-                    }
-                });
-                self.loaded_runtimes = hmri;
-            }
+        Ok(loaded)
+    }
+
+    fn legacy_v2_bound_runtime(&self) -> Result<Option<RuntimeVersion>, HostingError> {
+        legacy_v2_binding(&self.inner)
+    }
+
+    fn as_raw(&self) -> *mut ICLRMetaHost {
+        self.inner.as_raw()
+    }
+}
+
+//A MetaHost that can be cloned into other threads. ICLRMetaHost and 
+// ICLRRuntimeInfo are free-threaded, so only the caches need a lock; each 
+// runtime gets its own so callers working on different versions don't 
+// contend with each other.
+#[derive(Clone)]
+pub struct SharedMetaHost {
+    inner: Arc<SharedState>,
+}
+
+struct SharedState {
+    metahost: ComPtr<ICLRMetaHost>, 
+    runtimes: Mutex<HashMap<RuntimeVersion, Arc<Mutex<RuntimeInfoImpl>>>>,
+}
+
+unsafe impl Send for SharedState {}
+unsafe impl Sync for SharedState {}
+
+static GLOBAL_METAHOST: OnceCell<SharedMetaHost> = OnceCell::new();
+
+impl SharedMetaHost {
+    //One metahost for the whole process, for code that can't have an owner 
+    // threaded through to it. The first caller creates it; racing callers 
+    // block until that's done and all get the same one. A failed creation 
+    // isn't remembered, so a later call tries again. new() still makes 
+    // independent instances with their own caches.
+    pub fn global() -> Result<&'static SharedMetaHost, HostingError> {
+        GLOBAL_METAHOST.get_or_try_init(SharedMetaHost::new)
+    }
+
+    pub fn new() -> Result<SharedMetaHost, HostingError> {
+        Ok(SharedMetaHost {
+            inner: Arc::new(SharedState {
+                metahost: create_metahost()?, 
+                runtimes: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    //Takes over one reference the caller owns
+    pub unsafe fn from_raw(p: *mut ICLRMetaHost) -> Option<SharedMetaHost> {
+        ComPtr::from_raw(p).map(|metahost| SharedMetaHost {
+            inner: Arc::new(SharedState {
+                metahost: metahost, 
+                runtimes: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    //Borrowed: no AddRef, valid while any clone of this host is alive
+    pub fn as_raw(&self) -> *mut ICLRMetaHost {
+        self.inner.metahost.as_raw()
+    }
+
+    //Other clones may still hold the metahost, so the caller gets a fresh 
+    // reference of its own and must Release it
+    pub fn into_raw(self) -> *mut ICLRMetaHost {
+        self.inner.metahost.clone().into_raw()
+    }
+
+    //A panic while holding the lock can't leave the map half-updated, so 
+    // poisoning is ignored
+    fn cache(&self) -> MutexGuard<HashMap<RuntimeVersion, Arc<Mutex<RuntimeInfoImpl>>>> {
+        self.inner.runtimes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn runtime(&self, version: RuntimeVersion) -> Result<Arc<Mutex<RuntimeInfoImpl>>, HostingError> {
+        let mut cache = self.cache();
+        if let Some(ri) = cache.get(&version) {
+            return Ok(ri.clone());
+        }
+        let ri = Arc::new(Mutex::new(runtime_info(&self.inner.metahost, &version)?));
+        cache.insert(version, ri.clone());
+        Ok(ri)
+    }
+
+    //Fresh enumeration on every call; bypasses the runtimes() cache
+    pub fn installed_runtimes(&self) -> Result<InstalledRuntimes, HostingError> {
+        enumerate_installed(&self.inner.metahost)
+    }
+
+    pub fn runtimes(&self) -> Result<HashMap<RuntimeVersion, Arc<Mutex<RuntimeInfoImpl>>>, HostingError> {
+        let installed = installed_runtimes(&self.inner.metahost)?;
+        let mut cache = self.cache();
+        for ri in installed {
+            let v = ri.version.borrow().clone();
+            cache.entry(v).or_insert_with(|| Arc::new(Mutex::new(ri)));
         }
-        let mut clone = HashMap::new();
-        self.loaded_runtimes.iter().for_each(|(key, value)|{
-            clone.insert(key.clone(), *value);
+        Ok(cache.clone())
+    }
+
+    //Loaded-runtime queries are never cached here; this only resets the 
+    // flags cached on each runtime
+    pub fn invalidate(&self) {
+        for ri in self.cache().values() {
+            ri.lock().unwrap_or_else(PoisonError::into_inner).invalidate();
+        }
+    }
+
+    pub fn loaded_runtimes(&self) -> Result<HashMap<RuntimeVersion, bool>, HostingError> {
+        self.loaded_runtimes_in(Process::Handle(unsafe { GetCurrentProcess() }))
+    }
+
+    pub fn loaded_runtimes_in(&self, process: Process) -> Result<HashMap<RuntimeVersion, bool>, HostingError> {
+        let mut loaded: HashMap<RuntimeVersion, bool> = loaded_versions(&self.inner.metahost, process)?
+            .into_iter()
+            .map(|v| (v, true))
+            .collect();
+        self.runtimes()?.keys().for_each(|key| {
+            loaded.entry(key.clone()).or_insert(false);
         });
-        clone
+        Ok(loaded)
+    }
+
+    pub fn legacy_v2_bound_runtime(&self) -> Result<Option<RuntimeVersion>, HostingError> {
+        legacy_v2_binding(&self.inner.metahost)
+    }
+}
+
+//What select_runtime_for_application found. image_version is the version 
+// the binary was built against, which can differ from the runtime chosen.
+#[derive(Debug)]
+pub struct RuntimeSelection {
+    pub runtime: RuntimeInfoImpl, 
+    pub image_version: String, 
+    //useLegacyV2RuntimeActivationPolicy from the config, None when it isn't set
+    pub legacy_v2_activation: Option<bool>,
+}
+
+//The runtime Windows would start for exe_path, with config_path standing in 
+// for exe_path.config. Goes through ICLRMetaHostPolicy::GetRequestedRuntime 
+// with METAHOST_POLICY_EMULATE_EXE_LAUNCH, so supportedRuntime entries, 
+// upgrade policy and the image's own version are weighed the same way.
+pub fn select_runtime_for_application(exe_path: &Path, config_path: Option<&Path>) -> Result<RuntimeSelection, HostingError> {
+    apartment::ensure_current()?;
+    let policy: ComPtr<ICLRMetaHostPolicy> = unsafe {
+        ComPtr::from_out(CALL!(mscoree::CLRCreateInstance), |p: *mut *mut ICLRMetaHostPolicy| {
+            CLRCreateInstance(&CLSID_CLRMetaHostPolicy, &IID_ICLRMetaHostPolicy, p as *mut LPVOID)
+        })?
+    };
+    let stream = match config_path {
+        Some(path) => Some(config_stream(path)?), 
+        None => None,
+    };
+    let stream_ptr = stream.as_ref().map_or(ptr::null_mut(), |s| s.as_raw());
+    let exe = wide(exe_path);
+    let (mut version, mut image_version) = (vec![0u16; 64], vec![0u16; 64]);
+    //Like sized_call_buffer, the sizes the first call reports get one retry; 
+    // a second ERROR_INSUFFICIENT_BUFFER is returned as the error
+    let mut retried = false;
+    loop {
+        let mut version_len = version.len() as DWORD;
+        let mut image_len = image_version.len() as DWORD;
+        let mut config_flags: DWORD = 0;
+        let mut p: LPVOID = ptr::null_mut();
+        let hr = unsafe {
+            policy.GetRequestedRuntime(
+                METAHOST_POLICY_EMULATE_EXE_LAUNCH, 
+                exe.as_ptr(), 
+                stream_ptr, 
+                version.as_mut_ptr(), 
+                &mut version_len, 
+                image_version.as_mut_ptr(), 
+                &mut image_len, 
+                &mut config_flags, 
+                &IID_ICLRRuntimeInfo, 
+                &mut p
+            )
+        };
+        if hr == HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) && !retried {
+            retried = true;
+            version.resize(cmp::max(version_len as usize, version.len() * 2), 0);
+            image_version.resize(cmp::max(image_len as usize, image_version.len() * 2), 0);
+            continue;
+        }
+        if hr < 0 {
+            return Err(HostingError::from_hresult(hr, CALL!(ICLRMetaHostPolicy::GetRequestedRuntime)));
+        }
+        let runtime = unsafe { RuntimeInfoImpl::from_raw(p as *mut ICLRRuntimeInfo) }
+            .ok_or_else(|| HostingError::null_pointer(CALL!(ICLRMetaHostPolicy::GetRequestedRuntime)))?;
+        let end = image_version.iter().position(|&c| c == 0).unwrap_or(image_version.len());
+        return Ok(RuntimeSelection {
+            runtime, 
+            image_version: String::from_utf16_lossy(&image_version[..end]), 
+            legacy_v2_activation: legacy_v2_policy(config_flags),
+        });
+    }
+}
+
+//The policy reads the whole stream, so the file is read up front rather 
+// than held open
+fn config_stream(path: &Path) -> Result<ComPtr<IStream>, HostingError> {
+    let bytes = fs::read(path).map_err(|e| {
+        HostingError::from_hresult(e.raw_os_error().map_or(E_FAIL, |code| HRESULT_FROM_WIN32(code as u32)), CALL!(kernel32::ReadFile))
+    })?;
+    unsafe { ComPtr::from_raw(SHCreateMemStream(bytes.as_ptr(), bytes.len() as u32)) }
+        .ok_or_else(|| HostingError::from_hresult(E_OUTOFMEMORY, CALL!(shlwapi::SHCreateMemStream)))
+}
+
+fn legacy_v2_policy(config_flags: DWORD) -> Option<bool> {
+    match config_flags & METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_MASK {
+        METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_TRUE => Some(true), 
+        METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_FALSE => Some(false), 
+        _ => None,
     }
 }
 
@@ -388,4 +1132,42 @@ mod test {
         assert_eq!(RuntimeVersion::V3.to_string(), String::from("v3.0") );
         assert_eq!(RuntimeVersion::V4.to_string(), String::from("v4.0.30319") );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn version_numbers() {
+        assert_eq!(RuntimeVersion::V1_1.number(), Some(VersionNumber { major: 1, minor: 1, build: Some(4322) }));
+        assert_eq!(RuntimeVersion::V3.number(), Some(VersionNumber { major: 3, minor: 0, build: None }));
+        assert_eq!("v1.0.3705".parse::<RuntimeVersion>(), Ok(RuntimeVersion::V1_0));
+        assert_eq!("v4.5.1".parse::<RuntimeVersion>(), Ok(RuntimeVersion::Unknown(String::from("v4.5.1"))));
+        assert_eq!("4.0".parse::<RuntimeVersion>(), Err(VersionParseError::MissingPrefix(String::from("4.0"))));
+        assert_eq!("vNext".parse::<RuntimeVersion>(), Err(VersionParseError::BadNumber(String::from("vNext"))));
+    }
+
+    #[test]
+    fn version_order() {
+        let mut versions = vec![
+            RuntimeVersion::Unknown(String::from("dev")), 
+            RuntimeVersion::V4, 
+            RuntimeVersion::Unknown(String::from("v10.0")), 
+            RuntimeVersion::V1_0, 
+            RuntimeVersion::V3, 
+            RuntimeVersion::V2,
+        ];
+        versions.sort();
+        assert_eq!(versions, vec![
+            RuntimeVersion::V1_0, 
+            RuntimeVersion::V2, 
+            RuntimeVersion::V3, 
+            RuntimeVersion::V4, 
+            RuntimeVersion::Unknown(String::from("v10.0")), 
+            RuntimeVersion::Unknown(String::from("dev")),
+        ]);
+    }
+
+    #[test]
+    fn legacy_v2_policy_flags() {
+        assert_eq!(legacy_v2_policy(0), None);
+        assert_eq!(legacy_v2_policy(METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_TRUE), Some(true));
+        assert_eq!(legacy_v2_policy(0x10 | METAHOST_CONFIG_FLAGS_LEGACY_V2_ACTIVATION_POLICY_FALSE), Some(false));
+    }
+}
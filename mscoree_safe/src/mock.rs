@@ -0,0 +1,310 @@
+// mock.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//In-memory MetaHost and RuntimeInfo for testing hosting logic on machines 
+// without the .NET Framework. Nothing here touches COM: as_raw returns null 
+// and get_interface() always fails with E_NOTIMPL, so code that goes on to 
+// create a host has to be tested against a real runtime.
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use winapi::shared::guiddef::{REFCLSID, REFIID};
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::winerror::{E_INVALIDARG, E_NOTIMPL};
+
+use mscoree_sys::corerror::CLR_E_SHIM_RUNTIMELOAD;
+use mscoree_sys::metahost::{ICLRMetaHost, ICLRRuntimeInfo};
+
+use error::HostingError;
+use metahost::{MetaHost, Process, RuntimeInfo, RuntimeRef, RuntimeVersion};
+
+#[derive(Debug)]
+pub struct MockRuntime {
+    version: RuntimeVersion, 
+    directory: PathBuf, 
+    loaded: Cell<bool>, 
+    loadable: Cell<bool>, 
+    startup_flags: Cell<Option<DWORD>>, 
+    default_startup_flags: Cell<DWORD>, 
+    debugger_attached: Cell<bool>, 
+    libraries: RefCell<Vec<OsString>>,
+}
+
+impl MockRuntime {
+    //Installed, loadable, not yet loaded
+    pub fn new(version: RuntimeVersion) -> MockRuntime {
+        let directory = PathBuf::from(format!("C:\\Windows\\Microsoft.NET\\Framework\\{}", version.to_string()));
+        MockRuntime {
+            version, 
+            directory, 
+            loaded: Cell::new(false), 
+            loadable: Cell::new(true), 
+            startup_flags: Cell::new(None), 
+            default_startup_flags: Cell::new(0), 
+            debugger_attached: Cell::new(false), 
+            libraries: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn directory<P: AsRef<Path>>(mut self, path: P) -> MockRuntime {
+        self.directory = path.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn loaded(self, loaded: bool) -> MockRuntime {
+        self.loaded.set(loaded);
+        self
+    }
+
+    pub fn loadable(self, loadable: bool) -> MockRuntime {
+        self.loadable.set(loadable);
+        self
+    }
+
+    //A started runtime is also loaded
+    pub fn started(self, flags: DWORD) -> MockRuntime {
+        self.set_started(flags);
+        self
+    }
+
+    pub fn debugger_attached(self, attached: bool) -> MockRuntime {
+        self.debugger_attached.set(attached);
+        self
+    }
+
+    //For simulating a runtime that gets started while the test runs
+    pub fn set_started(&self, flags: DWORD) {
+        self.loaded.set(true);
+        self.startup_flags.set(Some(flags));
+    }
+
+    //Every name passed to load_library, in order
+    pub fn loaded_libraries(&self) -> Vec<OsString> {
+        self.libraries.borrow().clone()
+    }
+}
+
+impl RuntimeInfo for MockRuntime {
+    fn version(&self) -> RuntimeVersion {
+        self.version.clone()
+    }
+
+    fn loaded(&self) -> bool {
+        self.loaded.get()
+    }
+
+    fn loadable(&self) -> bool {
+        self.loadable.get()
+    }
+
+    fn started(&self) -> bool {
+        self.startup_flags.get().is_some()
+    }
+
+    //Nothing is cached, so the uncached queries are the same ones
+    fn loaded_uncached(&self) -> bool {
+        self.loaded()
+    }
+
+    fn loadable_uncached(&self) -> bool {
+        self.loadable()
+    }
+
+    fn started_uncached(&self) -> bool {
+        self.started()
+    }
+
+    fn invalidate(&self) {}
+
+    fn startup_flags(&self) -> Option<DWORD> {
+        self.startup_flags.get()
+    }
+
+    fn directory(&self) -> Result<PathBuf, HostingError> {
+        Ok(self.directory.clone())
+    }
+
+    fn default_startup_flags(&self) -> Result<DWORD, HostingError> {
+        Ok(self.default_startup_flags.get())
+    }
+
+    fn set_default_startup_flags(&self, flags: DWORD, _host_config: Option<&Path>) -> Result<(), HostingError> {
+        self.default_startup_flags.set(flags);
+        Ok(())
+    }
+
+    fn load_library(&self, dll_name: &OsStr) {
+        self.libraries.borrow_mut().push(dll_name.to_os_string());
+    }
+
+    fn get_interface(&self, _clsid: REFCLSID, _iid: REFIID) -> Result<LPVOID, HostingError> {
+        Err(HostingError::from_hresult(E_NOTIMPL, CALL!(ICLRRuntimeInfo::GetInterface)))
+    }
+
+    fn is_debugger_attached(&self) -> Result<bool, HostingError> {
+        Ok(self.debugger_attached.get())
+    }
+
+    fn bind_as_legacy_v2_runtime(&self) -> Result<(), HostingError> {
+        Ok(())
+    }
+
+    fn as_raw(&self) -> *mut ICLRRuntimeInfo {
+        ptr::null_mut()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MockMetaHost {
+    runtimes: HashMap<RuntimeVersion, Rc<MockRuntime>>, 
+    processes: HashMap<DWORD, Vec<RuntimeVersion>>, 
+    legacy_v2_binding: Option<RuntimeVersion>,
+}
+
+impl MockMetaHost {
+    pub fn new() -> MockMetaHost {
+        MockMetaHost::default()
+    }
+
+    pub fn with_runtime(mut self, runtime: MockRuntime) -> MockMetaHost {
+        self.runtimes.insert(runtime.version(), Rc::new(runtime));
+        self
+    }
+
+    //What loaded_runtimes_in reports for Process::Id(pid). Handles always 
+    // refer to the current process.
+    pub fn with_process(mut self, pid: DWORD, loaded: Vec<RuntimeVersion>) -> MockMetaHost {
+        self.processes.insert(pid, loaded);
+        self
+    }
+
+    pub fn with_legacy_v2_binding(mut self, version: RuntimeVersion) -> MockMetaHost {
+        self.legacy_v2_binding = Some(version);
+        self
+    }
+
+    //Strong handle to a runtime added with with_runtime, for flipping its 
+    // state mid-test
+    pub fn mock_runtime(&self, version: &RuntimeVersion) -> Option<Rc<MockRuntime>> {
+        self.runtimes.get(version).cloned()
+    }
+}
+
+impl MetaHost for MockMetaHost {
+    //VersionNotFound for versions that weren't added
+    fn runtime(&self, version: RuntimeVersion) -> Result<RuntimeRef, HostingError> {
+        match self.runtimes.get(&version) {
+            Some(ri) => Ok(RuntimeRef::new(ri.clone())), 
+            None => Err(HostingError::from_hresult(CLR_E_SHIM_RUNTIMELOAD, CALL!(ICLRMetaHost::GetRuntime))),
+        }
+    }
+
+    fn runtimes(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        self.runtimes.iter()
+            .map(|(version, ri)| (version.clone(), RuntimeRef::new(ri.clone())))
+            .collect()
+    }
+
+    fn loaded_runtimes(&self) -> HashMap<RuntimeVersion, bool> {
+        self.runtimes.iter()
+            .map(|(version, ri)| (version.clone(), ri.loaded()))
+            .collect()
+    }
+
+    fn runtimes_uncached(&self) -> HashMap<RuntimeVersion, RuntimeRef> {
+        self.runtimes()
+    }
+
+    fn loaded_runtimes_uncached(&self) -> HashMap<RuntimeVersion, bool> {
+        self.loaded_runtimes()
+    }
+
+    fn invalidate(&self) {}
+
+    fn loaded_runtimes_in(&self, process: Process) -> Result<HashMap<RuntimeVersion, bool>, HostingError> {
+        let pid = match process {
+            Process::Id(pid) => pid, 
+            Process::Handle(_) => return Ok(self.loaded_runtimes()),
+        };
+        let in_process = match self.processes.get(&pid) {
+            Some(versions) => versions, 
+            None => return Err(HostingError::from_hresult(E_INVALIDARG, CALL!(kernel32::OpenProcess))),
+        };
+        let mut loaded: HashMap<RuntimeVersion, bool> = self.runtimes.keys()
+            .map(|version| (version.clone(), false))
+            .collect();
+        for version in in_process {
+            loaded.insert(version.clone(), true);
+        }
+        Ok(loaded)
+    }
+
+    fn legacy_v2_bound_runtime(&self) -> Result<Option<RuntimeVersion>, HostingError> {
+        Ok(self.legacy_v2_binding.clone())
+    }
+
+    fn as_raw(&self) -> *mut ICLRMetaHost {
+        ptr::null_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn host() -> MockMetaHost {
+        MockMetaHost::new()
+            .with_runtime(MockRuntime::new(RuntimeVersion::V2))
+            .with_runtime(MockRuntime::new(RuntimeVersion::V4).started(0))
+            .with_process(42, vec![RuntimeVersion::V2])
+    }
+
+    #[test]
+    fn runtimes_outlive_the_host() {
+        let ri = host().runtime(RuntimeVersion::V4).unwrap();
+        assert!(ri.started());
+        let host = host();
+        assert_eq!(host.runtimes().len(), 2);
+        assert!(host.runtime(RuntimeVersion::V3).is_err());
+    }
+
+    #[test]
+    fn loaded_state_follows_the_runtime() {
+        let host = host();
+        assert_eq!(host.loaded_runtimes()[&RuntimeVersion::V2], false);
+        host.mock_runtime(&RuntimeVersion::V2).unwrap().set_started(0);
+        assert_eq!(host.loaded_runtimes()[&RuntimeVersion::V2], true);
+    }
+
+    #[test]
+    fn other_processes() {
+        let host = host();
+        let loaded = host.loaded_runtimes_in(Process::Id(42)).unwrap();
+        assert_eq!(loaded[&RuntimeVersion::V2], true);
+        assert_eq!(loaded[&RuntimeVersion::V4], false);
+        assert!(host.loaded_runtimes_in(Process::Id(7)).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+// monitor.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRAppDomainResourceMonitor: per-domain allocation, survival and CPU 
+// figures. Monitoring has to be switched on first 
+// (AppDomain.MonitoringIsEnabled, or <appDomainResourceMonitoring> in the 
+// config); until then every call fails.
+use std::time::Duration;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::ULONGLONG;
+
+use mscoree_sys::mscoree::ICLRAppDomainResourceMonitor;
+
+use control::ClrControl;
+use error::HostingError;
+use wrappers::PtrCtr;
+
+//Bytes that survived the last full blocking collection
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SurvivedBytes {
+    pub domain: u64, 
+    //Across every domain in the process
+    pub total: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DomainResources {
+    pub allocated: u64, 
+    pub survived: SurvivedBytes, 
+    pub cpu_time: Duration,
+}
+
+pub struct DomainResourceMonitor {
+    inner: PtrCtr<ICLRAppDomainResourceMonitor>,
+}
+
+impl DomainResourceMonitor {
+    pub fn new(control: &ClrControl) -> Result<DomainResourceMonitor, HostingError> {
+        control.manager::<ICLRAppDomainResourceMonitor>().map(|inner| DomainResourceMonitor { inner })
+    }
+
+    //Total bytes allocated by the domain since it was created
+    pub fn allocated(&self, domain_id: DWORD) -> Result<u64, HostingError> {
+        let mut bytes: ULONGLONG = 0;
+        CHECK_HR!(ICLRAppDomainResourceMonitor::GetCurrentAllocated, (*self.inner.as_const()).GetCurrentAllocated(domain_id, &mut bytes))?;
+        Ok(bytes)
+    }
+
+    pub fn survived(&self, domain_id: DWORD) -> Result<SurvivedBytes, HostingError> {
+        let mut survived = SurvivedBytes::default();
+        CHECK_HR!(ICLRAppDomainResourceMonitor::GetCurrentSurvived, (*self.inner.as_const()).GetCurrentSurvived(domain_id, &mut survived.domain, &mut survived.total))?;
+        Ok(survived)
+    }
+
+    //Processor time used by threads while running in the domain
+    pub fn cpu_time(&self, domain_id: DWORD) -> Result<Duration, HostingError> {
+        let mut millis: ULONGLONG = 0;
+        CHECK_HR!(ICLRAppDomainResourceMonitor::GetCurrentCpuTime, (*self.inner.as_const()).GetCurrentCpuTime(domain_id, &mut millis))?;
+        Ok(Duration::from_millis(millis))
+    }
+
+    pub fn snapshot(&self, domain_id: DWORD) -> Result<DomainResources, HostingError> {
+        Ok(DomainResources {
+            allocated: self.allocated(domain_id)?, 
+            survived: self.survived(domain_id)?, 
+            cpu_time: self.cpu_time(domain_id)?,
+        })
+    }
+}
+
+COM_WRAPPER!(DomainResourceMonitor);
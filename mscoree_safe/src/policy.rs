@@ -0,0 +1,148 @@
+// policy.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Escalation policy for reliability-focused hosts: what the runtime does when 
+// an operation times out or a resource failure happens. Configure before Start.
+use std::time::Duration;
+
+use winapi::shared::minwindef::DWORD;
+
+use mscoree_sys::mscoree::*;
+
+use control::ClrControl;
+use error::HostingError;
+use wrappers::PtrCtr;
+
+macro_rules! RAW_ENUM {
+    ($name:ident => $raw:ty { $($variant:ident = $value:ident),* }) => {
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            pub fn raw(self) -> $raw {
+                match self {
+                    $($name::$variant => $value),*
+                }
+            }
+        }
+    };
+}
+
+RAW_ENUM!(ClrOperation => EClrOperation {
+    ThreadAbort = OPR_ThreadAbort, 
+    ThreadRudeAbortInNonCriticalRegion = OPR_ThreadRudeAbortInNonCriticalRegion, 
+    ThreadRudeAbortInCriticalRegion = OPR_ThreadRudeAbortInCriticalRegion, 
+    AppDomainUnload = OPR_AppDomainUnload, 
+    AppDomainRudeUnload = OPR_AppDomainRudeUnload, 
+    ProcessExit = OPR_ProcessExit, 
+    FinalizerRun = OPR_FinalizerRun
+});
+
+RAW_ENUM!(ClrFailure => EClrFailure {
+    NonCriticalResource = FAIL_NonCriticalResource, 
+    CriticalResource = FAIL_CriticalResource, 
+    FatalRuntime = FAIL_FatalRuntime, 
+    OrphanedLock = FAIL_OrphanedLock, 
+    StackOverflow = FAIL_StackOverflow, 
+    AccessViolation = FAIL_AccessViolation, 
+    CodeContract = FAIL_CodeContract
+});
+
+RAW_ENUM!(PolicyAction => EPolicyAction {
+    NoAction = eNoAction, 
+    ThrowException = eThrowException, 
+    AbortThread = eAbortThread, 
+    RudeAbortThread = eRudeAbortThread, 
+    UnloadAppDomain = eUnloadAppDomain, 
+    RudeUnloadAppDomain = eRudeUnloadAppDomain, 
+    ExitProcess = eExitProcess, 
+    FastExitProcess = eFastExitProcess, 
+    RudeExitProcess = eRudeExitProcess, 
+    DisableRuntime = eDisableRuntime
+});
+
+RAW_ENUM!(UnhandledExceptionPolicy => EClrUnhandledException {
+    RuntimeDetermined = eRuntimeDeterminedPolicy, 
+    HostDetermined = eHostDeterminedPolicy
+});
+
+pub struct PolicyManager {
+    inner: PtrCtr<ICLRPolicyManager>,
+}
+
+impl PolicyManager {
+    pub fn new(control: &ClrControl) -> Result<PolicyManager, HostingError> {
+        control.manager::<ICLRPolicyManager>().map(|inner| PolicyManager { inner })
+    }
+
+    pub fn set_default_action(&self, operation: ClrOperation, action: PolicyAction) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRPolicyManager::SetDefaultAction, (*self.inner.as_const()).SetDefaultAction(operation.raw(), action.raw())).map(|_| ())
+    }
+
+    pub fn set_timeout(&self, operation: ClrOperation, timeout: Duration) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRPolicyManager::SetTimeout, (*self.inner.as_const()).SetTimeout(operation.raw(), millis(timeout))).map(|_| ())
+    }
+
+    pub fn set_action_on_timeout(&self, operation: ClrOperation, action: PolicyAction) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRPolicyManager::SetActionOnTimeout, (*self.inner.as_const()).SetActionOnTimeout(operation.raw(), action.raw())).map(|_| ())
+    }
+
+    pub fn set_timeout_and_action(&self, operation: ClrOperation, timeout: Duration, action: PolicyAction) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRPolicyManager::SetTimeoutAndAction, (*self.inner.as_const()).SetTimeoutAndAction(operation.raw(), millis(timeout), action.raw())).map(|_| ())
+    }
+
+    pub fn set_action_on_failure(&self, failure: ClrFailure, action: PolicyAction) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRPolicyManager::SetActionOnFailure, (*self.inner.as_const()).SetActionOnFailure(failure.raw(), action.raw())).map(|_| ())
+    }
+
+    pub fn set_unhandled_exception_policy(&self, policy: UnhandledExceptionPolicy) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRPolicyManager::SetUnhandledExceptionPolicy, (*self.inner.as_const()).SetUnhandledExceptionPolicy(policy.raw())).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(PolicyManager);
+
+//Saturates just below INFINITE so an overlong Duration isn't read as "never"
+pub(crate) fn millis(timeout: Duration) -> DWORD {
+    let ms = timeout.as_secs().saturating_mul(1000).saturating_add(u64::from(timeout.subsec_millis()));
+    if ms >= u64::from(DWORD::max_value()) { DWORD::max_value() - 1 } else { ms as DWORD }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn enums_map_to_raw_values() {
+        assert_eq!(ClrOperation::FinalizerRun.raw(), OPR_FinalizerRun);
+        assert_eq!(ClrFailure::StackOverflow.raw(), FAIL_StackOverflow);
+        assert_eq!(PolicyAction::DisableRuntime.raw(), eDisableRuntime);
+    }
+
+    #[test]
+    fn timeouts_saturate_below_infinite() {
+        assert_eq!(millis(Duration::from_millis(1500)), 1500);
+        assert_eq!(millis(Duration::from_secs(u64::max_value())), 0xFFFF_FFFE);
+    }
+}
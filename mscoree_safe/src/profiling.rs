@@ -0,0 +1,81 @@
+// profiling.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRProfiling: attach a profiler to a running v4 process. The target 
+// loads the profiler DLL on its own attach thread, so the call blocks 
+// until the profiler's InitializeForAttach has returned or the timeout hits.
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::time::Duration;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::CLSID;
+use winapi::shared::minwindef::{DWORD, LPVOID, UINT};
+use winapi::shared::ntdef::LPCWSTR;
+
+use mscoree_sys::metahost::{CLRCreateInstance, CLSID_CLRProfiling, ICLRProfiling, IID_ICLRProfiling};
+
+use apartment;
+use error::HostingError;
+use policy::millis;
+use wrappers::PtrCtr;
+
+pub struct Profiling {
+    inner: PtrCtr<ICLRProfiling>,
+}
+
+impl Profiling {
+    pub fn new() -> Result<Profiling, HostingError> {
+        apartment::ensure_current()?;
+        let mut p: *mut ICLRProfiling = ptr::null_mut();
+        CHECK_HR!(mscoree::CLRCreateInstance, CLRCreateInstance(&CLSID_CLRProfiling, &IID_ICLRProfiling, &mut p as *mut _ as *mut LPVOID))?;
+        PtrCtr::new_checked(p)
+            .map(|inner| Profiling { inner })
+            .map_err(|_| HostingError::null_pointer(CALL!(mscoree::CLRCreateInstance)))
+    }
+
+    //Without a path the target resolves the profiler CLSID through the 
+    // registry. The client data is copied to the target and handed to 
+    // InitializeForAttach.
+    pub fn attach_profiler(&self, pid: DWORD, timeout: Duration, profiler: &CLSID, profiler_path: Option<&Path>, client_data: &[u8]) 
+        -> Result<(), HostingError> 
+    {
+        let path: Option<Vec<u16>> = profiler_path.map(|p| p.as_os_str().encode_wide().chain(Some(0)).collect());
+        let path_ptr: LPCWSTR = match path {
+            Some(ref wide) => wide.as_ptr(), 
+            None => ptr::null(),
+        };
+        let mut data = client_data.to_vec();
+        let data_ptr = if data.is_empty() { ptr::null_mut() } else { data.as_mut_ptr() as *mut c_void };
+        CHECK_HR!(ICLRProfiling::AttachProfiler, (*self.inner.as_const()).AttachProfiler(
+            pid, 
+            millis(timeout), 
+            profiler, 
+            path_ptr, 
+            data_ptr, 
+            data.len() as UINT
+        )).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(Profiling);
@@ -0,0 +1,189 @@
+// publish.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICorPublish: the machine-wide view of managed processes a debugger's 
+// attach dialog is built on. Every process with a v1-v4 desktop runtime 
+// loaded shows up, along with the app domains it currently has.
+use std::ptr;
+
+use winapi::shared::minwindef::{BOOL, FALSE, UINT, ULONG};
+use winapi::shared::winerror::S_FALSE;
+use winapi::um::combaseapi::{CoCreateInstance, CLSCTX_INPROC_SERVER};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::winnt::PROCESS_ALL_ACCESS;
+use winapi::Interface;
+
+use mscoree_sys::corpub::*;
+
+use apartment;
+use buffer::double_call_string;
+use comptr::ComPtr;
+use error::HostingError;
+
+pub struct Publisher {
+    inner: ComPtr<ICorPublish>,
+}
+
+impl Publisher {
+    pub fn new() -> Result<Publisher, HostingError> {
+        apartment::ensure_current()?;
+        let inner = unsafe {
+            ComPtr::from_out(CALL!(ole32::CoCreateInstance), |p: *mut *mut ICorPublish| {
+                CoCreateInstance(&CLSID_CorpubPublish, ptr::null_mut(), CLSCTX_INPROC_SERVER, &ICorPublish::uuidof(), p as *mut _)
+            })?
+        };
+        Ok(Publisher { inner })
+    }
+
+    //Managed processes only; unmanaged ones are never interesting here
+    pub fn processes(&self) -> Result<Vec<PublishedProcess>, HostingError> {
+        let enumerator = unsafe {
+            ComPtr::from_out(CALL!(ICorPublish::EnumProcesses), |p: *mut *mut ICorPublishProcessEnum| {
+                self.inner.EnumProcesses(COR_PUB_MANAGEDONLY, p)
+            })?
+        };
+        let mut processes = Vec::new();
+        loop {
+            let mut process: *mut ICorPublishProcess = ptr::null_mut();
+            let mut fetched: ULONG = 0;
+            let hr = CHECK_HR!(ICorPublishProcessEnum::Next, enumerator.Next(1, &mut process, &mut fetched))?;
+            if hr == S_FALSE || fetched == 0 {
+                break;
+            }
+            if let Some(inner) = unsafe { ComPtr::from_raw(process) } {
+                processes.push(PublishedProcess { inner });
+            }
+        }
+        Ok(processes)
+    }
+
+    //Fails for processes without a runtime
+    pub fn process(&self, pid: u32) -> Result<PublishedProcess, HostingError> {
+        let inner = unsafe {
+            ComPtr::from_out(CALL!(ICorPublish::GetProcess), |p: *mut *mut ICorPublishProcess| {
+                self.inner.GetProcess(pid as UINT, p)
+            })?
+        };
+        Ok(PublishedProcess { inner })
+    }
+
+    //Everything the attach dialog shows, collected in one pass. Processes 
+    // that exit while being inspected are left out.
+    pub fn snapshot(&self) -> Result<Vec<ProcessSummary>, HostingError> {
+        Ok(self.processes()?.iter().filter_map(|process| process.summary().ok()).collect())
+    }
+}
+
+pub struct PublishedProcess {
+    inner: ComPtr<ICorPublishProcess>,
+}
+
+impl PublishedProcess {
+    pub fn pid(&self) -> Result<u32, HostingError> {
+        let mut pid: UINT = 0;
+        CHECK_HR!(ICorPublishProcess::GetProcessID, self.inner.GetProcessID(&mut pid))?;
+        Ok(pid)
+    }
+
+    //Usually the full path of the executable
+    pub fn display_name(&self) -> Result<String, HostingError> {
+        double_call_string(|buffer, len| unsafe { self.inner.GetDisplayName(*len, len, buffer) })
+            .map_err(|hr| HostingError::from_hresult(hr, CALL!(ICorPublishProcess::GetDisplayName)))
+    }
+
+    pub fn is_managed(&self) -> Result<bool, HostingError> {
+        let mut managed: BOOL = FALSE;
+        CHECK_HR!(ICorPublishProcess::IsManaged, self.inner.IsManaged(&mut managed))?;
+        Ok(managed != FALSE)
+    }
+
+    //The domains as of this call; the list goes stale as the process runs
+    pub fn app_domains(&self) -> Result<Vec<PublishedAppDomain>, HostingError> {
+        let enumerator = unsafe {
+            ComPtr::from_out(CALL!(ICorPublishProcess::EnumAppDomains), |p: *mut *mut ICorPublishAppDomainEnum| {
+                self.inner.EnumAppDomains(p)
+            })?
+        };
+        let mut domains = Vec::new();
+        loop {
+            let mut domain: *mut ICorPublishAppDomain = ptr::null_mut();
+            let mut fetched: ULONG = 0;
+            let hr = CHECK_HR!(ICorPublishAppDomainEnum::Next, enumerator.Next(1, &mut domain, &mut fetched))?;
+            if hr == S_FALSE || fetched == 0 {
+                break;
+            }
+            if let Some(domain) = unsafe { ComPtr::from_raw(domain) } {
+                domains.push(PublishedAppDomain::read(&domain)?);
+            }
+        }
+        Ok(domains)
+    }
+
+    //Whether this process could open the target with the access a 
+    // debugger needs. A denied open usually means another user's process 
+    // or a protected one; elevation may help.
+    pub fn attachable(&self) -> Result<bool, HostingError> {
+        let pid = self.pid()?;
+        let handle = unsafe { OpenProcess(PROCESS_ALL_ACCESS, FALSE, pid) };
+        if handle.is_null() {
+            return Ok(false);
+        }
+        unsafe { CloseHandle(handle) };
+        Ok(true)
+    }
+
+    pub fn summary(&self) -> Result<ProcessSummary, HostingError> {
+        Ok(ProcessSummary {
+            pid: self.pid()?, 
+            name: self.display_name()?, 
+            managed: self.is_managed()?, 
+            attachable: self.attachable()?, 
+            app_domains: self.app_domains()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishedAppDomain {
+    pub id: u32, 
+    pub name: String,
+}
+
+impl PublishedAppDomain {
+    fn read(domain: &ICorPublishAppDomain) -> Result<PublishedAppDomain, HostingError> {
+        let mut id: ULONG = 0;
+        CHECK_HR!(ICorPublishAppDomain::GetID, domain.GetID(&mut id))?;
+        let name = double_call_string(|buffer, len| unsafe { domain.GetName(*len, len, buffer) })
+            .map_err(|hr| HostingError::from_hresult(hr, CALL!(ICorPublishAppDomain::GetName)))?;
+        Ok(PublishedAppDomain { id, name })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProcessSummary {
+    pub pid: u32, 
+    pub name: String, 
+    pub managed: bool, 
+    pub attachable: bool, 
+    pub app_domains: Vec<PublishedAppDomain>,
+}
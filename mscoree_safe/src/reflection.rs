@@ -0,0 +1,497 @@
+// reflection.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Late-bound access to managed code through the mscorlib COM surface: 
+// load an assembly into a domain, look up a type, and invoke members on it 
+// via _Type::InvokeMember. Arguments and results travel as ClrValue.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{DISP_E_TYPEMISMATCH, E_FAIL, HRESULT, HRESULT_FROM_WIN32};
+use winapi::um::oaidl::VARIANT;
+use winapi::um::oleauto::VariantClear;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+use mscorlib_safe::BString;
+use mscorlib_sys::system::{_AppDomain, _Object, _Type};
+use mscorlib_sys::system::reflection::_Assembly;
+use mscorlib_sys::system::runtime::remoting::_ObjectHandle;
+
+use mscoree_sys::corerror::COR_E_TYPELOAD;
+
+use coreclr::typed_fn;
+use dispatch::DynamicObject;
+use error::{Call, HostingError};
+use exception::ManagedException;
+use variant::{self, ClrObject, ClrValue, OsBstr, SafeArrayPtr};
+use wrappers::PtrCtr;
+
+//System.Reflection.BindingFlags
+const BINDING_INSTANCE: u32 = 0x4;
+const BINDING_STATIC: u32 = 0x8;
+const BINDING_PUBLIC: u32 = 0x10;
+const BINDING_INVOKE_METHOD: u32 = 0x100;
+const BINDING_CREATE_INSTANCE: u32 = 0x200;
+const BINDING_GET_PROPERTY: u32 = 0x1000;
+const BINDING_SET_PROPERTY: u32 = 0x2000;
+
+pub struct AppDomain {
+    inner: PtrCtr<_AppDomain>,
+}
+
+impl AppDomain {
+    pub(crate) fn new_from(inner: PtrCtr<_AppDomain>) -> AppDomain {
+        AppDomain { inner }
+    }
+
+    pub(crate) fn as_unknown(&self) -> *mut IUnknown {
+        self.inner.as_const() as *mut IUnknown
+    }
+
+    //AppDomain.SetData; values cross the domain boundary by value, 
+    // so stick to primitives and strings
+    pub fn set_data(&self, name: &str, value: &ClrValue) -> Result<(), HostingError> {
+        let name = BString::from(name);
+        let mut data = value.to_variant()?;
+        let hr = CHECK_HR!(_AppDomain::SetData, (*self.inner.as_const()).SetData(name.as_sys(), data));
+        unsafe { VariantClear(&mut data) };
+        hr.map(|_| ())
+    }
+
+    pub fn get_data(&self, name: &str) -> Result<ClrValue, HostingError> {
+        let name = BString::from(name);
+        let mut data = variant::empty();
+        CHECK_HR!(_AppDomain::GetData, (*self.inner.as_const()).GetData(name.as_sys(), &mut data))?;
+        ClrValue::from_owned_variant(data)
+    }
+
+    //Runs the entry point of an executable assembly inside this domain
+    pub fn execute_assembly<P: AsRef<Path>>(&self, path: P) -> Result<i32, HostingError> {
+        let file = OsBstr::new(path.as_ref())?;
+        let mut exit_code = 0;
+        CHECK_HR!(_AppDomain::ExecuteAssembly_2, (*self.inner.as_const()).ExecuteAssembly_2(file.as_sys(), &mut exit_code))?;
+        Ok(exit_code)
+    }
+
+    //Desktop counterpart of CoreClrHost::delegate, going through 
+    // Delegate.CreateDelegate and Marshal.GetFunctionPointerForDelegate. 
+    // Only non-generic delegate types can be marshaled, so delegate_type 
+    // names one declared in the same assembly with the signature F mirrors. 
+    // Unsafe because nothing checks that it does.
+    pub unsafe fn delegate<F: Copy>(&self, assembly: &ManagedAssembly, type_name: &str, method: &str, delegate_type: &str) -> Result<NativeDelegate<F>, HostingError> {
+        let target = assembly.get_type(type_name)?;
+        let delegate_ty = assembly.get_type(delegate_type)?;
+        let mscorlib = self.load("mscorlib")?;
+        let delegate = mscorlib.get_type("System.Delegate")?
+            .invoke_static("CreateDelegate", &[delegate_ty.to_value(), target.to_value(), ClrValue::from(method)])?
+            .into_object()
+            .ok_or_else(|| HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(_Type::InvokeMember_3)))?;
+        let marshal = mscorlib.get_type("System.Runtime.InteropServices.Marshal")?;
+        let mut ret = marshal.invoke_member_variant(
+            "GetFunctionPointerForDelegate", 
+            BINDING_STATIC | BINDING_PUBLIC | BINDING_INVOKE_METHOD, 
+            variant::empty(), 
+            &[ClrValue::Object(delegate.clone())]
+        )?;
+        let fp = variant::int_ptr(&ret);
+        VariantClear(&mut ret);
+        match fp {
+            Some(0) => Err(HostingError::null_pointer(CALL!(_Type::InvokeMember_3))), 
+            Some(fp) => Ok(NativeDelegate { function: typed_fn(fp as *mut c_void), _delegate: delegate }), 
+            None => Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(_Type::InvokeMember_3))),
+        }
+    }
+
+    //Loads by display name, e.g. "System.Xml, Version=4.0.0.0, ..."
+    pub fn load(&self, display_name: &str) -> Result<ManagedAssembly, HostingError> {
+        let name = BString::from(display_name);
+        let mut assembly: *mut _Assembly = ptr::null_mut();
+        CHECK_HR!(_AppDomain::Load_2, (*self.inner.as_const()).Load_2(name.as_sys(), &mut assembly))?;
+        wrap(assembly, CALL!(_AppDomain::Load_2)).map(|inner| ManagedAssembly { inner })
+    }
+
+    //Reads the file and loads it from memory, so the image is not locked 
+    // on disk and no probing happens relative to the host executable.
+    pub fn load_assembly<P: AsRef<Path>>(&self, path: P) -> Result<ManagedAssembly, HostingError> {
+        let bytes = fs::read(path.as_ref())
+            .map_err(|err| HostingError::from_hresult(io_hresult(&err), CALL!(kernel32::ReadFile)))?;
+        self.load_bytes(&bytes)
+    }
+
+    pub fn load_bytes(&self, image: &[u8]) -> Result<ManagedAssembly, HostingError> {
+        let raw = SafeArrayPtr::from_bytes(image)?;
+        let mut assembly: *mut _Assembly = ptr::null_mut();
+        CHECK_HR!(_AppDomain::Load_3, (*self.inner.as_const()).Load_3(raw.as_ptr(), &mut assembly))?;
+        wrap(assembly, CALL!(_AppDomain::Load_3)).map(|inner| ManagedAssembly { inner })
+    }
+
+    //AppDomain.CreateInstance: loads the assembly by display name inside 
+    // this domain and runs the type's parameterless constructor there
+    pub fn create_instance(&self, assembly_name: &str, type_name: &str) -> Result<ObjectHandle, HostingError> {
+        let (assembly, ty) = (BString::from(assembly_name), BString::from(type_name));
+        let mut handle: *mut _ObjectHandle = ptr::null_mut();
+        CHECK_HR!(_AppDomain::CreateInstance, (*self.inner.as_const()).CreateInstance(assembly.as_sys(), ty.as_sys(), &mut handle))?;
+        wrap(handle, CALL!(_AppDomain::CreateInstance)).map(|inner| ObjectHandle { inner })
+    }
+
+    //As create_instance, with the assembly loaded from a file
+    pub fn create_instance_from<P: AsRef<Path>>(&self, assembly_file: P, type_name: &str) -> Result<ObjectHandle, HostingError> {
+        let file = OsBstr::new(assembly_file.as_ref())?;
+        let ty = BString::from(type_name);
+        let mut handle: *mut _ObjectHandle = ptr::null_mut();
+        CHECK_HR!(_AppDomain::CreateInstanceFrom, (*self.inner.as_const()).CreateInstanceFrom(file.as_sys(), ty.as_sys(), &mut handle))?;
+        wrap(handle, CALL!(_AppDomain::CreateInstanceFrom)).map(|inner| ObjectHandle { inner })
+    }
+}
+
+COM_WRAPPER!(AppDomain);
+
+//System.Runtime.Remoting.ObjectHandle. The object stays wrapped until 
+// unwrap(); for a MarshalByRefObject created in another domain that gives 
+// a proxy, anything else is serialized across by value.
+pub struct ObjectHandle {
+    inner: PtrCtr<_ObjectHandle>,
+}
+
+impl ObjectHandle {
+    pub fn unwrap(&self) -> Result<ManagedObject, HostingError> {
+        let mut object = variant::empty();
+        CHECK_HR!(_ObjectHandle::Unwrap, (*self.inner.as_const()).Unwrap(&mut object))?;
+        ManagedObject::from_value(ClrValue::from_owned_variant(object)?)
+    }
+}
+
+COM_WRAPPER!(ObjectHandle);
+
+pub struct ManagedAssembly {
+    inner: PtrCtr<_Assembly>,
+}
+
+impl ManagedAssembly {
+    //For assemblies handed back by managed code, e.g. CompilerResults.CompiledAssembly
+    pub fn from_value(value: &ClrValue) -> Result<ManagedAssembly, HostingError> {
+        match *value {
+            ClrValue::Object(ref object) => query::<_Assembly>(object.as_unknown()).map(|inner| ManagedAssembly { inner }), 
+            _ => Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(IUnknown::QueryInterface))),
+        }
+    }
+
+    //Namespace-qualified type name; COR_E_TYPELOAD when it doesn't exist
+    pub fn get_type(&self, name: &str) -> Result<ManagedType, HostingError> {
+        let bs = BString::from(name);
+        let mut ty: *mut _Type = ptr::null_mut();
+        CHECK_HR!(_Assembly::GetType_2, (*self.inner.as_const()).GetType_2(bs.as_sys(), &mut ty))?;
+        wrap(ty, CALL!(_Assembly::GetType_2))
+            .map(|inner| ManagedType { inner })
+            .map_err(|err| HostingError::from_hresult(COR_E_TYPELOAD, err.call()))
+    }
+
+    //Runs the public parameterless constructor of the named type
+    pub fn create_instance(&self, type_name: &str) -> Result<ManagedObject, HostingError> {
+        self.get_type(type_name)?.create_instance(&[])
+    }
+}
+
+//Another reference to the same loaded assembly
+impl Clone for ManagedAssembly {
+    fn clone(&self) -> ManagedAssembly {
+        unsafe { (*self.inner.as_const()).AddRef() };
+        ManagedAssembly { inner: wrap(self.inner.as_const() as *mut _Assembly, CALL!(IUnknown::AddRef)).expect("pointer is non-null by construction") }
+    }
+}
+
+COM_WRAPPER!(ManagedAssembly);
+
+pub struct ManagedType {
+    inner: PtrCtr<_Type>,
+}
+
+impl ManagedType {
+    pub fn invoke_static(&self, method: &str, args: &[ClrValue]) -> Result<ClrValue, InvokeError> {
+        self.invoke_member(method, BINDING_STATIC | BINDING_PUBLIC | BINDING_INVOKE_METHOD, variant::empty(), args)
+            .map_err(InvokeError::capture)
+    }
+
+    pub fn create_instance(self, args: &[ClrValue]) -> Result<ManagedObject, HostingError> {
+        let flags = BINDING_CREATE_INSTANCE | BINDING_INSTANCE | BINDING_PUBLIC;
+        match self.invoke_member("", flags, variant::empty(), args)? {
+            ClrValue::Object(object) => Ok(ManagedObject { object, ty: self }), 
+            _ => Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(_Type::InvokeMember_3))),
+        }
+    }
+
+    //Namespace-qualified, e.g. "System.String"
+    pub fn full_name(&self) -> Result<String, HostingError> {
+        match ManagedObject::from_value(self.to_value())?.property("FullName")? {
+            ClrValue::String(name) => Ok(name), 
+            _ => Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(_Type::InvokeMember_3))),
+        }
+    }
+
+    //The System.Type itself, e.g. as an argument to a managed method
+    pub fn to_value(&self) -> ClrValue {
+        let object = ClrObject::from_borrowed(self.inner.as_const() as *mut IUnknown)
+            .expect("pointer is non-null by construction");
+        ClrValue::Object(object)
+    }
+
+    fn invoke_member(&self, name: &str, flags: u32, target: VARIANT, args: &[ClrValue]) -> Result<ClrValue, HostingError> {
+        self.invoke_member_variant(name, flags, target, args).and_then(ClrValue::from_owned_variant)
+    }
+
+    //For results ClrValue has no variant for; the caller clears the VARIANT
+    fn invoke_member_variant(&self, name: &str, flags: u32, target: VARIANT, args: &[ClrValue]) -> Result<VARIANT, HostingError> {
+        self.invoke_member_array(name, flags, target, &ClrValue::to_safearray(args)?)
+    }
+
+    fn invoke_member_array(&self, name: &str, flags: u32, target: VARIANT, args: &SafeArrayPtr) -> Result<VARIANT, HostingError> {
+        let name = BString::from(name);
+        let mut ret = variant::empty();
+        CHECK_HR!(_Type::InvokeMember_3, (*self.inner.as_const()).InvokeMember_3(
+            name.as_sys(), 
+            flags as _, 
+            ptr::null_mut(), 
+            target, 
+            args.as_ptr(), 
+            &mut ret
+        ))?;
+        Ok(ret)
+    }
+}
+
+COM_WRAPPER!(ManagedType);
+
+//A failed member call, along with the exception managed code threw if 
+// that is what failed it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvokeError {
+    pub error: HostingError, 
+    pub exception: Option<ManagedException>,
+}
+
+impl InvokeError {
+    //Picks up the exception the failed call left behind, if there is one
+    pub fn capture(error: HostingError) -> InvokeError {
+        InvokeError { error, exception: ManagedException::capture() }
+    }
+}
+
+impl From<HostingError> for InvokeError {
+    fn from(error: HostingError) -> InvokeError {
+        InvokeError { error, exception: None }
+    }
+}
+
+impl From<InvokeError> for HostingError {
+    fn from(err: InvokeError) -> HostingError {
+        err.error
+    }
+}
+
+impl fmt::Display for InvokeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.exception {
+            Some(ref exception) => write!(f, "{}: {}", self.error, exception), 
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl Error for InvokeError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match self.exception {
+            Some(ref exception) => Some(exception), 
+            None => Some(&self.error),
+        }
+    }
+}
+
+//An instance living in managed code, paired with its runtime type
+pub struct ManagedObject {
+    object: ClrObject, 
+    ty: ManagedType,
+}
+
+impl ManagedObject {
+    //Resolves the object's type through _Object::GetType
+    pub fn from_value(value: ClrValue) -> Result<ManagedObject, HostingError> {
+        let object = value.into_object()
+            .ok_or_else(|| HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(_Object::GetType)))?;
+        let as_object = query::<_Object>(object.as_unknown())?;
+        let mut ty: *mut _Type = ptr::null_mut();
+        let hr = CHECK_HR!(_Object::GetType, (*as_object.as_const()).GetType(&mut ty));
+        unsafe { (*as_object.as_const()).Release() };
+        hr?;
+        wrap(ty, CALL!(_Object::GetType)).map(|inner| ManagedObject { object, ty: ManagedType { inner } })
+    }
+
+    pub(crate) fn as_unknown(&self) -> *mut IUnknown {
+        self.object.as_unknown()
+    }
+
+    pub fn managed_type(&self) -> &ManagedType {
+        &self.ty
+    }
+
+    pub fn to_value(&self) -> ClrValue {
+        ClrValue::Object(self.object.clone())
+    }
+
+    pub fn invoke(&self, method: &str, args: &[ClrValue]) -> Result<ClrValue, InvokeError> {
+        let flags = BINDING_INSTANCE | BINDING_PUBLIC | BINDING_INVOKE_METHOD;
+        self.ty.invoke_member(method, flags, variant::borrowed_object(&self.object), args)
+            .map_err(InvokeError::capture)
+    }
+
+    pub fn get_property(&self, name: &str) -> Result<ClrValue, InvokeError> {
+        self.property(name).map_err(InvokeError::capture)
+    }
+
+    //get_property without picking up a thrown exception, for reading the 
+    // exception objects themselves
+    pub(crate) fn property(&self, name: &str) -> Result<ClrValue, HostingError> {
+        let flags = BINDING_INSTANCE | BINDING_PUBLIC | BINDING_GET_PROPERTY;
+        self.ty.invoke_member(name, flags, variant::borrowed_object(&self.object), &[])
+    }
+
+    pub fn set_property(&self, name: &str, value: ClrValue) -> Result<(), HostingError> {
+        let flags = BINDING_INSTANCE | BINDING_PUBLIC | BINDING_SET_PROPERTY;
+        self.ty.invoke_member(name, flags, variant::borrowed_object(&self.object), &[value]).map(|_| ())
+    }
+
+    //For string properties holding a path, which may not be valid Unicode
+    pub fn set_path_property<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<(), HostingError> {
+        let flags = BINDING_INSTANCE | BINDING_PUBLIC | BINDING_SET_PROPERTY;
+        let args = variant::path_args(path.as_ref())?;
+        let mut ret = self.ty.invoke_member_array(name, flags, variant::borrowed_object(&self.object), &args)?;
+        unsafe { VariantClear(&mut ret) };
+        Ok(())
+    }
+
+    //The same object driven through IDispatch instead of InvokeMember
+    pub fn dynamic(&self) -> Result<DynamicObject, HostingError> {
+        DynamicObject::from_value(&self.to_value())
+    }
+
+    pub fn into_handle(self) -> ManagedHandle {
+        ManagedHandle { object: self }
+    }
+}
+
+//A strong reference to a managed object. Holding the CCW keeps the 
+// object reachable for the GC, much like a normal GCHandle; dropping the 
+// handle releases it.
+pub struct ManagedHandle {
+    object: ManagedObject,
+}
+
+impl ManagedHandle {
+    //Gives up ownership without releasing, so the object lives until the 
+    // process ends or whoever ends up with the pointer releases it
+    pub fn leak(self) -> *mut IUnknown {
+        self.object.object.into_raw()
+    }
+
+    //A System.WeakReference to the object, created in domain. The weak 
+    // handle keeps only the WeakReference alive, not the target.
+    pub fn downgrade(&self, domain: &AppDomain) -> Result<WeakManagedHandle, HostingError> {
+        let reference = domain.load("mscorlib")?
+            .get_type("System.WeakReference")?
+            .create_instance(&[self.object.to_value()])?;
+        Ok(WeakManagedHandle { reference })
+    }
+}
+
+impl Deref for ManagedHandle {
+    type Target = ManagedObject;
+
+    fn deref(&self) -> &ManagedObject {
+        &self.object
+    }
+}
+
+impl From<ManagedObject> for ManagedHandle {
+    fn from(object: ManagedObject) -> ManagedHandle {
+        object.into_handle()
+    }
+}
+
+pub struct WeakManagedHandle {
+    reference: ManagedObject,
+}
+
+impl WeakManagedHandle {
+    //None once the target has been collected
+    pub fn upgrade(&self) -> Result<Option<ManagedHandle>, HostingError> {
+        match self.reference.property("Target")? {
+            ClrValue::Null => Ok(None), 
+            target => ManagedObject::from_value(target).map(|object| Some(object.into_handle())),
+        }
+    }
+
+    //Only a hint: the target can be collected right after this returns true
+    pub fn is_alive(&self) -> Result<bool, HostingError> {
+        match self.reference.property("IsAlive")? {
+            ClrValue::Bool(alive) => Ok(alive), 
+            _ => Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(_Type::InvokeMember_3))),
+        }
+    }
+}
+
+//A native-callable pointer into the domain. The delegate object is held 
+// alongside it because the marshaling thunk behind the pointer lives only 
+// as long as the delegate does; don't keep copies of get() past this.
+pub struct NativeDelegate<F: Copy> {
+    function: F, 
+    _delegate: ClrObject,
+}
+
+impl<F: Copy> NativeDelegate<F> {
+    pub fn get(&self) -> F {
+        self.function
+    }
+}
+
+//AddRefs through QueryInterface; the borrowed pointer is left untouched
+fn query<T: Interface>(unk: *mut IUnknown) -> Result<PtrCtr<T>, HostingError> {
+    let mut p: *mut T = ptr::null_mut();
+    CHECK_HR!(IUnknown::QueryInterface, (*unk).QueryInterface(&T::uuidof(), &mut p as *mut *mut T as *mut *mut c_void))?;
+    wrap(p, CALL!(IUnknown::QueryInterface))
+}
+
+fn wrap<T>(p: *mut T, call: Call) -> Result<PtrCtr<T>, HostingError> {
+    PtrCtr::new_checked(p).map_err(|_| HostingError::null_pointer(call))
+}
+
+fn io_hresult(err: &io::Error) -> HRESULT {
+    match err.raw_os_error() {
+        Some(code) => HRESULT_FROM_WIN32(code as u32), 
+        None => E_FAIL,
+    }
+}
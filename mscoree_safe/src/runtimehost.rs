@@ -0,0 +1,264 @@
+// runtimehost.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::thread;
+
+use winapi::ctypes::{c_int, c_void};
+use winapi::shared::guiddef::{REFCLSID, REFIID};
+use winapi::shared::minwindef::{DWORD, FALSE, LPVOID, TRUE};
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+
+use mscoree_sys::corerror::{COR_E_APPDOMAINUNLOADED, COR_E_CANNOTUNLOADAPPDOMAIN, HOST_E_TIMEOUT};
+use mscoree_sys::mscoree::{CLSID_CLRRuntimeHost, ICLRControl, ICLRRuntimeHost, ICLRRuntimeHost4, IID_ICLRRuntimeHost};
+
+use buffer::wide;
+use comptr::ComPtr;
+use control::ClrControl;
+use error::HostingError;
+#[cfg(feature = "host-managers")]
+use managers::HostControl;
+use metahost::{HostInterface, QueryInterface, RuntimeInfo};
+use wrappers::PtrCtr;
+
+//The default domain always has id 1 and can never be unloaded
+pub const DEFAULT_APP_DOMAIN_ID: DWORD = 1;
+
+//How far unload_app_domain got
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Unload {
+    //The domain is gone, with its latched exit code when UnloadAppDomain2 
+    // was available
+    Done(Option<i32>), 
+    //Not waited for: the unload was started and finishes in the background
+    InProgress,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UnloadError {
+    DefaultDomain, 
+    //The domain was already gone by the time the unload was asked for
+    AlreadyUnloaded, 
+    Timeout, 
+    Failed(HostingError),
+}
+
+impl From<HostingError> for UnloadError {
+    fn from(err: HostingError) -> UnloadError {
+        match err.hresult() {
+            COR_E_APPDOMAINUNLOADED => UnloadError::AlreadyUnloaded, 
+            COR_E_CANNOTUNLOADAPPDOMAIN => UnloadError::DefaultDomain, 
+            HOST_E_TIMEOUT => UnloadError::Timeout, 
+            _ => UnloadError::Failed(err),
+        }
+    }
+}
+
+//Safe wrapper over ICLRRuntimeHost, the v2+ hosting interface
+pub struct ClrRuntimeHost {
+    inner: PtrCtr<ICLRRuntimeHost>,
+}
+
+impl HostInterface for ClrRuntimeHost {
+    fn clsid() -> REFCLSID {
+        &CLSID_CLRRuntimeHost
+    }
+
+    fn iid() -> REFIID {
+        &IID_ICLRRuntimeHost
+    }
+
+    unsafe fn from_interface(p: LPVOID) -> ClrRuntimeHost {
+        let inner = PtrCtr::new_checked(p as *mut ICLRRuntimeHost)
+            .expect("GetInterface pointers are never null");
+        ClrRuntimeHost { inner }
+    }
+}
+
+impl ClrRuntimeHost {
+    pub fn new(runtime: &dyn RuntimeInfo) -> Result<ClrRuntimeHost, HostingError> {
+        runtime.query::<ClrRuntimeHost>()
+    }
+
+    pub fn start(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeHost::Start, (*self.inner.as_const()).Start()).map(|_| ())
+    }
+
+    pub fn stop(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRRuntimeHost::Stop, (*self.inner.as_const()).Stop()).map(|_| ())
+    }
+
+    //Must be called before start; the runtime asks the host control for 
+    // its managers during startup and holds on to them from then on
+    #[cfg(feature = "host-managers")]
+    pub fn set_host_control(&self, control: HostControl) -> Result<(), HostingError> {
+        let raw = control.into_raw();
+        let hr = CHECK_HR!(ICLRRuntimeHost::SetHostControl, (*self.inner.as_const()).SetHostControl(raw));
+        unsafe { (*raw).Release() };
+        hr.map(|_| ())
+    }
+
+    pub fn control(&self) -> Result<ClrControl, HostingError> {
+        let mut control: *mut ICLRControl = ptr::null_mut();
+        CHECK_HR!(ICLRRuntimeHost::GetCLRControl, (*self.inner.as_const()).GetCLRControl(&mut control))?;
+        PtrCtr::new_checked(control)
+            .map(ClrControl::new_from)
+            .map_err(|_| HostingError::null_pointer(CALL!(ICLRRuntimeHost::GetCLRControl)))
+    }
+
+    //Runs the closure inside the context of the given app domain. 
+    // A panic in the closure is caught before it reaches the CLR's frames 
+    // and resumed here once ExecuteInAppDomain has returned.
+    pub fn execute_in_app_domain<F, R>(&self, domain_id: DWORD, f: F) -> Result<R, HostingError> 
+        where F: FnOnce() -> R
+    {
+        let mut cookie: Cookie<F, R> = Cookie { f: Some(f), result: None };
+        let hr = CHECK_HR!(ICLRRuntimeHost::ExecuteInAppDomain, (*self.inner.as_const()).ExecuteInAppDomain(
+            domain_id, 
+            trampoline::<F, R>, 
+            &mut cookie as *mut Cookie<F, R> as *mut c_void
+        ));
+        match cookie.result.take() {
+            Some(Err(payload)) => panic::resume_unwind(payload), 
+            Some(Ok(value)) => hr.map(|_| value), 
+            None => hr.and_then(|_| Err(HostingError::from_hresult(E_FAIL, CALL!(ICLRRuntimeHost::ExecuteInAppDomain)))),
+        }
+    }
+
+    //Unloads the given domain. When the host also exposes ICLRRuntimeHost4 
+    // UnloadAppDomain2 is used instead, and the domain's latched exit code 
+    // is returned once the unload has been waited for.
+    pub fn unload_app_domain(&self, domain_id: DWORD, wait_until_done: bool) -> Result<Unload, UnloadError> {
+        if domain_id == DEFAULT_APP_DOMAIN_ID {
+            return Err(UnloadError::DefaultDomain);
+        }
+        let wait = if wait_until_done { TRUE } else { FALSE };
+        let host = unsafe { ComPtr::from_borrowed(self.inner.as_const() as *mut ICLRRuntimeHost) }
+            .expect("pointer is non-null by construction");
+        let exit_code = match host.query_interface::<ICLRRuntimeHost4>() {
+            Ok(host4) => {
+                let mut exit_code: i32 = 0;
+                CHECK_HR!(ICLRRuntimeHost4::UnloadAppDomain2, host4.UnloadAppDomain2(domain_id, wait, &mut exit_code))?;
+                Some(exit_code)
+            }, 
+            Err(_) => {
+                CHECK_HR!(ICLRRuntimeHost::UnloadAppDomain, host.UnloadAppDomain(domain_id, wait))?;
+                None
+            },
+        };
+        Ok(if wait_until_done { Unload::Done(exit_code) } else { Unload::InProgress })
+    }
+
+    //Calls a `static int Method(string)` in the default domain, loading the 
+    // assembly from assembly_path first, and returns what it returned
+    pub fn execute_in_default_app_domain<P: AsRef<Path>>(&self, assembly_path: P, type_name: &str, method_name: &str, argument: &str) -> Result<DWORD, HostingError> {
+        let (path, ty, method, arg) = (wide(assembly_path.as_ref()), wide(type_name), wide(method_name), wide(argument));
+        let mut ret: DWORD = 0;
+        CHECK_HR!(ICLRRuntimeHost::ExecuteInDefaultAppDomain, (*self.inner.as_const()).ExecuteInDefaultAppDomain(
+            path.as_ptr(), 
+            ty.as_ptr(), 
+            method.as_ptr(), 
+            arg.as_ptr(), 
+            &mut ret
+        ))?;
+        Ok(ret)
+    }
+
+    //Activates a manifest-based (ClickOnce) application by its full name, 
+    // returning the application's exit code.
+    pub fn execute_application<P: AsRef<Path>>(&self, app_full_name: &str, manifest_paths: &[P], activation_data: &[&str]) -> Result<i32, HostingError> {
+        let name = wide(app_full_name);
+        let manifests: Vec<Vec<u16>> = manifest_paths.iter().map(|p| wide(p.as_ref())).collect();
+        let data: Vec<Vec<u16>> = activation_data.iter().map(|d| wide(*d)).collect();
+        let mut manifest_ptrs: Vec<LPCWSTR> = manifests.iter().map(|w| w.as_ptr()).collect();
+        let mut data_ptrs: Vec<LPCWSTR> = data.iter().map(|w| w.as_ptr()).collect();
+
+        let mut ret: c_int = 0;
+        CHECK_HR!(ICLRRuntimeHost::ExecuteApplication, (*self.inner.as_const()).ExecuteApplication(
+            name.as_ptr(), 
+            manifest_ptrs.len() as u32, 
+            if manifest_ptrs.is_empty() { ptr::null_mut() } else { manifest_ptrs.as_mut_ptr() }, 
+            data_ptrs.len() as u32, 
+            if data_ptrs.is_empty() { ptr::null_mut() } else { data_ptrs.as_mut_ptr() }, 
+            &mut ret
+        ))?;
+        Ok(ret)
+    }
+}
+
+//State handed to the CLR as the callback cookie
+struct Cookie<F, R> {
+    f: Option<F>, 
+    result: Option<thread::Result<R>>,
+}
+
+extern fn trampoline<F, R>(cookie: *mut c_void) -> HRESULT 
+    where F: FnOnce() -> R
+{
+    let cookie = unsafe { &mut *(cookie as *mut Cookie<F, R>) };
+    let f = match cookie.f.take() {
+        Some(f) => f, 
+        None => return E_FAIL,
+    };
+    //Not catch_and_translate: the payload is kept so execute_in_app_domain 
+    // can resume the panic on the caller's side of the CLR frames
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let hr = if result.is_ok() { S_OK } else { E_FAIL };
+    cookie.result = Some(result);
+    hr
+}
+
+COM_WRAPPER!(ClrRuntimeHost, ICLRRuntimeHost);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trampoline_runs_closure_once() {
+        let mut cookie = Cookie { f: Some(|| 42), result: None };
+        let raw = &mut cookie as *mut _ as *mut c_void;
+        assert_eq!(trampoline::<_, i32>(raw), S_OK);
+        assert_eq!(trampoline::<_, i32>(raw), E_FAIL);
+        assert_eq!(cookie.result.take().unwrap().unwrap(), 42);
+    }
+
+    #[test]
+    fn unload_error_from_codes() {
+        let call = CALL!(ICLRRuntimeHost::UnloadAppDomain);
+        assert_eq!(UnloadError::from(HostingError::from_hresult(COR_E_APPDOMAINUNLOADED, call)), UnloadError::AlreadyUnloaded);
+        assert_eq!(UnloadError::from(HostingError::from_hresult(HOST_E_TIMEOUT, call)), UnloadError::Timeout);
+        let err = HostingError::from_hresult(E_FAIL, call);
+        assert_eq!(UnloadError::from(err), UnloadError::Failed(err));
+    }
+
+    #[test]
+    fn trampoline_catches_panic() {
+        let mut cookie: Cookie<_, ()> = Cookie { f: Some(|| panic!("boom")), result: None };
+        let raw = &mut cookie as *mut _ as *mut c_void;
+        assert_eq!(trampoline::<_, ()>(raw), E_FAIL);
+        assert!(cookie.result.take().unwrap().is_err());
+    }
+}
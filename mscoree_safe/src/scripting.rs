@@ -0,0 +1,289 @@
+// scripting.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//In-process C# compilation through CodeDom. Drives 
+// Microsoft.CSharp.CSharpCodeProvider in whichever domain it is given 
+// (the default one, or a sandbox domain the caller created), compiling 
+// source strings into in-memory assemblies. ScriptSession builds on it to 
+// run code inside a dedicated, recyclable domain.
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use corhost::CorRuntimeHost;
+use error::HostingError;
+use reflection::{AppDomain, InvokeError, ManagedAssembly, ManagedObject};
+use variant::ClrValue;
+
+const SYSTEM_ASSEMBLY: &str = "System, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089";
+const PROVIDER_TYPE: &str = "Microsoft.CSharp.CSharpCodeProvider";
+const PARAMETERS_TYPE: &str = "System.CodeDom.Compiler.CompilerParameters";
+
+#[derive(Clone, Debug)]
+pub struct CompileDiagnostic {
+    pub line: i32, 
+    pub column: i32, 
+    pub number: String, 
+    pub message: String, 
+    pub is_warning: bool,
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Com(HostingError), 
+    //A CodeDom call failed, possibly with the exception it threw
+    Invoke(InvokeError), 
+    Compilation(Vec<CompileDiagnostic>), 
+    Io(io::Error),
+}
+
+impl From<HostingError> for ScriptError {
+    fn from(err: HostingError) -> ScriptError {
+        ScriptError::Com(err)
+    }
+}
+
+impl From<InvokeError> for ScriptError {
+    fn from(err: InvokeError) -> ScriptError {
+        ScriptError::Invoke(err)
+    }
+}
+
+pub struct ScriptCompiler {
+    system: ManagedAssembly, 
+    provider: ManagedObject, 
+    references: Vec<String>,
+}
+
+impl ScriptCompiler {
+    pub fn new(domain: &AppDomain) -> Result<ScriptCompiler, ScriptError> {
+        let system = domain.load(SYSTEM_ASSEMBLY)?;
+        let provider = system.create_instance(PROVIDER_TYPE)?;
+        Ok(ScriptCompiler {
+            system, 
+            provider, 
+            references: vec![String::from("System.dll")],
+        })
+    }
+
+    //Assembly file names or paths passed to the compiler as /reference
+    pub fn reference(mut self, assembly: &str) -> ScriptCompiler {
+        self.references.push(assembly.to_string());
+        self
+    }
+
+    pub fn compile(&self, source: &str) -> Result<ManagedAssembly, ScriptError> {
+        let results = self.run(&[source], |parameters| {
+            parameters.set_property("GenerateInMemory", ClrValue::Bool(true))?;
+            parameters.set_property("GenerateExecutable", ClrValue::Bool(false))
+        })?;
+        Ok(ManagedAssembly::from_value(&results.get_property("CompiledAssembly")?)?)
+    }
+
+    //Writes an assembly to disk without loading it anywhere, so it can be 
+    // executed in another domain
+    pub fn compile_to_file(&self, sources: &[&str], output: &Path, executable: bool) -> Result<(), ScriptError> {
+        self.run(sources, |parameters| {
+            parameters.set_property("GenerateInMemory", ClrValue::Bool(false))?;
+            parameters.set_property("GenerateExecutable", ClrValue::Bool(executable))?;
+            parameters.set_path_property("OutputAssembly", output)
+        }).map(|_| ())
+    }
+
+    fn run<F>(&self, sources: &[&str], configure: F) -> Result<ManagedObject, ScriptError> 
+        where F: FnOnce(&ManagedObject) -> Result<(), HostingError>
+    {
+        let parameters = self.system.create_instance(PARAMETERS_TYPE)?;
+        configure(&parameters)?;
+        let references = ManagedObject::from_value(parameters.get_property("ReferencedAssemblies")?)?;
+        for reference in &self.references {
+            references.invoke("Add", &[ClrValue::from(reference.as_str())])?;
+        }
+
+        //The sources parameter is `params string[]`, which the default binder expands
+        let mut args = vec![parameters.to_value()];
+        args.extend(sources.iter().map(|source| ClrValue::from(*source)));
+        let results = ManagedObject::from_value(self.provider.invoke("CompileAssemblyFromSource", &args)?)?;
+        let errors = ManagedObject::from_value(results.get_property("Errors")?)?;
+        if errors.get_property("HasErrors")?.as_bool() != Some(false) {
+            return Err(ScriptError::Compilation(diagnostics(&errors)?));
+        }
+        Ok(results)
+    }
+
+    //Compiles the source and instantiates the named entry type from it
+    pub fn compile_entry(&self, source: &str, entry_type: &str) -> Result<ManagedObject, ScriptError> {
+        self.compile(source)?.create_instance(entry_type).map_err(ScriptError::Com)
+    }
+}
+
+//Domain data slot the generated entry points write their result to
+const RESULT_SLOT: &str = "__script_result";
+
+//Shared by every generated program; Global() reads session globals
+const PRELUDE: &str = "using System;\n\
+static class __ScriptHost {\n\
+    public static object Global(string name) { return AppDomain.CurrentDomain.GetData(name); }\n\
+    public static void Return(object value) { AppDomain.CurrentDomain.SetData(\"__script_result\", value); }\n\
+}\n";
+
+static SESSION_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+//A REPL-like scripting context backed by its own AppDomain. Code is compiled 
+// to disk by a compiler living in the default domain, then executed inside 
+// the session domain, so everything it loads goes away on recycle().
+pub struct ScriptSession<'h> {
+    host: &'h CorRuntimeHost, 
+    name: String, 
+    domain: Option<AppDomain>, 
+    compiler: ScriptCompiler, 
+    //Plain values only; object proxies can't outlive their domain
+    globals: Vec<(String, ClrValue)>, 
+    work_dir: PathBuf, 
+    programs: usize,
+}
+
+impl<'h> ScriptSession<'h> {
+    pub fn new(host: &'h CorRuntimeHost, name: &str) -> Result<ScriptSession<'h>, ScriptError> {
+        let compiler = ScriptCompiler::new(&host.default_domain()?)?;
+        let id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let work_dir = env::temp_dir().join(format!("mscoree_script_{}_{}", process::id(), id));
+        fs::create_dir_all(&work_dir).map_err(ScriptError::Io)?;
+        let domain = host.create_domain(name)?;
+        Ok(ScriptSession {
+            host, 
+            name: name.to_string(), 
+            domain: Some(domain), 
+            compiler, 
+            globals: Vec::new(), 
+            work_dir, 
+            programs: 0,
+        })
+    }
+
+    pub fn domain(&self) -> &AppDomain {
+        self.domain.as_ref().expect("session domain is only absent while dropping")
+    }
+
+    //Visible to scripts as __ScriptHost.Global(name). Plain values are 
+    // captured and reapplied after a recycle; objects are not.
+    pub fn set_global(&mut self, name: &str, value: ClrValue) -> Result<(), ScriptError> {
+        self.domain().set_data(name, &value)?;
+        self.globals.retain(|&(ref n, _)| n != name);
+        match value {
+            ClrValue::Object(_) => {}, 
+            value => self.globals.push((name.to_string(), value)),
+        }
+        Ok(())
+    }
+
+    pub fn global(&self, name: &str) -> Result<ClrValue, ScriptError> {
+        Ok(self.domain().get_data(name)?)
+    }
+
+    //Evaluates a C# expression inside the session domain
+    pub fn eval(&mut self, expression: &str) -> Result<ClrValue, ScriptError> {
+        let program = format!(
+            "static class __ScriptEval {{ static void Main() {{ __ScriptHost.Return((object)({})); }} }}\n", 
+            expression
+        );
+        self.execute(&[&program])
+    }
+
+    //Compiles the given source and calls a public static, parameterless 
+    // method on it, e.g. invoke(src, "Plugin.Entry.Run")
+    pub fn invoke(&mut self, source: &str, method: &str) -> Result<ClrValue, ScriptError> {
+        let program = format!(
+            "static class __ScriptInvoke {{ static void Main() {{ __ScriptHost.Return((object){}()); }} }}\n", 
+            method
+        );
+        self.execute(&[source, &program])
+    }
+
+    //Unloads the domain, with everything compiled into it, and starts over 
+    // with the captured globals. The new domain is ready before the old one 
+    // goes, so a failure leaves the session with a usable domain either way.
+    pub fn recycle(&mut self) -> Result<(), ScriptError> {
+        let domain = self.host.create_domain(&self.name)?;
+        for &(ref name, ref value) in &self.globals {
+            if let Err(err) = domain.set_data(name, value) {
+                let _ = self.host.unload_domain(domain);
+                return Err(err.into());
+            }
+        }
+        if let Some(old) = mem::replace(&mut self.domain, Some(domain)) {
+            self.host.unload_domain(old)?;
+        }
+        self.clear_programs();
+        Ok(())
+    }
+
+    fn execute(&mut self, sources: &[&str]) -> Result<ClrValue, ScriptError> {
+        self.programs += 1;
+        let output = self.work_dir.join(format!("script{}.exe", self.programs));
+        let mut all = vec![PRELUDE];
+        all.extend_from_slice(sources);
+        self.compiler.compile_to_file(&all, &output, true)?;
+        let domain = self.domain();
+        domain.set_data(RESULT_SLOT, &ClrValue::Null)?;
+        domain.execute_assembly(&output)?;
+        Ok(domain.get_data(RESULT_SLOT)?)
+    }
+
+    //Best effort: files still mapped by a live domain fail to delete
+    fn clear_programs(&mut self) {
+        if let Ok(entries) = fs::read_dir(&self.work_dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+impl<'h> Drop for ScriptSession<'h> {
+    fn drop(&mut self) {
+        if let Some(domain) = self.domain.take() {
+            let _ = self.host.unload_domain(domain);
+        }
+        let _ = fs::remove_dir_all(&self.work_dir);
+    }
+}
+
+fn diagnostics(errors: &ManagedObject) -> Result<Vec<CompileDiagnostic>, HostingError> {
+    let count = errors.get_property("Count")?.as_i32().unwrap_or(0);
+    let mut result = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let error = ManagedObject::from_value(errors.invoke("get_Item", &[ClrValue::I4(i)])?)?;
+        result.push(CompileDiagnostic {
+            line: error.get_property("Line")?.as_i32().unwrap_or(0), 
+            column: error.get_property("Column")?.as_i32().unwrap_or(0), 
+            number: error.get_property("ErrorNumber")?.as_str().unwrap_or("").to_string(), 
+            message: error.get_property("ErrorText")?.as_str().unwrap_or("").to_string(), 
+            is_warning: error.get_property("IsWarning")?.as_bool().unwrap_or(false),
+        });
+    }
+    Ok(result)
+}
@@ -0,0 +1,157 @@
+// sidebyside.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//In-process side-by-side loading. One process can hold a v4 runtime next to 
+// at most one pre-v4 runtime, and v1.x can't share the process with anything. 
+// 3.0 and 3.5 run on the 2.0 runtime, so a request for v3.0 means v2. The 
+// pre-v4 runtime is started first and bound as the legacy v2 runtime, so 
+// that anything still going through CorBindToRuntimeEx finds it rather 
+// than failing.
+use error::HostingError;
+use metahost::{MetaHost, RuntimeInfo, RuntimeVersion};
+use runtimehost::ClrRuntimeHost;
+
+#[derive(Debug)]
+pub enum SideBySideRejection {
+    //Asked for twice, or v3.0 next to v2
+    Duplicate, 
+    //Can't share the process with an earlier runtime in the request
+    ConflictsWith(RuntimeVersion), 
+    NotLoadable, 
+    Failed(HostingError),
+}
+
+#[derive(Debug, Default)]
+pub struct SideBySideReport {
+    //In the order they were started
+    pub started: Vec<RuntimeVersion>, 
+    pub rejected: Vec<(RuntimeVersion, SideBySideRejection)>,
+}
+
+impl SideBySideReport {
+    pub fn all_started(&self) -> bool {
+        self.rejected.is_empty()
+    }
+}
+
+pub(crate) fn load<M: MetaHost + ?Sized>(host: &M, versions: &[RuntimeVersion]) -> SideBySideReport {
+    let (order, rejected) = plan(versions);
+    let mut report = SideBySideReport { started: Vec::new(), rejected };
+    for version in order {
+        match start(host, &version) {
+            Ok(()) => report.started.push(version), 
+            Err(reason) => report.rejected.push((version, reason)),
+        }
+    }
+    report
+}
+
+fn start<M: MetaHost + ?Sized>(host: &M, version: &RuntimeVersion) -> Result<(), SideBySideRejection> {
+    let runtime = host.runtime(version.clone()).map_err(SideBySideRejection::Failed)?;
+    if runtime.started() {
+        return Ok(());
+    }
+    if !runtime.loadable() {
+        return Err(SideBySideRejection::NotLoadable);
+    }
+    //An existing legacy binding is left alone
+    if is_legacy(version) {
+        if let Ok(None) = host.legacy_v2_bound_runtime() {
+            runtime.bind_as_legacy_v2_runtime().map_err(SideBySideRejection::Failed)?;
+        }
+    }
+    let runtime_host = ClrRuntimeHost::new(&*runtime).map_err(SideBySideRejection::Failed)?;
+    runtime_host.start().map_err(SideBySideRejection::Failed)
+}
+
+//Start order plus whatever can be turned down without touching the runtime
+fn plan(versions: &[RuntimeVersion]) -> (Vec<RuntimeVersion>, Vec<(RuntimeVersion, SideBySideRejection)>) {
+    let mut legacy: Option<RuntimeVersion> = None;
+    let mut modern: Vec<RuntimeVersion> = Vec::new();
+    let mut rejected = Vec::new();
+    for requested in versions {
+        let version = if *requested == RuntimeVersion::V3 { RuntimeVersion::V2 } else { requested.clone() };
+        if legacy.as_ref() == Some(&version) || modern.contains(&version) {
+            rejected.push((requested.clone(), SideBySideRejection::Duplicate));
+        } else if let Some(conflict) = conflict(&version, legacy.as_ref(), &modern) {
+            rejected.push((requested.clone(), SideBySideRejection::ConflictsWith(conflict)));
+        } else if is_legacy(&version) {
+            legacy = Some(version);
+        } else {
+            modern.push(version);
+        }
+    }
+    let order = legacy.into_iter().chain(modern).collect();
+    (order, rejected)
+}
+
+fn conflict(version: &RuntimeVersion, legacy: Option<&RuntimeVersion>, modern: &[RuntimeVersion]) -> Option<RuntimeVersion> {
+    if is_v1(version) {
+        return legacy.cloned().or_else(|| modern.first().cloned());
+    }
+    if let Some(v1) = legacy.filter(|v| is_v1(v)) {
+        return Some(v1.clone());
+    }
+    if is_legacy(version) {
+        return legacy.cloned();
+    }
+    None
+}
+
+fn is_legacy(version: &RuntimeVersion) -> bool {
+    version.number().map_or(false, |n| n.major < 4)
+}
+
+fn is_v1(version: &RuntimeVersion) -> bool {
+    version.number().map_or(false, |n| n.major == 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn started(versions: &[RuntimeVersion]) -> Vec<RuntimeVersion> {
+        plan(versions).0
+    }
+
+    #[test]
+    fn legacy_runtime_goes_first() {
+        assert_eq!(started(&[RuntimeVersion::V4, RuntimeVersion::V3]), vec![RuntimeVersion::V2, RuntimeVersion::V4]);
+    }
+
+    #[test]
+    fn only_one_legacy_runtime() {
+        let (order, rejected) = plan(&[RuntimeVersion::V2, RuntimeVersion::V3, RuntimeVersion::V1_1, RuntimeVersion::V4]);
+        assert_eq!(order, vec![RuntimeVersion::V2, RuntimeVersion::V4]);
+        match rejected.as_slice() {
+            [(RuntimeVersion::V3, SideBySideRejection::Duplicate), (RuntimeVersion::V1_1, SideBySideRejection::ConflictsWith(RuntimeVersion::V2))] => (), 
+            other => panic!("unexpected rejections {:?}", other),
+        }
+    }
+
+    #[test]
+    fn v1_stands_alone() {
+        let (order, rejected) = plan(&[RuntimeVersion::V1_1, RuntimeVersion::V4]);
+        assert_eq!(order, vec![RuntimeVersion::V1_1]);
+        assert_eq!(rejected.len(), 1);
+    }
+}
@@ -0,0 +1,219 @@
+// tasks.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRTaskManager/ICLRTask: the runtime's view of the logical tasks running 
+// on host threads. Abort and RudeAbort are only meaningful while the task 
+// is switched in.
+use std::mem;
+use std::ptr;
+
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::ntdef::{HANDLE, LCID};
+
+use mscoree_sys::gchost::COR_GC_THREAD_STATS;
+use mscoree_sys::mscoree::*;
+
+use control::ClrControl;
+use error::{Call, HostingError};
+use wrappers::{PtrCtr, RefCounted};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TaskType {
+    DebuggerHelper, 
+    Gc, 
+    Finalizer, 
+    ThreadPoolTimer, 
+    ThreadPoolGate, 
+    ThreadPoolWorker, 
+    ThreadPoolIoCompletion, 
+    AppDomainUnload, 
+    User, 
+    ThreadPoolWait, 
+    Unknown,
+}
+
+impl TaskType {
+    pub(crate) fn from_raw(raw: ETaskType) -> TaskType {
+        match raw {
+            TT_DEBUGGERHELPER => TaskType::DebuggerHelper, 
+            TT_GC => TaskType::Gc, 
+            TT_FINALIZER => TaskType::Finalizer, 
+            TT_THREADPOOL_TIMER => TaskType::ThreadPoolTimer, 
+            TT_THREADPOOL_GATE => TaskType::ThreadPoolGate, 
+            TT_THREADPOOL_WORKER => TaskType::ThreadPoolWorker, 
+            TT_THREADPOOL_IOCOMPLETION => TaskType::ThreadPoolIoCompletion, 
+            TT_ADUNLOAD => TaskType::AppDomainUnload, 
+            TT_USER => TaskType::User, 
+            TT_THREADPOOL_WAIT => TaskType::ThreadPoolWait, 
+            _ => TaskType::Unknown,
+        }
+    }
+}
+
+pub struct TaskManager {
+    inner: PtrCtr<ICLRTaskManager>,
+}
+
+unsafe impl Send for TaskManager {}
+unsafe impl Sync for TaskManager {}
+
+impl TaskManager {
+    pub fn new(control: &ClrControl) -> Result<TaskManager, HostingError> {
+        control.manager::<ICLRTaskManager>().map(|inner| TaskManager { inner })
+    }
+
+    //Takes its own reference to a manager the runtime handed us
+    pub(crate) fn from_borrowed(p: *mut ICLRTaskManager) -> Result<TaskManager, HostingError> {
+        let manager = PtrCtr::new_checked(p).map(|inner| TaskManager { inner })
+            .map_err(|_| HostingError::null_pointer(CALL!(IHostTaskManager::SetCLRTaskManager)))?;
+        manager.increment();
+        Ok(manager)
+    }
+
+    //Only valid when the host provides IHostTaskManager
+    pub fn create_task(&self) -> Result<ClrTask, HostingError> {
+        let mut p: *mut ICLRTask = ptr::null_mut();
+        CHECK_HR!(ICLRTaskManager::CreateTask, (*self.inner.as_const()).CreateTask(&mut p))?;
+        ClrTask::from_raw(p, CALL!(ICLRTaskManager::CreateTask))
+    }
+
+    //None when the calling thread has no runtime task yet
+    pub fn current_task(&self) -> Result<Option<ClrTask>, HostingError> {
+        let mut p: *mut ICLRTask = ptr::null_mut();
+        CHECK_HR!(ICLRTaskManager::GetCurrentTask, (*self.inner.as_const()).GetCurrentTask(&mut p))?;
+        if p.is_null() {
+            return Ok(None);
+        }
+        ClrTask::from_raw(p, CALL!(ICLRTaskManager::GetCurrentTask)).map(Some)
+    }
+
+    pub fn current_task_type(&self) -> Result<TaskType, HostingError> {
+        let mut raw: ETaskType = TT_UNKNOWN;
+        CHECK_HR!(ICLRTaskManager::GetCurrentTaskType, (*self.inner.as_const()).GetCurrentTaskType(&mut raw))?;
+        Ok(TaskType::from_raw(raw))
+    }
+
+    pub fn set_ui_locale(&self, lcid: LCID) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTaskManager::SetUILocale, (*self.inner.as_const()).SetUILocale(lcid)).map(|_| ())
+    }
+
+    pub fn set_locale(&self, lcid: LCID) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTaskManager::SetLocale, (*self.inner.as_const()).SetLocale(lcid)).map(|_| ())
+    }
+}
+
+COM_WRAPPER!(TaskManager);
+
+pub struct ClrTask {
+    inner: PtrCtr<ICLRTask>,
+}
+
+//Runtime tasks are free-threaded; the host moves them between threads
+unsafe impl Send for ClrTask {}
+unsafe impl Sync for ClrTask {}
+
+impl ClrTask {
+    //Takes ownership of an already AddRef'd task pointer
+    pub(crate) fn from_raw(p: *mut ICLRTask, call: Call) -> Result<ClrTask, HostingError> {
+        PtrCtr::new_checked(p).map(|inner| ClrTask { inner }).map_err(|_| HostingError::null_pointer(call))
+    }
+
+    pub(crate) fn from_borrowed(p: *mut ICLRTask) -> Result<ClrTask, HostingError> {
+        let task = ClrTask::from_raw(p, CALL!(IHostTask::SetCLRTask))?;
+        task.increment();
+        Ok(task)
+    }
+
+    pub(crate) fn as_raw(&self) -> *mut ICLRTask {
+        self.inner.as_const() as *mut ICLRTask
+    }
+
+    //Associates the task with the given OS thread handle
+    pub unsafe fn switch_in(&self, thread: HANDLE) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::SwitchIn, (*self.inner.as_const()).SwitchIn(thread)).map(|_| ())
+    }
+
+    pub fn switch_out(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::SwitchOut, (*self.inner.as_const()).SwitchOut()).map(|_| ())
+    }
+
+    pub fn abort(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::Abort, (*self.inner.as_const()).Abort()).map(|_| ())
+    }
+
+    //Skips finally blocks and finalizers; use when a graceful abort hangs
+    pub fn rude_abort(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::RudeAbort, (*self.inner.as_const()).RudeAbort()).map(|_| ())
+    }
+
+    //A full reset also clears the task's thread-local state
+    pub fn reset(&self, full: bool) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::Reset, (*self.inner.as_const()).Reset(full as BOOL)).map(|_| ())
+    }
+
+    pub fn exit(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::ExitTask, (*self.inner.as_const()).ExitTask()).map(|_| ())
+    }
+
+    pub fn yield_task(&self) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::YieldTask, (*self.inner.as_const()).YieldTask()).map(|_| ())
+    }
+
+    pub fn needs_priority_scheduling(&self) -> Result<bool, HostingError> {
+        let mut needs: BOOL = 0;
+        CHECK_HR!(ICLRTask::NeedsPriorityScheduling, (*self.inner.as_const()).NeedsPriorityScheduling(&mut needs))?;
+        Ok(needs != 0)
+    }
+
+    pub fn locks_held(&self) -> Result<usize, HostingError> {
+        let mut count: SIZE_T = 0;
+        CHECK_HR!(ICLRTask::LocksHeld, (*self.inner.as_const()).LocksHeld(&mut count))?;
+        Ok(count as usize)
+    }
+
+    pub fn set_identifier(&self, id: TASKID) -> Result<(), HostingError> {
+        CHECK_HR!(ICLRTask::SetTaskIdentifier, (*self.inner.as_const()).SetTaskIdentifier(id)).map(|_| ())
+    }
+
+    //Bytes this task has allocated; Flags is filled in by the runtime
+    pub fn mem_stats(&self) -> Result<COR_GC_THREAD_STATS, HostingError> {
+        let mut stats: COR_GC_THREAD_STATS = unsafe { mem::zeroed() };
+        CHECK_HR!(ICLRTask::GetMemStats, (*self.inner.as_const()).GetMemStats(&mut stats))?;
+        Ok(stats)
+    }
+}
+
+COM_WRAPPER!(ClrTask);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn task_type_from_raw() {
+        assert_eq!(TaskType::from_raw(TT_GC), TaskType::Gc);
+        assert_eq!(TaskType::from_raw(TT_THREADPOOL_WAIT), TaskType::ThreadPoolWait);
+        assert_eq!(TaskType::from_raw(TT_UNKNOWN), TaskType::Unknown);
+        assert_eq!(TaskType::from_raw(0x3), TaskType::Unknown);
+    }
+}
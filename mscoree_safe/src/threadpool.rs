@@ -0,0 +1,66 @@
+// threadpool.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICorThreadpool: size the runtime's thread pool to fit alongside the 
+// host's own scheduler. Reached through CorRuntimeHost::thread_pool.
+use winapi::shared::minwindef::DWORD;
+
+use mscoree_sys::mscoree::ICorThreadPool;
+
+use error::HostingError;
+use wrappers::PtrCtr;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ThreadCounts {
+    pub worker: DWORD, 
+    pub io_completion: DWORD,
+}
+
+pub struct ThreadPool {
+    inner: PtrCtr<ICorThreadPool>,
+}
+
+impl ThreadPool {
+    pub(crate) fn new_from(inner: PtrCtr<ICorThreadPool>) -> ThreadPool {
+        ThreadPool { inner }
+    }
+
+    pub fn max_threads(&self) -> Result<ThreadCounts, HostingError> {
+        let mut counts = ThreadCounts::default();
+        CHECK_HR!(ICorThreadPool::CorGetMaxThreads, (*self.inner.as_const()).CorGetMaxThreads(&mut counts.worker, &mut counts.io_completion))?;
+        Ok(counts)
+    }
+
+    //Fails if either count is below the number of processors
+    pub fn set_max_threads(&self, counts: ThreadCounts) -> Result<(), HostingError> {
+        CHECK_HR!(ICorThreadPool::CorSetMaxThreads, (*self.inner.as_const()).CorSetMaxThreads(counts.worker, counts.io_completion)).map(|_| ())
+    }
+
+    //The maximum minus the threads currently busy
+    pub fn available_threads(&self) -> Result<ThreadCounts, HostingError> {
+        let mut counts = ThreadCounts::default();
+        CHECK_HR!(ICorThreadPool::CorGetAvailableThreads, (*self.inner.as_const()).CorGetAvailableThreads(&mut counts.worker, &mut counts.io_completion))?;
+        Ok(counts)
+    }
+}
+
+COM_WRAPPER!(ThreadPool);
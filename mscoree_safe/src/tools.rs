@@ -0,0 +1,118 @@
+// tools.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Locates the framework tools that ship alongside a runtime, so automation 
+// invokes the csc/ngen/regasm that match the runtime it is going to host. 
+// GetRuntimeDirectory reflects the bitness of the calling process, so a 
+// 64-bit host gets Framework64 tools.
+use std::path::{Path, PathBuf};
+
+
+use error::HostingError;
+use metahost::RuntimeInfo;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameworkTool {
+    Csc, 
+    Ngen, 
+    RegAsm, 
+    InstallUtil,
+}
+
+impl FrameworkTool {
+    pub fn file_name(&self) -> &'static str {
+        match *self {
+            FrameworkTool::Csc => "csc.exe", 
+            FrameworkTool::Ngen => "ngen.exe", 
+            FrameworkTool::RegAsm => "RegAsm.exe", 
+            FrameworkTool::InstallUtil => "InstallUtil.exe",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameworkTools {
+    directory: PathBuf,
+}
+
+impl FrameworkTools {
+    pub fn new(runtime: &dyn RuntimeInfo) -> Result<FrameworkTools, HostingError> {
+        runtime.directory().map(FrameworkTools::from_directory)
+    }
+
+    pub fn from_directory<P: Into<PathBuf>>(directory: P) -> FrameworkTools {
+        FrameworkTools { directory: directory.into() }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn mscorlib(&self) -> Option<PathBuf> {
+        self.probe("mscorlib.dll")
+    }
+
+    //None when the tool isn't installed for this runtime (csc.exe is absent 
+    // from client-profile installs, for instance)
+    pub fn tool(&self, tool: FrameworkTool) -> Option<PathBuf> {
+        self.probe(tool.file_name())
+    }
+
+    pub fn csc(&self) -> Option<PathBuf> {
+        self.tool(FrameworkTool::Csc)
+    }
+
+    pub fn ngen(&self) -> Option<PathBuf> {
+        self.tool(FrameworkTool::Ngen)
+    }
+
+    pub fn regasm(&self) -> Option<PathBuf> {
+        self.tool(FrameworkTool::RegAsm)
+    }
+
+    pub fn installutil(&self) -> Option<PathBuf> {
+        self.tool(FrameworkTool::InstallUtil)
+    }
+
+    fn probe(&self, file_name: &str) -> Option<PathBuf> {
+        let path = self.directory.join(file_name);
+        if path.is_file() { Some(path) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn probes_existing_tools_only() {
+        let dir = env::temp_dir().join("mscoree_safe_tools_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("csc.exe"), b"").unwrap();
+        let tools = FrameworkTools::from_directory(dir.clone());
+        assert_eq!(tools.csc(), Some(dir.join("csc.exe")));
+        assert_eq!(tools.ngen(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
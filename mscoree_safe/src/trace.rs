@@ -0,0 +1,73 @@
+// trace.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Instrumentation for the COM calls made through the named CHECK_HR! form. 
+// With the `tracing` feature each call gets a span carrying the interface 
+// and method, plus an event with the HRESULT and how long the call took. 
+// Without the feature com_call is a plain pass-through.
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+use winapi::shared::winerror::HRESULT;
+
+use error::Call;
+
+#[cfg(feature = "tracing")]
+pub(crate) fn com_call<F>(call: Call, f: F) -> HRESULT 
+    where F: FnOnce() -> HRESULT
+{
+    let span = trace_span!("com_call", interface = call.interface, method = call.method);
+    let _entered = span.enter();
+    let start = Instant::now();
+    let hr = f();
+    let elapsed_us = start.elapsed().as_micros() as u64;
+    //Failure codes are routine for some queries (not loaded, not found), 
+    // so they go out at debug rather than warn
+    if hr < 0 {
+        debug!(hresult = %format_args!("0x{:08X}", hr as u32), elapsed_us, "{} failed", call);
+    } else {
+        trace!(hresult = %format_args!("0x{:08X}", hr as u32), elapsed_us, "{} returned", call);
+    }
+    hr
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn com_call<F>(_call: Call, f: F) -> HRESULT 
+    where F: FnOnce() -> HRESULT
+{
+    f()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use winapi::shared::winerror::{E_FAIL, S_FALSE};
+
+    const CALL: Call = Call { interface: "ICLRRuntimeInfo", method: "IsLoaded" };
+
+    #[test]
+    fn passes_hresult_through() {
+        assert_eq!(com_call(CALL, || S_FALSE), S_FALSE);
+        assert_eq!(com_call(CALL, || E_FAIL), E_FAIL);
+    }
+}
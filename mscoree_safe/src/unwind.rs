@@ -0,0 +1,94 @@
+// unwind.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//The boundary between vtables the CLR calls and the Rust code behind them. 
+// Unwinding into mscoree's frames is undefined behavior, so every callback 
+// body goes through catch_and_translate: errors become the HRESULT handed 
+// back, a panic becomes E_FAIL (logged first with the `tracing` feature).
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+
+pub(crate) fn catch_and_translate<F>(f: F) -> HRESULT 
+    where F: FnOnce() -> Result<(), HRESULT>
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => S_OK, 
+        Ok(Err(hr)) => hr, 
+        Err(payload) => {
+            report(&*payload);
+            E_FAIL
+        },
+    }
+}
+
+//For callbacks with nothing to report back, such as Release dropping host state
+pub(crate) fn catch_silently<F>(f: F) 
+    where F: FnOnce()
+{
+    let _ = catch_and_translate(|| {
+        f();
+        Ok(())
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "non-string panic payload"
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn report(payload: &(dyn Any + Send)) {
+    error!(panic = panic_message(payload), "host callback panicked");
+}
+
+#[cfg(not(feature = "tracing"))]
+fn report(payload: &(dyn Any + Send)) {
+    let _ = panic_message(payload);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use winapi::shared::winerror::E_POINTER;
+
+    #[test]
+    fn catch_and_translate_maps_results() {
+        assert_eq!(catch_and_translate(|| Ok(())), S_OK);
+        assert_eq!(catch_and_translate(|| Err(E_POINTER)), E_POINTER);
+        assert_eq!(catch_and_translate(|| panic!("host callback")), E_FAIL);
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload = panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "static");
+        let payload = panic::catch_unwind(|| panic!("{}", 42)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "42");
+    }
+}
@@ -0,0 +1,225 @@
+// validator.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//ICLRValidator: PEVerify-style checks of a PE image's metadata and IL 
+// against the runtime the host was created from. Each problem found is 
+// handed to a Rust callback through an IVEHandler object.
+use std::cell::{Cell, RefCell};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::ntdef::ULONG;
+use winapi::shared::winerror::{E_ABORT, E_FAIL, HRESULT, HRESULT_FROM_WIN32, S_OK};
+use winapi::um::oaidl::SAFEARRAY;
+use winapi::Interface;
+
+use mscoree_sys::ivalidator::{
+    ICLRValidator, 
+    VALIDATOR_CHECK_ILONLY, 
+    VALIDATOR_CHECK_PEFORMAT_ONLY, 
+    VALIDATOR_EXTRA_VERBOSE, 
+    VALIDATOR_NOCHECK_PEFORMAT, 
+    VALIDATOR_TRANSPARENT_ONLY
+};
+use mscoree_sys::ivehandler::{IVEHandler, IVEHandlerVtbl, VEContext};
+
+use buffer::wide;
+use com::ComBox;
+use error::HostingError;
+use runtimehost::{ClrRuntimeHost, DEFAULT_APP_DOMAIN_ID};
+use unwind::catch_and_translate;
+use wrappers::PtrCtr;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ValidationOptions {
+    //Skip metadata validation and only verify IL
+    pub il_only: bool, 
+    //Only check the PE file format
+    pub pe_format_only: bool, 
+    pub skip_pe_format: bool, 
+    //Only verify security-transparent methods
+    pub transparent_only: bool, 
+    pub verbose: bool, 
+    //Stop after this many errors; zero means no limit
+    pub max_errors: u32,
+}
+
+impl ValidationOptions {
+    pub(crate) fn raw(&self) -> ULONG {
+        let mut flags = 0;
+        if self.il_only {
+            flags |= VALIDATOR_CHECK_ILONLY;
+        }
+        if self.pe_format_only {
+            flags |= VALIDATOR_CHECK_PEFORMAT_ONLY;
+        }
+        if self.skip_pe_format {
+            flags |= VALIDATOR_NOCHECK_PEFORMAT;
+        }
+        if self.transparent_only {
+            flags |= VALIDATOR_TRANSPARENT_ONLY;
+        }
+        if self.verbose {
+            flags |= VALIDATOR_EXTRA_VERBOSE;
+        }
+        flags
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    //The VER_E_*/VLDTR_E_* code
+    pub code: HRESULT, 
+    //Metadata token of the offending method or item, if any
+    pub token: u32, 
+    //IL offset within the method
+    pub offset: u32, 
+    pub opcode: u32, 
+    pub message: String,
+}
+
+struct Handler<F> {
+    validator: *mut ICLRValidator, 
+    callback: RefCell<F>, 
+    errors: Cell<usize>,
+}
+
+type HandlerObject<F> = ComBox<IVEHandlerVtbl, Handler<F>>;
+
+pub struct Validator {
+    inner: PtrCtr<ICLRValidator>,
+}
+
+impl Validator {
+    pub fn new(host: &ClrRuntimeHost) -> Result<Validator, HostingError> {
+        let mut validator: *mut ICLRValidator = ptr::null_mut();
+        CHECK_HR!(IUnknown::QueryInterface, (*host.as_raw()).QueryInterface(
+            &ICLRValidator::uuidof(), 
+            &mut validator as *mut *mut ICLRValidator as *mut *mut c_void
+        ))?;
+        PtrCtr::new_checked(validator)
+            .map(|inner| Validator { inner })
+            .map_err(|_| HostingError::null_pointer(CALL!(IUnknown::QueryInterface)))
+    }
+
+    pub fn validate_file<F>(&self, path: &Path, options: ValidationOptions, on_error: F) -> Result<usize, HostingError> 
+        where F: FnMut(&ValidationError) -> bool
+    {
+        let image = fs::read(path).map_err(|err| {
+            let hr = match err.raw_os_error() {
+                Some(code) => HRESULT_FROM_WIN32(code as u32), 
+                None => E_FAIL,
+            };
+            HostingError::from_hresult(hr, CALL!(kernel32::ReadFile))
+        })?;
+        self.validate(path, &image, options, on_error)
+    }
+
+    //Returns the number of errors reported. The callback returns false to 
+    // stop validation early.
+    pub fn validate<S, F>(&self, file_name: &S, image: &[u8], options: ValidationOptions, on_error: F) -> Result<usize, HostingError> 
+        where S: AsRef<OsStr> + ?Sized, F: FnMut(&ValidationError) -> bool
+    {
+        let validator = self.inner.as_const() as *mut ICLRValidator;
+        let vtable = IVEHandlerVtbl {
+            parent: HandlerObject::<F>::unknown_vtbl(), 
+            VEHandler: ve_handler::<F>, 
+            SetReporterFtn: set_reporter_ftn,
+        };
+        let handler = HandlerObject::new(vtable, vec![IVEHandler::uuidof()], Handler {
+            validator, 
+            callback: RefCell::new(on_error), 
+            errors: Cell::new(0),
+        });
+        let mut name = wide(file_name);
+        let mut image = image.to_vec();
+        let hr = unsafe {
+            (*validator).Validate(
+                HandlerObject::as_interface(handler), 
+                DEFAULT_APP_DOMAIN_ID, 
+                options.raw(), 
+                options.max_errors, 
+                0, 
+                name.as_mut_ptr(), 
+                image.as_mut_ptr(), 
+                image.len() as ULONG
+            )
+        };
+        let errors = unsafe { (*handler).value.errors.get() };
+        unsafe { HandlerObject::release(handler) };
+        //The overall HRESULT reflects the errors already reported
+        if hr < 0 && errors == 0 && hr != E_ABORT {
+            return Err(HostingError::from_hresult(hr, CALL!(ICLRValidator::Validate)));
+        }
+        Ok(errors)
+    }
+}
+
+COM_WRAPPER!(Validator);
+
+unsafe fn format_message(validator: *mut ICLRValidator, code: HRESULT, context: VEContext, psa: *mut SAFEARRAY) -> String {
+    let mut buffer = [0u16; 1024];
+    let hr = (*validator).FormatEventInfo(code, context, buffer.as_mut_ptr(), buffer.len() as ULONG, psa);
+    if hr < 0 {
+        return format!("0x{:08X}", code);
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
+
+unsafe extern "system" fn ve_handler<F>(this: *mut IVEHandler, code: HRESULT, context: VEContext, psa: *mut SAFEARRAY) -> HRESULT 
+    where F: FnMut(&ValidationError) -> bool
+{
+    let handler = &HandlerObject::<F>::from_this(this).value;
+    let error = ValidationError {
+        code, 
+        token: context.Token, 
+        offset: context.uOffset, 
+        opcode: context.opcode, 
+        message: format_message(handler.validator, code, context, psa),
+    };
+    handler.errors.set(handler.errors.get() + 1);
+    //Any failure code makes the validator stop
+    catch_and_translate(|| {
+        let mut callback = handler.callback.try_borrow_mut().map_err(|_| E_ABORT)?;
+        if (&mut *callback)(&error) { Ok(()) } else { Err(E_ABORT) }
+    })
+}
+
+unsafe extern "system" fn set_reporter_ftn(_this: *mut IVEHandler, _reporter: i64) -> HRESULT {
+    S_OK
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn options_to_flags() {
+        assert_eq!(ValidationOptions::default().raw(), 0);
+        let options = ValidationOptions { il_only: true, verbose: true, ..ValidationOptions::default() };
+        assert_eq!(options.raw(), VALIDATOR_CHECK_ILONLY | VALIDATOR_EXTRA_VERBOSE);
+    }
+}
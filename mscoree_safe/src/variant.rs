@@ -0,0 +1,533 @@
+// variant.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Typed values for managed invocation, and their VARIANT / SAFEARRAY forms. 
+// Every VARIANT produced here owns its payload (BSTR, SAFEARRAY or 
+// interface reference) and must be released with VariantClear.
+use std::ffi::OsStr;
+use std::fmt;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+use std::slice;
+
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{DISP_E_TYPEMISMATCH, E_OUTOFMEMORY};
+use winapi::shared::wtypes::{
+    BSTR, 
+    VARIANT_BOOL, 
+    VARTYPE, 
+    VT_ARRAY, 
+    VT_BOOL, 
+    VT_BSTR, 
+    VT_DISPATCH, 
+    VT_EMPTY, 
+    VT_I4, 
+    VT_I8, 
+    VT_INT, 
+    VT_NULL, 
+    VT_R8, 
+    VT_UI1, 
+    VT_UI4, 
+    VT_UI8, 
+    VT_UINT, 
+    VT_UNKNOWN, 
+    VT_VARIANT
+};
+use winapi::um::oaidl::{SAFEARRAY, VARIANT};
+use winapi::um::oleauto::{
+    SafeArrayAccessData, 
+    SafeArrayCreateVector, 
+    SafeArrayDestroy, 
+    SafeArrayGetElement, 
+    SafeArrayGetLBound, 
+    SafeArrayGetUBound, 
+    SafeArrayPutElement, 
+    SafeArrayUnaccessData, 
+    SysAllocStringLen, 
+    SysFreeString, 
+    VariantClear
+};
+use winapi::um::unknwnbase::IUnknown;
+
+use error::HostingError;
+use marshal::{self, bstr_to_string};
+use wrappers::PtrCtr;
+
+const VARIANT_TRUE: VARIANT_BOOL = -1;
+const VARIANT_FALSE: VARIANT_BOOL = 0;
+
+//Owned reference to a managed object's COM-callable wrapper
+pub struct ClrObject {
+    inner: PtrCtr<IUnknown>,
+}
+
+impl ClrObject {
+    //AddRefs; the caller keeps its own reference
+    pub(crate) fn from_borrowed(unk: *mut IUnknown) -> Result<ClrObject, HostingError> {
+        let inner = PtrCtr::new_checked(unk).map_err(|_| HostingError::null_pointer(CALL!(IUnknown::AddRef)))?;
+        unsafe { (*unk).AddRef() };
+        Ok(ClrObject { inner })
+    }
+
+    pub(crate) fn as_unknown(&self) -> *mut IUnknown {
+        self.inner.as_const() as *mut IUnknown
+    }
+
+    //Hands our reference over without releasing it
+    pub(crate) fn into_raw(self) -> *mut IUnknown {
+        let unk = self.as_unknown();
+        mem::forget(self);
+        unk
+    }
+}
+
+impl Clone for ClrObject {
+    fn clone(&self) -> ClrObject {
+        ClrObject::from_borrowed(self.as_unknown()).expect("pointer is non-null by construction")
+    }
+}
+
+impl fmt::Debug for ClrObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ClrObject({:p})", self.as_unknown())
+    }
+}
+
+COM_WRAPPER!(ClrObject);
+
+#[derive(Clone, Debug)]
+pub enum ClrValue {
+    I4(i32), 
+    U4(u32), 
+    I8(i64), 
+    R8(f64), 
+    Bool(bool), 
+    String(String), 
+    Null, 
+    Object(ClrObject), 
+    Array(Vec<ClrValue>), 
+    //byte[], carried as a SAFEARRAY of VT_UI1
+    Bytes(Vec<u8>),
+}
+
+impl ClrValue {
+    pub fn to_variant(&self) -> Result<VARIANT, HostingError> {
+        let mut v = empty();
+        unsafe {
+            let n2 = v.n1.n2_mut();
+            match *self {
+                ClrValue::I4(i) => { n2.vt = VT_I4 as VARTYPE; *n2.n3.lVal_mut() = i; }, 
+                ClrValue::U4(u) => { n2.vt = VT_UI4 as VARTYPE; *n2.n3.ulVal_mut() = u; }, 
+                ClrValue::I8(i) => { n2.vt = VT_I8 as VARTYPE; *n2.n3.llVal_mut() = i; }, 
+                ClrValue::R8(d) => { n2.vt = VT_R8 as VARTYPE; *n2.n3.dblVal_mut() = d; }, 
+                ClrValue::Bool(b) => { 
+                    n2.vt = VT_BOOL as VARTYPE; 
+                    *n2.n3.boolVal_mut() = if b { VARIANT_TRUE } else { VARIANT_FALSE }; 
+                }, 
+                ClrValue::String(ref s) => { n2.vt = VT_BSTR as VARTYPE; *n2.n3.bstrVal_mut() = alloc_bstr(s)?; }, 
+                //VT_EMPTY marshals to a null reference; VT_NULL would become DBNull
+                ClrValue::Null => {}, 
+                ClrValue::Object(ref o) => {
+                    (*o.as_unknown()).AddRef();
+                    n2.vt = VT_UNKNOWN as VARTYPE; 
+                    *n2.n3.punkVal_mut() = o.as_unknown();
+                }, 
+                ClrValue::Array(ref values) => {
+                    n2.vt = (VT_ARRAY | VT_VARIANT) as VARTYPE;
+                    *n2.n3.parray_mut() = ClrValue::to_safearray(values)?.into_raw();
+                }, 
+                ClrValue::Bytes(ref bytes) => {
+                    n2.vt = (VT_ARRAY | VT_UI1) as VARTYPE;
+                    *n2.n3.parray_mut() = SafeArrayPtr::from_bytes(bytes)?.into_raw();
+                },
+            }
+        }
+        Ok(v)
+    }
+
+    //Copies out of the VARIANT; it stays owned by the caller
+    pub fn from_variant(v: &VARIANT) -> Result<ClrValue, HostingError> {
+        let ty = vt(v);
+        unsafe {
+            let n3 = &v.n1.n2().n3;
+            if ty == VT_EMPTY as VARTYPE || ty == VT_NULL as VARTYPE {
+                Ok(ClrValue::Null)
+            } else if ty == VT_I4 as VARTYPE {
+                Ok(ClrValue::I4(*n3.lVal()))
+            } else if ty == VT_UI4 as VARTYPE {
+                Ok(ClrValue::U4(*n3.ulVal()))
+            } else if ty == VT_I8 as VARTYPE {
+                Ok(ClrValue::I8(*n3.llVal()))
+            } else if ty == VT_R8 as VARTYPE {
+                Ok(ClrValue::R8(*n3.dblVal()))
+            } else if ty == VT_BOOL as VARTYPE {
+                Ok(ClrValue::Bool(*n3.boolVal() != VARIANT_FALSE))
+            } else if ty == VT_BSTR as VARTYPE {
+                Ok(ClrValue::String(bstr_to_string(*n3.bstrVal())))
+            } else if ty == VT_UNKNOWN as VARTYPE {
+                object_or_null(*n3.punkVal())
+            } else if ty == VT_DISPATCH as VARTYPE {
+                object_or_null(*n3.pdispVal() as *mut IUnknown)
+            } else if ty == (VT_ARRAY | VT_VARIANT) as VARTYPE {
+                ClrValue::from_safearray(*n3.parray())
+            } else if ty == (VT_ARRAY | VT_UI1) as VARTYPE {
+                ClrValue::bytes_from_safearray(*n3.parray())
+            } else if ty == (VT_ARRAY | VT_BSTR) as VARTYPE {
+                //string[]; the BSTRs are copied, VariantClear frees them
+                marshal::strings_from_safearray(*n3.parray())
+                    .map(|strings| ClrValue::Array(strings.into_iter().map(ClrValue::String).collect()))
+            } else {
+                Err(HostingError::from_hresult(DISP_E_TYPEMISMATCH, CALL!(ClrValue::from_variant)))
+            }
+        }
+    }
+
+    //Takes ownership of the VARIANT and clears it
+    pub fn from_owned_variant(mut v: VARIANT) -> Result<ClrValue, HostingError> {
+        let value = ClrValue::from_variant(&v);
+        unsafe { VariantClear(&mut v) };
+        value
+    }
+
+    //One-dimensional SAFEARRAY of VARIANT, as taken by InvokeMember and friends
+    pub(crate) fn to_safearray(values: &[ClrValue]) -> Result<SafeArrayPtr, HostingError> {
+        let psa = SafeArrayPtr::create(VT_VARIANT as VARTYPE, values.len())?;
+        for (i, value) in values.iter().enumerate() {
+            let mut v = value.to_variant()?;
+            let mut index = i as i32;
+            //SafeArrayPutElement copies with VariantCopy, so ours is cleared either way
+            let hr = CHECK_HR!(oleaut32::SafeArrayPutElement, SafeArrayPutElement(psa.as_ptr(), &mut index, &mut v as *mut VARIANT as *mut c_void));
+            unsafe { VariantClear(&mut v) };
+            hr?;
+        }
+        Ok(psa)
+    }
+
+    pub(crate) fn from_safearray(psa: *mut SAFEARRAY) -> Result<ClrValue, HostingError> {
+        if psa.is_null() {
+            return Ok(ClrValue::Null);
+        }
+        let (mut lower, mut upper) = (0i32, -1i32);
+        CHECK_HR!(oleaut32::SafeArrayGetLBound, SafeArrayGetLBound(psa, 1, &mut lower))?;
+        CHECK_HR!(oleaut32::SafeArrayGetUBound, SafeArrayGetUBound(psa, 1, &mut upper))?;
+        let mut values = Vec::with_capacity((upper - lower + 1).max(0) as usize);
+        for mut index in lower..upper + 1 {
+            let mut element = empty();
+            CHECK_HR!(oleaut32::SafeArrayGetElement, SafeArrayGetElement(psa, &mut index, &mut element as *mut VARIANT as *mut c_void))?;
+            values.push(ClrValue::from_owned_variant(element)?);
+        }
+        Ok(ClrValue::Array(values))
+    }
+
+    pub(crate) fn bytes_from_safearray(psa: *mut SAFEARRAY) -> Result<ClrValue, HostingError> {
+        if psa.is_null() {
+            return Ok(ClrValue::Null);
+        }
+        let (mut lower, mut upper) = (0i32, -1i32);
+        CHECK_HR!(oleaut32::SafeArrayGetLBound, SafeArrayGetLBound(psa, 1, &mut lower))?;
+        CHECK_HR!(oleaut32::SafeArrayGetUBound, SafeArrayGetUBound(psa, 1, &mut upper))?;
+        let len = (upper - lower + 1).max(0) as usize;
+        let mut data: *mut c_void = ptr::null_mut();
+        CHECK_HR!(oleaut32::SafeArrayAccessData, SafeArrayAccessData(psa, &mut data))?;
+        let bytes = unsafe {
+            let bytes = slice::from_raw_parts(data as *const u8, len).to_vec();
+            SafeArrayUnaccessData(psa);
+            bytes
+        };
+        Ok(ClrValue::Bytes(bytes))
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self { ClrValue::I4(i) => Some(i), _ => None }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self { ClrValue::Bool(b) => Some(b), _ => None }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self { ClrValue::String(ref s) => Some(s), _ => None }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self { ClrValue::Bytes(ref b) => Some(b), _ => None }
+    }
+
+    pub fn into_object(self) -> Option<ClrObject> {
+        match self { ClrValue::Object(o) => Some(o), _ => None }
+    }
+
+    //A string[] result; null elements read as "", a null array as empty
+    pub fn into_strings(self) -> Option<Vec<String>> {
+        match self {
+            ClrValue::Null => Some(Vec::new()), 
+            ClrValue::Array(values) => values.into_iter().map(|v| match v {
+                ClrValue::String(s) => Some(s), 
+                ClrValue::Null => Some(String::new()), 
+                _ => None,
+            }).collect(), 
+            _ => None,
+        }
+    }
+}
+
+impl From<i32> for ClrValue {
+    fn from(i: i32) -> ClrValue { ClrValue::I4(i) }
+}
+
+impl From<u32> for ClrValue {
+    fn from(u: u32) -> ClrValue { ClrValue::U4(u) }
+}
+
+impl From<i64> for ClrValue {
+    fn from(i: i64) -> ClrValue { ClrValue::I8(i) }
+}
+
+impl From<f64> for ClrValue {
+    fn from(d: f64) -> ClrValue { ClrValue::R8(d) }
+}
+
+impl From<bool> for ClrValue {
+    fn from(b: bool) -> ClrValue { ClrValue::Bool(b) }
+}
+
+impl<'s> From<&'s str> for ClrValue {
+    fn from(s: &'s str) -> ClrValue { ClrValue::String(s.to_string()) }
+}
+
+impl From<String> for ClrValue {
+    fn from(s: String) -> ClrValue { ClrValue::String(s) }
+}
+
+impl From<ClrObject> for ClrValue {
+    fn from(o: ClrObject) -> ClrValue { ClrValue::Object(o) }
+}
+
+impl From<Vec<ClrValue>> for ClrValue {
+    fn from(values: Vec<ClrValue>) -> ClrValue { ClrValue::Array(values) }
+}
+
+impl From<Vec<u8>> for ClrValue {
+    fn from(bytes: Vec<u8>) -> ClrValue { ClrValue::Bytes(bytes) }
+}
+
+impl<'b> From<&'b [u8]> for ClrValue {
+    fn from(bytes: &'b [u8]) -> ClrValue { ClrValue::Bytes(bytes.to_vec()) }
+}
+
+//Owned one-dimensional SAFEARRAY, destroyed on drop
+pub(crate) struct SafeArrayPtr {
+    inner: *mut SAFEARRAY,
+}
+
+impl SafeArrayPtr {
+    pub(crate) fn create(vt: VARTYPE, len: usize) -> Result<SafeArrayPtr, HostingError> {
+        let inner = unsafe { SafeArrayCreateVector(vt, 0, len as u32) };
+        if inner.is_null() {
+            return Err(HostingError::from_hresult(E_OUTOFMEMORY, CALL!(oleaut32::SafeArrayCreateVector)));
+        }
+        Ok(SafeArrayPtr { inner })
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<SafeArrayPtr, HostingError> {
+        let psa = SafeArrayPtr::create(VT_UI1 as VARTYPE, bytes.len())?;
+        let mut data: *mut c_void = ptr::null_mut();
+        CHECK_HR!(oleaut32::SafeArrayAccessData, SafeArrayAccessData(psa.inner, &mut data))?;
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), data as *mut u8, bytes.len());
+            SafeArrayUnaccessData(psa.inner);
+        }
+        Ok(psa)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut SAFEARRAY {
+        self.inner
+    }
+
+    //Hands ownership over, e.g. to a VARIANT that VariantClear will destroy
+    pub(crate) fn into_raw(self) -> *mut SAFEARRAY {
+        let inner = self.inner;
+        mem::forget(self);
+        inner
+    }
+}
+
+impl Drop for SafeArrayPtr {
+    fn drop(&mut self) {
+        unsafe { SafeArrayDestroy(self.inner) };
+    }
+}
+
+pub(crate) fn empty() -> VARIANT {
+    unsafe { mem::zeroed() }
+}
+
+//A boxed IntPtr/UIntPtr; the marshaler picks VT_INT or VT_I8 depending 
+// on the bitness of the process
+pub(crate) fn int_ptr(v: &VARIANT) -> Option<usize> {
+    let ty = vt(v);
+    unsafe {
+        let n3 = &v.n1.n2().n3;
+        if ty == VT_INT as VARTYPE || ty == VT_I4 as VARTYPE {
+            Some(*n3.intVal() as isize as usize)
+        } else if ty == VT_UINT as VARTYPE || ty == VT_UI4 as VARTYPE {
+            Some(*n3.uintVal() as usize)
+        } else if ty == VT_I8 as VARTYPE {
+            Some(*n3.llVal() as isize as usize)
+        } else if ty == VT_UI8 as VARTYPE {
+            Some(*n3.ullVal() as usize)
+        } else {
+            None
+        }
+    }
+}
+
+//Borrowed VT_UNKNOWN view of an object, for InvokeMember targets. 
+// Must not be passed to VariantClear.
+pub(crate) fn borrowed_object(object: &ClrObject) -> VARIANT {
+    let mut v = empty();
+    unsafe {
+        let n2 = v.n1.n2_mut();
+        n2.vt = VT_UNKNOWN as VARTYPE;
+        *n2.n3.punkVal_mut() = object.as_unknown();
+    }
+    v
+}
+
+fn vt(v: &VARIANT) -> VARTYPE {
+    unsafe { v.n1.n2().vt }
+}
+
+fn object_or_null(unk: *mut IUnknown) -> Result<ClrValue, HostingError> {
+    if unk.is_null() {
+        Ok(ClrValue::Null)
+    } else {
+        ClrObject::from_borrowed(unk).map(ClrValue::Object)
+    }
+}
+
+fn alloc_bstr(value: &str) -> Result<BSTR, HostingError> {
+    let wide: Vec<u16> = value.encode_utf16().collect();
+    alloc_wide_bstr(&wide)
+}
+
+fn alloc_wide_bstr(wide: &[u16]) -> Result<BSTR, HostingError> {
+    let bstr = unsafe { SysAllocStringLen(wide.as_ptr(), wide.len() as u32) };
+    if bstr.is_null() {
+        return Err(HostingError::from_hresult(E_OUTOFMEMORY, CALL!(oleaut32::SysAllocStringLen)));
+    }
+    Ok(bstr)
+}
+
+//Owned BSTR built straight from an OsStr, for BSTR parameters that carry 
+// paths; BString only goes through &str
+pub(crate) struct OsBstr {
+    inner: BSTR,
+}
+
+impl OsBstr {
+    pub(crate) fn new<S: AsRef<OsStr> + ?Sized>(s: &S) -> Result<OsBstr, HostingError> {
+        let wide: Vec<u16> = s.as_ref().encode_wide().collect();
+        alloc_wide_bstr(&wide).map(|inner| OsBstr { inner })
+    }
+
+    pub(crate) fn as_sys(&self) -> BSTR {
+        self.inner
+    }
+
+    //Hands the BSTR over, e.g. to a VARIANT that VariantClear will free
+    pub(crate) fn into_raw(self) -> BSTR {
+        let inner = self.inner;
+        mem::forget(self);
+        inner
+    }
+}
+
+//A one-element argument array holding a path as a managed string, without 
+// the lossy trip through &str that ClrValue::String takes
+pub(crate) fn path_args<S: AsRef<OsStr> + ?Sized>(path: &S) -> Result<SafeArrayPtr, HostingError> {
+    let psa = SafeArrayPtr::create(VT_VARIANT as VARTYPE, 1)?;
+    let mut v = empty();
+    unsafe {
+        let n2 = v.n1.n2_mut();
+        n2.vt = VT_BSTR as VARTYPE;
+        *n2.n3.bstrVal_mut() = OsBstr::new(path)?.into_raw();
+    }
+    let mut index = 0i32;
+    let hr = CHECK_HR!(oleaut32::SafeArrayPutElement, SafeArrayPutElement(psa.as_ptr(), &mut index, &mut v as *mut VARIANT as *mut c_void));
+    unsafe { VariantClear(&mut v) };
+    hr?;
+    Ok(psa)
+}
+
+impl Drop for OsBstr {
+    fn drop(&mut self) {
+        unsafe { SysFreeString(self.inner) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scalar_roundtrip() {
+        for value in vec![ClrValue::I4(-7), ClrValue::U4(7), ClrValue::I8(-1 << 40), ClrValue::Bool(true), ClrValue::from("héllo")] {
+            let v = value.to_variant().unwrap();
+            let back = ClrValue::from_owned_variant(v).unwrap();
+            assert_eq!(format!("{:?}", back), format!("{:?}", value));
+        }
+    }
+
+    #[test]
+    fn array_roundtrip() {
+        let value = ClrValue::Array(vec![ClrValue::I4(1), ClrValue::Null, ClrValue::from("two")]);
+        let back = ClrValue::from_owned_variant(value.to_variant().unwrap()).unwrap();
+        match back {
+            ClrValue::Array(ref values) => {
+                assert_eq!(values.len(), 3);
+                assert_eq!(values[0].as_i32(), Some(1));
+                assert_eq!(values[2].as_str(), Some("two"));
+            }, 
+            _ => panic!("expected an array, got {:?}", back),
+        }
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let value = ClrValue::from(&[0u8, 1, 254, 255][..]);
+        let back = ClrValue::from_owned_variant(value.to_variant().unwrap()).unwrap();
+        assert_eq!(back.as_bytes(), Some(&[0u8, 1, 254, 255][..]));
+    }
+
+    #[test]
+    fn int_ptr_accepts_pointer_sized_tags() {
+        let mut v = empty();
+        unsafe {
+            v.n1.n2_mut().vt = VT_I8 as VARTYPE;
+            *v.n1.n2_mut().n3.llVal_mut() = 0x1234;
+        }
+        assert_eq!(int_ptr(&v), Some(0x1234));
+        assert_eq!(int_ptr(&ClrValue::Bool(true).to_variant().unwrap()), None);
+    }
+}
@@ -21,7 +21,7 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
-use winapi::ctypes::{c_int, c_short, c_void};
+use winapi::ctypes::{c_char, c_int, c_short, c_void};
 use winapi::shared::basetsd::ULONG32;
 use winapi::shared::guiddef::{GUID, REFCLSID, REFIID};
 use winapi::shared::minwindef::{BOOL, DWORD, HINSTANCE, LPCVOID, LPVOID, PBYTE, ULONG, USHORT};
@@ -31,7 +31,38 @@ use winapi::um::winnt::{IMAGE_SCN_MEM_READ, IMAGE_SCN_CNT_INITIALIZED_DATA,  IMA
 use winapi::um::objidlbase::IStream;
 use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
 
-use crate::corhdr::{CorSaveSize, mdToken, mdTypeDef};
+use crate::corhdr::{
+    CorSaveSize, 
+    COR_FIELD_OFFSET, 
+    HCORENUM, 
+    MDUTF8CSTR, 
+    PCCOR_SIGNATURE, 
+    mdAssembly, 
+    mdAssemblyRef, 
+    mdCustomAttribute, 
+    mdEvent, 
+    mdExportedType, 
+    mdFieldDef, 
+    mdFile, 
+    mdGenericParam, 
+    mdGenericParamConstraint, 
+    mdInterfaceImpl, 
+    mdManifestResource, 
+    mdMemberRef, 
+    mdMethodDef, 
+    mdMethodSpec, 
+    mdModule, 
+    mdModuleRef, 
+    mdParamDef, 
+    mdPermission, 
+    mdProperty, 
+    mdSignature, 
+    mdString, 
+    mdToken, 
+    mdTypeDef, 
+    mdTypeRef, 
+    mdTypeSpec,
+};
 
 DEFINE_GUID!(LIBID_ComPlusRuntime, 0xbed7f4ea, 0x1a96, 0x11d2, 0x8f, 0x8, 0x0, 0xa0, 0xc9, 0xa6, 0x18, 0x6d);
 DEFINE_GUID!(GUID_ExportedFromComPlus, 0x90883f05, 0x3d28, 0x11d2, 0x8f, 0x17, 0x0, 0xa0, 0xc9, 0xa6, 0x18, 0x6d);
@@ -138,7 +169,8 @@ INTERFACE_BINDING!{interface IMapToken(IMapTokenVtbl): IUnknown(IUnknownVtbl){
 
 DEFINE_GUID!(IID_IMetaDataDispenser, 0x809c652e, 0x7396, 0x11d2, 0x97, 0x71, 0x00, 0xa0, 0xc9, 0xb4, 0xd5, 0x0c);
 
-INTERFACE_BINDING!{interface IMetaDataDispenser(IMetaDataDispenserVtbl): IUnknown(IUnknownVtbl){
+RIDL!{#[uuid(0x809c652e, 0x7396, 0x11d2, 0x97, 0x71, 0x00, 0xa0, 0xc9, 0xb4, 0xd5, 0x0c)]
+interface IMetaDataDispenser(IMetaDataDispenserVtbl): IUnknown(IUnknownVtbl){
     fn DefineScope(
         rclsid: REFCLSID, 
         dwCreateFlags: DWORD, 
@@ -244,13 +276,492 @@ INTERFACE_BINDING!{interface IMetaDataEmit2(IMetaDataEmit2Vtbl): IMetaDataEmit(I
 }}
 
 DEFINE_GUID!(IID_IMetaDataImport, 0x7dac8207, 0xd3ae, 0x4c75, 0x9b, 0x67, 0x92, 0x80, 0x1a, 0x49, 0x7d, 0x44);
-INTERFACE_BINDING!{interface IMetaDataImport(IMetaDataImportVtbl): IUnknown(IUnknownVtbl){
-
+RIDL!{#[uuid(0x7dac8207, 0xd3ae, 0x4c75, 0x9b, 0x67, 0x92, 0x80, 0x1a, 0x49, 0x7d, 0x44)]
+interface IMetaDataImport(IMetaDataImportVtbl): IUnknown(IUnknownVtbl){
+    fn CloseEnum(
+        hEnum: HCORENUM,
+    ) -> (), 
+    fn CountEnum(
+        hEnum: HCORENUM, 
+        pulCount: *mut ULONG,
+    ) -> HRESULT, 
+    fn ResetEnum(
+        hEnum: HCORENUM, 
+        ulPos: ULONG,
+    ) -> HRESULT, 
+    fn EnumTypeDefs(
+        phEnum: *mut HCORENUM, 
+        rTypeDefs: *mut mdTypeDef, 
+        cMax: ULONG, 
+        pcTypeDefs: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumInterfaceImpls(
+        phEnum: *mut HCORENUM, 
+        td: mdTypeDef, 
+        rImpls: *mut mdInterfaceImpl, 
+        cMax: ULONG, 
+        pcImpls: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumTypeRefs(
+        phEnum: *mut HCORENUM, 
+        rTypeRefs: *mut mdTypeRef, 
+        cMax: ULONG, 
+        pcTypeRefs: *mut ULONG,
+    ) -> HRESULT, 
+    fn FindTypeDefByName(
+        szTypeDef: LPCWSTR, 
+        tkEnclosingClass: mdToken, 
+        ptd: *mut mdTypeDef,
+    ) -> HRESULT, 
+    fn GetScopeProps(
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        pmvid: *mut GUID,
+    ) -> HRESULT, 
+    fn GetModuleFromScope(
+        pmd: *mut mdModule,
+    ) -> HRESULT, 
+    fn GetTypeDefProps(
+        td: mdTypeDef, 
+        szTypeDef: LPWSTR, 
+        cchTypeDef: ULONG, 
+        pchTypeDef: *mut ULONG, 
+        pdwTypeDefFlags: *mut DWORD, 
+        ptkExtends: *mut mdToken,
+    ) -> HRESULT, 
+    fn GetInterfaceImplProps(
+        iiImpl: mdInterfaceImpl, 
+        pClass: *mut mdTypeDef, 
+        ptkIface: *mut mdToken,
+    ) -> HRESULT, 
+    fn GetTypeRefProps(
+        tr: mdTypeRef, 
+        ptkResolutionScope: *mut mdToken, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG,
+    ) -> HRESULT, 
+    fn ResolveTypeRef(
+        tr: mdTypeRef, 
+        riid: REFIID, 
+        ppIScope: *mut *mut IUnknown, 
+        ptd: *mut mdTypeDef,
+    ) -> HRESULT, 
+    fn EnumMembers(
+        phEnum: *mut HCORENUM, 
+        cl: mdTypeDef, 
+        rMembers: *mut mdToken, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumMembersWithName(
+        phEnum: *mut HCORENUM, 
+        cl: mdTypeDef, 
+        szName: LPCWSTR, 
+        rMembers: *mut mdToken, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumMethods(
+        phEnum: *mut HCORENUM, 
+        cl: mdTypeDef, 
+        rMethods: *mut mdMethodDef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumMethodsWithName(
+        phEnum: *mut HCORENUM, 
+        cl: mdTypeDef, 
+        szName: LPCWSTR, 
+        rMethods: *mut mdMethodDef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumFields(
+        phEnum: *mut HCORENUM, 
+        cl: mdTypeDef, 
+        rFields: *mut mdFieldDef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumFieldsWithName(
+        phEnum: *mut HCORENUM, 
+        cl: mdTypeDef, 
+        szName: LPCWSTR, 
+        rFields: *mut mdFieldDef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumParams(
+        phEnum: *mut HCORENUM, 
+        mb: mdMethodDef, 
+        rParams: *mut mdParamDef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumMemberRefs(
+        phEnum: *mut HCORENUM, 
+        tkParent: mdToken, 
+        rMemberRefs: *mut mdMemberRef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumMethodImpls(
+        phEnum: *mut HCORENUM, 
+        td: mdTypeDef, 
+        rMethodBody: *mut mdToken, 
+        rMethodDecl: *mut mdToken, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumPermissionSets(
+        phEnum: *mut HCORENUM, 
+        tk: mdToken, 
+        dwActions: DWORD, 
+        rPermission: *mut mdPermission, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn FindMember(
+        td: mdTypeDef, 
+        szName: LPCWSTR, 
+        pvSigBlob: PCCOR_SIGNATURE, 
+        cbSigBlob: ULONG, 
+        pmb: *mut mdToken,
+    ) -> HRESULT, 
+    fn FindMethod(
+        td: mdTypeDef, 
+        szName: LPCWSTR, 
+        pvSigBlob: PCCOR_SIGNATURE, 
+        cbSigBlob: ULONG, 
+        pmb: *mut mdMethodDef,
+    ) -> HRESULT, 
+    fn FindField(
+        td: mdTypeDef, 
+        szName: LPCWSTR, 
+        pvSigBlob: PCCOR_SIGNATURE, 
+        cbSigBlob: ULONG, 
+        pmb: *mut mdFieldDef,
+    ) -> HRESULT, 
+    fn FindMemberRef(
+        td: mdTypeRef, 
+        szName: LPCWSTR, 
+        pvSigBlob: PCCOR_SIGNATURE, 
+        cbSigBlob: ULONG, 
+        pmr: *mut mdMemberRef,
+    ) -> HRESULT, 
+    fn GetMethodProps(
+        mb: mdMethodDef, 
+        pClass: *mut mdTypeDef, 
+        szMethod: LPWSTR, 
+        cchMethod: ULONG, 
+        pchMethod: *mut ULONG, 
+        pdwAttr: *mut DWORD, 
+        ppvSigBlob: *mut PCCOR_SIGNATURE, 
+        pcbSigBlob: *mut ULONG, 
+        pulCodeRVA: *mut ULONG, 
+        pdwImplFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetMemberRefProps(
+        mr: mdMemberRef, 
+        ptk: *mut mdToken, 
+        szMember: LPWSTR, 
+        cchMember: ULONG, 
+        pchMember: *mut ULONG, 
+        ppvSigBlob: *mut PCCOR_SIGNATURE, 
+        pbSig: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumProperties(
+        phEnum: *mut HCORENUM, 
+        td: mdTypeDef, 
+        rProperties: *mut mdProperty, 
+        cMax: ULONG, 
+        pcProperties: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumEvents(
+        phEnum: *mut HCORENUM, 
+        td: mdTypeDef, 
+        rEvents: *mut mdEvent, 
+        cMax: ULONG, 
+        pcEvents: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetEventProps(
+        ev: mdEvent, 
+        pClass: *mut mdTypeDef, 
+        szEvent: LPWSTR, 
+        cchEvent: ULONG, 
+        pchEvent: *mut ULONG, 
+        pdwEventFlags: *mut DWORD, 
+        ptkEventType: *mut mdToken, 
+        pmdAddOn: *mut mdMethodDef, 
+        pmdRemoveOn: *mut mdMethodDef, 
+        pmdFire: *mut mdMethodDef, 
+        rmdOtherMethod: *mut mdMethodDef, 
+        cMax: ULONG, 
+        pcOtherMethod: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumMethodSemantics(
+        phEnum: *mut HCORENUM, 
+        mb: mdMethodDef, 
+        rEventProp: *mut mdToken, 
+        cMax: ULONG, 
+        pcEventProp: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetMethodSemantics(
+        mb: mdMethodDef, 
+        tkEventProp: mdToken, 
+        pdwSemanticsFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetClassLayout(
+        td: mdTypeDef, 
+        pdwPackSize: *mut DWORD, 
+        rFieldOffset: *mut COR_FIELD_OFFSET, 
+        cMax: ULONG, 
+        pcFieldOffset: *mut ULONG, 
+        pulClassSize: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetFieldMarshal(
+        tk: mdToken, 
+        ppvNativeType: *mut PCCOR_SIGNATURE, 
+        pcbNativeType: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetRVA(
+        tk: mdToken, 
+        pulCodeRVA: *mut ULONG, 
+        pdwImplFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetPermissionSetProps(
+        pm: mdPermission, 
+        pdwAction: *mut DWORD, 
+        ppvPermission: *mut *const c_void, 
+        pcbPermission: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetSigFromToken(
+        mdSig: mdSignature, 
+        ppvSig: *mut PCCOR_SIGNATURE, 
+        pcbSig: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetModuleRefProps(
+        mur: mdModuleRef, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumModuleRefs(
+        phEnum: *mut HCORENUM, 
+        rModuleRefs: *mut mdModuleRef, 
+        cMax: ULONG, 
+        pcModuleRefs: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetTypeSpecFromToken(
+        typespec: mdTypeSpec, 
+        ppvSig: *mut PCCOR_SIGNATURE, 
+        pcbSig: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetNameFromToken(
+        tk: mdToken, 
+        pszUtf8NamePtr: *mut MDUTF8CSTR,
+    ) -> HRESULT, 
+    fn EnumUnresolvedMethods(
+        phEnum: *mut HCORENUM, 
+        rMethods: *mut mdToken, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetUserString(
+        stk: mdString, 
+        szString: LPWSTR, 
+        cchString: ULONG, 
+        pchString: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetPinvokeMap(
+        tk: mdToken, 
+        pdwMappingFlags: *mut DWORD, 
+        szImportName: LPWSTR, 
+        cchImportName: ULONG, 
+        pchImportName: *mut ULONG, 
+        pmrImportDLL: *mut mdModuleRef,
+    ) -> HRESULT, 
+    fn EnumSignatures(
+        phEnum: *mut HCORENUM, 
+        rSignatures: *mut mdSignature, 
+        cMax: ULONG, 
+        pcSignatures: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumTypeSpecs(
+        phEnum: *mut HCORENUM, 
+        rTypeSpecs: *mut mdTypeSpec, 
+        cMax: ULONG, 
+        pcTypeSpecs: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumUserStrings(
+        phEnum: *mut HCORENUM, 
+        rStrings: *mut mdString, 
+        cMax: ULONG, 
+        pcStrings: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetParamForMethodIndex(
+        md: mdMethodDef, 
+        ulParamSeq: ULONG, 
+        ppd: *mut mdParamDef,
+    ) -> HRESULT, 
+    fn EnumCustomAttributes(
+        phEnum: *mut HCORENUM, 
+        tk: mdToken, 
+        tkType: mdToken, 
+        rCustomAttributes: *mut mdCustomAttribute, 
+        cMax: ULONG, 
+        pcCustomAttributes: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetCustomAttributeProps(
+        cv: mdCustomAttribute, 
+        ptkObj: *mut mdToken, 
+        ptkType: *mut mdToken, 
+        ppBlob: *mut *const c_void, 
+        pcbSize: *mut ULONG,
+    ) -> HRESULT, 
+    fn FindTypeRef(
+        tkResolutionScope: mdToken, 
+        szName: LPCWSTR, 
+        ptr: *mut mdTypeRef,
+    ) -> HRESULT, 
+    fn GetMemberProps(
+        mb: mdToken, 
+        pClass: *mut mdTypeDef, 
+        szMember: LPWSTR, 
+        cchMember: ULONG, 
+        pchMember: *mut ULONG, 
+        pdwAttr: *mut DWORD, 
+        ppvSigBlob: *mut PCCOR_SIGNATURE, 
+        pcbSigBlob: *mut ULONG, 
+        pulCodeRVA: *mut ULONG, 
+        pdwImplFlags: *mut DWORD, 
+        pdwCPlusTypeFlag: *mut DWORD, 
+        ppValue: *mut UVCP_CONSTANT, 
+        pcchValue: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetFieldProps(
+        mb: mdFieldDef, 
+        pClass: *mut mdTypeDef, 
+        szField: LPWSTR, 
+        cchField: ULONG, 
+        pchField: *mut ULONG, 
+        pdwAttr: *mut DWORD, 
+        ppvSigBlob: *mut PCCOR_SIGNATURE, 
+        pcbSigBlob: *mut ULONG, 
+        pdwCPlusTypeFlag: *mut DWORD, 
+        ppValue: *mut UVCP_CONSTANT, 
+        pcchValue: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetPropertyProps(
+        prop: mdProperty, 
+        pClass: *mut mdTypeDef, 
+        szProperty: LPWSTR, 
+        cchProperty: ULONG, 
+        pchProperty: *mut ULONG, 
+        pdwPropFlags: *mut DWORD, 
+        ppvSig: *mut PCCOR_SIGNATURE, 
+        pbSig: *mut ULONG, 
+        pdwCPlusTypeFlag: *mut DWORD, 
+        ppDefaultValue: *mut UVCP_CONSTANT, 
+        pcchDefaultValue: *mut ULONG, 
+        pmdSetter: *mut mdMethodDef, 
+        pmdGetter: *mut mdMethodDef, 
+        rmdOtherMethod: *mut mdMethodDef, 
+        cMax: ULONG, 
+        pcOtherMethod: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetParamProps(
+        tk: mdParamDef, 
+        pmd: *mut mdMethodDef, 
+        pulSequence: *mut ULONG, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        pdwAttr: *mut DWORD, 
+        pdwCPlusTypeFlag: *mut DWORD, 
+        ppValue: *mut UVCP_CONSTANT, 
+        pcchValue: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetCustomAttributeByName(
+        tkObj: mdToken, 
+        szName: LPCWSTR, 
+        ppData: *mut *const c_void, 
+        pcbData: *mut ULONG,
+    ) -> HRESULT, 
+    fn IsValidToken(
+        tk: mdToken,
+    ) -> BOOL, 
+    fn GetNestedClassProps(
+        tdNestedClass: mdTypeDef, 
+        ptdEnclosingClass: *mut mdTypeDef,
+    ) -> HRESULT, 
+    fn GetNativeCallConvFromSig(
+        pvSig: *const c_void, 
+        cbSig: ULONG, 
+        pCallConv: *mut ULONG,
+    ) -> HRESULT, 
+    fn IsGlobal(
+        pd: mdToken, 
+        pbGlobal: *mut c_int,
+    ) -> HRESULT,
 }}
 
 DEFINE_GUID!(IID_IMetaDataImport2, 0xfce5efa0, 0x8bba, 0x4f8e, 0xa0, 0x36, 0x8f, 0x20, 0x22, 0xb0, 0x84, 0x66);
-INTERFACE_BINDING!{interface IMetaDataImport2(IMetaDataImport2Vtbl): IMetaDataImport(IMetaDataImportVtbl){
-
+RIDL!{#[uuid(0xfce5efa0, 0x8bba, 0x4f8e, 0xa0, 0x36, 0x8f, 0x20, 0x22, 0xb0, 0x84, 0x66)]
+interface IMetaDataImport2(IMetaDataImport2Vtbl): IMetaDataImport(IMetaDataImportVtbl){
+    fn EnumGenericParams(
+        phEnum: *mut HCORENUM, 
+        tk: mdToken, 
+        rGenericParams: *mut mdGenericParam, 
+        cMax: ULONG, 
+        pcGenericParams: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetGenericParamProps(
+        gp: mdGenericParam, 
+        pulParamSeq: *mut ULONG, 
+        pdwParamFlags: *mut DWORD, 
+        ptOwner: *mut mdToken, 
+        reserved: *mut DWORD, 
+        wzname: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetMethodSpecProps(
+        mi: mdMethodSpec, 
+        tkParent: *mut mdToken, 
+        ppvSigBlob: *mut PCCOR_SIGNATURE, 
+        pcbSigBlob: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumGenericParamConstraints(
+        phEnum: *mut HCORENUM, 
+        tk: mdGenericParam, 
+        rGenericParamConstraints: *mut mdGenericParamConstraint, 
+        cMax: ULONG, 
+        pcGenericParamConstraints: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetGenericParamConstraintProps(
+        gpc: mdGenericParamConstraint, 
+        ptGenericParam: *mut mdGenericParam, 
+        ptkConstraintType: *mut mdToken,
+    ) -> HRESULT, 
+    fn GetPEKind(
+        pdwPEKind: *mut DWORD, 
+        pdwMAchine: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetVersionString(
+        pwzBuf: LPWSTR, 
+        ccBufSize: DWORD, 
+        pccBufSize: *mut DWORD,
+    ) -> HRESULT, 
+    fn EnumMethodSpecs(
+        phEnum: *mut HCORENUM, 
+        tk: mdToken, 
+        rMethodSpecs: *mut mdMethodSpec, 
+        cMax: ULONG, 
+        pcMethodSpecs: *mut ULONG,
+    ) -> HRESULT,
 }}
 
 DEFINE_GUID!(IID_IMetaDataFilter, 0xd0e80dd1, 0x12d4, 0x11d3, 0xb3, 0x9d, 0x0, 0xc0, 0x4f, 0xf8, 0x17, 0x95);
@@ -299,8 +810,105 @@ INTERFACE_BINDING!{interface IMetaDataAssemblyEmit(IMetaDataAssemblyEmitVtbl): I
 }}
 
 DEFINE_GUID!(IID_IMetaDataAssemblyImport, 0xee62470b, 0xe94b, 0x424e, 0x9b, 0x7c, 0x2f, 0x0, 0xc9, 0x24, 0x9f, 0x93);
-INTERFACE_BINDING!{interface IMetaDataAssemblyImport(IMetaDataAssemblyImportVtbl): IUnknown(IUnknownVtbl){
-
+RIDL!{#[uuid(0xee62470b, 0xe94b, 0x424e, 0x9b, 0x7c, 0x2f, 0x0, 0xc9, 0x24, 0x9f, 0x93)]
+interface IMetaDataAssemblyImport(IMetaDataAssemblyImportVtbl): IUnknown(IUnknownVtbl){
+    fn GetAssemblyProps(
+        mda: mdAssembly, 
+        ppbPublicKey: *mut *const c_void, 
+        pcbPublicKey: *mut ULONG, 
+        pulHashAlgId: *mut ULONG, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        pMetaData: *mut ASSEMBLYMETADATA, 
+        pdwAssemblyFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetAssemblyRefProps(
+        mdar: mdAssemblyRef, 
+        ppbPublicKeyOrToken: *mut *const c_void, 
+        pcbPublicKeyOrToken: *mut ULONG, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        pMetaData: *mut ASSEMBLYMETADATA, 
+        ppbHashValue: *mut *const c_void, 
+        pcbHashValue: *mut ULONG, 
+        pdwAssemblyRefFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetFileProps(
+        mdf: mdFile, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        ppbHashValue: *mut *const c_void, 
+        pcbHashValue: *mut ULONG, 
+        pdwFileFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetExportedTypeProps(
+        mdct: mdExportedType, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        ptkImplementation: *mut mdToken, 
+        ptkTypeDef: *mut mdTypeDef, 
+        pdwExportedTypeFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn GetManifestResourceProps(
+        mdmr: mdManifestResource, 
+        szName: LPWSTR, 
+        cchName: ULONG, 
+        pchName: *mut ULONG, 
+        ptkImplementation: *mut mdToken, 
+        pdwOffset: *mut DWORD, 
+        pdwResourceFlags: *mut DWORD,
+    ) -> HRESULT, 
+    fn EnumAssemblyRefs(
+        phEnum: *mut HCORENUM, 
+        rAssemblyRefs: *mut mdAssemblyRef, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumFiles(
+        phEnum: *mut HCORENUM, 
+        rFiles: *mut mdFile, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumExportedTypes(
+        phEnum: *mut HCORENUM, 
+        rExportedTypes: *mut mdExportedType, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn EnumManifestResources(
+        phEnum: *mut HCORENUM, 
+        rManifestResources: *mut mdManifestResource, 
+        cMax: ULONG, 
+        pcTokens: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetAssemblyFromScope(
+        ptkAssembly: *mut mdAssembly,
+    ) -> HRESULT, 
+    fn FindExportedTypeByName(
+        szName: LPCWSTR, 
+        mdtExportedType: mdToken, 
+        ptkExportedType: *mut mdExportedType,
+    ) -> HRESULT, 
+    fn FindManifestResourceByName(
+        szName: LPCWSTR, 
+        ptkManifestResource: *mut mdManifestResource,
+    ) -> HRESULT, 
+    fn CloseEnum(
+        hEnum: HCORENUM,
+    ) -> (), 
+    fn FindAssembliesByName(
+        szAppBase: LPCWSTR, 
+        szPrivateBin: LPCWSTR, 
+        szAssemblyName: LPCWSTR, 
+        ppIUnk: *mut *mut IUnknown, 
+        cMax: ULONG, 
+        pcAssemblies: *mut ULONG,
+    ) -> HRESULT,
 }}
 
 ENUM!{enum CorValidatorModuleType
@@ -315,9 +923,11 @@ ENUM!{enum CorValidatorModuleType
 }}
 
 DEFINE_GUID!(IID_IMetaDataValidate, 0x4709c9c6, 0x81ff, 0x11d3, 0x9f, 0xc7, 0x0, 0xc0, 0x4f, 0x79, 0xa0, 0xa3);
-INTERFACE_BINDING!{interface IMetaDataValidate(IMetaDataValidateVtbl): IUnknown(IUnknownVtbl){
+RIDL!{#[uuid(0x4709c9c6, 0x81ff, 0x11d3, 0x9f, 0xc7, 0x0, 0xc0, 0x4f, 0x79, 0xa0, 0xa3)]
+interface IMetaDataValidate(IMetaDataValidateVtbl): IUnknown(IUnknownVtbl){
     fn ValidatorInit(
         dwModuleType: DWORD, 
+        pUnk: *mut IUnknown,
     ) -> HRESULT, 
     fn ValidateMetaData() -> HRESULT, 
 }}
@@ -327,6 +937,114 @@ INTERFACE_BINDING!{interface IMetaDataDispenserEx(IMetaDataDispenserExVtbl): IMe
 
 }}
 
+//Column type codes from IMetaDataTables::GetColumnInfo: below iCodedToken 
+// the column is a RID into that table, then coded token kinds, then fixed types
+ENUM!{enum CorColumnType
+{
+    iRidMax                 = 63,
+    iCodedToken             = 64,
+    iCodedTokenMax          = 95,
+    iSHORT                  = 96,
+    iUSHORT                 = 97,
+    iLONG                   = 98,
+    iULONG                  = 99,
+    iBYTE                   = 100,
+    iSTRING                 = 101,
+    iGUID                   = 102,
+    iBLOB                   = 103,
+}}
+
+DEFINE_GUID!(IID_IMetaDataTables, 0xd8f579ab, 0x402d, 0x4b8e, 0x82, 0xd9, 0x5d, 0x63, 0xb1, 0x06, 0x5c, 0x68);
+RIDL!{#[uuid(0xd8f579ab, 0x402d, 0x4b8e, 0x82, 0xd9, 0x5d, 0x63, 0xb1, 0x06, 0x5c, 0x68)]
+interface IMetaDataTables(IMetaDataTablesVtbl): IUnknown(IUnknownVtbl){
+    fn GetStringHeapSize(
+        pcbStrings: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetBlobHeapSize(
+        pcbBlobs: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetGuidHeapSize(
+        pcbGuids: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetUserStringHeapSize(
+        pcbBlobs: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetNumTables(
+        pcTables: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetTableIndex(
+        token: ULONG, 
+        pixTbl: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetTableInfo(
+        ixTbl: ULONG, 
+        pcbRow: *mut ULONG, 
+        pcRows: *mut ULONG, 
+        pcCols: *mut ULONG, 
+        piKey: *mut ULONG, 
+        ppName: *mut *const c_char,
+    ) -> HRESULT, 
+    fn GetColumnInfo(
+        ixTbl: ULONG, 
+        ixCol: ULONG, 
+        poCol: *mut ULONG, 
+        pcbCol: *mut ULONG, 
+        pType: *mut ULONG, 
+        ppName: *mut *const c_char,
+    ) -> HRESULT, 
+    fn GetCodedTokenInfo(
+        ixCdTkn: ULONG, 
+        pcTokens: *mut ULONG, 
+        ppTokens: *mut *const ULONG, 
+        ppName: *mut *const c_char,
+    ) -> HRESULT, 
+    fn GetRow(
+        ixTbl: ULONG, 
+        rid: ULONG, 
+        ppRow: *mut *const c_void,
+    ) -> HRESULT, 
+    fn GetColumn(
+        ixTbl: ULONG, 
+        ixCol: ULONG, 
+        rid: ULONG, 
+        pVal: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetString(
+        ixString: ULONG, 
+        ppString: *mut *const c_char,
+    ) -> HRESULT, 
+    fn GetBlob(
+        ixBlob: ULONG, 
+        pcbData: *mut ULONG, 
+        ppData: *mut *const c_void,
+    ) -> HRESULT, 
+    fn GetGuid(
+        ixGuid: ULONG, 
+        ppGUID: *mut *const GUID,
+    ) -> HRESULT, 
+    fn GetUserString(
+        ixUserString: ULONG, 
+        pcbData: *mut ULONG, 
+        ppData: *mut *const c_void,
+    ) -> HRESULT, 
+    fn GetNextString(
+        ixString: ULONG, 
+        pNext: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetNextBlob(
+        ixBlob: ULONG, 
+        pNext: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetNextGuid(
+        ixGuid: ULONG, 
+        pNext: *mut ULONG,
+    ) -> HRESULT, 
+    fn GetNextUserString(
+        ixUserString: ULONG, 
+        pNext: *mut ULONG,
+    ) -> HRESULT,
+}}
+
 ENUM!{enum CorRegFlags
 {
     regNoCopy = 0x00000001,
@@ -0,0 +1,65 @@
+// corerror.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Subset of corerror.h: failure codes returned by the hosting interfaces
+
+use winapi::shared::winerror::HRESULT;
+
+pub const COR_E_APPDOMAINUNLOADED: HRESULT = 0x80131014u32 as HRESULT;
+pub const COR_E_CANNOTUNLOADAPPDOMAIN: HRESULT = 0x80131015u32 as HRESULT;
+pub const COR_E_ASSEMBLYEXPECTED: HRESULT = 0x80131018u32 as HRESULT;
+pub const COR_E_NEWER_RUNTIME: HRESULT = 0x8013101Bu32 as HRESULT;
+
+pub const COR_E_EXCEPTION: HRESULT = 0x80131500u32 as HRESULT;
+pub const COR_E_SYSTEM: HRESULT = 0x80131501u32 as HRESULT;
+pub const COR_E_INVALIDOPERATION: HRESULT = 0x80131509u32 as HRESULT;
+pub const COR_E_MISSINGFIELD: HRESULT = 0x80131511u32 as HRESULT;
+pub const COR_E_MISSINGMEMBER: HRESULT = 0x80131512u32 as HRESULT;
+pub const COR_E_MISSINGMETHOD: HRESULT = 0x80131513u32 as HRESULT;
+pub const COR_E_TYPELOAD: HRESULT = 0x80131522u32 as HRESULT;
+pub const COR_E_THREADABORTED: HRESULT = 0x80131530u32 as HRESULT;
+pub const COR_E_TARGETINVOCATION: HRESULT = 0x80131604u32 as HRESULT;
+
+pub const CLDB_S_TRUNCATION: HRESULT = 0x00131106;
+pub const CLDB_E_FILE_CORRUPT: HRESULT = 0x8013110Eu32 as HRESULT;
+pub const CLDB_E_RECORD_NOTFOUND: HRESULT = 0x80131130u32 as HRESULT;
+pub const META_E_BAD_SIGNATURE: HRESULT = 0x80131192u32 as HRESULT;
+pub const META_E_CA_INVALID_BLOB: HRESULT = 0x801311C2u32 as HRESULT;
+
+pub const HOST_E_DEADLOCK: HRESULT = 0x80131020u32 as HRESULT;
+pub const HOST_E_INTERRUPTED: HRESULT = 0x80131021u32 as HRESULT;
+pub const HOST_E_INVALIDOPERATION: HRESULT = 0x80131022u32 as HRESULT;
+pub const HOST_E_CLRNOTAVAILABLE: HRESULT = 0x80131023u32 as HRESULT;
+pub const HOST_E_TIMEOUT: HRESULT = 0x80131024u32 as HRESULT;
+pub const HOST_E_NOT_OWNER: HRESULT = 0x80131025u32 as HRESULT;
+pub const HOST_E_ABANDONED: HRESULT = 0x80131026u32 as HRESULT;
+pub const HOST_E_EXITPROCESS_THREADABORT: HRESULT = 0x80131027u32 as HRESULT;
+pub const HOST_E_EXITPROCESS_ADUNLOAD: HRESULT = 0x80131028u32 as HRESULT;
+pub const HOST_E_EXITPROCESS_TIMEOUT: HRESULT = 0x80131029u32 as HRESULT;
+pub const HOST_E_EXITPROCESS_OUTOFMEMORY: HRESULT = 0x8013102au32 as HRESULT;
+
+pub const CLR_E_SHIM_RUNTIMELOAD: HRESULT = 0x80131700u32 as HRESULT;
+pub const CLR_E_SHIM_RUNTIMEEXPORT: HRESULT = 0x80131701u32 as HRESULT;
+pub const CLR_E_SHIM_INSTALLROOT: HRESULT = 0x80131702u32 as HRESULT;
+pub const CLR_E_SHIM_INSTALLCOMP: HRESULT = 0x80131703u32 as HRESULT;
+pub const CLR_E_SHIM_LEGACYRUNTIMEALREADYBOUND: HRESULT = 0x80131704u32 as HRESULT;
+pub const CLR_E_SHIM_SHUTDOWNINPROGRESS: HRESULT = 0x80131705u32 as HRESULT;
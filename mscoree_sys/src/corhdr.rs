@@ -21,8 +21,9 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
+use winapi::ctypes::{c_char, c_void};
 use winapi::shared::basetsd::{UINT32};
-use winapi::shared::minwindef::LPVOID;
+use winapi::shared::minwindef::{LPVOID, ULONG};
 
 pub type mdScope = LPVOID;
 pub type mdToken = UINT32;
@@ -50,6 +51,211 @@ pub type mdMethodSpec = mdToken;
 pub type mdGenericParamConstraint = mdToken;
 pub type mdString = mdToken;
 pub type mdCPToken = mdToken;
+pub type mdModule = mdToken;
+
+pub const mdTokenNil: mdToken = 0;
+
+//Top byte of every token: which table the row lives in
+ENUM!{enum CorTokenType
+{
+    mdtModule               = 0x00000000,
+    mdtTypeRef              = 0x01000000,
+    mdtTypeDef              = 0x02000000,
+    mdtFieldDef             = 0x04000000,
+    mdtMethodDef            = 0x06000000,
+    mdtParamDef             = 0x08000000,
+    mdtInterfaceImpl        = 0x09000000,
+    mdtMemberRef            = 0x0a000000,
+    mdtCustomAttribute      = 0x0c000000,
+    mdtPermission           = 0x0e000000,
+    mdtSignature            = 0x11000000,
+    mdtEvent                = 0x14000000,
+    mdtProperty             = 0x17000000,
+    mdtMethodImpl           = 0x19000000,
+    mdtModuleRef            = 0x1a000000,
+    mdtTypeSpec             = 0x1b000000,
+    mdtAssembly             = 0x20000000,
+    mdtAssemblyRef          = 0x23000000,
+    mdtFile                 = 0x26000000,
+    mdtExportedType         = 0x27000000,
+    mdtManifestResource     = 0x28000000,
+    mdtGenericParam         = 0x2a000000,
+    mdtMethodSpec           = 0x2b000000,
+    mdtGenericParamConstraint = 0x2c000000,
+    mdtString               = 0x70000000,
+    mdtName                 = 0x71000000,
+    mdtBaseType             = 0x72000000,
+}}
+
+pub type HCORENUM = *mut c_void;
+pub type COR_SIGNATURE = u8;
+pub type PCOR_SIGNATURE = *mut COR_SIGNATURE;
+pub type PCCOR_SIGNATURE = *const COR_SIGNATURE;
+pub type MDUTF8CSTR = *const c_char;
+
+STRUCT!{struct COR_FIELD_OFFSET
+{
+    ridOfField: mdToken,
+    ulOffset: ULONG,
+}}
+
+//Only the MethodDef attribute bits the wrappers look at
+ENUM!{enum CorMethodAttr
+{
+    mdStatic                = 0x0010,
+    mdVirtual               = 0x0040,
+    mdAbstract              = 0x0400,
+    mdPinvokeImpl           = 0x2000,
+}}
+
+ENUM!{enum CorPinvokeMap
+{
+    pmNoMangle              = 0x0001,
+    pmCharSetMask           = 0x0006,
+    pmCharSetNotSpec        = 0x0000,
+    pmCharSetAnsi           = 0x0002,
+    pmCharSetUnicode        = 0x0004,
+    pmCharSetAuto           = 0x0006,
+    pmBestFitUseAssem       = 0x0000,
+    pmBestFitEnabled        = 0x0010,
+    pmBestFitDisabled       = 0x0020,
+    pmBestFitMask           = 0x0030,
+    pmThrowOnUnmappableCharUseAssem = 0x0000,
+    pmThrowOnUnmappableCharEnabled  = 0x1000,
+    pmThrowOnUnmappableCharDisabled = 0x2000,
+    pmThrowOnUnmappableCharMask     = 0x3000,
+    pmSupportsLastError     = 0x0040,
+    pmCallConvMask          = 0x0700,
+    pmCallConvWinapi        = 0x0100,
+    pmCallConvCdecl         = 0x0200,
+    pmCallConvStdcall       = 0x0300,
+    pmCallConvThiscall      = 0x0400,
+    pmCallConvFastcall      = 0x0500,
+    pmMaxValue              = 0xFFFF,
+}}
+
+//Only the TypeDef/ExportedType attribute bits the wrappers look at
+ENUM!{enum CorTypeAttr
+{
+    tdVisibilityMask        = 0x00000007,
+    tdNotPublic             = 0x00000000,
+    tdPublic                = 0x00000001,
+    tdNestedPublic          = 0x00000002,
+    tdInterface             = 0x00000020,
+    tdAbstract              = 0x00000080,
+    tdSealed                = 0x00000100,
+    tdForwarder             = 0x00200000,
+}}
+
+ENUM!{enum CorGenericParamAttr
+{
+    gpVarianceMask          = 0x0003,
+    gpNonVariant            = 0x0000,
+    gpCovariant             = 0x0001,
+    gpContravariant         = 0x0002,
+    gpSpecialConstraintMask = 0x001C,
+    gpNoSpecialConstraint   = 0x0000,
+    gpReferenceTypeConstraint = 0x0004,
+    gpNotNullableValueTypeConstraint = 0x0008,
+    gpDefaultConstructorConstraint = 0x0010,
+}}
+
+ENUM!{enum CorAssemblyFlags
+{
+    afPublicKey             = 0x0001,
+    afRetargetable          = 0x0100,
+}}
+
+ENUM!{enum CorManifestResourceFlags
+{
+    mrVisibilityMask        = 0x0007,
+    mrPublic                = 0x0001,
+    mrPrivate               = 0x0002,
+}}
+
+ENUM!{enum CorOpenFlags
+{
+    ofRead                  = 0x00000000,
+    ofWrite                 = 0x00000001,
+    ofReadWriteMask         = 0x00000001,
+    ofCopyMemory            = 0x00000002,
+    ofReadOnly              = 0x00000010,
+    ofTakeOwnership         = 0x00000020,
+    ofNoTypeLib             = 0x00000080,
+    ofNoTransform           = 0x00001000,
+}}
+
+ENUM!{enum CorElementType
+{
+    ELEMENT_TYPE_END            = 0x00,
+    ELEMENT_TYPE_VOID           = 0x01,
+    ELEMENT_TYPE_BOOLEAN        = 0x02,
+    ELEMENT_TYPE_CHAR           = 0x03,
+    ELEMENT_TYPE_I1             = 0x04,
+    ELEMENT_TYPE_U1             = 0x05,
+    ELEMENT_TYPE_I2             = 0x06,
+    ELEMENT_TYPE_U2             = 0x07,
+    ELEMENT_TYPE_I4             = 0x08,
+    ELEMENT_TYPE_U4             = 0x09,
+    ELEMENT_TYPE_I8             = 0x0a,
+    ELEMENT_TYPE_U8             = 0x0b,
+    ELEMENT_TYPE_R4             = 0x0c,
+    ELEMENT_TYPE_R8             = 0x0d,
+    ELEMENT_TYPE_STRING         = 0x0e,
+    ELEMENT_TYPE_PTR            = 0x0f,
+    ELEMENT_TYPE_BYREF          = 0x10,
+    ELEMENT_TYPE_VALUETYPE      = 0x11,
+    ELEMENT_TYPE_CLASS          = 0x12,
+    ELEMENT_TYPE_VAR            = 0x13,
+    ELEMENT_TYPE_ARRAY          = 0x14,
+    ELEMENT_TYPE_GENERICINST    = 0x15,
+    ELEMENT_TYPE_TYPEDBYREF     = 0x16,
+    ELEMENT_TYPE_I              = 0x18,
+    ELEMENT_TYPE_U              = 0x19,
+    ELEMENT_TYPE_FNPTR          = 0x1b,
+    ELEMENT_TYPE_OBJECT         = 0x1c,
+    ELEMENT_TYPE_SZARRAY        = 0x1d,
+    ELEMENT_TYPE_MVAR           = 0x1e,
+    ELEMENT_TYPE_CMOD_REQD      = 0x1f,
+    ELEMENT_TYPE_CMOD_OPT       = 0x20,
+    ELEMENT_TYPE_INTERNAL       = 0x21,
+    ELEMENT_TYPE_MAX            = 0x22,
+    ELEMENT_TYPE_MODIFIER       = 0x40,
+    ELEMENT_TYPE_SENTINEL       = 0x01 | ELEMENT_TYPE_MODIFIER,
+    ELEMENT_TYPE_PINNED         = 0x05 | ELEMENT_TYPE_MODIFIER,
+}}
+
+//Custom attribute blobs only: the encodings for System.Type, boxed 
+// object arguments, and the field/property markers of named arguments
+ENUM!{enum CorSerializationType
+{
+    SERIALIZATION_TYPE_TYPE         = 0x50,
+    SERIALIZATION_TYPE_TAGGED_OBJECT = 0x51,
+    SERIALIZATION_TYPE_FIELD        = 0x53,
+    SERIALIZATION_TYPE_PROPERTY     = 0x54,
+    SERIALIZATION_TYPE_ENUM         = 0x55,
+}}
+
+ENUM!{enum CorCallingConvention
+{
+    IMAGE_CEE_CS_CALLCONV_DEFAULT       = 0x0,
+    IMAGE_CEE_CS_CALLCONV_C             = 0x1,
+    IMAGE_CEE_CS_CALLCONV_STDCALL       = 0x2,
+    IMAGE_CEE_CS_CALLCONV_THISCALL      = 0x3,
+    IMAGE_CEE_CS_CALLCONV_FASTCALL      = 0x4,
+    IMAGE_CEE_CS_CALLCONV_VARARG        = 0x5,
+    IMAGE_CEE_CS_CALLCONV_FIELD         = 0x6,
+    IMAGE_CEE_CS_CALLCONV_LOCAL_SIG     = 0x7,
+    IMAGE_CEE_CS_CALLCONV_PROPERTY      = 0x8,
+    IMAGE_CEE_CS_CALLCONV_UNMGD         = 0x9,
+    IMAGE_CEE_CS_CALLCONV_GENERICINST   = 0xa,
+    IMAGE_CEE_CS_CALLCONV_NATIVEVARARG  = 0xb,
+    IMAGE_CEE_CS_CALLCONV_MAX           = 0xc,
+    IMAGE_CEE_CS_CALLCONV_MASK          = 0x0f,
+    IMAGE_CEE_CS_CALLCONV_HASTHIS       = 0x20,
+    IMAGE_CEE_CS_CALLCONV_EXPLICITTHIS  = 0x40,
+    IMAGE_CEE_CS_CALLCONV_GENERIC       = 0x10,
+}}
 
 ENUM!{enum CorSaveSize
 {
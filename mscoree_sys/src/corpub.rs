@@ -20,3 +20,92 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
+
+//corpub.h: the publishing interfaces debuggers use to find managed 
+// processes and the domains inside them
+
+use winapi::shared::minwindef::{BOOL, UINT, ULONG};
+use winapi::shared::ntdef::WCHAR;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+
+DEFINE_GUID!(CLSID_CorpubPublish, 0x047a9a40, 0x657e, 0x11d3, 0x8d, 0x5b, 0x00, 0x10, 0x4b, 0x35, 0xe7, 0xef);
+DEFINE_GUID!(IID_ICorPublish, 0x9613A0E7, 0x5A68, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C);
+
+ENUM!{enum COR_PUB_ENUMPROCESS {
+    COR_PUB_MANAGEDONLY = 0x00000001,
+}}
+
+RIDL!{#[uuid(0x9613A0E7, 0x5A68, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C)]
+interface ICorPublish(ICorPublishVtbl): IUnknown(IUnknownVtbl){
+    fn EnumProcesses(
+        Type: COR_PUB_ENUMPROCESS, 
+        ppIEnum: *mut *mut ICorPublishProcessEnum, 
+    ) -> HRESULT,
+    fn GetProcess(
+        pid: UINT, 
+        ppProcess: *mut *mut ICorPublishProcess, 
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0xC0B22967, 0x5A69, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C)]
+interface ICorPublishEnum(ICorPublishEnumVtbl): IUnknown(IUnknownVtbl){
+    fn Skip(
+        celt: ULONG, 
+    ) -> HRESULT,
+    fn Reset() -> HRESULT,
+    fn Clone(
+        ppEnum: *mut *mut ICorPublishEnum, 
+    ) -> HRESULT,
+    fn GetCount(
+        pcelt: *mut ULONG, 
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0x18D87AF1, 0x5A6A, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C)]
+interface ICorPublishProcess(ICorPublishProcessVtbl): IUnknown(IUnknownVtbl){
+    fn IsManaged(
+        pbManaged: *mut BOOL, 
+    ) -> HRESULT,
+    fn EnumAppDomains(
+        ppEnum: *mut *mut ICorPublishAppDomainEnum, 
+    ) -> HRESULT,
+    fn GetProcessID(
+        pid: *mut UINT, 
+    ) -> HRESULT,
+    fn GetDisplayName(
+        cchName: ULONG, 
+        pcchName: *mut ULONG, 
+        szName: *mut WCHAR, 
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0xD6315C8F, 0x5A6A, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C)]
+interface ICorPublishAppDomain(ICorPublishAppDomainVtbl): IUnknown(IUnknownVtbl){
+    fn GetID(
+        puId: *mut ULONG, 
+    ) -> HRESULT,
+    fn GetName(
+        cchName: ULONG, 
+        pcchName: *mut ULONG, 
+        szName: *mut WCHAR, 
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0xA37FBD41, 0x5A69, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C)]
+interface ICorPublishProcessEnum(ICorPublishProcessEnumVtbl): ICorPublishEnum(ICorPublishEnumVtbl){
+    fn Next(
+        celt: ULONG, 
+        objects: *mut *mut ICorPublishProcess, 
+        pceltFetched: *mut ULONG, 
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0x9F0C98F5, 0x5A6A, 0x11d3, 0x8F, 0x84, 0x00, 0xA0, 0xC9, 0xB4, 0xD5, 0x0C)]
+interface ICorPublishAppDomainEnum(ICorPublishAppDomainEnumVtbl): ICorPublishEnum(ICorPublishEnumVtbl){
+    fn Next(
+        celt: ULONG, 
+        objects: *mut *mut ICorPublishAppDomain, 
+        pceltFetched: *mut ULONG, 
+    ) -> HRESULT,
+}}
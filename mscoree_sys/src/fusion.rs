@@ -20,3 +20,169 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
+
+//Types from fusion.h. fusion.dll lives in the runtime directory rather 
+// than on the search path, so CreateAssemblyEnum and 
+// CreateAssemblyNameObject are function pointers to resolve after 
+// LoadLibraryShim("fusion.dll").
+#![allow(non_camel_case_types, non_upper_case_globals)]
+use winapi::shared::minwindef::{DWORD, LPDWORD, LPVOID};
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::ntdef::{LONGLONG, LPCWSTR, WCHAR};
+use winapi::shared::winerror::HRESULT;
+use winapi::shared::wtypesbase::{LPCOLESTR, LPOLESTR};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+
+ENUM!{enum ASM_NAME {
+    ASM_NAME_PUBLIC_KEY = 0, 
+    ASM_NAME_PUBLIC_KEY_TOKEN, 
+    ASM_NAME_HASH_VALUE, 
+    ASM_NAME_NAME, 
+    ASM_NAME_MAJOR_VERSION, 
+    ASM_NAME_MINOR_VERSION, 
+    ASM_NAME_BUILD_NUMBER, 
+    ASM_NAME_REVISION_NUMBER, 
+    ASM_NAME_CULTURE, 
+    ASM_NAME_PROCESSOR_ID_ARRAY, 
+    ASM_NAME_OSINFO_ARRAY, 
+    ASM_NAME_HASH_ALGID, 
+    ASM_NAME_ALIAS, 
+    ASM_NAME_CODEBASE_URL, 
+    ASM_NAME_CODEBASE_LASTMOD, 
+    ASM_NAME_NULL_PUBLIC_KEY, 
+    ASM_NAME_NULL_PUBLIC_KEY_TOKEN, 
+    ASM_NAME_CUSTOM, 
+    ASM_NAME_NULL_CUSTOM, 
+    ASM_NAME_MVID, 
+    ASM_NAME_FILE_MAJOR_VERSION, 
+    ASM_NAME_FILE_MINOR_VERSION, 
+    ASM_NAME_FILE_BUILD_NUMBER, 
+    ASM_NAME_FILE_REVISION_NUMBER, 
+    ASM_NAME_RETARGET, 
+    ASM_NAME_SIGNATURE_BLOB, 
+    ASM_NAME_CONFIG_MASK, 
+    ASM_NAME_ARCHITECTURE, 
+    ASM_NAME_MAX_PARAMS,
+}}
+
+ENUM!{enum ASM_DISPLAY_FLAGS {
+    ASM_DISPLAYF_VERSION = 0x1, 
+    ASM_DISPLAYF_CULTURE = 0x2, 
+    ASM_DISPLAYF_PUBLIC_KEY_TOKEN = 0x4, 
+    ASM_DISPLAYF_PUBLIC_KEY = 0x8, 
+    ASM_DISPLAYF_CUSTOM = 0x10, 
+    ASM_DISPLAYF_PROCESSORARCHITECTURE = 0x20, 
+    ASM_DISPLAYF_LANGUAGEID = 0x40, 
+    ASM_DISPLAYF_RETARGET = 0x80, 
+    ASM_DISPLAYF_CONFIG_MASK = 0x100, 
+    ASM_DISPLAYF_MVID = 0x200, 
+    ASM_DISPLAYF_FULL = 0xA7,
+}}
+
+ENUM!{enum ASM_CMP_FLAGS {
+    ASM_CMPF_NAME = 0x1, 
+    ASM_CMPF_MAJOR_VERSION = 0x2, 
+    ASM_CMPF_MINOR_VERSION = 0x4, 
+    ASM_CMPF_BUILD_NUMBER = 0x8, 
+    ASM_CMPF_REVISION_NUMBER = 0x10, 
+    ASM_CMPF_VERSION = 0x1E, 
+    ASM_CMPF_PUBLIC_KEY_TOKEN = 0x20, 
+    ASM_CMPF_CULTURE = 0x40, 
+    ASM_CMPF_CUSTOM = 0x80, 
+    ASM_CMPF_ALL = 0xFF, 
+    ASM_CMPF_DEFAULT = 0x100, 
+    ASM_CMPF_RETARGET = 0x200, 
+    ASM_CMPF_ARCHITECTURE = 0x400, 
+    ASM_CMPF_CONFIG_MASK = 0x800, 
+    ASM_CMPF_MVID = 0x1000, 
+    ASM_CMPF_SIGNATURE = 0x2000,
+}}
+
+ENUM!{enum CREATE_ASM_NAME_OBJ_FLAGS {
+    CANOF_PARSE_DISPLAY_NAME = 0x1, 
+    CANOF_SET_DEFAULT_VALUES = 0x2, 
+    CANOF_VERIFY_FRIEND_ASSEMBLYNAME = 0x4, 
+    CANOF_PARSE_FRIEND_DISPLAY_NAME = 0x5,
+}}
+
+ENUM!{enum ASM_CACHE_FLAGS {
+    ASM_CACHE_ZAP = 0x1, 
+    ASM_CACHE_GAC = 0x2, 
+    ASM_CACHE_DOWNLOAD = 0x4, 
+    ASM_CACHE_ROOT = 0x8, 
+    ASM_CACHE_ROOT_EX = 0x80,
+}}
+
+RIDL!{#[uuid(0xCD193BC0, 0xB4BC, 0x11d2, 0x98, 0x33, 0x00, 0xC0, 0x4F, 0xC3, 0x1D, 0x2E)]
+interface IAssemblyName(IAssemblyNameVtbl): IUnknown(IUnknownVtbl){
+    fn SetProperty(
+        PropertyId: DWORD, 
+        pvProperty: LPVOID, 
+        cbProperty: DWORD,
+    ) -> HRESULT, 
+    fn GetProperty(
+        PropertyId: DWORD, 
+        pvProperty: LPVOID, 
+        pcbProperty: LPDWORD,
+    ) -> HRESULT, 
+    fn Finalize() -> HRESULT, 
+    fn GetDisplayName(
+        szDisplayName: LPOLESTR, 
+        pccDisplayName: LPDWORD, 
+        dwDisplayFlags: DWORD,
+    ) -> HRESULT, 
+    fn Reserved(
+        refIID: REFIID, 
+        pUnkReserved1: *mut IUnknown, 
+        pUnkReserved2: *mut IUnknown, 
+        szReserved: LPCOLESTR, 
+        llReserved: LONGLONG, 
+        pvReserved: LPVOID, 
+        cbReserved: DWORD, 
+        ppReserved: *mut LPVOID,
+    ) -> HRESULT, 
+    fn GetName(
+        lpcwBuffer: LPDWORD, 
+        pwzName: *mut WCHAR,
+    ) -> HRESULT, 
+    fn GetVersion(
+        pdwVersionHi: LPDWORD, 
+        pdwVersionLow: LPDWORD,
+    ) -> HRESULT, 
+    fn IsEqual(
+        pName: *mut IAssemblyName, 
+        dwCmpFlags: DWORD,
+    ) -> HRESULT, 
+    fn Clone(
+        pName: *mut *mut IAssemblyName,
+    ) -> HRESULT,
+}}
+
+//ppAppCtx is an IApplicationContext, which nothing here uses
+RIDL!{#[uuid(0x21b8916c, 0xf28e, 0x11d2, 0xa4, 0x73, 0x00, 0xc0, 0x4f, 0x8e, 0xf4, 0x48)]
+interface IAssemblyEnum(IAssemblyEnumVtbl): IUnknown(IUnknownVtbl){
+    fn GetNextAssembly(
+        ppAppCtx: *mut LPVOID, 
+        ppName: *mut *mut IAssemblyName, 
+        dwFlags: DWORD,
+    ) -> HRESULT, 
+    fn Reset() -> HRESULT, 
+    fn Clone(
+        ppEnum: *mut *mut IAssemblyEnum,
+    ) -> HRESULT,
+}}
+
+FUNC_PTR!{CreateAssemblyEnumFn(
+    pEnum: *mut *mut IAssemblyEnum, 
+    pUnkReserved: *mut IUnknown, 
+    pName: *mut IAssemblyName, 
+    dwFlags: DWORD, 
+    pvReserved: LPVOID
+) -> HRESULT}
+
+FUNC_PTR!{CreateAssemblyNameObjectFn(
+    ppAssemblyNameObj: *mut *mut IAssemblyName, 
+    szAssemblyName: LPCWSTR, 
+    dwFlags: DWORD, 
+    pvReserved: LPVOID
+) -> HRESULT}
@@ -1,7 +1,16 @@
 #![allow(dead_code, non_upper_case_globals, non_camel_case_types, non_snake_case)]
 
+use winapi::ctypes::c_long;
 use winapi::shared::basetsd::SIZE_T;
-use winapi::shared::minwindef::{ULONG};
+use winapi::shared::minwindef::{DWORD, ULONG};
+use winapi::shared::ntdef::ULONGLONG;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+
+ENUM!{enum COR_GC_STAT_TYPES {
+    COR_GC_COUNTS = 0x00000001,
+    COR_GC_MEMORYUSAGE = 0x00000002,
+}}
 
 STRUCT!{ struct _COR_GC_STATS {
     Flags: ULONG,   
@@ -16,4 +25,43 @@ STRUCT!{ struct _COR_GC_STATS {
     KBytesPromotedFromGen0: SIZE_T,
     KBytesPromotedFromGen1: SIZE_T,
 }}
-pub type COR_GC_STATS = _COR_GC_STATS;
\ No newline at end of file
+pub type COR_GC_STATS = _COR_GC_STATS;
+
+ENUM!{enum COR_GC_THREAD_STATS_TYPES {
+    COR_GC_THREAD_HAS_PROMOTED_BYTES = 0x00000001,
+}}
+
+STRUCT!{ struct _COR_GC_THREAD_STATS {
+    PerThreadAllocation: ULONGLONG,
+    Flags: ULONG,
+}}
+pub type COR_GC_THREAD_STATS = _COR_GC_THREAD_STATS;
+
+RIDL!{#[uuid(0xFAC34F6E, 0x0DCD, 0x47b5, 0x80, 0x21, 0x53, 0x1B, 0xC5, 0xEC, 0xCA, 0x63)]
+interface IGCHost(IGCHostVtbl): IUnknown(IUnknownVtbl){
+    fn SetGCStartupLimits(
+        SegmentSize: DWORD, 
+        MaxGen0Size: DWORD, 
+    ) -> HRESULT,
+    fn Collect(
+        Generation: c_long, 
+    ) -> HRESULT,
+    fn GetStats(
+        pStats: *mut COR_GC_STATS, 
+    ) -> HRESULT,
+    fn GetThreadStats(
+        pFiberCookie: *mut DWORD, 
+        pStats: *mut COR_GC_THREAD_STATS, 
+    ) -> HRESULT,
+    fn SetVirtualMemLimit(
+        sztMaxVirtualMemMB: SIZE_T, 
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0xA1D70CEC, 0x2DBE, 0x4E2F, 0x92, 0x91, 0xFD, 0xF8, 0x14, 0x38, 0xA1, 0xDF)]
+interface IGCHost2(IGCHost2Vtbl): IGCHost(IGCHostVtbl){
+    fn SetGCStartupLimitsEx(
+        SegmentSize: SIZE_T, 
+        MaxGen0Size: SIZE_T, 
+    ) -> HRESULT,
+}}
@@ -0,0 +1,121 @@
+// hostfxr.rs - MIT License
+//  MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Types from nethost.h, hostfxr.h and coreclr_delegates.h for hosting .NET 
+// Core and .NET 5+. None of these are import-library exports: nethost.dll 
+// ships beside the host and hostfxr.dll is located at run time, so every 
+// entry point is a function pointer resolved with GetProcAddress.
+#![allow(non_camel_case_types, non_upper_case_globals)]
+use winapi::ctypes::c_void;
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::ntdef::WCHAR;
+
+//Windows builds of the host use wide strings throughout
+pub type char_t = WCHAR;
+pub type hostfxr_handle = *mut c_void;
+
+STRUCT!{struct get_hostfxr_parameters {
+    size: SIZE_T, 
+    assembly_path: *const char_t, 
+    dotnet_root: *const char_t,
+}}
+
+STRUCT!{struct hostfxr_initialize_parameters {
+    size: SIZE_T, 
+    host_path: *const char_t, 
+    dotnet_root: *const char_t,
+}}
+
+ENUM!{enum hostfxr_delegate_type {
+    hdt_com_activation = 0, 
+    hdt_load_in_memory_assembly = 1, 
+    hdt_winrt_activation = 2, 
+    hdt_com_register = 3, 
+    hdt_com_unregister = 4, 
+    hdt_load_assembly_and_get_function_pointer = 5, 
+    hdt_get_function_pointer = 6,
+}}
+
+//Non-negative results are successes; failures are HRESULT-style 0x8000_8xxx codes
+pub const Success: i32 = 0;
+pub const Success_HostAlreadyInitialized: i32 = 0x00000001;
+pub const Success_DifferentRuntimeProperties: i32 = 0x00000002;
+pub const InvalidArgFailure: i32 = 0x80008081u32 as i32;
+pub const CoreHostLibLoadFailure: i32 = 0x80008082u32 as i32;
+pub const CoreHostLibMissingFailure: i32 = 0x80008083u32 as i32;
+pub const CoreHostEntryPointFailure: i32 = 0x80008084u32 as i32;
+pub const HostApiBufferTooSmall: i32 = 0x80008098u32 as i32;
+pub const FrameworkMissingFailure: i32 = 0x80008096u32 as i32;
+pub const HostInvalidState: i32 = 0x800080a3u32 as i32;
+pub const HostPropertyNotFound: i32 = 0x800080a4u32 as i32;
+
+//Passed as delegate_type_name for methods marked [UnmanagedCallersOnly]
+pub const UNMANAGEDCALLERSONLY_METHOD: *const char_t = -1isize as *const char_t;
+
+//nethost.dll
+FUNC_PTR!{get_hostfxr_path_fn(
+    buffer: *mut char_t, 
+    buffer_size: *mut SIZE_T, 
+    parameters: *const get_hostfxr_parameters
+) -> i32}
+
+//hostfxr.dll
+FUNC_PTR!{hostfxr_initialize_for_runtime_config_fn(
+    runtime_config_path: *const char_t, 
+    parameters: *const hostfxr_initialize_parameters, 
+    host_context_handle: *mut hostfxr_handle
+) -> i32}
+FUNC_PTR!{hostfxr_get_runtime_delegate_fn(
+    host_context_handle: hostfxr_handle, 
+    delegate_type: hostfxr_delegate_type, 
+    delegate: *mut *mut c_void
+) -> i32}
+FUNC_PTR!{hostfxr_get_runtime_property_value_fn(
+    host_context_handle: hostfxr_handle, 
+    name: *const char_t, 
+    value: *mut *const char_t
+) -> i32}
+FUNC_PTR!{hostfxr_set_runtime_property_value_fn(
+    host_context_handle: hostfxr_handle, 
+    name: *const char_t, 
+    value: *const char_t
+) -> i32}
+FUNC_PTR!{hostfxr_close_fn(host_context_handle: hostfxr_handle) -> i32}
+
+//coreclr_delegates.h
+FUNC_PTR!{load_assembly_and_get_function_pointer_fn(
+    assembly_path: *const char_t, 
+    type_name: *const char_t, 
+    method_name: *const char_t, 
+    delegate_type_name: *const char_t, 
+    reserved: *mut c_void, 
+    delegate: *mut *mut c_void
+) -> i32}
+FUNC_PTR!{get_function_pointer_fn(
+    type_name: *const char_t, 
+    method_name: *const char_t, 
+    delegate_type_name: *const char_t, 
+    load_context: *mut c_void, 
+    reserved: *mut c_void, 
+    delegate: *mut *mut c_void
+) -> i32}
+FUNC_PTR!{component_entry_point_fn(arg: *mut c_void, arg_size_in_bytes: i32) -> i32}
@@ -27,18 +27,21 @@ extern crate mscorlib_sys;
 pub mod activation;
 pub mod clrdata;
 pub mod cor;
+pub mod corerror;
 pub mod cordebug;
 pub mod corhdr;
 pub mod corhlpr;
 pub mod corprof;
 pub mod corpub;
 pub mod corsym;
+pub mod fusion;
 pub mod iceefilegen;
 pub mod isolation;
 pub mod ivalidator;
 pub mod ivehandler;
 pub mod inspectable;
 pub mod gchost;
+pub mod hostfxr;
 pub mod metahost;
 pub mod mscoree;
 pub mod openum;
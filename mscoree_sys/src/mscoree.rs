@@ -23,7 +23,7 @@
 
 
 use winapi::ctypes::{c_char, c_int, c_long, c_void};
-use winapi::shared::basetsd::{SIZE_T, UINT64};
+use winapi::shared::basetsd::{INT_PTR, SIZE_T, UINT64};
 use winapi::shared::guiddef::{REFCLSID, REFIID};
 use winapi::shared::minwindef::{BOOL, BYTE, DWORD, HINSTANCE, HMODULE, LPVOID, UINT, ULONG};
 use winapi::shared::ntdef::{HANDLE, LCID, LPCSTR, LPCWSTR, LPWSTR, WCHAR, ULONGLONG};
@@ -38,7 +38,7 @@ use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
 use winapi::um::winnt::{EXCEPTION_POINTERS, PACL, PVOID,  WAITORTIMERCALLBACK};
 
 use crate::activation::IActivationFactory;
-use crate::gchost::COR_GC_STATS;
+use crate::gchost::{COR_GC_STATS, COR_GC_THREAD_STATS};
 
 pub type HWND = *mut c_void;
 
@@ -620,7 +620,7 @@ interface IHostMemoryManager(IHostMemoryManagerVtbl): IUnknown(IUnknownVtbl){
         lpAddress: *mut c_void, 
         lpBuffer: *mut c_void, 
         dwLength: SIZE_T, 
-        pResult: SIZE_T,
+        pResult: *mut SIZE_T,
     ) -> HRESULT,
     fn VirtualProtect(
         lpAddress: *mut c_void,
@@ -658,7 +658,7 @@ interface ICLRTask(ICLRTaskVtbl): IUnknown(IUnknownVtbl){
     ) -> HRESULT, 
     fn SwitchOut() -> HRESULT, 
     fn GetMemStats(
-        memUsage: *mut COR_GC_STATS, 
+        memUsage: *mut COR_GC_THREAD_STATS, 
     ) -> HRESULT, 
     fn Reset(
         fFull: BOOL,
@@ -1356,7 +1356,7 @@ interface ICLRGCManager(ICLRGCManagerVtbl): IUnknown(IUnknownVtbl){
 //V4
 RIDL!{#[uuid(0x0603B793, 0xA97A, 0x4712, 0x9C, 0xB4, 0x0C, 0xD1, 0xC7, 0x4C, 0x0F, 0x7C)]
 interface ICLRGCManager2(ICLRGCManager2Vtbl): ICLRGCManager(ICLRGCManagerVtbl){
-    fn SetGCStartupLimitEx(
+    fn SetGCStartupLimitsEx(
         SegmentSize: SIZE_T, 
         MaxGen0Size: SIZE_T,
     ) -> HRESULT,
@@ -1515,7 +1515,7 @@ interface ICLRRuntimeHost2(ICLRRuntimeHost2Vtbl): ICLRRuntimeHost(ICLRRuntimeHos
         wszAssemblyName: LPCWSTR, 
         wszClassName: LPCWSTR, 
         wszMethodName: LPCWSTR, 
-        fnPtr: *mut i32,
+        fnPtr: *mut INT_PTR,
     ) -> HRESULT,
     fn Authenticate(
         authKey: ULONGLONG,